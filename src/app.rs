@@ -1,64 +1,275 @@
+use std::collections::HashSet;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::io::BufRead;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
-use crate::config::{self, InstallerConfig};
+use ratatui::layout::Rect;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, HostDirStrategy, InstallerConfig, PasswordMode, RootPasswordPolicy};
 use crate::disk::{self, BlockDevice, CloneState, FsType, PartitionPlan};
 use crate::nix::{self, HostPreset, NixModule};
-use crate::theme::Theme;
+use crate::theme::{Theme, ThemeName};
+
+/// Default EFI system partition size for full-disk mode, in MiB.
+pub const DEFAULT_EFI_SIZE_MB: u64 = 512;
+
+/// Smallest EFI system partition we allow, in MiB.
+pub const MIN_EFI_SIZE_MB: u64 = 256;
+
+/// Default size of the /persist partition for a tmpfs root, in GiB.
+pub const DEFAULT_PERSIST_SIZE_GB: u64 = 32;
 
-/// Persistent log file path for debugging installation failures.
+/// Smallest root partition the full-disk plan leaves room for, in MiB.
+pub const MIN_ROOT_SIZE_MB: u64 = 8 * 1024;
+
+/// Default zram size as a percentage of RAM.
+pub const DEFAULT_ZRAM_PERCENT: u8 = 50;
+
+/// Default persistent log file path for debugging installation failures.
+/// Overridable via `--log-file` or the `log_file` config field.
 pub const LOG_FILE: &str = "/tmp/nixos-installer.log";
 
-/// User being created during the wizard.
-#[derive(Debug, Clone)]
+/// The log file to use: `requested`, or the default `LOG_FILE`. Nothing is
+/// created yet (see `open_log_file`).
+pub fn resolve_log_file(requested: Option<PathBuf>) -> PathBuf {
+    requested.unwrap_or_else(|| PathBuf::from(LOG_FILE))
+}
+
+/// Create the log file once logging starts: `path` if it can be opened for
+/// appending, otherwise the default `LOG_FILE`. Returns the chosen path and
+/// a warning when `path` had to be abandoned.
+pub fn open_log_file(path: PathBuf) -> (PathBuf, Option<String>) {
+    let default = PathBuf::from(LOG_FILE);
+    if path == default {
+        return (default, None);
+    }
+    match OpenOptions::new().create(true).append(true).open(&path) {
+        Ok(_) => (path, None),
+        Err(e) => {
+            let warning = format!(
+                "Log file {} is not writable ({}). Logging to {} instead.",
+                path.display(),
+                e,
+                LOG_FILE
+            );
+            (default, Some(warning))
+        }
+    }
+}
+
+/// User being created during the wizard. Passwords (and their hashes) are
+/// never serialized.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserEntry {
     pub username: String,
+    #[serde(skip)]
     pub password: String,
+    /// Supplementary groups (`extraGroups`) for the user.
+    pub groups: Vec<String>,
+    /// Login shell; `None` keeps the NixOS default.
+    pub shell: Option<nix::Shell>,
+    /// Crypt hash of the password, collected before installing in
+    /// password_mode "hashed".
+    #[serde(skip)]
+    pub hashed_password: Option<String>,
+    /// Log this user in automatically (console and display manager).
+    pub autologin: bool,
     pub hm_modules: Vec<NixModule>,
     pub package_modules: Vec<NixModule>,
+    #[serde(skip)]
     pub needs_hm_selection: bool,
+    /// Already defined by the preset's `user-<name>.nix`, which is kept as
+    /// it is; only listed so the user isn't created again.
+    #[serde(default)]
+    pub existing: bool,
 }
 
 /// Partition mode choice.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum PartitionMode {
     FullDisk,
     Custom,
+    /// Full disk with / on tmpfs: EFI + optional swap + /persist + /nix.
+    TmpfsRoot,
+}
+
+/// The fully resolved installation, written by --export-plan for auditing
+/// and read back by --answers to reproduce an install.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallPlan {
+    pub host_name: String,
+    /// Whether the host's configuration.nix is generated (not a preset).
+    pub is_custom: bool,
+    /// Selected disks, boot disk first.
+    pub disks: Vec<String>,
+    pub partition_mode: PartitionMode,
+    pub partitions: Vec<PartitionPlan>,
+    pub zram_percent: Option<u8>,
+    /// Size of the swap file on the root filesystem, in MiB.
+    #[serde(default)]
+    pub swap_file_mb: Option<u64>,
+    pub bootloader: Option<nix::Bootloader>,
+    #[serde(default)]
+    pub kernel: Option<nix::Kernel>,
+    #[serde(default)]
+    pub gpu: Option<nix::GpuDriver>,
+    /// Public key authorized for the first user (enables sshd).
+    #[serde(default)]
+    pub ssh_authorized_key: Option<String>,
+    pub password_mode: PasswordMode,
+    pub accept_flake_config: bool,
+    pub secure_wipe: bool,
+    pub fstrim: bool,
+    pub hm_base_modules: Vec<String>,
+    pub nixos_modules: Vec<NixModule>,
+    pub system_packages: Vec<NixModule>,
+    pub users: Vec<UserEntry>,
+}
+
+impl InstallPlan {
+    /// Serialize for `path`: TOML for a `.toml` file, JSON otherwise.
+    pub fn to_file_content(&self, path: &Path) -> Result<String, String> {
+        if is_toml(path) {
+            toml::to_string_pretty(self).map_err(|e| e.to_string())
+        } else {
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+        }
+    }
+
+    /// Parse a plan written by `to_file_content` for the same `path`.
+    pub fn from_file_content(path: &Path, content: &str) -> Result<Self, String> {
+        if is_toml(path) {
+            toml::from_str(content).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_str(content).map_err(|e| e.to_string())
+        }
+    }
+
+    /// Read a plan written by --export-plan (for --answers).
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Self::from_file_content(path, &content)
+            .map_err(|e| format!("Invalid plan in {}: {}", path.display(), e))
+    }
+}
+
+/// Share of `total` steps done when step `progress` (1-based) is running and
+/// reports `step_percent` of its own progress.
+fn progress_ratio(progress: usize, total: usize, step_percent: Option<u8>) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    let steps = match step_percent {
+        Some(percent) => progress.saturating_sub(1) as f64 + f64::from(percent) / 100.0,
+        None => progress as f64,
+    };
+    (steps / total as f64).min(1.0)
 }
 
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "toml")
+}
+
+/// Where the `prebuild_check` thread leaves its result.
+pub type PrebuildSlot = Arc<Mutex<Option<Result<(), String>>>>;
+
+/// Where the module name check thread leaves the flake's `nixosModules`.
+pub type ModuleCheckSlot = Arc<Mutex<Option<Result<HashSet<String>, String>>>>;
+
 /// Shared state between the installation background thread and the UI.
 #[derive(Debug, Clone)]
 pub struct InstallState {
     pub log: Vec<String>,
     pub progress: usize,
     pub total: usize,
+    /// How far the current step is, when it reports that (formatting).
+    pub step_percent: Option<u8>,
+    /// Last step that finished successfully; a retry resumes after it.
+    pub completed: usize,
     pub error: Option<String>,
+    /// Last stderr lines of a failed nixos-install, for the diagnostics popup.
+    pub diagnostics: Option<Vec<String>>,
     pub done: bool,
 }
 
+/// Screen geometry of the most recently rendered list, used to map mouse
+/// clicks back to list indices.
+#[derive(Debug, Clone)]
+pub struct ListHitbox {
+    /// Inner area of the list (inside the block borders).
+    pub area: Rect,
+    /// Index of the first visible row (the list's scroll offset).
+    pub offset: usize,
+    /// Item index for each rendered row; `None` for non-selectable rows
+    /// such as category headers.
+    pub rows: Vec<Option<usize>>,
+}
+
+impl ListHitbox {
+    /// Map a terminal cell to the list item under it, if any.
+    pub fn index_at(&self, column: u16, row: u16) -> Option<usize> {
+        let a = self.area;
+        if column < a.x || column >= a.x + a.width || row < a.y || row >= a.y + a.height {
+            return None;
+        }
+        let rendered_row = self.offset + (row - a.y) as usize;
+        self.rows.get(rendered_row).copied().flatten()
+    }
+}
+
+/// Home/End/PageUp/PageDown movement in lists and logs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Jump {
+    First,
+    Last,
+    PageUp,
+    PageDown,
+}
+
 /// All the wizard steps.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Step {
     CloningRepo,
+    Welcome,
+    EmptyRepo,
     SelectPreset,
     HostName,
+    OverwriteHost,
     SelectNixosModules,
     SelectSystemPackages,
+    Bootloader,
+    Kernel,
+    Gpu,
+    SshKey,
     CreateUser,
+    UserShell,
     AddAnotherUser,
+    ReviewUsers,
     SelectHmModules,
     SelectUserPackages,
     SelectDisk,
     PartitionModeSelect,
+    EfiSize,
+    RootFs,
+    PersistSize,
+    SwapMode,
     SwapSize,
+    ZramPercent,
     CustomPartitionMount,
+    CustomPartitionDisk,
     CustomPartitionSize,
     CustomPartitionFs,
+    CustomPartitionFormat,
     CustomPartitionAnother,
     Confirm,
+    PreviewConfig,
+    EncryptionPassphrase,
+    EncryptionPassphraseConfirm,
     Installing,
     RootPassword,
     RootPasswordConfirm,
@@ -78,19 +289,30 @@ pub struct App {
     pub clone_log: Vec<String>,
     pub clone_phase: String,
     pub clone_percent: u8,
+    pub clone_transferred: Option<String>,
+    pub clone_rate: Option<String>,
     pub clone_error: Option<String>,
     pub clone_done: bool,
     pub clone_log_scroll: usize,
     pub shared_clone: Option<Arc<Mutex<CloneState>>>,
 
+    /// Problems `validate_base_path` found in the repo, shown on Welcome.
+    pub base_path_warnings: Vec<String>,
+    /// EmptyRepo choice: 0 = quit, 1 = continue anyway.
+    pub empty_repo_cursor: usize,
+
     // Preset selection
     pub presets: Vec<HostPreset>,
     pub preset_cursor: usize,
     pub is_custom: bool,
+    /// `configuration.nix` of a preset opened with "Edit preset". Only its
+    /// imports are rewritten to follow the module selection.
+    pub preset_config: Option<String>,
 
     // Host configuration
     pub host_name: String,
     pub host_name_input: String,
+    pub overwrite_host_cursor: usize,
 
     // NixOS module selection (filtered: no home-manager, wsl, home-*)
     pub nixos_modules: Vec<NixModule>,
@@ -107,7 +329,24 @@ pub struct App {
     pub current_password_confirm: String,
     pub password_mismatch: bool,
 
-    // HM module selection (iterating through users; filtered: no home, home-wsl, packages-*)
+    // User review (edit/delete collected users before HM selection)
+    pub review_user_cursor: usize,
+    pub editing_user: Option<usize>,
+
+    // Result of evaluating the flake's `nixosModules` attribute names
+    pub flake_nixos_modules: Option<Result<HashSet<String>, String>>,
+    /// Result slot of the running `nix eval` behind `flake_nixos_modules`,
+    /// filled in by its thread and picked up by `sync_module_check`.
+    pub shared_module_check: Option<ModuleCheckSlot>,
+
+    // Login shell picker: 0 = keep the default, 1.. = `nix::Shell::ALL`
+    pub shell_cursor: usize,
+
+    /// Whether users get Home Manager imports (the repo has HM modules and
+    /// `disable_home_manager` is not set).
+    pub home_manager: bool,
+
+    // HM module selection (iterating through users; filtered by hidden_hm_modules)
     pub hm_user_index: usize,
     pub hm_modules: Vec<NixModule>,
     pub hm_cursor: usize,
@@ -116,30 +355,111 @@ pub struct App {
     pub user_pkg_modules: Vec<NixModule>,
     pub user_pkg_cursor: usize,
 
+    /// Also list the modules `hidden_modules` leaves out (`A` on the module
+    /// lists), marked as revealed.
+    pub show_hidden_modules: bool,
+
     // Disk selection
     pub disks: Vec<BlockDevice>,
     pub disk_cursor: usize,
+    /// Paths of the disks marked with Space for a multi-disk install.
+    pub marked_disks: Vec<String>,
+    /// The boot disk: full-disk modes install to it and BIOS GRUB goes to
+    /// its MBR unless /boot or / are placed on another disk.
     pub selected_disk: Option<BlockDevice>,
+    /// Further disks selected for custom partitioning.
+    pub extra_disks: Vec<BlockDevice>,
 
     // Partitioning
     pub partition_mode: PartitionMode,
     pub partition_mode_cursor: usize,
+    pub efi_size_input: String,
     pub swap_size_input: String,
     pub partitions: Vec<PartitionPlan>,
+    pub efi_size_mb: u64,
+    pub persist_size_input: String,
+    pub persist_size_mb: u64,
+    /// Detected boot mode of the live system.
+    pub uefi: bool,
+    /// NixOS release of the live system, the default `system.stateVersion`.
+    pub nixos_release: Option<String>,
+    /// Installed RAM, used to suggest a swap size.
+    pub ram_bytes: Option<u64>,
+
+    // Bootloader (custom hosts): 0 = systemd-boot, 1 = GRUB, 2 = from modules
+    pub bootloader_cursor: usize,
+    pub bootloader: Option<nix::Bootloader>,
+
+    // Kernel (custom hosts): 0 = nixpkgs default, then `nix::Kernel::ALL`
+    pub kernel_cursor: usize,
+    pub kernel: Option<nix::Kernel>,
+
+    // Graphics driver (custom hosts): 0 = none, then `nix::GpuDriver::ALL`
+    pub gpu_cursor: usize,
+    pub gpu: Option<nix::GpuDriver>,
+    /// GPU vendor guessed from lspci; its driver is preselected.
+    pub detected_gpu: Option<nix::GpuDriver>,
+
+    // SSH access for the first user: a public key or a path to one
+    pub ssh_key_input: String,
+    pub ssh_authorized_key: Option<String>,
 
     // Custom partition entry
     pub part_mount_input: String,
     pub part_size_input: String,
     pub part_fs_cursor: usize,
+    /// Index into `install_disks()` of the disk the custom partition goes on.
+    pub part_disk_cursor: usize,
+    pub root_fs_cursor: usize,
+    pub swap_mode_cursor: usize,
+    pub zram_percent_input: String,
+    /// zram swap size as a percentage of RAM, when zram was chosen in
+    /// full-disk mode.
+    pub zram_percent: Option<u8>,
+    /// Swap file size in MiB, when a swap file was chosen in full-disk mode.
+    pub swap_file_mb: Option<u64>,
 
     // Confirm
     pub confirm_cursor: usize,
+    /// Partition selected for reordering on the Confirm screen.
+    pub confirm_partition_cursor: usize,
     pub accept_flake_config: bool,
+    /// Erase the whole disk before partitioning (see `disk::secure_wipe`).
+    pub secure_wipe: bool,
+    /// Emit `services.fstrim.enable = true;`. Defaults to on when the
+    /// selected disk is an SSD/NVMe.
+    pub enable_fstrim: bool,
+    /// SMART health of the selected disks that report it (see `smart_check`).
+    pub disk_health: Vec<(String, disk::SmartHealth)>,
+    /// The failing-disk warning was shown; the next Install goes ahead.
+    pub smart_failure_acknowledged: bool,
+    pub preview_scroll: usize,
+
+    // Disk encryption (toggled on the Confirm screen)
+    /// Encrypt the root partition with LUKS2 using `encryption_passphrase`.
+    pub encrypt_root: bool,
+    pub encryption_passphrase: String,
+    pub encryption_passphrase_confirm: String,
+    pub encryption_mismatch: bool,
+
+    /// Stops the running build check (Esc).
+    prebuild_cancel: Option<Arc<nix::CancelHandle>>,
+    /// Result slot of the running `prebuild_check` build, filled in by its
+    /// thread.
+    pub shared_prebuild: Option<PrebuildSlot>,
+    /// What the last successful build check was run for (see
+    /// `prebuild_key`); installing again with the same plan skips it.
+    prebuild_passed_for: Option<String>,
+    /// Output of a failed build check, shown in a scrollable popup.
+    pub prebuild_error: Option<String>,
+    pub prebuild_scroll: usize,
 
     // Root password
     pub root_password: String,
     pub root_password_confirm: String,
     pub root_password_mismatch: bool,
+    /// Root's crypt hash in password_mode "hashed".
+    pub root_hashed_password: Option<String>,
 
     // Post-install user password collection
     pub password_user_index: usize,
@@ -147,13 +467,26 @@ pub struct App {
     // Add another user / partition prompt
     pub another_user_cursor: usize,
     pub another_partition_cursor: usize,
+    /// Custom partition "format it?" prompt: 0 = format, 1 = keep existing.
+    pub part_format_cursor: usize,
 
     // Installation
     pub install_log: Vec<String>,
     pub install_progress: usize,
     pub install_total: usize,
+    /// How far the current install step is, if it reports that.
+    pub install_step_percent: Option<u8>,
     pub install_error: Option<String>,
+    /// stderr tail of a failed nixos-install; shown in a popup while `Some`.
+    pub install_diagnostics: Option<Vec<String>>,
+    pub diagnostics_scroll: usize,
     pub install_done: bool,
+    /// Terminal title to announce once the install succeeds or fails; taken
+    /// by the main loop, which also rings the bell.
+    pub finish_notice: Option<&'static str>,
+    /// Event loop iterations (one per 50 ms poll while background work
+    /// runs), drives the spinner.
+    pub frame_count: usize,
     pub log_scroll: usize,
     pub auto_scroll: bool,
     pub shared_install: Option<Arc<Mutex<InstallState>>>,
@@ -163,15 +496,37 @@ pub struct App {
 
     // Status / error display
     pub status_message: Option<String>,
+    /// Step that fixes the problem in `status_message`; `f` jumps there.
+    pub fix_step: Option<Step>,
+    /// Keybinding help overlay toggled with `?` / F1.
+    pub show_help: bool,
+
+    // Geometry of the list rendered last frame (for mouse clicks)
+    pub list_hitbox: Option<ListHitbox>,
+    /// Inner height of the log or preview rendered last frame (for
+    /// PageUp/PageDown).
+    pub log_view_height: usize,
 
     // Installer configuration (from config.toml)
     pub config: InstallerConfig,
+    /// Config file the theme picked at runtime is saved to.
+    pub config_path: PathBuf,
 
     // Active color theme
     pub theme: Theme,
 
     // Branding title from config (used in header)
     pub branding_title: String,
+
+    // Persistent log file (from --log-file / config, or LOG_FILE)
+    pub log_file: PathBuf,
+
+    // --config-only: generate host files, skip disk setup and nixos-install
+    pub config_only: bool,
+    pub generated_files: Vec<PathBuf>,
+
+    /// Plan loaded with --answers, applied once the repo has been scanned.
+    pub answers: Option<InstallPlan>,
 }
 
 impl App {
@@ -180,22 +535,29 @@ impl App {
         repo_url: Option<String>,
         installer_config: InstallerConfig,
         theme: Theme,
+        log_file: PathBuf,
     ) -> Self {
+        let uefi = disk::is_uefi();
+        let detected_gpu = disk::lspci().and_then(|out| nix::gpu_from_lspci(&out));
+
         // If we already have a local base path, scan immediately.
         // Otherwise, start with CloningRepo step.
-        let (step, base_path, presets, nixos_modules, package_modules, status, needs_clone, cfg) =
+        let (step, base_path, presets, nixos_modules, package_modules, warnings, needs_clone, cfg) =
             if let Some(bp) = base_path {
-                let warnings = nix::validate_base_path(&bp);
-                let status = if warnings.is_empty() {
-                    None
+                let cfg = config::load_repo_config(&bp, &installer_config);
+                let layout = cfg.module_layout();
+                let warnings = nix::validate_base_path(&bp, &layout);
+                let step = if cfg.show_welcome.unwrap_or(true) {
+                    Step::Welcome
                 } else {
-                    Some(warnings.join("\n"))
+                    Step::SelectPreset
                 };
-                let cfg = config::load_repo_config(&bp, &installer_config);
-                let presets = nix::scan_host_presets(&bp);
-                let nixos_modules = nix::scan_nixos_modules(&bp);
-                let package_modules = nix::scan_package_modules(&bp);
-                (Step::SelectPreset, bp, presets, nixos_modules, package_modules, status, false, cfg)
+                let presets = nix::scan_host_presets(&bp, &layout);
+                let hidden = cfg.hidden_modules();
+                let nixos_modules = nix::scan_nixos_modules(&bp, &layout, &hidden);
+                let package_modules =
+                    nix::scan_package_modules(&bp, &layout, &hidden, &cfg.attr_refs());
+                (step, bp, presets, nixos_modules, package_modules, warnings, false, cfg)
             } else {
                 // Will clone into /tmp/nixos-dotfiles
                 let bp = PathBuf::from("/tmp/nixos-dotfiles");
@@ -205,11 +567,18 @@ impl App {
                     Vec::new(),
                     Vec::new(),
                     Vec::new(),
-                    None,
+                    Vec::new(),
                     true,
                     installer_config,
                 )
             };
+        // Without the welcome screen, layout problems pop up over the first
+        // step instead
+        let status = if step == Step::SelectPreset && !warnings.is_empty() {
+            Some(warnings.join("\n"))
+        } else {
+            None
+        };
 
         let branding = cfg.branding_title.clone()
             .unwrap_or_else(|| "NixOS Installer".to_string());
@@ -223,17 +592,24 @@ impl App {
             clone_log: Vec::new(),
             clone_phase: String::new(),
             clone_percent: 0,
+            clone_transferred: None,
+            clone_rate: None,
             clone_error: None,
             clone_done: false,
             clone_log_scroll: 0,
             shared_clone: None,
 
+            base_path_warnings: warnings,
+            empty_repo_cursor: 0,
+
             presets,
             preset_cursor: 0,
+            preset_config: None,
             is_custom: false,
 
             host_name: String::new(),
             host_name_input: cfg.default_hostname.clone().unwrap_or_default(),
+            overwrite_host_cursor: 0,
 
             nixos_modules,
             nixos_cursor: 0,
@@ -247,6 +623,13 @@ impl App {
             current_password_confirm: String::new(),
             password_mismatch: false,
 
+            review_user_cursor: 0,
+            editing_user: None,
+            shell_cursor: 0,
+            flake_nixos_modules: None,
+            shared_module_check: None,
+
+            home_manager: false,
             hm_user_index: 0,
             hm_modules: Vec::new(),
             hm_cursor: 0,
@@ -254,36 +637,90 @@ impl App {
             user_pkg_modules: Vec::new(),
             user_pkg_cursor: 0,
 
+            show_hidden_modules: false,
+
             disks: Vec::new(),
             disk_cursor: 0,
+            marked_disks: Vec::new(),
             selected_disk: None,
+            extra_disks: Vec::new(),
 
             partition_mode: PartitionMode::FullDisk,
             partition_mode_cursor: 0,
+            efi_size_input: cfg
+                .default_efi_size_mb
+                .unwrap_or(DEFAULT_EFI_SIZE_MB)
+                .to_string(),
             swap_size_input: cfg.default_swap_size.clone().unwrap_or_else(|| "4".to_string()),
             partitions: Vec::new(),
+            efi_size_mb: DEFAULT_EFI_SIZE_MB,
+            persist_size_input: DEFAULT_PERSIST_SIZE_GB.to_string(),
+            persist_size_mb: DEFAULT_PERSIST_SIZE_GB * 1024,
+            uefi,
+            nixos_release: disk::nixos_release(),
+            ram_bytes: disk::total_memory_bytes(),
+            bootloader_cursor: default_bootloader_cursor(&cfg, uefi),
+            bootloader: None,
+            kernel_cursor: default_kernel_cursor(&cfg),
+            kernel: None,
+            gpu_cursor: detected_gpu
+                .and_then(|g| nix::GpuDriver::ALL.iter().position(|&d| d == g))
+                .map_or(0, |i| i + 1),
+            gpu: None,
+            detected_gpu,
+
+            ssh_key_input: String::new(),
+            ssh_authorized_key: None,
 
             part_mount_input: String::new(),
             part_size_input: String::new(),
             part_fs_cursor: 0,
+            part_disk_cursor: 0,
+            root_fs_cursor: 0,
+            swap_mode_cursor: 0,
+            zram_percent_input: DEFAULT_ZRAM_PERCENT.to_string(),
+            zram_percent: None,
+            swap_file_mb: None,
 
             confirm_cursor: 0,
-            accept_flake_config: true,
+            confirm_partition_cursor: 0,
+            accept_flake_config: cfg.accept_flake_config.unwrap_or(true),
+            secure_wipe: cfg.secure_wipe.unwrap_or(false),
+            enable_fstrim: false,
+            disk_health: Vec::new(),
+            smart_failure_acknowledged: false,
+            preview_scroll: 0,
+            shared_prebuild: None,
+            encrypt_root: false,
+            encryption_passphrase: String::new(),
+            encryption_passphrase_confirm: String::new(),
+            encryption_mismatch: false,
+            prebuild_cancel: None,
+            prebuild_passed_for: None,
+            prebuild_error: None,
+            prebuild_scroll: 0,
 
             root_password: String::new(),
             root_password_confirm: String::new(),
             root_password_mismatch: false,
+            root_hashed_password: None,
 
             password_user_index: 0,
 
             another_user_cursor: 0,
             another_partition_cursor: 0,
+            part_format_cursor: 0,
 
             install_log: Vec::new(),
             install_progress: 0,
             install_total: 8,
+            install_step_percent: None,
             install_error: None,
+            install_diagnostics: None,
+            diagnostics_scroll: 0,
             install_done: false,
+            finish_notice: None,
+            frame_count: 0,
             log_scroll: 0,
             auto_scroll: true,
             shared_install: None,
@@ -291,22 +728,228 @@ impl App {
             reboot_cursor: 0,
 
             status_message: status,
+            fix_step: None,
+            show_help: false,
+
+            list_hitbox: None,
+            log_view_height: 0,
 
             config: cfg,
 
             theme,
 
             branding_title: branding,
+
+            log_file,
+
+            config_only: false,
+            config_path: PathBuf::from(config::DEFAULT_CONFIG_PATH),
+            generated_files: Vec::new(),
+            answers: None,
         };
 
         // If we need to clone, start the background clone thread
+        // (selections are applied once the repo is scanned)
         if needs_clone {
             app.start_clone();
+        } else {
+            app.home_manager = app.detect_home_manager();
+            app.apply_last_selections();
+            if app.step == Step::SelectPreset {
+                app.enter_preset_selection();
+            }
         }
 
         app
     }
 
+    /// Home Manager is used unless disabled in the config or the repo has no
+    /// HM modules.
+    fn detect_home_manager(&self) -> bool {
+        !self.config.disable_home_manager.unwrap_or(false)
+            && nix::has_hm_modules(&self.base_path, &self.config.module_layout())
+    }
+
+    /// Whether selections are remembered across runs (opt-in with
+    /// `remember_selections`, off with --no-remember).
+    fn remember_selections(&self) -> bool {
+        self.config.remember_selections.unwrap_or(false)
+    }
+
+    /// Pre-fill inputs and pre-select modules from the previous run. Names
+    /// that no longer exist in the repo are ignored.
+    fn apply_last_selections(&mut self) {
+        if !self.remember_selections() {
+            return;
+        }
+        let Some(last) = config::load_last_selections() else {
+            return;
+        };
+        if let Some(host) = last.host_name {
+            self.host_name_input = host;
+        }
+        if let Some(name) = last.username {
+            self.current_username = name;
+        }
+        if let Some(swap) = last.swap_size {
+            self.swap_size_input = swap;
+        }
+        if let Some(mb) = last.efi_size_mb {
+            self.efi_size_input = mb.to_string();
+        }
+        for m in &mut self.nixos_modules {
+            m.selected = m.required || last.nixos_modules.contains(&m.name);
+        }
+        for m in &mut self.system_packages {
+            m.selected = m.required || last.system_packages.contains(&m.name);
+        }
+    }
+
+    /// Remember this run's selections for the next one (best effort).
+    fn save_last_selections(&mut self) {
+        if !self.remember_selections() {
+            return;
+        }
+        let selected = |modules: &[NixModule]| -> Vec<String> {
+            modules
+                .iter()
+                .filter(|m| m.selected)
+                .map(|m| m.name.clone())
+                .collect()
+        };
+        let selections = config::LastSelections {
+            host_name: Some(self.host_name.clone()).filter(|h| !h.is_empty()),
+            username: self.users.first().map(|u| u.username.clone()),
+            nixos_modules: selected(&self.nixos_modules),
+            system_packages: selected(&self.system_packages),
+            swap_size: Some(self.swap_size_input.clone()),
+            efi_size_mb: Some(self.efi_size_mb),
+        };
+        if let Err(e) = config::save_last_selections(&selections) {
+            self.log_install(&format!("Could not remember selections: {}", e));
+        }
+    }
+
+    /// The current selections as an `InstallPlan`.
+    pub fn install_plan(&self) -> InstallPlan {
+        InstallPlan {
+            host_name: self.host_name.clone(),
+            is_custom: self.is_custom,
+            disks: self.install_disks().iter().map(|d| d.path.clone()).collect(),
+            partition_mode: self.partition_mode.clone(),
+            partitions: self.partitions.clone(),
+            zram_percent: self.zram_percent,
+            swap_file_mb: self.swap_file_mb,
+            bootloader: self.bootloader,
+            kernel: self.kernel,
+            gpu: self.gpu,
+            ssh_authorized_key: self.ssh_authorized_key.clone(),
+            password_mode: self.config.password_mode.unwrap_or_default(),
+            accept_flake_config: self.accept_flake_config,
+            secure_wipe: self.secure_wipe,
+            fstrim: self.host_settings().fstrim,
+            hm_base_modules: self.config.hm_base_modules.clone(),
+            nixos_modules: self.nixos_modules.clone(),
+            system_packages: self.system_packages.clone(),
+            users: self.users.clone(),
+        }
+    }
+
+    /// Write the plan to the --export-plan path, if one was given.
+    fn export_plan(&mut self) -> Result<(), String> {
+        let Some(path) = self.config.export_plan.clone() else {
+            return Ok(());
+        };
+        let content = self.install_plan().to_file_content(&path)?;
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        self.log_install(&format!("Installation plan written to {}", path.display()));
+        Ok(())
+    }
+
+    /// Apply the --answers plan once the repo is scanned and continue at
+    /// Confirm. Anything the plan can't fill in (a missing disk, passwords in
+    /// password_mode "hashed") is reported like a failed skip to Confirm.
+    pub fn apply_answers(&mut self) {
+        if self.step == Step::CloningRepo {
+            return;
+        }
+        let Some(plan) = self.answers.take() else {
+            return;
+        };
+        match self.apply_plan(plan) {
+            Ok(()) => self.skip_to_confirm(),
+            Err(e) => {
+                self.set_status(format!("Could not apply the answers file: {}", e));
+            }
+        }
+    }
+
+    /// Take over the selections of an exported plan. Modules, the preset and
+    /// the disks are looked up by name, so the plan must match this repo and
+    /// machine.
+    fn apply_plan(&mut self, plan: InstallPlan) -> Result<(), String> {
+        fn select(modules: &mut [NixModule], wanted: &[NixModule]) -> Result<(), String> {
+            for w in wanted.iter().filter(|w| w.selected) {
+                if !modules.iter().any(|m| m.name == w.name) {
+                    return Err(format!("module '{}' doesn't exist in this repo", w.name));
+                }
+            }
+            for m in modules {
+                m.selected = m.required || wanted.iter().any(|w| w.selected && w.name == m.name);
+            }
+            Ok(())
+        }
+
+        if self.disks.is_empty() {
+            self.disks = disk::list_block_devices()?;
+        }
+        let mut disks = Vec::new();
+        for path in &plan.disks {
+            let Some(d) = self.disks.iter().find(|d| &d.path == path) else {
+                return Err(format!("disk {} was not found", path));
+            };
+            disks.push(d.clone());
+        }
+
+        self.host_name = plan.host_name;
+        self.host_name_input = self.host_name.clone();
+        self.is_custom = plan.is_custom;
+        self.preset_config = None;
+        if plan.is_custom {
+            self.users.clear();
+        } else {
+            let Some(index) = self.presets.iter().position(|p| p.name == self.host_name) else {
+                return Err(format!("preset '{}' doesn't exist in this repo", self.host_name));
+            };
+            self.preset_cursor = index;
+            self.load_existing_users();
+            self.users.retain(|u| u.existing);
+        }
+        self.users.extend(plan.users.into_iter().filter(|u| !u.existing));
+
+        select(&mut self.nixos_modules, &plan.nixos_modules)?;
+        select(&mut self.system_packages, &plan.system_packages)?;
+
+        let mut disks = disks.into_iter();
+        self.selected_disk = disks.next();
+        self.extra_disks = disks.collect();
+        self.partition_mode = plan.partition_mode;
+        self.partitions = plan.partitions;
+        self.zram_percent = plan.zram_percent;
+        self.swap_file_mb = plan.swap_file_mb;
+        self.bootloader = plan.bootloader;
+        self.kernel = plan.kernel;
+        self.gpu = plan.gpu;
+        self.ssh_authorized_key = plan.ssh_authorized_key;
+        self.config.password_mode = Some(plan.password_mode);
+        self.accept_flake_config = plan.accept_flake_config;
+        self.secure_wipe = plan.secure_wipe;
+        self.enable_fstrim = plan.fstrim;
+        self.config.hm_base_modules = plan.hm_base_modules;
+        Ok(())
+    }
+
     /// Get the display names for the preset list (including "Custom" at the end).
     pub fn preset_display_items(&self) -> Vec<String> {
         let mut items: Vec<String> = self
@@ -334,6 +977,285 @@ impl App {
         *cursor = if *cursor == 0 { len - 1 } else { *cursor - 1 };
     }
 
+    /// Cursor and item count of the list shown on the current step, if any.
+    pub fn active_list(&mut self) -> Option<(&mut usize, usize)> {
+        match self.step {
+            Step::SelectPreset => {
+                let len = self.presets.len() + 1;
+                Some((&mut self.preset_cursor, len))
+            }
+            Step::SelectNixosModules => Some((&mut self.nixos_cursor, self.nixos_modules.len())),
+            Step::SelectSystemPackages => {
+                Some((&mut self.system_package_cursor, self.system_packages.len()))
+            }
+            Step::ReviewUsers => {
+                let len = self.review_user_items();
+                Some((&mut self.review_user_cursor, len))
+            }
+            Step::SelectHmModules => Some((&mut self.hm_cursor, self.hm_modules.len())),
+            Step::SelectUserPackages => {
+                Some((&mut self.user_pkg_cursor, self.user_pkg_modules.len()))
+            }
+            Step::SelectDisk => Some((&mut self.disk_cursor, self.disks.len())),
+            Step::EmptyRepo => Some((&mut self.empty_repo_cursor, 2)),
+            Step::OverwriteHost => Some((&mut self.overwrite_host_cursor, 3)),
+            Step::PartitionModeSelect => Some((&mut self.partition_mode_cursor, 3)),
+            Step::CustomPartitionDisk => {
+                let len = self.install_disks().len();
+                Some((&mut self.part_disk_cursor, len))
+            }
+            Step::CustomPartitionFs => Some((&mut self.part_fs_cursor, FsType::all().len())),
+            Step::RootFs => Some((&mut self.root_fs_cursor, FsType::ROOT.len())),
+            Step::SwapMode => Some((&mut self.swap_mode_cursor, 4)),
+            Step::UserShell => Some((&mut self.shell_cursor, nix::Shell::ALL.len() + 1)),
+            Step::Bootloader => Some((&mut self.bootloader_cursor, 3)),
+            Step::Kernel => Some((&mut self.kernel_cursor, nix::Kernel::ALL.len() + 1)),
+            Step::Gpu => Some((&mut self.gpu_cursor, nix::GpuDriver::ALL.len() + 1)),
+            _ => None,
+        }
+    }
+
+    /// `t` on the welcome and preset screens: switch to the next theme in
+    /// `ThemeName::all_names()`, keeping the custom color overrides.
+    pub fn cycle_theme(&mut self) {
+        let names = ThemeName::all_names();
+        let current = self.config.theme.clone().unwrap_or_default().to_string();
+        let next = names
+            .iter()
+            .position(|n| *n == current)
+            .map_or(0, |i| (i + 1) % names.len());
+        let Some(name) = ThemeName::from_str_loose(names[next]) else {
+            return;
+        };
+        let mut theme = name.to_theme();
+        if let Some(custom) = self.config.theme_custom.as_ref().filter(|c| c.has_overrides()) {
+            theme = theme.with_custom_overrides(custom);
+        }
+        self.theme = theme;
+        self.config.theme = Some(name);
+    }
+
+    /// `T` on the welcome and preset screens: save the current theme to the
+    /// config file so it is used on the next run.
+    pub fn save_theme(&mut self) {
+        let name = self.config.theme.clone().unwrap_or_default();
+        self.set_status(match config::save_theme(&self.config_path, &name) {
+            Ok(()) => format!("Saved theme = \"{}\" to {}", name, self.config_path.display()),
+            Err(e) => format!("Could not save the theme: {}", e),
+        });
+    }
+
+    /// The hidden module patterns, revealing the matches while
+    /// `show_hidden_modules` is on.
+    fn hidden_modules(&self) -> nix::HiddenModules {
+        nix::HiddenModules {
+            reveal: self.show_hidden_modules,
+            ..self.config.hidden_modules()
+        }
+    }
+
+    /// `A` on the module lists: list the modules `hidden_modules` leaves out
+    /// as well, or hide them again. Every list (including each user's) is
+    /// rescanned and keeps its selections; revealed modules that are
+    /// selected stay listed after hiding so no choice disappears silently.
+    pub fn toggle_hidden_modules(&mut self) {
+        self.show_hidden_modules = !self.show_hidden_modules;
+        let show = self.show_hidden_modules;
+        let layout = self.config.module_layout();
+        let hidden = nix::HiddenModules {
+            reveal: true,
+            ..self.config.hidden_modules()
+        };
+        let refs = self.config.attr_refs();
+        let nixos = nix::scan_nixos_modules(&self.base_path, &layout, &hidden);
+        let packages = nix::scan_package_modules(&self.base_path, &layout, &hidden, &refs);
+        let hm = nix::scan_hm_modules(&self.base_path, &layout, &hidden);
+
+        let rescan = |modules: &mut Vec<NixModule>, scanned: &[NixModule]| {
+            let mut fresh = scanned.to_vec();
+            for m in &mut fresh {
+                m.selected = m.required || modules.iter().any(|o| o.name == m.name && o.selected);
+            }
+            fresh.retain(|m| show || !m.revealed || m.selected);
+            *modules = fresh;
+        };
+        rescan(&mut self.nixos_modules, &nixos);
+        rescan(&mut self.system_packages, &packages);
+        rescan(&mut self.hm_modules, &hm);
+        rescan(&mut self.user_pkg_modules, &packages);
+        for user in &mut self.users {
+            // Lists not scanned yet pick up the flag when they are
+            if !user.hm_modules.is_empty() || !user.package_modules.is_empty() {
+                rescan(&mut user.hm_modules, &hm);
+                rescan(&mut user.package_modules, &packages);
+            }
+        }
+
+        let clamp = |cursor: &mut usize, len: usize| *cursor = (*cursor).min(len.saturating_sub(1));
+        clamp(&mut self.nixos_cursor, self.nixos_modules.len());
+        clamp(&mut self.system_package_cursor, self.system_packages.len());
+        clamp(&mut self.hm_cursor, self.hm_modules.len());
+        clamp(&mut self.user_pkg_cursor, self.user_pkg_modules.len());
+    }
+
+    /// Toggle the module under the cursor on checklist steps. Selecting a
+    /// module deselects the ones it conflicts with (declared on either side);
+    /// required modules can't be toggled or deselected.
+    pub fn toggle_current_module(&mut self) {
+        let (modules, cursor) = match self.step {
+            Step::SelectNixosModules => (&mut self.nixos_modules, self.nixos_cursor),
+            Step::SelectSystemPackages => (&mut self.system_packages, self.system_package_cursor),
+            Step::SelectHmModules => (&mut self.hm_modules, self.hm_cursor),
+            Step::SelectUserPackages => (&mut self.user_pkg_modules, self.user_pkg_cursor),
+            _ => return,
+        };
+        let Some(m) = modules.get_mut(cursor) else {
+            return;
+        };
+        if m.required {
+            return;
+        }
+        let (name, conflicts) = (m.name.clone(), m.conflicts.clone());
+        let conflicts_with =
+            |o: &NixModule| conflicts.contains(&o.name) || o.conflicts.contains(&name);
+        if !m.selected {
+            let blocking: Vec<&str> = modules
+                .iter()
+                .filter(|o| o.required && conflicts_with(o))
+                .map(|o| o.name.as_str())
+                .collect();
+            if !blocking.is_empty() {
+                let msg = format!(
+                    "{} conflicts with the required module {}.",
+                    name,
+                    blocking.join(", ")
+                );
+                self.set_status(msg);
+                return;
+            }
+        }
+        let m = &mut modules[cursor];
+        m.selected = !m.selected;
+        if !m.selected {
+            return;
+        }
+        let mut deselected = Vec::new();
+        for other in modules.iter_mut() {
+            if other.selected && other.name != name && conflicts_with(other) {
+                other.selected = false;
+                deselected.push(other.name.clone());
+            }
+        }
+        if !deselected.is_empty() {
+            self.set_status(format!(
+                "Deselected {}: it conflicts with {}.",
+                deselected.join(", "),
+                name
+            ));
+        }
+    }
+
+    /// Scroll the clone or install log by one line. Scrolling up pauses
+    /// auto-scroll; reaching the bottom re-enables it.
+    pub fn scroll_log(&mut self, down: bool) {
+        if self.step == Step::PreviewConfig {
+            // The preview has no auto-scroll; the renderer clamps the offset
+            self.preview_scroll = if down {
+                self.preview_scroll + 1
+            } else {
+                self.preview_scroll.saturating_sub(1)
+            };
+            return;
+        }
+        let (scroll, len) = match self.step {
+            Step::CloningRepo => (&mut self.clone_log_scroll, self.clone_log.len()),
+            Step::Installing => (&mut self.log_scroll, self.install_log.len()),
+            _ => return,
+        };
+        if down {
+            let max = len.saturating_sub(1);
+            if *scroll < max {
+                *scroll += 1;
+            }
+            if *scroll >= max {
+                self.auto_scroll = true;
+            }
+        } else {
+            self.auto_scroll = false;
+            *scroll = scroll.saturating_sub(1);
+        }
+    }
+
+    /// The terminal was resized: keep auto-scrolling logs pinned to their
+    /// last line (the renderer recomputes the exact offset for the new
+    /// height).
+    pub fn handle_resize(&mut self) {
+        if self.auto_scroll {
+            self.clone_log_scroll = self.clone_log.len().saturating_sub(1);
+            self.log_scroll = self.install_log.len().saturating_sub(1);
+        }
+    }
+
+    /// Spinner frame for long-running work, so a silent phase doesn't look
+    /// like a hang.
+    pub fn spinner(&self) -> char {
+        const FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+        FRAMES[(self.frame_count / 2) % FRAMES.len()]
+    }
+
+    /// Move through the current log or list by a page, or to either end.
+    /// In the clone/install logs PageUp and Home pause auto-scroll and End
+    /// re-enables it (as does paging down to the bottom).
+    pub fn jump(&mut self, jump: Jump) {
+        if matches!(self.step, Step::CloningRepo | Step::Installing | Step::PreviewConfig) {
+            self.jump_log(jump);
+            return;
+        }
+
+        // Page by the number of items that fit on screen; two-line items
+        // and category headers take more than one row each
+        let page = self.list_hitbox.as_ref().map_or(1, |h| {
+            let items = h.rows.iter().flatten().max().map_or(0, |i| i + 1);
+            (h.area.height as usize * items / h.rows.len().max(1)).max(1)
+        });
+        if let Some((cursor, len)) = self.active_list() {
+            if len == 0 {
+                return;
+            }
+            *cursor = match jump {
+                Jump::First => 0,
+                Jump::Last => len - 1,
+                Jump::PageUp => cursor.saturating_sub(page),
+                Jump::PageDown => (*cursor + page).min(len - 1),
+            };
+        }
+    }
+
+    fn jump_log(&mut self, jump: Jump) {
+        let page = self.log_view_height.max(1);
+        let (scroll, len) = match self.step {
+            Step::CloningRepo => (&mut self.clone_log_scroll, self.clone_log.len()),
+            Step::Installing => (&mut self.log_scroll, self.install_log.len()),
+            // The preview renderer clamps the offset to its content
+            _ => (&mut self.preview_scroll, usize::MAX),
+        };
+        let max = len.saturating_sub(1);
+        match jump {
+            Jump::First => *scroll = 0,
+            Jump::Last => *scroll = max,
+            Jump::PageUp => *scroll = scroll.saturating_sub(page),
+            Jump::PageDown => *scroll = scroll.saturating_add(page).min(max),
+        }
+        let at_bottom = *scroll >= max;
+        if self.step != Step::PreviewConfig {
+            self.auto_scroll = match jump {
+                Jump::First | Jump::PageUp => false,
+                Jump::Last => true,
+                Jump::PageDown => at_bottom,
+            };
+        }
+    }
+
     // ---- Clone management ----
 
     /// Start cloning the dotfiles repository in a background thread.
@@ -342,6 +1264,8 @@ impl App {
             log: Vec::new(),
             phase: String::new(),
             percent: 0,
+            transferred: None,
+            rate: None,
             error: None,
             done: false,
         }));
@@ -349,6 +1273,11 @@ impl App {
 
         let url = self.repo_url.clone().unwrap_or_default();
         let dest = self.base_path.clone();
+        let options = disk::CloneOptions {
+            branch: self.config.repo_branch.clone(),
+            depth: self.config.clone_depth,
+            token: self.config.clone_token.clone(),
+        };
 
         // Clean up any previous clone at the destination
         if dest.exists() {
@@ -356,10 +1285,25 @@ impl App {
         }
 
         std::thread::spawn(move || {
-            disk::clone_repo(&url, &dest, state);
+            disk::clone_repo(&url, &dest, &options, state);
         });
     }
 
+    /// Retry a failed clone: reset the clone state and start over. The
+    /// partial checkout is removed by `start_clone`.
+    pub fn retry_clone(&mut self) {
+        self.clone_log.clear();
+        self.clone_phase.clear();
+        self.clone_percent = 0;
+        self.clone_transferred = None;
+        self.clone_rate = None;
+        self.clone_error = None;
+        self.clone_done = false;
+        self.clone_log_scroll = 0;
+        self.auto_scroll = true;
+        self.start_clone();
+    }
+
     /// Copy state from the background clone thread into App fields.
     pub fn sync_clone_state(&mut self) {
         if let Some(shared) = &self.shared_clone {
@@ -368,6 +1312,8 @@ impl App {
                     self.clone_log = s.log.clone();
                     self.clone_phase = s.phase.clone();
                     self.clone_percent = s.percent;
+                    self.clone_transferred = s.transferred.clone();
+                    self.clone_rate = s.rate.clone();
                     self.clone_error = s.error.clone();
                     self.clone_done = s.done;
                 }
@@ -386,18 +1332,24 @@ impl App {
         }
     }
 
-    /// Called when clone is done: scan modules and advance to SelectPreset.
+    /// Called when clone is done: scan modules and advance to Welcome (or
+    /// SelectPreset).
     pub fn finish_clone(&mut self) {
         // Validate and scan the freshly cloned repo
-        let warnings = nix::validate_base_path(&self.base_path);
-        if !warnings.is_empty() {
-            self.status_message = Some(warnings.join("\n"));
-        }
-
         self.config = config::load_repo_config(&self.base_path, &self.config);
-        self.presets = nix::scan_host_presets(&self.base_path);
-        self.nixos_modules = nix::scan_nixos_modules(&self.base_path);
-        self.system_packages = nix::scan_package_modules(&self.base_path);
+        let layout = self.config.module_layout();
+        self.base_path_warnings = nix::validate_base_path(&self.base_path, &layout);
+
+        self.presets = nix::scan_host_presets(&self.base_path, &layout);
+        let hidden = self.config.hidden_modules();
+        self.nixos_modules = nix::scan_nixos_modules(&self.base_path, &layout, &hidden);
+        self.system_packages = nix::scan_package_modules(
+            &self.base_path,
+            &layout,
+            &hidden,
+            &self.config.attr_refs(),
+        );
+        self.home_manager = self.detect_home_manager();
 
         // Apply repo-level config defaults that weren't set at startup
         if self.host_name_input.is_empty() {
@@ -408,27 +1360,114 @@ impl App {
         if let Some(ref s) = self.config.default_swap_size {
             self.swap_size_input = s.clone();
         }
+        if let Some(ref key) = self.config.ssh_authorized_key {
+            self.ssh_key_input = key.clone();
+        }
+        if let Some(mb) = self.config.default_efi_size_mb {
+            self.efi_size_input = mb.to_string();
+        }
         if let Some(ref t) = self.config.branding_title {
             self.branding_title = t.clone();
         }
+        self.bootloader_cursor = default_bootloader_cursor(&self.config, self.uefi);
+        self.kernel_cursor = default_kernel_cursor(&self.config);
+        if let Some(accept) = self.config.accept_flake_config {
+            self.accept_flake_config = accept;
+        }
+        if let Some(wipe) = self.config.secure_wipe {
+            self.secure_wipe = wipe;
+        }
+        self.apply_last_selections();
+
+        if self.show_welcome() {
+            self.step = Step::Welcome;
+        } else {
+            if !self.base_path_warnings.is_empty() {
+                self.set_status(self.base_path_warnings.join("\n"));
+            }
+            self.enter_preset_selection();
+        }
+        self.apply_answers();
+    }
+
+    /// Whether scanning found no host presets and no modules at all, so a
+    /// generated configuration would import nothing.
+    pub fn repo_is_empty(&self) -> bool {
+        self.presets.is_empty() && self.nixos_modules.is_empty() && self.system_packages.is_empty()
+    }
+
+    /// Move on to preset selection, stopping at EmptyRepo first when the
+    /// scan found nothing.
+    pub fn enter_preset_selection(&mut self) {
+        self.step = if self.repo_is_empty() {
+            self.empty_repo_cursor = 0;
+            Step::EmptyRepo
+        } else {
+            Step::SelectPreset
+        };
+    }
+
+    /// Resolve the empty repo warning: quit, or continue at the user's own
+    /// risk.
+    pub fn confirm_empty_repo(&mut self) {
+        if self.empty_repo_cursor == 0 {
+            self.should_quit = true;
+        } else {
+            self.step = Step::SelectPreset;
+        }
+    }
 
-        self.step = Step::SelectPreset;
+    /// Whether the wizard starts with the Welcome screen.
+    pub fn show_welcome(&self) -> bool {
+        self.config.show_welcome.unwrap_or(true)
     }
 
     // ---- Go-back navigation ----
 
     /// Go back to the previous logical step when the user presses Esc.
     /// Returns `true` if we went back, `false` if there is no previous step.
+    ///
+    /// Undo semantics for the user steps:
+    /// - `UserShell` pops the user that was just committed and puts their
+    ///   name back into the `CreateUser` input, so `users` never holds an
+    ///   entry the wizard has backed out of.
+    /// - `AddAnotherUser` returns to the last user's shell picker.
+    /// - `SelectUserPackages` returns to the same user's HM module list.
+    /// - `ReviewUsers` returns to `AddAnotherUser` without touching `users`.
+    /// - `CreateUser` while re-editing a reviewed user discards the edit and
+    ///   returns to `ReviewUsers`.
+    /// - `SelectHmModules` returns to the previous HM user's package list, or
+    ///   to `ReviewUsers` for the first one.
+    /// - `SelectDisk` returns to the last HM user's package list, or to
+    ///   `ReviewUsers` if no user needed HM selection.
+    ///
+    /// Selections made in the HM/package lists are saved back to the user
+    /// before stepping back, so walking forward again restores them.
     pub fn go_back(&mut self) -> bool {
         match self.step {
             // First step — can't go back
-            Step::CloningRepo | Step::SelectPreset => false,
+            Step::CloningRepo | Step::Welcome => false,
+            Step::SelectPreset if self.repo_is_empty() => {
+                self.step = Step::EmptyRepo;
+                true
+            }
+            Step::SelectPreset | Step::EmptyRepo => {
+                if !self.show_welcome() {
+                    return false;
+                }
+                self.step = Step::Welcome;
+                true
+            }
 
             Step::HostName => {
                 self.step = Step::SelectPreset;
                 true
             }
-            Step::SelectNixosModules => {
+            Step::SelectNixosModules if self.preset_config.is_some() => {
+                self.step = Step::SelectPreset;
+                true
+            }
+            Step::OverwriteHost | Step::SelectNixosModules => {
                 self.step = Step::HostName;
                 true
             }
@@ -436,36 +1475,120 @@ impl App {
                 self.step = Step::SelectNixosModules;
                 true
             }
-            Step::CreateUser => {
-                if self.is_custom {
-                    self.step = Step::SelectSystemPackages;
-                } else {
-                    self.step = Step::SelectPreset;
-                }
+            Step::Bootloader => {
+                self.step = Step::SelectSystemPackages;
                 true
             }
-
-            // After a user is committed, going back is complex (would need to
-            // undo the push). Let Esc quit instead.
-            Step::AddAnotherUser => false,
-            Step::SelectHmModules => false,
-            Step::SelectUserPackages => false,
-
-            Step::SelectDisk => {
-                // Go back to the step before disk selection.
-                // If any user needed HM selection we'd go back there, but
-                // re-entering HM selection is messy, so go to AddAnotherUser.
-                // Simpler: just don't go back from here (q to quit).
-                false
+            Step::Kernel => {
+                self.step = Step::Bootloader;
+                true
+            }
+            Step::Gpu => {
+                self.step = Step::Kernel;
+                true
+            }
+            Step::SshKey => {
+                self.step = if self.is_custom {
+                    Step::Gpu
+                } else if self.users.iter().any(|u| u.existing) {
+                    Step::AddAnotherUser
+                } else {
+                    self.preset_start_step()
+                };
+                true
+            }
+            Step::CreateUser => {
+                if self.editing_user.take().is_some() {
+                    self.current_username.clear();
+                    self.step = Step::ReviewUsers;
+                } else {
+                    self.step = Step::SshKey;
+                }
+                true
+            }
+
+            Step::UserShell => {
+                match self.editing_user {
+                    Some(index) => self.current_username = self.users[index].username.clone(),
+                    None => {
+                        if let Some(user) = self.users.pop() {
+                            self.current_username = user.username;
+                        }
+                    }
+                }
+                self.step = Step::CreateUser;
+                true
+            }
+            Step::AddAnotherUser if self.users.last().is_some_and(|u| u.existing) => {
+                self.another_user_cursor = 0;
+                self.step = self.preset_start_step();
+                true
+            }
+            Step::AddAnotherUser => {
+                self.another_user_cursor = 0;
+                self.shell_cursor = self.shell_cursor_for(self.users.len().saturating_sub(1));
+                self.step = Step::UserShell;
+                true
+            }
+            Step::ReviewUsers => {
+                self.step = Step::AddAnotherUser;
+                true
+            }
+            Step::SelectHmModules => {
+                if let Some(user) = self.users.get_mut(self.hm_user_index) {
+                    user.hm_modules = self.hm_modules.clone();
+                }
+                self.back_to_previous_hm_user(self.hm_user_index);
+                true
+            }
+            Step::SelectUserPackages => {
+                if let Some(user) = self.users.get_mut(self.hm_user_index) {
+                    user.package_modules = self.user_pkg_modules.clone();
+                    self.hm_modules = user.hm_modules.clone();
+                }
+                self.hm_cursor = 0;
+                self.step = Step::SelectHmModules;
+                true
+            }
+
+            Step::SelectDisk if self.hashed_passwords() => {
+                self.clear_status();
+                self.begin_password_collection();
+                true
+            }
+            Step::SelectDisk => {
+                self.clear_status();
+                self.back_to_previous_hm_user(self.users.len());
+                true
             }
             Step::PartitionModeSelect => {
                 self.step = Step::SelectDisk;
                 true
             }
-            Step::SwapSize => {
+            Step::EfiSize => {
                 self.step = Step::PartitionModeSelect;
                 true
             }
+            Step::RootFs => {
+                self.step = Step::EfiSize;
+                true
+            }
+            Step::PersistSize => {
+                self.step = Step::RootFs;
+                true
+            }
+            Step::SwapMode => {
+                self.step = if self.partition_mode == PartitionMode::TmpfsRoot {
+                    Step::PersistSize
+                } else {
+                    Step::RootFs
+                };
+                true
+            }
+            Step::SwapSize | Step::ZramPercent => {
+                self.step = Step::SwapMode;
+                true
+            }
             Step::CustomPartitionMount => {
                 if self.partitions.is_empty() {
                     // First partition — go back to mode select
@@ -476,20 +1599,76 @@ impl App {
                 }
                 true
             }
-            Step::CustomPartitionSize => {
+            Step::CustomPartitionDisk => {
                 self.step = Step::CustomPartitionMount;
                 true
             }
+            Step::CustomPartitionSize => {
+                self.step = if self.extra_disks.is_empty() {
+                    Step::CustomPartitionMount
+                } else {
+                    Step::CustomPartitionDisk
+                };
+                true
+            }
             Step::CustomPartitionFs => {
                 self.step = Step::CustomPartitionSize;
                 true
             }
+            Step::CustomPartitionFormat => {
+                self.step = Step::CustomPartitionFs;
+                true
+            }
             Step::CustomPartitionAnother => false,
 
             Step::Confirm => {
                 self.step = Step::PartitionModeSelect;
                 true
             }
+            Step::PreviewConfig => {
+                self.step = Step::Confirm;
+                true
+            }
+            // Backing out of the passphrase leaves encryption off
+            Step::EncryptionPassphrase => {
+                self.encryption_passphrase.clear();
+                self.encryption_mismatch = false;
+                self.step = Step::Confirm;
+                true
+            }
+            Step::EncryptionPassphraseConfirm => {
+                self.encryption_passphrase_confirm.clear();
+                self.step = Step::EncryptionPassphrase;
+                true
+            }
+
+            // Hashed passwords are collected before installing: back from
+            // root's prompt leaves them, the others restart at root
+            Step::RootPassword if self.hashed_passwords() => {
+                self.root_password.clear();
+                self.root_password_confirm.clear();
+                self.back_to_previous_hm_user(self.users.len());
+                true
+            }
+            // Without a root prompt the first user's prompt comes first
+            Step::UserPassword
+                if self.hashed_passwords()
+                    && self.users[..self.password_user_index].iter().all(|u| u.existing)
+                    && self.root_password_policy() == RootPasswordPolicy::Disable =>
+            {
+                self.current_password.clear();
+                self.current_password_confirm.clear();
+                self.back_to_previous_hm_user(self.users.len());
+                true
+            }
+            Step::RootPasswordConfirm | Step::UserPassword | Step::UserPasswordConfirm
+                if self.hashed_passwords() =>
+            {
+                self.current_password.clear();
+                self.current_password_confirm.clear();
+                self.begin_password_collection();
+                true
+            }
 
             // Can't go back from active installation or post-install steps
             Step::Installing | Step::RootPassword | Step::RootPasswordConfirm
@@ -507,28 +1686,176 @@ impl App {
             return;
         }
 
+        self.preset_config = None;
         if self.preset_cursor == items.len() - 1 {
             // "Custom" selected
             self.is_custom = true;
+            self.users.retain(|u| !u.existing);
             self.step = Step::HostName;
         } else {
             // Existing preset
             self.is_custom = false;
             self.host_name = self.presets[self.preset_cursor].name.clone();
-            self.prefill_username_if_empty();
-            self.step = Step::CreateUser;
+            self.load_existing_users();
+            self.begin_preset_users();
+        }
+    }
+
+    /// The step before a preset's user steps: the preset list, or the
+    /// package list of an edited preset.
+    fn preset_start_step(&self) -> Step {
+        if self.preset_config.is_some() {
+            Step::SelectSystemPackages
+        } else {
+            Step::SelectPreset
+        }
+    }
+
+    /// Start the user steps of a preset host. When the preset already has
+    /// users it's first asked whether to add one: the SSH key goes into a
+    /// generated user's module, so it's only asked for once there is one.
+    fn begin_preset_users(&mut self) {
+        if self.users.iter().any(|u| u.existing) {
+            self.another_user_cursor = 0;
+            self.step = Step::AddAnotherUser;
+        } else {
+            self.step = Step::SshKey;
+        }
+    }
+
+    /// List the users a preset already defines (its `user-<name>.nix`
+    /// files) first, replacing any loaded for another preset. Their files
+    /// are kept; only users created in the wizard are generated.
+    fn load_existing_users(&mut self) {
+        let names = nix::scan_existing_users(
+            &self.base_path,
+            &self.config.module_layout(),
+            &self.host_name,
+        );
+        self.users.retain(|u| !u.existing && !names.contains(&u.username));
+        let existing = names.into_iter().map(|username| UserEntry {
+            username,
+            password: String::new(),
+            groups: Vec::new(),
+            shell: None,
+            hashed_password: None,
+            autologin: false,
+            hm_modules: Vec::new(),
+            package_modules: Vec::new(),
+            needs_hm_selection: false,
+            existing: true,
+        });
+        self.users.splice(0..0, existing);
+    }
+
+    /// "Edit preset": pick the highlighted preset's modules, starting from
+    /// the ones its configuration.nix already references, then continue as
+    /// with the preset. Only the imports of its configuration.nix change.
+    pub fn edit_preset(&mut self) {
+        let Some(preset) = self.presets.get(self.preset_cursor) else {
+            return;
+        };
+        let config_path = preset.path.join("configuration.nix");
+        let config_nix = match std::fs::read_to_string(&config_path) {
+            Ok(content) => content,
+            Err(e) => {
+                self.set_status(format!("Can't edit preset: {}: {}", config_path.display(), e));
+                return;
+            }
+        };
+        let used = nix::referenced_nixos_modules(&config_nix, &self.config.attr_refs());
+        if used.is_empty() {
+            self.set_status(format!(
+                "Can't edit preset: {} references no modules to add the selection to.",
+                config_path.display()
+            ));
+            return;
+        }
+        for m in self.nixos_modules.iter_mut().chain(&mut self.system_packages) {
+            m.selected = m.required || used.contains(&m.name);
         }
+        self.host_name = preset.name.clone();
+        self.preset_config = Some(config_nix);
+        self.is_custom = false;
+        self.load_existing_users();
+        self.nixos_cursor = 0;
+        self.step = Step::SelectNixosModules;
     }
 
     pub fn confirm_host_name(&mut self) {
         let name = self.host_name_input.trim().to_string();
         if name.is_empty() {
-            self.status_message = Some("Host name cannot be empty".to_string());
+            self.set_status("Host name cannot be empty".to_string());
+            return;
+        }
+        // Validate against RFC 1123 hostname label rules
+        if name.len() > 63 {
+            self.set_status(format!(
+                "Host name is {} characters long; the maximum is 63",
+                name.len()
+            ));
+            return;
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            self.set_status(
+                "Host name may only contain lowercase letters, digits and hyphens".to_string(),
+            );
+            return;
+        }
+        if name.starts_with('-') || name.ends_with('-') {
+            self.set_status("Host name cannot start or end with a hyphen".to_string());
             return;
         }
         self.host_name = name;
+        self.clear_status();
+
+        // Don't silently clobber an existing host's configuration
+        let layout = self.config.module_layout();
+        if nix::host_exists(&self.base_path, &layout, &self.host_name) {
+            if self.config.host_dir_strategy.unwrap_or_default() == HostDirStrategy::Suffix {
+                let resolved = nix::next_free_host_name(&self.base_path, &layout, &self.host_name);
+                if resolved.len() > 63 {
+                    self.set_status(format!(
+                        "Host '{}' already exists and '{}' would be longer than 63 characters. \
+                         Choose a shorter name.",
+                        self.host_name, resolved
+                    ));
+                    return;
+                }
+                self.set_status(format!(
+                    "Host '{}' already exists; this configuration is written to '{}' (with \
+                     networking.hostName = \"{}\") instead.",
+                    self.host_name, resolved, resolved
+                ));
+                self.host_name = resolved;
+            } else {
+                self.overwrite_host_cursor = 0;
+                self.step = Step::OverwriteHost;
+                return;
+            }
+        }
         self.step = Step::SelectNixosModules;
-        self.status_message = None;
+    }
+
+    /// Resolve the "host already exists" prompt: load the existing host as a
+    /// preset, explicitly overwrite it, or go back and pick another name.
+    pub fn confirm_overwrite_host(&mut self) {
+        match self.overwrite_host_cursor {
+            0 => {
+                // Load as preset: same as picking it from the preset list
+                self.is_custom = false;
+                if let Some(i) = self.presets.iter().position(|p| p.name == self.host_name) {
+                    self.preset_cursor = i;
+                }
+                self.load_existing_users();
+                self.begin_preset_users();
+            }
+            1 => self.step = Step::SelectNixosModules,
+            _ => self.step = Step::HostName,
+        }
     }
 
     pub fn confirm_nixos_modules(&mut self) {
@@ -536,10 +1863,171 @@ impl App {
     }
 
     pub fn confirm_system_packages(&mut self) {
+        self.check_module_references();
+        if self.preset_config.is_some() {
+            // An edited preset keeps its own settings
+            self.begin_preset_users();
+        } else {
+            self.step = Step::Bootloader;
+        }
+    }
+
+    /// Warn about selected NixOS modules and package sets that the flake
+    /// does not expose under `nixosModules`; `nixos-install` would fail on
+    /// them later. The flake is evaluated once, in a background thread
+    /// (`sync_module_check` reports when it's done), and the result cached.
+    fn check_module_references(&mut self) {
+        // Custom reference templates may point anywhere in the flake
+        if !self.config.attr_refs().uses_default_nixos_modules() {
+            return;
+        }
+        if self.flake_nixos_modules.is_some() {
+            self.report_unexported_modules();
+            return;
+        }
+        if self.module_check_running() {
+            return;
+        }
+        let base_path = self.base_path.clone();
+        let slot = Arc::new(Mutex::new(None));
+        let thread_slot = Arc::clone(&slot);
+        std::thread::spawn(move || {
+            let result = nix::flake_nixos_module_names(&base_path);
+            if let Ok(mut s) = thread_slot.lock() {
+                *s = Some(result);
+            }
+        });
+        self.shared_module_check = Some(slot);
+    }
+
+    /// Whether the `nix eval` of the flake's `nixosModules` is running.
+    pub fn module_check_running(&self) -> bool {
+        self.shared_module_check.is_some()
+    }
+
+    /// Pick up a finished module name check, cache it and warn about the
+    /// selected modules the flake doesn't export.
+    pub fn sync_module_check(&mut self) {
+        let Some(slot) = &self.shared_module_check else {
+            return;
+        };
+        let result = match slot.lock() {
+            Ok(mut s) => s.take(),
+            Err(_) => Some(Err("the module check thread crashed unexpectedly".to_string())),
+        };
+        let Some(result) = result else {
+            return;
+        };
+        self.shared_module_check = None;
+        self.flake_nixos_modules = Some(result);
+        self.report_unexported_modules();
+    }
+
+    /// Show the selected modules missing from the cached `nixosModules`.
+    fn report_unexported_modules(&mut self) {
+        let available = match &self.flake_nixos_modules {
+            Some(Ok(names)) => names,
+            Some(Err(e)) => {
+                self.log_install(&format!("Could not verify module names: {}", e));
+                return;
+            }
+            None => return,
+        };
+        let missing: Vec<&str> = self
+            .nixos_modules
+            .iter()
+            .chain(&self.system_packages)
+            .filter(|m| m.selected && !available.contains(&m.name))
+            .map(|m| m.name.as_str())
+            .collect();
+        if !missing.is_empty() {
+            self.set_status(format!(
+                "Not exported by the flake as nixosModules: {}. The install will fail \
+                 unless these are deselected or renamed.",
+                missing.join(", ")
+            ));
+        }
+    }
+
+    pub fn confirm_bootloader(&mut self) {
+        let bootloader = match self.bootloader_cursor {
+            0 => Some(nix::Bootloader::SystemdBoot),
+            1 => Some(nix::Bootloader::Grub),
+            _ => None,
+        };
+        if bootloader == Some(nix::Bootloader::SystemdBoot) && !self.uefi {
+            self.set_status(
+                "systemd-boot requires UEFI, but this system booted in BIOS mode. Choose GRUB."
+                    .to_string(),
+            );
+            return;
+        }
+        self.bootloader = bootloader;
+        self.clear_status();
+        self.step = Step::Kernel;
+    }
+
+    pub fn confirm_kernel(&mut self) {
+        self.kernel = self.kernel_cursor.checked_sub(1).map(|i| nix::Kernel::ALL[i]);
+        self.step = Step::Gpu;
+    }
+
+    pub fn confirm_gpu(&mut self) {
+        self.gpu = self.gpu_cursor.checked_sub(1).map(|i| nix::GpuDriver::ALL[i]);
+        self.step = Step::SshKey;
+    }
+
+    /// Accept the SSH key input: empty skips SSH, a path to a file reads the
+    /// first public key in it, anything else must be a key itself.
+    pub fn confirm_ssh_key(&mut self) {
+        let input = self.ssh_key_input.trim();
+        if input.is_empty() {
+            self.ssh_authorized_key = None;
+        } else {
+            let path = match (input.strip_prefix("~/"), std::env::var_os("HOME")) {
+                (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+                _ => PathBuf::from(input),
+            };
+            let key = if path.is_file() {
+                match std::fs::read_to_string(&path) {
+                    Ok(content) => content
+                        .lines()
+                        .map(str::trim)
+                        .find(|l| !l.is_empty() && !l.starts_with('#'))
+                        .unwrap_or_default()
+                        .to_string(),
+                    Err(e) => {
+                        self.set_status(format!("Cannot read {}: {}", input, e));
+                        return;
+                    }
+                }
+            } else {
+                input.to_string()
+            };
+            if !nix::is_ssh_public_key(&key) {
+                self.set_status(
+                    "Not an SSH public key: expected e.g. \"ssh-ed25519 AAAA... comment\" or \
+                     a path to a .pub file"
+                        .to_string(),
+                );
+                return;
+            }
+            self.ssh_authorized_key = Some(key);
+        }
+        self.clear_status();
         self.prefill_username_if_empty();
         self.step = Step::CreateUser;
     }
 
+    /// Groups for a newly created user: `default_user_groups` from config,
+    /// or just "wheel".
+    fn default_user_groups(&self) -> Vec<String> {
+        self.config
+            .default_user_groups
+            .clone()
+            .unwrap_or_else(|| vec!["wheel".to_string()])
+    }
+
     /// Pre-fill the username input from config if the user list is empty
     /// and a default_username is configured.
     fn prefill_username_if_empty(&mut self) {
@@ -553,7 +2041,7 @@ impl App {
     pub fn confirm_username(&mut self) {
         let name = self.current_username.trim().to_string();
         if name.is_empty() {
-            self.status_message = Some("Username cannot be empty".to_string());
+            self.set_status("Username cannot be empty".to_string());
             return;
         }
         // Validate: lowercase alphanumeric, underscores, hyphens
@@ -563,45 +2051,104 @@ impl App {
             || !name
                 .chars()
                 .next()
-                .map_or(false, |c| c.is_ascii_lowercase() || c == '_')
+                .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
         {
-            self.status_message = Some(
+            self.set_status(
                 "Username must start with a lowercase letter or underscore".to_string(),
             );
             return;
         }
-        // Check for duplicate
-        if self.users.iter().any(|u| u.username == name) {
-            self.status_message = Some("User already exists".to_string());
+        // Check for duplicate (ignoring the user being re-edited)
+        if self
+            .users
+            .iter()
+            .enumerate()
+            .any(|(i, u)| u.username == name && Some(i) != self.editing_user)
+        {
+            self.set_status("User already exists".to_string());
             return;
         }
-        self.status_message = None;
+        self.clear_status();
 
         // Check if user config already exists
         let needs_hm = !nix::user_config_exists(
             &self.base_path,
+            &self.config.module_layout(),
             &self.host_name,
             &name,
         );
 
+        // Re-editing a reviewed user: rename in place, then pick the shell
+        if let Some(index) = self.editing_user {
+            let user = &mut self.users[index];
+            user.username = name;
+            user.needs_hm_selection = needs_hm;
+            self.current_username.clear();
+            self.shell_cursor = self.shell_cursor_for(index);
+            self.step = Step::UserShell;
+            return;
+        }
+
         self.users.push(UserEntry {
             username: name,
             password: String::new(),
+            groups: self.default_user_groups(),
+            shell: None,
+            hashed_password: None,
+            autologin: false,
             hm_modules: Vec::new(),
             package_modules: Vec::new(),
             needs_hm_selection: needs_hm,
+            existing: false,
         });
 
         self.current_username.clear();
-        self.step = Step::AddAnotherUser;
+        self.shell_cursor = 0;
+        self.step = Step::UserShell;
+    }
+
+    /// The user the shell picker applies to: the one being re-edited, else
+    /// the one just created.
+    fn shell_user(&self) -> Option<usize> {
+        self.editing_user
+            .or_else(|| self.users.len().checked_sub(1))
+            .filter(|&i| i < self.users.len())
+    }
+
+    /// Shell picker cursor matching a user's current shell.
+    fn shell_cursor_for(&self, index: usize) -> usize {
+        self.users
+            .get(index)
+            .and_then(|u| u.shell)
+            .and_then(|shell| nix::Shell::ALL.iter().position(|s| *s == shell))
+            .map_or(0, |i| i + 1)
+    }
+
+    /// Enter on the shell picker: store the choice ("keep the default" is
+    /// `None`) and continue to the next user prompt, or back to review when
+    /// re-editing.
+    pub fn confirm_user_shell(&mut self) {
+        let shell = self
+            .shell_cursor
+            .checked_sub(1)
+            .and_then(|i| nix::Shell::ALL.get(i).copied());
+        if let Some(index) = self.shell_user() {
+            self.users[index].shell = shell;
+        }
+        self.shell_cursor = 0;
+        if self.editing_user.take().is_some() {
+            self.step = Step::ReviewUsers;
+        } else {
+            self.step = Step::AddAnotherUser;
+        }
     }
 
     pub fn confirm_user_password(&mut self) {
         if self.current_password.is_empty() {
-            self.status_message = Some("Password cannot be empty".to_string());
+            self.set_status("Password cannot be empty".to_string());
             return;
         }
-        self.status_message = None;
+        self.clear_status();
         self.step = Step::UserPasswordConfirm;
     }
 
@@ -615,11 +2162,29 @@ impl App {
         }
         self.password_mismatch = false;
 
+        if self.hashed_passwords() {
+            let user = &mut self.users[self.password_user_index];
+            match nix::hash_password(&self.current_password) {
+                Ok(hash) => user.hashed_password = Some(hash),
+                Err(e) => {
+                    let msg =
+                        format!("Failed to hash the password for '{}': {}", user.username, e);
+                    self.set_status(msg);
+                    return;
+                }
+            }
+            self.current_password.clear();
+            self.current_password_confirm.clear();
+            self.password_user_index += 1;
+            self.advance_to_next_user_password();
+            return;
+        }
+
         // Set the password for this user via nixos-enter
         let username = self.users[self.password_user_index].username.clone();
         self.log_install(&format!("Setting password for user '{}'...", username));
         if let Err(e) = disk::set_user_password_in_target(&username, &self.current_password) {
-            self.status_message = Some(format!(
+            self.set_status(format!(
                 "Failed to set password for '{}': {}. Press any key to retry.",
                 username, e
             ));
@@ -647,28 +2212,101 @@ impl App {
     }
 
     /// Advance to the next user that needs a password, or go to Complete.
+    /// In password_mode "hashed" the wizard continues to disk selection, and
+    /// users whose files are kept are skipped (the hash has nowhere to go).
     fn advance_to_next_user_password(&mut self) {
+        while self.hashed_passwords()
+            && self.users.get(self.password_user_index).is_some_and(|u| u.existing)
+        {
+            self.password_user_index += 1;
+        }
         if self.password_user_index < self.users.len() {
             self.current_password.clear();
             self.current_password_confirm.clear();
             self.password_mismatch = false;
             self.step = Step::UserPassword;
+        } else if self.hashed_passwords() {
+            self.finish_user_setup();
         } else {
             self.step = Step::Complete;
         }
     }
 
     pub fn confirm_another_user(&mut self) {
+        let generates_user = self.users.iter().any(|u| !u.existing);
         if self.another_user_cursor == 0 {
-            // Yes - create another user
-            self.step = Step::CreateUser;
+            // Yes - create another user; the first one on a preset with
+            // users gets the SSH key question first
+            self.step = if generates_user { Step::CreateUser } else { Step::SshKey };
         } else {
-            // No - move to HM module selection for users that need it
-            self.begin_hm_selection();
+            // No - review the collected users before HM module selection.
+            // Without a generated user there is nowhere to put an SSH key
+            if !generates_user {
+                self.ssh_authorized_key = None;
+            }
+            self.review_user_cursor = 0;
+            self.step = Step::ReviewUsers;
         }
         self.another_user_cursor = 0;
     }
 
+    /// Entries shown on the review screen: one per user plus "Continue".
+    pub fn review_user_items(&self) -> usize {
+        self.users.len() + 1
+    }
+
+    /// Enter on the review screen: re-edit the highlighted user, or continue
+    /// to HM module selection when "Continue" is highlighted.
+    pub fn confirm_review_users(&mut self) {
+        if self.review_user_cursor >= self.users.len() {
+            self.begin_hm_selection();
+            return;
+        }
+        let index = self.review_user_cursor;
+        if self.users[index].existing {
+            self.set_status(existing_user_notice(&self.users[index].username));
+            return;
+        }
+        self.current_username = self.users[index].username.clone();
+        self.editing_user = Some(index);
+        self.step = Step::CreateUser;
+    }
+
+    /// Toggle autologin for the highlighted user on the review screen. Only
+    /// one user can be the autologin user.
+    pub fn toggle_autologin_user(&mut self) {
+        let index = self.review_user_cursor;
+        if index >= self.users.len() {
+            return;
+        }
+        if self.users[index].existing {
+            self.set_status(existing_user_notice(&self.users[index].username));
+            return;
+        }
+        let enable = !self.users[index].autologin;
+        for (i, user) in self.users.iter_mut().enumerate() {
+            user.autologin = enable && i == index;
+        }
+    }
+
+    /// Delete the highlighted user on the review screen. HM bookkeeping is
+    /// reset so `advance_to_next_hm_user` never indexes a removed user.
+    pub fn delete_reviewed_user(&mut self) {
+        if self.review_user_cursor >= self.users.len() {
+            return;
+        }
+        self.users.remove(self.review_user_cursor);
+        self.hm_user_index = 0;
+
+        if self.users.is_empty() {
+            // At least one user is required — start over with a fresh one
+            self.review_user_cursor = 0;
+            self.step = Step::CreateUser;
+            return;
+        }
+        self.review_user_cursor = self.review_user_cursor.min(self.users.len() - 1);
+    }
+
     fn begin_hm_selection(&mut self) {
         self.hm_user_index = 0;
         self.advance_to_next_hm_user();
@@ -676,13 +2314,22 @@ impl App {
 
     fn advance_to_next_hm_user(&mut self) {
         // Find the next user that needs HM selection
-        while self.hm_user_index < self.users.len() {
+        while self.home_manager && self.hm_user_index < self.users.len() {
             if self.users[self.hm_user_index].needs_hm_selection {
-                // Scan HM modules and package modules on demand
-                self.users[self.hm_user_index].hm_modules =
-                    nix::scan_hm_modules(&self.base_path);
-                self.users[self.hm_user_index].package_modules =
-                    nix::scan_package_modules(&self.base_path);
+                // Scan HM modules and package modules on demand. Users we
+                // stepped back over keep their earlier selections.
+                let layout = self.config.module_layout();
+                let hidden = self.hidden_modules();
+                let user = &mut self.users[self.hm_user_index];
+                if user.hm_modules.is_empty() && user.package_modules.is_empty() {
+                    user.hm_modules = nix::scan_hm_modules(&self.base_path, &layout, &hidden);
+                    user.package_modules = nix::scan_package_modules(
+                        &self.base_path,
+                        &layout,
+                        &hidden,
+                        &self.config.attr_refs(),
+                    );
+                }
                 // Load their HM modules for selection
                 self.hm_modules = self.users[self.hm_user_index].hm_modules.clone();
                 self.hm_cursor = 0;
@@ -692,23 +2339,79 @@ impl App {
             self.hm_user_index += 1;
         }
 
-        // No more users need HM selection, move to disk selection
-        self.go_to_disk_selection();
+        // No more users need HM selection: collect passwords first if they
+        // get hashed into the config
+        if self.hashed_passwords() {
+            self.begin_password_collection();
+        } else {
+            self.finish_user_setup();
+        }
     }
 
-    fn go_to_disk_selection(&mut self) {
-        match disk::list_block_devices() {
-            Ok(disks) => self.disks = disks,
+    /// Move on from the user steps to disk selection (or, in config-only
+    /// mode, write the files and finish).
+    fn finish_user_setup(&mut self) {
+        if self.config_only {
+            self.write_config_only();
+        } else {
+            self.go_to_disk_selection();
+        }
+    }
+
+    /// Config-only mode: write the generated host and user files to the
+    /// repo and jump straight to the Complete summary.
+    fn write_config_only(&mut self) {
+        self.save_last_selections();
+        let files = self.preview_files();
+        let layout = self.config.module_layout();
+        match write_generated_files(&self.base_path, &layout, &self.host_name, &files) {
+            Ok(paths) => {
+                for path in &paths {
+                    self.log_install(&format!("Wrote {}", path.display()));
+                }
+                self.generated_files = paths;
+                self.step = Step::Complete;
+            }
             Err(e) => {
-                self.disks = Vec::new();
-                self.status_message = Some(format!("Failed to list disks: {}", e));
+                self.set_status(e);
             }
         }
-        self.disk_cursor = 0;
-        self.step = Step::SelectDisk;
     }
 
-    pub fn confirm_hm_modules(&mut self) {
+    /// Step back to the package list of the last user before `before` that
+    /// needed HM selection, or to `ReviewUsers` if there is none.
+    fn back_to_previous_hm_user(&mut self, before: usize) {
+        let previous = self.users[..before.min(self.users.len())]
+            .iter()
+            .rposition(|u| u.needs_hm_selection)
+            .filter(|_| self.home_manager);
+        match previous {
+            Some(index) => {
+                self.hm_user_index = index;
+                self.user_pkg_modules = self.users[index].package_modules.clone();
+                self.user_pkg_cursor = 0;
+                self.step = Step::SelectUserPackages;
+            }
+            None => {
+                self.hm_user_index = 0;
+                self.step = Step::ReviewUsers;
+            }
+        }
+    }
+
+    fn go_to_disk_selection(&mut self) {
+        match disk::list_block_devices() {
+            Ok(disks) => self.disks = disks,
+            Err(e) => {
+                self.disks = Vec::new();
+                self.set_status(format!("Failed to list disks: {}", e));
+            }
+        }
+        self.disk_cursor = 0;
+        self.step = Step::SelectDisk;
+    }
+
+    pub fn confirm_hm_modules(&mut self) {
         // Save HM selections back to the user
         self.users[self.hm_user_index].hm_modules = self.hm_modules.clone();
         // Now transition to per-user package selection
@@ -724,67 +2427,433 @@ impl App {
         self.advance_to_next_hm_user();
     }
 
+    /// Mark or unmark the disk under the cursor for a multi-disk install.
+    pub fn toggle_disk_mark(&mut self) {
+        let Some(disk) = self.disks.get(self.disk_cursor) else {
+            return;
+        };
+        if let Some(pos) = self.marked_disks.iter().position(|p| *p == disk.path) {
+            self.marked_disks.remove(pos);
+        } else {
+            self.marked_disks.push(disk.path.clone());
+        }
+    }
+
+    /// Select the marked disks, or just the one under the cursor if none
+    /// are marked. The first one in list order becomes the boot disk.
     pub fn confirm_disk(&mut self) {
         if self.disks.is_empty() {
-            self.status_message = Some("No disks available".to_string());
+            self.set_status("No disks available".to_string());
             return;
         }
-        self.selected_disk = Some(self.disks[self.disk_cursor].clone());
-        self.status_message = None;
+        let chosen: Vec<BlockDevice> = if self.marked_disks.is_empty() {
+            vec![self.disks[self.disk_cursor].clone()]
+        } else {
+            self.disks
+                .iter()
+                .filter(|d| self.marked_disks.contains(&d.path))
+                .cloned()
+                .collect()
+        };
+
+        // Wiping a disk that's in use would fail halfway or take down the
+        // live environment (e.g. the USB stick the installer booted from)
+        for disk in &chosen {
+            let in_use = disk::disk_mounts(&disk.path);
+            if in_use.is_empty() {
+                continue;
+            }
+            let lines: Vec<String> = in_use
+                .iter()
+                .map(|(dev, mount_point)| {
+                    let live = matches!(mount_point.as_str(), "/" | "/iso" | "/run/rootfsbase");
+                    format!(
+                        "  {} on {}{}",
+                        dev,
+                        mount_point,
+                        if live { " (the live boot medium)" } else { "" }
+                    )
+                })
+                .collect();
+            self.set_status(format!(
+                "{} is in use and can't be installed to:\n{}\n\
+                 Unmount it (umount / swapoff) or choose another disk.",
+                disk.path,
+                lines.join("\n")
+            ));
+            return;
+        }
+
+        self.enable_fstrim = chosen.iter().any(|d| !d.rotational);
+        self.disk_health = if self.config.smart_check.unwrap_or(true) {
+            chosen
+                .iter()
+                .filter_map(|d| disk::smart_health(&d.path).map(|h| (d.path.clone(), h)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.smart_failure_acknowledged = false;
+        let mut chosen = chosen.into_iter();
+        self.selected_disk = chosen.next();
+        self.extra_disks = chosen.collect();
+        self.part_disk_cursor = 0;
+        self.clear_status();
         self.step = Step::PartitionModeSelect;
     }
 
+    /// Every disk selected for the install, boot disk first.
+    pub fn install_disks(&self) -> Vec<&BlockDevice> {
+        self.selected_disk.iter().chain(&self.extra_disks).collect()
+    }
+
+    /// Partition modes: 0 = full disk, 1 = custom, 2 = full disk with a
+    /// tmpfs root.
     pub fn confirm_partition_mode(&mut self) {
-        if self.partition_mode_cursor == 0 {
-            self.partition_mode = PartitionMode::FullDisk;
-            self.step = Step::SwapSize;
-        } else {
-            self.partition_mode = PartitionMode::Custom;
-            self.partitions.clear();
-            self.step = Step::CustomPartitionMount;
+        if self.partition_mode_cursor != 1 && !self.extra_disks.is_empty() {
+            self.set_status(format!(
+                "Full-disk modes install to a single disk. Choose Custom Partitions to \
+                 use all {} selected disks, or go back and select one.",
+                self.install_disks().len()
+            ));
+            return;
+        }
+        match self.partition_mode_cursor {
+            0 => {
+                self.partition_mode = PartitionMode::FullDisk;
+                self.step = Step::EfiSize;
+            }
+            1 => {
+                self.partition_mode = PartitionMode::Custom;
+                self.partitions.clear();
+                self.zram_percent = None;
+                self.swap_file_mb = None;
+                self.step = Step::CustomPartitionMount;
+            }
+            _ => {
+                self.partition_mode = PartitionMode::TmpfsRoot;
+                self.step = Step::EfiSize;
+                self.remind_tmpfs_root_requirements();
+            }
         }
     }
 
-    pub fn confirm_swap_size(&mut self) {
-        let input = self.swap_size_input.trim();
-        let swap_gb: u64 = if input.is_empty() {
-            0
+    /// A tmpfs root only works if the configuration persists state to
+    /// /persist; point out what's missing for that.
+    fn remind_tmpfs_root_requirements(&mut self) {
+        let mut notes = Vec::new();
+        let has_impermanence = self
+            .nixos_modules
+            .iter()
+            .any(|m| m.selected && m.name.contains("impermanence"));
+        if !has_impermanence {
+            notes.push(
+                "Select an impermanence module (e.g. nix-community/impermanence) that \
+                 persists what you need to /persist, or / is wiped on every boot.",
+            );
+        }
+        if !self.hashed_passwords() {
+            notes.push(
+                "Passwords set after install live in /etc/shadow on the tmpfs and are \
+                 lost on reboot. Consider password_mode = \"hashed\".",
+            );
+        }
+        if !notes.is_empty() {
+            self.set_status(format!("Ephemeral root:\n{}", notes.join("\n")));
+        }
+    }
+
+    /// Whether / is a tmpfs rather than a partition.
+    pub fn tmpfs_root(&self) -> bool {
+        self.partition_mode == PartitionMode::TmpfsRoot
+    }
+
+    /// Whether the plan provides a root filesystem (a / partition, or a
+    /// tmpfs root).
+    fn has_root(&self) -> bool {
+        self.tmpfs_root() || self.partitions.iter().any(|p| p.mount_point == "/")
+    }
+
+    pub fn confirm_efi_size(&mut self) {
+        let input = self.efi_size_input.trim();
+        let size_mb: u64 = if input.is_empty() {
+            DEFAULT_EFI_SIZE_MB
         } else {
             match input.parse::<u64>() {
                 Ok(v) => v,
                 Err(_) => {
-                    self.status_message =
-                        Some("Invalid swap size. Enter a whole number in GiB (e.g. 4) or leave empty for no swap.".to_string());
+                    self.set_status(
+                        "Invalid EFI size. Enter a whole number in MiB (e.g. 512 or 1024).".to_string(),
+                    );
                     return;
                 }
             }
         };
+        if size_mb < MIN_EFI_SIZE_MB {
+            self.set_status(format!(
+                "EFI partition must be at least {} MiB (1024 recommended if you keep many generations).",
+                MIN_EFI_SIZE_MB
+            ));
+            return;
+        }
+        self.efi_size_mb = size_mb;
+        self.clear_status();
+        self.step = Step::RootFs;
+    }
+
+    pub fn confirm_root_fs(&mut self) {
+        self.clear_status();
+        self.step = if self.tmpfs_root() {
+            Step::PersistSize
+        } else {
+            Step::SwapMode
+        };
+    }
+
+    pub fn confirm_persist_size(&mut self) {
+        let size_gb = match self.persist_size_input.trim().parse::<u64>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                self.set_status(
+                    "Invalid /persist size. Enter a whole number in GiB (e.g. 32).".to_string(),
+                );
+                return;
+            }
+        };
+        // /nix gets the rest of the disk and needs room for the store
+        if let Some(disk) = &self.selected_disk {
+            let disk_mb = disk.size_bytes / (1024 * 1024);
+            let reserved_mb = 1 + self.efi_size_mb + MIN_ROOT_SIZE_MB;
+            let max_gb = disk_mb.saturating_sub(reserved_mb) / 1024;
+            if size_gb > max_gb {
+                self.set_status(format!(
+                    "/persist of {} GiB doesn't fit on {} ({}). With the EFI partition and at \
+                     least {} GiB for /nix, the largest /persist possible is {} GiB.",
+                    size_gb,
+                    disk.path,
+                    disk.size_human,
+                    MIN_ROOT_SIZE_MB / 1024,
+                    max_gb
+                ));
+                return;
+            }
+        }
+        self.persist_size_mb = size_gb * 1024;
+        self.clear_status();
+        self.step = Step::SwapMode;
+    }
+
+    /// Swap options: 0 = swap partition, 1 = swap file, 2 = zram, 3 = no
+    /// swap.
+    pub fn confirm_swap_mode(&mut self) {
+        self.clear_status();
+        self.swap_file_mb = None;
+        match self.swap_mode_cursor {
+            0 | 1 => {
+                self.zram_percent = None;
+                self.step = Step::SwapSize;
+            }
+            2 => self.step = Step::ZramPercent,
+            _ => {
+                self.zram_percent = None;
+                self.build_full_disk_plan(0);
+            }
+        }
+    }
+
+    /// Whether the swap is a file on the root filesystem rather than a
+    /// partition.
+    fn swap_as_file(&self) -> bool {
+        self.swap_mode_cursor == 1
+    }
+
+    /// Directory of the swap file in the installed system: /swap on the
+    /// root filesystem, or under /persist when / is a tmpfs.
+    fn swap_file_dir(&self) -> &'static str {
+        if self.tmpfs_root() {
+            "/persist/swap"
+        } else {
+            "/swap"
+        }
+    }
+
+    pub fn swap_file_path(&self) -> String {
+        format!("{}/swapfile", self.swap_file_dir())
+    }
+
+    /// Filesystem the swap file is created on.
+    pub fn swap_file_fs(&self) -> Option<FsType> {
+        let mount_point = if self.tmpfs_root() { "/persist" } else { "/" };
+        self.partitions
+            .iter()
+            .find(|p| p.mount_point == mount_point)
+            .map(|p| p.fs_type.clone())
+    }
+
+    pub fn confirm_zram_percent(&mut self) {
+        let input = self.zram_percent_input.trim();
+        let percent: u8 = if input.is_empty() {
+            DEFAULT_ZRAM_PERCENT
+        } else {
+            match input.parse::<u8>() {
+                Ok(v) if (1..=100).contains(&v) => v,
+                _ => {
+                    self.set_status(
+                        "zram size must be a percentage between 1 and 100.".to_string(),
+                    );
+                    return;
+                }
+            }
+        };
+        self.zram_percent = Some(percent);
+        self.clear_status();
+        self.build_full_disk_plan(0);
+    }
+
+    /// Whether `c` may be typed into the swap size input: digits, then one
+    /// `M`/`G` unit letter, or the word "auto".
+    pub fn accepts_swap_char(&self, c: char) -> bool {
+        let input = &self.swap_size_input;
+        let has_unit = input.ends_with(|ch: char| ch.is_ascii_alphabetic());
+        if c.is_ascii_digit() {
+            return !has_unit;
+        }
+        let starts_number = input.starts_with(|ch: char| ch.is_ascii_digit());
+        if starts_number && !has_unit && matches!(c, 'M' | 'm' | 'G' | 'g') {
+            return true;
+        }
+        "auto".starts_with(&format!("{}{}", input, c.to_ascii_lowercase()))
+    }
+
+    /// Swap size in MiB from the swap size input: empty = no swap, a plain
+    /// number = GiB, `512M` / `8G` with an explicit unit, `auto` = RAM.
+    fn parse_swap_size_mb(&self) -> Result<u64, String> {
+        let input = self.swap_size_input.trim().to_ascii_lowercase();
+        if input.is_empty() {
+            return Ok(0);
+        }
+        if input == "auto" {
+            return self
+                .ram_bytes
+                .map(|bytes| bytes.div_ceil(1024 * 1024))
+                .ok_or_else(|| "Couldn't read the installed RAM; enter a size instead.".to_string());
+        }
+        let (number, mb_per_unit) = match input.strip_suffix('m') {
+            Some(number) => (number, 1),
+            None => (input.strip_suffix('g').unwrap_or(&input), 1024),
+        };
+        number.parse::<u64>().map(|v| v * mb_per_unit).map_err(|_| {
+            "Invalid swap size. Enter e.g. 4 (GiB), 512M, 8G or auto, or leave empty for \
+             no swap."
+                .to_string()
+        })
+    }
+
+    pub fn confirm_swap_size(&mut self) {
+        let swap_mb = match self.parse_swap_size_mb() {
+            Ok(mb) => mb,
+            Err(e) => {
+                self.set_status(e);
+                return;
+            }
+        };
+
+        // The swap partition must leave room for the EFI partition and a
+        // usable root. Partitions start at the alignment offset, and each
+        // fixed-size one can be followed by up to one alignment of padding
+        if let Some(disk) = &self.selected_disk {
+            let disk_mb = disk.size_bytes / (1024 * 1024);
+            let align_mb = self.config.partition_alignment_mb().unwrap_or(1);
+            let mut reserved_mb =
+                align_mb + 2 * (align_mb - 1) + self.efi_size_mb + MIN_ROOT_SIZE_MB;
+            if self.tmpfs_root() {
+                reserved_mb += self.persist_size_mb + align_mb - 1;
+            }
+            let max_swap_mb = disk_mb.saturating_sub(reserved_mb);
+            if swap_mb > max_swap_mb {
+                self.set_status(format!(
+                    "Swap of {} MiB doesn't fit on {} ({}). With the EFI partition and at least \
+                     {} GiB for root, the largest swap possible is {} MiB.",
+                    swap_mb,
+                    disk.path,
+                    disk.size_human,
+                    MIN_ROOT_SIZE_MB / 1024,
+                    max_swap_mb
+                ));
+                return;
+            }
+        }
+
+        self.clear_status();
+        if self.swap_as_file() {
+            self.swap_file_mb = Some(swap_mb).filter(|&mb| mb > 0);
+            self.build_full_disk_plan(0);
+        } else {
+            self.build_full_disk_plan(swap_mb);
+        }
+    }
+
+    /// Suggested swap size for hibernation: installed RAM rounded up to a
+    /// whole GiB, plus one.
+    pub fn hibernation_swap_gb(&self) -> Option<u64> {
+        self.ram_bytes.map(|bytes| bytes.div_ceil(1024 * 1024 * 1024) + 1)
+    }
 
-        // Build full-disk partition plan: EFI (512M) + swap + root (rest)
+    /// Build the full-disk partition plan (EFI + optional swap + root on the
+    /// rest of the disk) and move on to Confirm.
+    fn build_full_disk_plan(&mut self, swap_mb: u64) {
         self.partitions.clear();
+        let disk = self.selected_disk.as_ref().map(|d| d.path.clone()).unwrap_or_default();
 
         self.partitions.push(PartitionPlan {
+            disk: disk.clone(),
             label: "EFI".to_string(),
             mount_point: "/boot".to_string(),
-            size_mb: Some(512),
+            size_mb: Some(self.efi_size_mb),
             fs_type: FsType::Fat32,
+            format: true,
         });
 
-        if swap_gb > 0 {
+        if swap_mb > 0 {
             self.partitions.push(PartitionPlan {
+                disk: disk.clone(),
                 label: "swap".to_string(),
                 mount_point: "swap".to_string(),
-                size_mb: Some(swap_gb * 1024),
+                size_mb: Some(swap_mb),
                 fs_type: FsType::Swap,
+                format: true,
             });
         }
 
-        self.partitions.push(PartitionPlan {
-            label: "root".to_string(),
-            mount_point: "/".to_string(),
-            size_mb: None, // use remaining space
-            fs_type: FsType::Ext4,
-        });
+        let fs_type = FsType::ROOT[self.root_fs_cursor].clone();
+        if self.tmpfs_root() {
+            // / is a tmpfs; state lives on /persist and the store on /nix
+            self.partitions.push(PartitionPlan {
+                disk: disk.clone(),
+                label: "persist".to_string(),
+                mount_point: "/persist".to_string(),
+                size_mb: Some(self.persist_size_mb),
+                fs_type: fs_type.clone(),
+                format: true,
+            });
+            self.partitions.push(PartitionPlan {
+                disk: disk.clone(),
+                label: "nix".to_string(),
+                mount_point: "/nix".to_string(),
+                size_mb: None, // use remaining space
+                fs_type,
+                format: true,
+            });
+        } else {
+            self.partitions.push(PartitionPlan {
+                disk: disk.clone(),
+                label: "root".to_string(),
+                mount_point: "/".to_string(),
+                size_mb: None, // use remaining space
+                fs_type,
+                format: true,
+            });
+        }
 
         self.step = Step::Confirm;
     }
@@ -792,51 +2861,103 @@ impl App {
     pub fn confirm_custom_mount(&mut self) {
         let mount = self.part_mount_input.trim().to_string();
         if mount.is_empty() {
-            self.status_message = Some("Mount point cannot be empty".to_string());
+            self.set_status("Mount point cannot be empty".to_string());
             return;
         }
         if mount != "swap" && !mount.starts_with('/') {
-            self.status_message =
-                Some("Mount point must start with '/' or be 'swap'".to_string());
+            self.set_status("Mount point must start with '/' or be 'swap'".to_string());
             return;
         }
-        self.status_message = None;
+        // Two filesystems on one mount point would shadow each other; only
+        // swap partitions can repeat
+        let mount = mount.trim_end_matches('/');
+        let mount = if mount.is_empty() { "/" } else { mount };
+        if mount != "swap" && self.partitions.iter().any(|p| p.mount_point == mount) {
+            self.set_status(format!(
+                "A partition is already mounted at {}. Choose another mount point.",
+                mount
+            ));
+            return;
+        }
+        self.part_mount_input = mount.to_string();
+        self.clear_status();
+        self.step = if self.extra_disks.is_empty() {
+            Step::CustomPartitionSize
+        } else {
+            Step::CustomPartitionDisk
+        };
+    }
+
+    pub fn confirm_custom_disk(&mut self) {
+        self.clear_status();
         self.step = Step::CustomPartitionSize;
     }
 
     pub fn confirm_custom_size(&mut self) {
-        self.status_message = None;
+        self.clear_status();
         self.step = Step::CustomPartitionFs;
     }
 
+    /// Size of the custom partition being added, in MiB (None = the rest
+    /// of the disk).
+    fn custom_partition_size_mb(&self) -> Result<Option<u64>, String> {
+        if self.part_size_input.trim().is_empty() {
+            return Ok(None);
+        }
+        match self.part_size_input.trim().parse::<u64>() {
+            Ok(v) if v > 0 => Ok(Some(v * 1024)),
+            Ok(_) => Err("Size must be greater than 0.".to_string()),
+            Err(_) => Err(
+                "Invalid size. Enter a whole number in GiB or leave empty for remaining space."
+                    .to_string(),
+            ),
+        }
+    }
+
     pub fn confirm_custom_fs(&mut self) {
-        let fs_types = FsType::all();
-        let fs = fs_types[self.part_fs_cursor].clone();
+        if let Err(e) = self.custom_partition_size_mb() {
+            self.set_status(e);
+            return;
+        }
+        let is_swap_mount = self.part_mount_input.trim() == "swap";
+        let is_swap_fs = FsType::all()[self.part_fs_cursor] == FsType::Swap;
+        if is_swap_mount != is_swap_fs {
+            self.set_status(if is_swap_mount {
+                "A swap partition must use the swap filesystem.".to_string()
+            } else {
+                format!(
+                    "{} can't be a swap partition. Choose a filesystem, or go back and \
+                     use 'swap' as the mount point.",
+                    self.part_mount_input.trim()
+                )
+            });
+            return;
+        }
+        self.clear_status();
+        self.part_format_cursor = 0;
+        self.step = Step::CustomPartitionFormat;
+    }
 
-        let mount = self.part_mount_input.trim().to_string();
-        let size_mb: Option<u64> = if self.part_size_input.trim().is_empty() {
-            None
-        } else {
-            match self.part_size_input.trim().parse::<u64>() {
-                Ok(v) if v > 0 => Some(v * 1024),
-                Ok(_) => {
-                    self.status_message =
-                        Some("Size must be greater than 0.".to_string());
-                    return;
-                }
-                Err(_) => {
-                    self.status_message = Some(
-                        "Invalid size. Enter a whole number in GiB or leave empty for remaining space."
-                            .to_string(),
-                    );
-                    return;
-                }
+    pub fn confirm_custom_format(&mut self) {
+        let size_mb = match self.custom_partition_size_mb() {
+            Ok(size_mb) => size_mb,
+            Err(e) => {
+                self.set_status(e);
+                return;
             }
         };
+        let fs_types = FsType::all();
+        let fs = fs_types[self.part_fs_cursor].clone();
+        let mount = self.part_mount_input.trim().to_string();
+        let disk = self
+            .install_disks()
+            .get(self.part_disk_cursor)
+            .map(|d| d.path.clone())
+            .unwrap_or_default();
 
         let label = if mount == "/" {
             "root".to_string()
-        } else if mount == "/boot" {
+        } else if mount == "/boot" || (fs == FsType::Fat32 && mount.starts_with("/boot/")) {
             "EFI".to_string()
         } else if mount == "swap" {
             "swap".to_string()
@@ -845,10 +2966,12 @@ impl App {
         };
 
         self.partitions.push(PartitionPlan {
+            disk,
             label,
             mount_point: mount,
             size_mb,
             fs_type: fs,
+            format: self.part_format_cursor == 0,
         });
 
         self.part_mount_input.clear();
@@ -861,21 +2984,255 @@ impl App {
     pub fn confirm_custom_another(&mut self) {
         if self.another_partition_cursor == 0 {
             self.step = Step::CustomPartitionMount;
+        } else if !self.has_root() {
+            // e.g. only swap so far: there is nothing to install to yet
+            self.set_status("No root (/) partition yet. Add one before continuing.".to_string());
+            self.step = Step::CustomPartitionMount;
         } else {
             self.step = Step::Confirm;
         }
         self.another_partition_cursor = 0;
     }
 
+    /// Whether the Confirm screen lets the partitions be reordered (custom
+    /// partitioning only; the full-disk layouts are fixed).
+    pub fn can_reorder_partitions(&self) -> bool {
+        self.partition_mode == PartitionMode::Custom && self.partitions.len() > 1
+    }
+
+    /// Select the previous or next partition on the Confirm screen.
+    pub fn move_confirm_partition_cursor(&mut self, down: bool) {
+        let last = self.partitions.len().saturating_sub(1);
+        self.confirm_partition_cursor = if down {
+            (self.confirm_partition_cursor + 1).min(last)
+        } else {
+            self.confirm_partition_cursor.saturating_sub(1).min(last)
+        };
+    }
+
+    /// Swap the selected partition with its neighbour, changing the order
+    /// (and so the numbering) they are created in.
+    pub fn move_partition(&mut self, down: bool) {
+        let i = self.confirm_partition_cursor.min(self.partitions.len().saturating_sub(1));
+        let j = if down { i + 1 } else { i.wrapping_sub(1) };
+        if j < self.partitions.len() {
+            self.partitions.swap(i, j);
+            self.confirm_partition_cursor = j;
+        }
+    }
+
+    /// Warning about how long a secure wipe will take, when selected disks
+    /// are spinning disks that have to be overwritten with zeros.
+    pub fn secure_wipe_warning(&self) -> Option<String> {
+        // Sequential write speed of a typical hard disk
+        const HDD_BYTES_PER_SEC: u64 = 150_000_000;
+        let hdds: Vec<&BlockDevice> =
+            self.install_disks().into_iter().filter(|d| d.rotational).collect();
+        if hdds.is_empty() {
+            return None;
+        }
+        let bytes: u64 = hdds.iter().map(|d| d.size_bytes).sum();
+        let minutes = bytes / HDD_BYTES_PER_SEC / 60;
+        let estimate = if minutes >= 60 {
+            format!("about {:.1} hours", minutes as f64 / 60.0)
+        } else {
+            format!("about {} minutes", minutes.max(1))
+        };
+        let disks: Vec<String> =
+            hdds.iter().map(|d| format!("{} ({})", d.path, d.size_human)).collect();
+        Some(format!("Zeroing spinning disks takes {}: {}", estimate, disks.join(", ")))
+    }
+
+    /// Selected disks whose SMART self-assessment reports failing.
+    pub fn failing_disks(&self) -> Vec<&str> {
+        self.disk_health
+            .iter()
+            .filter(|(_, health)| !health.passed)
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+
+    /// Report a validation error that going back to `step` fixes.
+    fn report_with_fix(&mut self, msg: impl Into<String>, step: Step) {
+        self.set_status(msg);
+        self.fix_step = Some(step);
+    }
+
+    /// Show `msg` in the status popup. Any fix step belongs to the message
+    /// it replaces, so it's dropped.
+    pub fn set_status(&mut self, msg: impl Into<String>) {
+        self.status_message = Some(msg.into());
+        self.fix_step = None;
+    }
+
+    /// Hide the status popup along with its fix step.
+    pub fn clear_status(&mut self) {
+        self.status_message = None;
+        self.fix_step = None;
+    }
+
+    /// Close the status popup, going to its fix step if `fix` is set.
+    pub fn dismiss_status(&mut self, fix: bool) {
+        let fix_step = self.fix_step.take();
+        self.clear_status();
+        if let Some(step) = fix_step {
+            if !fix {
+                return;
+            }
+            // The module lists belong to a user: open the one still pending
+            if let (Step::SelectHmModules, Some(index)) = (&step, self.pending_hm_user()) {
+                self.keep_list_selections();
+                self.hm_user_index = index;
+                self.advance_to_next_hm_user();
+                return;
+            }
+            self.step = step;
+        }
+    }
+
     pub fn confirm_install(&mut self) {
         if self.confirm_cursor == 0 {
             // Validate that there is a root partition
-            if !self.partitions.iter().any(|p| p.mount_point == "/") {
-                self.status_message = Some(
-                    "No root (/) partition defined. Please go back and add one.".to_string(),
+            if !self.has_root() {
+                self.report_with_fix(
+                    "No root (/) partition defined. Please go back and add one.",
+                    Step::PartitionModeSelect,
+                );
+                return;
+            }
+            // UEFI firmware needs an ESP; without one the system won't boot
+            let esps: Vec<&str> = self
+                .partitions
+                .iter()
+                .filter(|p| p.is_esp())
+                .map(|p| p.mount_point.as_str())
+                .collect();
+            if self.uefi && esps.is_empty() {
+                self.report_with_fix(
+                    "This system booted in UEFI mode but no FAT32 partition is mounted at /boot \
+                     (or /boot/efi). Please go back and add an EFI partition.",
+                    Step::PartitionModeSelect,
+                );
+                return;
+            }
+            // Every one would get the esp flag, and the bootloader could only
+            // be installed to one of them
+            if esps.len() > 1 {
+                let msg = format!(
+                    "There can only be one EFI partition, but FAT32 partitions are mounted at \
+                     {}. Change all but one of them to another filesystem or mount point.",
+                    esps.join(" and ")
+                );
+                self.report_with_fix(msg, Step::PartitionModeSelect);
+                return;
+            }
+            if self.encrypt_root && !self.offers_encryption() {
+                self.set_status(
+                    "Only a root (/) partition that is formatted can be encrypted. Turn \
+                     encryption off with e, or go back and format /.",
+                );
+                return;
+            }
+            // The bootloader reads the kernel from /boot, which can't be on
+            // the encrypted partition
+            if self.encrypt_root && !self.partitions.iter().any(|p| p.is_boot_mount()) {
+                self.set_status(
+                    "An encrypted / needs a separate /boot partition (the EFI partition on \
+                     UEFI). Add one, or turn encryption off with e.",
                 );
                 return;
             }
+            // Autologin is host-wide: a second user would conflict
+            if self.users.iter().filter(|u| u.autologin).count() > 1 {
+                self.report_with_fix(
+                    "Only one user can log in automatically. Toggle it off for the others with a.",
+                    Step::ReviewUsers,
+                );
+                return;
+            }
+            // parted can't create anything after a partition that runs to
+            // the end of the disk
+            for (i, p) in self.partitions.iter().enumerate() {
+                if p.size_mb.is_none()
+                    && !disk::keeps_partition_table(&p.disk, &self.partitions)
+                    && self.partitions[i + 1..].iter().any(|later| later.disk == p.disk)
+                {
+                    self.set_status(format!(
+                        "{} uses the remaining space of {}, so it must be the last partition \
+                         on it. Move it down with J.",
+                        p.mount_point, p.disk
+                    ));
+                    return;
+                }
+            }
+            // Wiping the disk would destroy the filesystems meant to be kept
+            if self.secure_wipe && self.partitions.iter().any(|p| !p.format) {
+                self.set_status(
+                    "Secure wipe would erase the partitions marked to keep their data. \
+                     Press w to turn it off, or go back and format them."
+                        .to_string(),
+                );
+                return;
+            }
+            // Disks with kept partitions are used as they are partitioned now
+            let missing = disk::missing_kept_devices(&self.partitions);
+            if !missing.is_empty() {
+                self.report_with_fix(
+                    format!(
+                        "{} does not exist. A disk with partitions marked to keep their data \
+                         is not repartitioned: the Nth partition planned on it must be its \
+                         existing Nth partition.",
+                        missing.join(", ")
+                    ),
+                    Step::PartitionModeSelect,
+                );
+                return;
+            }
+            if let Err(e) = self.config.partition_alignment_mb() {
+                self.set_status(format!("{}\nNothing has been written to disk.", e));
+                return;
+            }
+            if let Err(e) = self.config.flake_attr(&self.host_name) {
+                self.set_status(format!("{}\nNothing has been written to disk.", e));
+                return;
+            }
+            // A dying drive is likely to take the new system with it
+            let failing = self.failing_disks();
+            if !failing.is_empty() && !self.smart_failure_acknowledged {
+                self.set_status(format!(
+                    "WARNING: {} reports a FAILING SMART health check and may be about to die.\n\
+                     Back up anything on it and install to another disk.\n\
+                     Press Enter again to install anyway.",
+                    failing.join(", ")
+                ));
+                self.smart_failure_acknowledged = true;
+                return;
+            }
+            if let Err(missing) = disk::preflight(&self.partitions, self.encrypt_root) {
+                self.set_status(format!(
+                    "Cannot start the installation, these tools are missing:\n  {}\n\
+                     Nothing has been written to disk.",
+                    missing.join(", ")
+                ));
+                return;
+            }
+            // Check the flake inputs and build the system first so input,
+            // evaluation and build errors show up while the disk is untouched
+            if (self.config.prebuild_check.unwrap_or(false)
+                || self.config.check_flake_inputs.unwrap_or(false))
+                && self.prebuild_passed_for.as_deref() != Some(self.prebuild_key().as_str())
+            {
+                self.start_prebuild();
+                return;
+            }
+            if let Err(e) = self.export_plan() {
+                self.set_status(format!(
+                    "Could not export the installation plan: {}\nNothing has been written to disk.",
+                    e
+                ));
+                return;
+            }
+            self.save_last_selections();
             self.step = Step::Installing;
             self.start_installation();
         } else {
@@ -883,12 +3240,341 @@ impl App {
         }
     }
 
+    /// Whether the `check_flake_inputs`/`prebuild_check` check is running.
+    pub fn prebuild_running(&self) -> bool {
+        self.shared_prebuild.is_some()
+    }
+
+    /// Esc during the build check: kill it and stay on Confirm.
+    pub fn cancel_prebuild(&mut self) {
+        if let Some(cancel) = &self.prebuild_cancel {
+            cancel.cancel();
+        }
+    }
+
+    /// Whether a background thread (clone, install, build or module check) is
+    /// changing what's on screen, so the event loop has to keep redrawing
+    /// instead of waiting for input.
+    pub fn background_active(&self) -> bool {
+        let cloning = self.step == Step::CloningRepo && !self.clone_done;
+        let installing = self.step == Step::Installing
+            && !self.install_done
+            && self.install_error.is_none();
+        cloning || installing || self.prebuild_running() || self.module_check_running()
+    }
+
+    /// What a build check covers: the resolved plan and the generated files.
+    fn prebuild_key(&self) -> String {
+        let plan = serde_json::to_string(&self.install_plan()).unwrap_or_default();
+        let files: Vec<String> = self
+            .preview_files()
+            .into_iter()
+            .map(|(name, content)| format!("{}\n{}", name, content))
+            .collect();
+        format!("{}\n{}", plan, files.join("\n"))
+    }
+
+    /// Check the flake inputs and/or build the host's system in a background
+    /// thread; `sync_prebuild` picks up the result.
+    fn start_prebuild(&mut self) {
+        let job = PrebuildJob {
+            check_inputs: self.config.check_flake_inputs.unwrap_or(false),
+            build: self.config.prebuild_check.unwrap_or(false),
+            base_path: self.base_path.clone(),
+            layout: self.config.module_layout(),
+            host_name: self.host_name.clone(),
+            flake_attr: self.flake_attr(),
+            generated: self.preview_files(),
+            partitions: self
+                .partitions
+                .iter()
+                .map(|p| (p.label.clone(), p.mount_point.clone(), p.fs_type.as_str().to_string()))
+                .collect(),
+            tmpfs_root: self.tmpfs_root(),
+            swap_file: self.swap_file_mb.map(|_| self.swap_file_path()),
+            offline: self.config.offline,
+            nix_config: nix_config_env(
+                self.accept_flake_config,
+                self.config.extra_nix_config.as_deref(),
+            ),
+            cancel: Arc::new(nix::CancelHandle::default()),
+        };
+        self.prebuild_cancel = Some(Arc::clone(&job.cancel));
+        if job.check_inputs {
+            self.log_install("Build check: checking the flake inputs...");
+        }
+        if job.build {
+            self.log_install(&format!("Build check: building {}...", self.host_name));
+        }
+        let slot = Arc::new(Mutex::new(None));
+        let thread_slot = Arc::clone(&slot);
+        std::thread::spawn(move || {
+            let result = job.run();
+            if let Ok(mut s) = thread_slot.lock() {
+                *s = Some(result);
+            }
+        });
+        self.shared_prebuild = Some(slot);
+    }
+
+    /// Pick up a finished build check: continue with the installation if it
+    /// passed, otherwise stay on Confirm and show the error.
+    pub fn sync_prebuild(&mut self) {
+        let Some(slot) = &self.shared_prebuild else {
+            return;
+        };
+        let result = match slot.lock() {
+            Ok(mut s) => s.take(),
+            Err(_) => Some(Err("The build check thread crashed unexpectedly".to_string())),
+        };
+        let Some(result) = result else {
+            return;
+        };
+        self.shared_prebuild = None;
+        let cancelled = self.prebuild_cancel.take().is_some_and(|c| c.is_cancelled());
+        match result {
+            _ if cancelled => self.log_install("Build check cancelled."),
+            Ok(()) => {
+                self.log_install("Build check passed.");
+                self.prebuild_passed_for = Some(self.prebuild_key());
+                self.confirm_install();
+            }
+            Err(e) => {
+                self.log_install(&format!("Build check failed: {}", e));
+                self.prebuild_error = Some(e);
+                self.prebuild_scroll = 0;
+            }
+        }
+    }
+
+    /// Whether the F2 "skip to Confirm" shortcut applies on the current step.
+    pub fn can_skip_to_confirm(&self) -> bool {
+        !matches!(
+            self.step,
+            Step::CloningRepo
+                | Step::Confirm
+                | Step::PreviewConfig
+                | Step::EncryptionPassphrase
+                | Step::EncryptionPassphraseConfirm
+                | Step::Installing
+                | Step::RootPassword
+                | Step::RootPasswordConfirm
+                | Step::UserPassword
+                | Step::UserPasswordConfirm
+                | Step::Complete
+        )
+    }
+
+    /// Jump straight to Confirm when everything the install needs was
+    /// already entered (e.g. after stepping back from Confirm to tweak one
+    /// thing). Otherwise report what's still missing.
+    pub fn skip_to_confirm(&mut self) {
+        if self.config_only {
+            self.set_status("There is no Confirm screen in --config-only mode.".to_string());
+            return;
+        }
+        let mut missing = Vec::new();
+        // The step that fills in the first missing item, if it can be
+        // entered directly
+        let mut fix = None;
+        if self.host_name.is_empty() {
+            missing.push("a host name");
+            fix = fix.or(Some(Step::HostName));
+        }
+        if self.users.is_empty() {
+            missing.push("at least one user");
+            fix = fix.or(Some(Step::CreateUser));
+        }
+        if self.selected_disk.is_none() {
+            missing.push("a target disk");
+        }
+        if !self.has_root() {
+            missing.push("a partition plan with a root (/) partition");
+            if missing.len() == 1 {
+                fix = Some(Step::PartitionModeSelect);
+            }
+        }
+        if self.pending_hm_user().is_some() {
+            missing.push("the Home Manager module selection of every user");
+            fix = fix.or(Some(Step::SelectHmModules));
+        }
+        if self.hashed_passwords()
+            && ((self.root_password_policy() == RootPasswordPolicy::Require
+                && self.root_hashed_password.is_none())
+                || self.users.iter().any(|u| !u.existing && u.hashed_password.is_none()))
+        {
+            missing.push("passwords for root and every user");
+        }
+        if !missing.is_empty() {
+            self.set_status(format!(
+                "Can't skip to Confirm yet. Still missing: {}.",
+                missing.join(", ")
+            ));
+            self.fix_step = fix;
+            return;
+        }
+
+        self.keep_list_selections();
+        if self.editing_user.take().is_some() {
+            self.current_username.clear();
+        }
+        self.clear_status();
+        self.step = Step::Confirm;
+    }
+
+    /// Keep the selections made on the HM or user package list of the
+    /// current user before jumping away from it.
+    fn keep_list_selections(&mut self) {
+        match self.step {
+            Step::SelectHmModules => {
+                if let Some(user) = self.users.get_mut(self.hm_user_index) {
+                    user.hm_modules = self.hm_modules.clone();
+                }
+            }
+            Step::SelectUserPackages => {
+                if let Some(user) = self.users.get_mut(self.hm_user_index) {
+                    user.package_modules = self.user_pkg_modules.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// First user whose Home Manager modules haven't been picked yet (their
+    /// lists are only scanned once the selection is reached).
+    fn pending_hm_user(&self) -> Option<usize> {
+        if !self.home_manager {
+            return None;
+        }
+        self.users.iter().position(|u| {
+            u.needs_hm_selection && u.hm_modules.is_empty() && u.package_modules.is_empty()
+        })
+    }
+
+    /// Show the generated Nix files from the Confirm screen.
+    pub fn open_preview(&mut self) {
+        self.preview_scroll = 0;
+        self.step = Step::PreviewConfig;
+    }
+
+    /// The files the installer would write, as (file name, content) pairs.
+    /// Nothing is written to disk.
+    pub fn preview_files(&self) -> Vec<(String, String)> {
+        generate_files(
+            &self.host_name,
+            self.is_custom,
+            &self.nixos_modules,
+            &self.system_packages,
+            &self.users,
+            &self.config.hm_base_modules,
+            &self.host_settings(),
+        )
+    }
+
+    /// Host-level settings for the generated configuration.nix.
+    pub fn host_settings(&self) -> nix::HostSettings {
+        nix::HostSettings {
+            zram_percent: self.zram_percent,
+            bootloader: self.bootloader,
+            kernel: self.kernel,
+            gpu: self.gpu,
+            uefi: self.uefi,
+            efi_mount_point: self
+                .partitions
+                .iter()
+                .find(|p| p.is_esp())
+                .map(|p| p.mount_point.clone())
+                .filter(|m| m != "/boot"),
+            // "!" locks the account: no password login for root at all
+            root_hashed_password: match self.root_password_policy() {
+                RootPasswordPolicy::Disable => Some("!".to_string()),
+                _ => self.root_hashed_password.clone(),
+            },
+            grub_device: self.grub_device(),
+            fstrim: self.offers_fstrim() && self.enable_fstrim,
+            refs: self.config.attr_refs(),
+            home_manager: self.home_manager,
+            hm_mode: self.config.hm_mode.unwrap_or_default(),
+            state_version: self.config.state_version.clone().or(self.nixos_release.clone()),
+            flake_attr: Some(self.flake_attr()).filter(|attr| *attr != self.host_name),
+            ssh_authorized_key: self.ssh_authorized_key.clone(),
+            autologin_user: self
+                .users
+                .iter()
+                .find(|u| u.autologin && !u.existing)
+                .map(|u| u.username.clone()),
+            preset_config: self.preset_config.clone(),
+        }
+    }
+
+    /// The host's `nixosConfigurations` attribute: `flake_attr` from the
+    /// config, or the host name. Checked in `confirm_install`.
+    pub fn flake_attr(&self) -> String {
+        self.config.flake_attr(&self.host_name).unwrap_or_else(|_| self.host_name.clone())
+    }
+
+    /// Disk GRUB is installed to in BIOS mode: the one holding /boot, else
+    /// the one holding /, else the boot disk.
+    fn grub_device(&self) -> Option<String> {
+        let on = |mount: &str| self.partitions.iter().find(|p| p.mount_point == mount);
+        on("/boot")
+            .or_else(|| on("/"))
+            .map(|p| p.disk.clone())
+            .or_else(|| self.selected_disk.as_ref().map(|d| d.path.clone()))
+    }
+
+    /// Whether the Confirm screen offers periodic TRIM: the selected disk is
+    /// an SSD/NVMe and the host's configuration.nix is generated.
+    pub fn offers_fstrim(&self) -> bool {
+        self.is_custom && self.install_disks().iter().any(|d| !d.rotational)
+    }
+
+    /// Whether passwords are hashed into the generated nix before installing
+    /// instead of being set with chpasswd afterwards.
+    pub fn hashed_passwords(&self) -> bool {
+        self.config.password_mode == Some(PasswordMode::Hashed)
+    }
+
+    pub fn root_password_policy(&self) -> RootPasswordPolicy {
+        self.config.root_password_policy.unwrap_or_default()
+    }
+
+    /// Leave the Installing screen once nixos-install succeeded: prompt for
+    /// passwords, unless they were already hashed into the config.
+    pub fn finish_installation(&mut self) {
+        if self.hashed_passwords() {
+            self.step = Step::Complete;
+        } else {
+            self.begin_password_collection();
+        }
+    }
+
+    /// Start collecting passwords, beginning with root (unless root's
+    /// password is disabled).
+    fn begin_password_collection(&mut self) {
+        self.root_password.clear();
+        self.root_password_confirm.clear();
+        self.root_password_mismatch = false;
+        if self.root_password_policy() == RootPasswordPolicy::Disable {
+            self.begin_user_password_collection();
+        } else {
+            self.step = Step::RootPassword;
+        }
+    }
+
     pub fn confirm_root_password(&mut self) {
         if self.root_password.is_empty() {
-            self.status_message = Some("Root password cannot be empty".to_string());
+            if self.root_password_policy() == RootPasswordPolicy::Optional {
+                // Root keeps no password (or the one set in the repo)
+                self.clear_status();
+                self.root_hashed_password = None;
+                self.begin_user_password_collection();
+                return;
+            }
+            self.set_status("Root password cannot be empty".to_string());
             return;
         }
-        self.status_message = None;
+        self.clear_status();
         self.step = Step::RootPasswordConfirm;
     }
 
@@ -902,9 +3588,23 @@ impl App {
         }
         self.root_password_mismatch = false;
 
+        if self.hashed_passwords() {
+            match nix::hash_password(&self.root_password) {
+                Ok(hash) => self.root_hashed_password = Some(hash),
+                Err(e) => {
+                    self.set_status(format!("Failed to hash root password: {}", e));
+                    return;
+                }
+            }
+            self.root_password.clear();
+            self.root_password_confirm.clear();
+            self.begin_user_password_collection();
+            return;
+        }
+
         self.log_install("Setting root password...");
         if let Err(e) = disk::set_root_password(&self.root_password) {
-            self.status_message = Some(format!("Failed to set root password: {}. Press any key to retry.", e));
+            self.set_status(format!("Failed to set root password: {}. Press any key to retry.", e));
             self.root_password.clear();
             self.root_password_confirm.clear();
             self.step = Step::RootPassword;
@@ -915,8 +3615,63 @@ impl App {
         self.begin_user_password_collection();
     }
 
+    /// Whether the root partition can be encrypted: a / partition that is
+    /// formatted (not kept, and not a tmpfs root).
+    pub fn offers_encryption(&self) -> bool {
+        self.partitions.iter().any(|p| p.mount_point == "/" && p.format)
+    }
+
+    /// Confirm screen `e`: turn encryption off, or ask for the passphrase
+    /// that turns it on.
+    pub fn toggle_encryption(&mut self) {
+        self.encryption_passphrase.clear();
+        self.encryption_passphrase_confirm.clear();
+        self.encryption_mismatch = false;
+        if self.encrypt_root {
+            self.encrypt_root = false;
+        } else if self.offers_encryption() {
+            self.step = Step::EncryptionPassphrase;
+        } else {
+            self.set_status("Only a root (/) partition that is formatted can be encrypted.");
+        }
+    }
+
+    pub fn confirm_encryption_passphrase(&mut self) {
+        if self.encryption_passphrase.is_empty() {
+            self.set_status("Passphrase cannot be empty");
+            return;
+        }
+        self.clear_status();
+        self.step = Step::EncryptionPassphraseConfirm;
+    }
+
+    pub fn confirm_encryption_passphrase_confirm(&mut self) {
+        if self.encryption_passphrase != self.encryption_passphrase_confirm {
+            self.encryption_mismatch = true;
+            self.encryption_passphrase.clear();
+            self.encryption_passphrase_confirm.clear();
+            self.step = Step::EncryptionPassphrase;
+            return;
+        }
+        self.encryption_mismatch = false;
+        self.encryption_passphrase_confirm.clear();
+        self.encrypt_root = true;
+        self.step = Step::Confirm;
+    }
+
+    /// Complete screen options: 0 = reboot, 1 = set as boot default, 2 =
+    /// shell (handled by the caller, which owns the terminal), 3 = exit.
     pub fn confirm_reboot(&mut self) {
-        if self.reboot_cursor == 0 {
+        if self.reboot_cursor == 1 && !self.config_only {
+            self.set_status(match disk::set_boot_default() {
+                Ok(()) => "The new system is now the boot default. It starts on the next reboot; \
+                           exit the installer when you're done."
+                    .to_string(),
+                Err(e) => format!("Setting the boot default failed: {}", e),
+            });
+            return;
+        }
+        if self.reboot_cursor == 0 && !self.config_only {
             let _ = disk::reboot();
         }
         self.should_quit = true;
@@ -930,27 +3685,69 @@ impl App {
         if let Ok(mut f) = OpenOptions::new()
             .create(true)
             .append(true)
-            .open(LOG_FILE)
+            .open(&self.log_file)
         {
             let _ = writeln!(f, "{}", msg);
         }
     }
 
+    /// Users whose standalone Home Manager activation package is built after
+    /// nixos-install (none unless `hm_mode = "standalone"`).
+    fn hm_activation_users(&self) -> Vec<String> {
+        if !self.home_manager || self.config.hm_mode != Some(nix::HmMode::Standalone) {
+            return Vec::new();
+        }
+        self.users.iter().map(|u| u.username.clone()).collect()
+    }
+
     fn start_installation(&mut self) {
-        // Calculate total steps: base 9 + pre-hooks + post-hooks
+        // Calculate total steps: base 9 + pre-hooks + post-hooks (+ the
+        // standalone Home Manager build)
         let pre_hook_count = self.config.pre_install_hooks.len();
         let post_hook_count = self.config.post_install_hooks.len();
-        let total = 9 + pre_hook_count + post_hook_count;
+        let hm_build_step = usize::from(!self.hm_activation_users().is_empty());
+        let total = 9 + pre_hook_count + post_hook_count + hm_build_step;
 
         let state = Arc::new(Mutex::new(InstallState {
             log: Vec::new(),
             progress: 0,
             total,
+            step_percent: None,
+            completed: 0,
             error: None,
+            diagnostics: None,
             done: false,
         }));
         self.shared_install = Some(Arc::clone(&state));
+        self.spawn_install_thread(state, 0);
+    }
+
+    /// Retry a failed installation. Steps that already completed (most
+    /// importantly partitioning) are skipped, so the disk is not wiped again.
+    pub fn retry_installation(&mut self) {
+        let Some(state) = self.shared_install.clone() else {
+            return;
+        };
+        let resume_after = match state.lock() {
+            Ok(mut s) => {
+                s.error = None;
+                s.diagnostics = None;
+                let msg = format!("Retrying from step {}...", s.completed + 1);
+                s.log.push(msg);
+                s.completed
+            }
+            // The install thread panicked; there is no consistent state to resume
+            Err(_) => return,
+        };
+        self.install_error = None;
+        self.install_diagnostics = None;
+        self.auto_scroll = true;
+        self.spawn_install_thread(state, resume_after);
+    }
 
+    /// Run the installation steps in a background thread, skipping every
+    /// step numbered `resume_after` or lower.
+    fn spawn_install_thread(&mut self, state: Arc<Mutex<InstallState>>, resume_after: usize) {
         // Clone everything the background thread needs.
         let disk_path = match &self.selected_disk {
             Some(d) => d.path.clone(),
@@ -963,16 +3760,43 @@ impl App {
             }
         };
         let partitions = self.partitions.clone();
+        let tmpfs_root = self.tmpfs_root();
+        let root_passphrase = self.encrypt_root.then(|| self.encryption_passphrase.clone());
+        let swap_file = self.swap_file_mb.map(|mb| {
+            let btrfs = self.swap_file_fs() == Some(FsType::Btrfs);
+            (self.swap_file_dir(), mb, btrfs)
+        });
         let base_path = self.base_path.clone();
+        let layout = self.config.module_layout();
         let host_name = self.host_name.clone();
-        let is_custom = self.is_custom;
-        let nixos_modules = self.nixos_modules.clone();
-        let system_packages = self.system_packages.clone();
-        let users = self.users.clone();
+        let flake_attr = self.flake_attr();
+        let generated = self.preview_files();
         let accept_flake_config = self.accept_flake_config;
-        let installer_config = self.config.clone();
+        let secure_wipe = self.secure_wipe;
+        let alignment_mb = self.config.partition_alignment_mb().unwrap_or(1);
+        let extra_nix_config = self.config.extra_nix_config.clone();
+        let offline = self.config.offline;
+        let extra_install_args = self.config.nixos_install_extra_args.clone();
         let pre_hooks = self.config.pre_install_hooks.clone();
         let post_hooks = self.config.post_install_hooks.clone();
+        let hm_activation_users = self.hm_activation_users();
+        // Where the repo lives in the installed system. With a tmpfs root
+        // it would be wiped on reboot; impermanence setups persist it from
+        // /persist
+        let target_repo = self.config.target_repo_path();
+        let repo_dir = if tmpfs_root {
+            format!("/persist{}", target_repo)
+        } else {
+            target_repo.clone()
+        };
+        // A repo in someone's home should be theirs to edit and push
+        let repo_owner = if target_repo.starts_with("/home/") {
+            self.users.first().map(|u| u.username.clone())
+        } else {
+            None
+        };
+        let copy_git = self.config.copy_git_history.unwrap_or(true);
+        let log_file = self.log_file.clone();
 
         std::thread::spawn(move || {
             // Helper: log a message to shared state and the log file.
@@ -983,7 +3807,7 @@ impl App {
                 if let Ok(mut f) = OpenOptions::new()
                     .create(true)
                     .append(true)
-                    .open(LOG_FILE)
+                    .open(&log_file)
                 {
                     let _ = writeln!(f, "{}", msg);
                 }
@@ -998,7 +3822,7 @@ impl App {
                 if let Ok(mut f) = OpenOptions::new()
                     .create(true)
                     .append(true)
-                    .open(LOG_FILE)
+                    .open(&log_file)
                 {
                     let _ = writeln!(f, "ERROR: {}", msg);
                 }
@@ -1007,237 +3831,387 @@ impl App {
             let set_progress = |state: &Arc<Mutex<InstallState>>, p: usize| {
                 if let Ok(mut s) = state.lock() {
                     s.progress = p;
+                    s.step_percent = None;
                 }
             };
 
+            // Record that step `n` finished so a retry can resume after it.
+            let mark_done = |state: &Arc<Mutex<InstallState>>, n: usize| {
+                if let Ok(mut s) = state.lock() {
+                    s.completed = n;
+                }
+            };
+
+            let should_run = |n: usize| runs_step(resume_after, n);
+
             let fail = |state: &Arc<Mutex<InstallState>>, msg: String| {
                 if let Ok(mut s) = state.lock() {
                     s.error = Some(msg);
                 }
-            };
-
-            // Truncate/create the log file
-            if let Ok(mut f) = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(LOG_FILE)
-            {
-                let _ = writeln!(f, "=== NixOS Installer Log ===\n");
-            }
-
-            // Step 1: Partition
-            log(&state, &format!("Partitioning {}...", disk_path));
-            set_progress(&state, 1);
-            if let Err(e) = disk::partition_disk(&disk_path, &partitions) {
-                let msg = format!("Partitioning failed: {}", e);
-                log_error(&state, &msg);
-                fail(&state, msg);
-                return;
+            };
+
+            // Truncate/create the log file (a retry keeps the earlier attempt)
+            if resume_after == 0 {
+                if let Ok(mut f) = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(&log_file)
+                {
+                    let _ = writeln!(f, "=== NixOS Installer Log ===\n");
+                }
+            }
+
+            // Step 1: Partition
+            set_progress(&state, 1);
+            if should_run(1) {
+                for disk in disk::plan_disks(&partitions) {
+                    if disk::keeps_partition_table(&disk, &partitions) {
+                        log(
+                            &state,
+                            &format!("Keeping the partition table of {} (kept data)", disk),
+                        );
+                        continue;
+                    }
+                    if secure_wipe {
+                        let mut method = disk::WipeMethod::for_disk(&disk);
+                        log(
+                            &state,
+                            &format!("Securely wiping {} ({})...", disk, method.description()),
+                        );
+                        let mut progress = |line: &str| log(&state, line);
+                        let mut result = disk::secure_wipe(&disk, method, &mut progress);
+                        if result.is_err() && method == disk::WipeMethod::Discard {
+                            // Not every SSD (or USB bridge) supports discard
+                            log(
+                                &state,
+                                "blkdiscard is not supported; overwriting with zeros instead.",
+                            );
+                            method = disk::WipeMethod::Zero;
+                            result = disk::secure_wipe(&disk, method, &mut progress);
+                        }
+                        if let Err(e) = result {
+                            let msg = format!("Secure wipe failed: {}", e);
+                            log_error(&state, &msg);
+                            fail(&state, msg);
+                            return;
+                        }
+                    }
+                    log(&state, &format!("Partitioning {}...", disk));
+                    if let Err(e) = disk::partition_disk(&disk, &partitions, alignment_mb) {
+                        let msg = format!("Partitioning {} failed: {}", disk, e);
+                        log_error(&state, &msg);
+                        fail(&state, msg);
+                        return;
+                    }
+                }
+                mark_done(&state, 1);
             }
 
             // Step 2: Format and mount
-            log(&state, "Formatting and mounting partitions...");
             set_progress(&state, 2);
-            if let Err(e) = disk::format_and_mount(&disk_path, &partitions) {
-                let msg = format!("Format/mount failed: {}", e);
-                log_error(&state, &msg);
-                fail(&state, msg);
-                return;
-            }
-
-            // Step 3: Generate hardware config
-            log(&state, "Generating hardware configuration...");
-            set_progress(&state, 3);
-            let hw_config = match disk::generate_hardware_config() {
-                Ok(c) => c,
-                Err(e) => {
-                    let msg = format!("Hardware config generation failed: {}", e);
+            if should_run(2) {
+                if undoes_partial_mount(resume_after) {
+                    log(&state, "Undoing the previous attempt's mounts...");
+                    let swap_file_path = swap_file.as_ref().map(|(dir, _, _)| {
+                        format!("/mnt{}/swapfile", dir)
+                    });
+                    if let Err(e) =
+                        disk::undo_format_and_mount(&partitions, swap_file_path.as_deref())
+                    {
+                        let msg = format!("Cleaning up the previous attempt failed: {}", e);
+                        log_error(&state, &msg);
+                        fail(&state, msg);
+                        return;
+                    }
+                }
+                log(&state, "Formatting and mounting partitions...");
+                let mut on_output = |line: &str, percent: Option<u8>| {
+                    log(&state, line);
+                    if let (Some(percent), Ok(mut s)) = (percent, state.lock()) {
+                        s.step_percent = Some(percent);
+                    }
+                };
+                let passphrase = root_passphrase.as_deref();
+                if let Err(e) =
+                    disk::format_and_mount(&partitions, tmpfs_root, passphrase, &mut on_output)
+                {
+                    let msg = format!("Format/mount failed: {}", e);
                     log_error(&state, &msg);
                     fail(&state, msg);
                     return;
                 }
-            };
-
-            // Step 4: Write hardware config
-            log(&state, "Writing hardware configuration...");
-            set_progress(&state, 4);
-            if let Err(e) = nix::write_hardware_config(&base_path, &host_name, &hw_config) {
-                let msg = format!("Failed to write hardware config: {}", e);
-                log_error(&state, &msg);
-                fail(&state, msg);
-                return;
+                if let Some((dir, mb, btrfs)) = swap_file {
+                    log(&state, &format!("Creating a {} MiB swap file in {}...", mb, dir));
+                    match disk::create_swap_file(&format!("/mnt{}", dir), mb, btrfs) {
+                        Ok(Some(warning)) => log(&state, &format!("  warning: {}", warning)),
+                        Ok(None) => {}
+                        Err(e) => {
+                            let msg = format!("Swap file creation failed: {}", e);
+                            log_error(&state, &msg);
+                            fail(&state, msg);
+                            return;
+                        }
+                    }
+                }
+                mark_done(&state, 2);
             }
 
-            // Step 5: Write host configuration (if custom)
-            set_progress(&state, 5);
-            if is_custom {
-                log(&state, "Writing host configuration...");
-                let usernames: Vec<String> = users.iter().map(|u| u.username.clone()).collect();
-                let config = nix::generate_configuration_nix(
-                    &host_name,
-                    &nixos_modules,
-                    &system_packages,
-                    &usernames,
-                );
-                if let Err(e) = nix::write_host_config(&base_path, &host_name, &config) {
-                    let msg = format!("Failed to write configuration: {}", e);
+            // Steps 3-4: Generate and write hardware config. These run as a
+            // unit since step 4 needs the output of step 3.
+            if should_run(4) {
+                log(&state, "Generating hardware configuration...");
+                set_progress(&state, 3);
+                let mut hw_config = match disk::generate_hardware_config() {
+                    Ok(c) if tmpfs_root => nix::tmpfs_root_hardware_config(&c),
+                    Ok(c) => c,
+                    Err(e) => {
+                        let msg = format!("Hardware config generation failed: {}", e);
+                        log_error(&state, &msg);
+                        fail(&state, msg);
+                        return;
+                    }
+                };
+                if let Some((dir, _, _)) = swap_file {
+                    let device = format!("{}/swapfile", dir);
+                    hw_config = nix::swap_file_hardware_config(&hw_config, &device);
+                }
+
+                log(&state, "Writing hardware configuration...");
+                set_progress(&state, 4);
+                if let Err(e) = nix::write_hardware_config(&base_path, &layout, &host_name, &hw_config) {
+                    let msg = format!("Failed to write hardware config: {}", e);
                     log_error(&state, &msg);
                     fail(&state, msg);
                     return;
                 }
+                mark_done(&state, 4);
             }
 
-            // Step 6: Write user definition files (user + HM imports combined)
-            for user in &users {
-                log(&state, &format!("Writing user-{}.nix...", user.username));
-                let user_nix = nix::generate_user_nix(
-                    &host_name,
-                    &user.username,
-                    &user.hm_modules,
-                    &user.package_modules,
-                    &installer_config.hm_base_modules,
-                );
-                if let Err(e) = nix::write_user_config(
-                    &base_path,
-                    &host_name,
-                    &user.username,
-                    &user_nix,
-                ) {
-                    let msg = format!("Failed to write user config: {}", e);
-                    log_error(&state, &msg);
-                    fail(&state, msg);
-                    return;
+            // Step 5: Write host configuration (if custom) and user definition
+            // files (user + HM imports combined)
+            set_progress(&state, 5);
+            if should_run(5) {
+                log(&state, "Writing host configuration and user files...");
+                match write_generated_files(&base_path, &layout, &host_name, &generated) {
+                    Ok(paths) => {
+                        for path in paths {
+                            log(&state, &format!("  wrote {}", path.display()));
+                        }
+                    }
+                    Err(e) => {
+                        log_error(&state, &e);
+                        fail(&state, e);
+                        return;
+                    }
                 }
+                mark_done(&state, 5);
             }
 
-            // Step 7: Stage generated files so the flake can see them
-            log(&state, "Staging generated files (git add)...");
+            // Step 6: Stage generated files so the flake can see them
             set_progress(&state, 6);
-            if let Err(e) = disk::git_add_all(&base_path) {
-                let msg = format!("git add failed: {}", e);
-                log_error(&state, &msg);
-                fail(&state, msg);
-                return;
+            if should_run(6) {
+                log(&state, "Staging generated files (git add)...");
+                if let Err(e) = disk::git_add_all(&base_path) {
+                    let msg = format!("git add failed: {}", e);
+                    log_error(&state, &msg);
+                    fail(&state, msg);
+                    return;
+                }
+                mark_done(&state, 6);
             }
 
             // Pre-install hooks
             let mut step_counter = 7;
             for hook in &pre_hooks {
-                log(&state, &format!("Running pre-install hook: {}...", hook));
                 set_progress(&state, step_counter);
-                match disk::run_hook(hook, &host_name, &base_path, &disk_path) {
-                    Ok(output) => {
-                        for line in output.lines() {
-                            let trimmed = line.trim();
-                            if !trimmed.is_empty() {
-                                log(&state, &format!("  [hook] {}", trimmed));
+                if should_run(step_counter) {
+                    log(&state, &format!("Running pre-install hook: {}...", hook));
+                    match disk::run_hook(hook, &host_name, &base_path, &disk_path) {
+                        Ok(output) => {
+                            for line in output.lines() {
+                                let trimmed = line.trim();
+                                if !trimmed.is_empty() {
+                                    log(&state, &format!("  [hook] {}", trimmed));
+                                }
                             }
                         }
+                        Err(e) => {
+                            let msg = format!("Pre-install hook failed: {}", e);
+                            log_error(&state, &msg);
+                            fail(&state, msg);
+                            return;
+                        }
                     }
-                    Err(e) => {
-                        let msg = format!("Pre-install hook failed: {}", e);
-                        log_error(&state, &msg);
-                        fail(&state, msg);
-                        return;
-                    }
+                    mark_done(&state, step_counter);
                 }
                 step_counter += 1;
             }
 
             // Step N: Run nixos-install (stream output in real time)
-            log(&state, "Running nixos-install (this may take a while)...");
             set_progress(&state, step_counter);
-            step_counter += 1;
-            let flake_arg = format!("{}#{}", base_path.to_string_lossy(), host_name);
-            let mut cmd = std::process::Command::new("nixos-install");
-            cmd.args(["--flake", &flake_arg, "--no-root-passwd"])
-                .stdout(std::process::Stdio::null())
-                .stderr(std::process::Stdio::piped());
-            if accept_flake_config {
-                cmd.env("NIX_CONFIG", "accept-flake-config = true");
-            }
-
-            match cmd.spawn() {
-                Ok(mut child) => {
-                    // Stream stderr line-by-line (nixos-install/nix build outputs to stderr)
-                    if let Some(stderr) = child.stderr.take() {
-                        let reader = std::io::BufReader::new(stderr);
-                        for line in reader.lines() {
-                            if let Ok(line) = line {
-                                let trimmed = line.trim().to_string();
-                                if !trimmed.is_empty() {
-                                    if let Ok(mut s) = state.lock() {
-                                        s.log.push(trimmed.clone());
-                                    }
-                                    if let Ok(mut f) = OpenOptions::new()
-                                        .create(true)
-                                        .append(true)
-                                        .open(LOG_FILE)
-                                    {
-                                        let _ = writeln!(f, "{}", trimmed);
-                                    }
+            if should_run(step_counter) {
+                log(&state, "Running nixos-install (this may take a while)...");
+                if !extra_install_args.is_empty() {
+                    log(&state, &format!("  extra args: {}", extra_install_args.join(" ")));
+                }
+                let flake_arg = nix::flake_ref(&base_path, &flake_attr);
+                let mut cmd = std::process::Command::new("nixos-install");
+                cmd.current_dir(&base_path)
+                    .args(["--flake", &flake_arg, "--no-root-passwd"])
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped());
+                if offline {
+                    cmd.args(["--option", "substitute", "false"]);
+                }
+                cmd.args(&extra_install_args);
+                if let Some(nix_config) =
+                    nix_config_env(accept_flake_config, extra_nix_config.as_deref())
+                {
+                    cmd.env("NIX_CONFIG", nix_config);
+                }
+
+                match cmd.spawn() {
+                    Ok(mut child) => {
+                        // Stream both pipes line-by-line. nix build output goes
+                        // to stderr, activation script output to stdout; each
+                        // pipe gets its own reader so neither can block the other.
+                        let readers = [
+                            child.stdout.take().map(|out| {
+                                spawn_log_reader(out, "[out] ", Arc::clone(&state), log_file.clone())
+                            }),
+                            child.stderr.take().map(|err| {
+                                spawn_log_reader(err, "[err] ", Arc::clone(&state), log_file.clone())
+                            }),
+                        ];
+                        for reader in readers.into_iter().flatten() {
+                            let _ = reader.join();
+                        }
+
+                        match child.wait() {
+                            Ok(status) if status.success() => {}
+                            Ok(status) => {
+                                let msg = format!(
+                                    "nixos-install failed with exit code {:?}",
+                                    status.code()
+                                );
+                                log_error(&state, &msg);
+                                if let Ok(mut s) = state.lock() {
+                                    s.diagnostics = Some(stderr_tail(&s.log));
                                 }
+                                fail(&state, msg);
+                                return;
+                            }
+                            Err(e) => {
+                                let msg = format!("Failed to wait for nixos-install: {}", e);
+                                log_error(&state, &msg);
+                                fail(&state, msg);
+                                return;
                             }
                         }
                     }
-
-                    match child.wait() {
-                        Ok(status) if status.success() => {}
-                        Ok(status) => {
-                            let msg = format!(
-                                "nixos-install failed with exit code {:?}",
-                                status.code()
-                            );
-                            log_error(&state, &msg);
-                            fail(&state, msg);
-                            return;
-                        }
-                        Err(e) => {
-                            let msg = format!("Failed to wait for nixos-install: {}", e);
-                            log_error(&state, &msg);
-                            fail(&state, msg);
-                            return;
-                        }
+                    Err(e) => {
+                        let msg = format!("Failed to run nixos-install: {}", e);
+                        log_error(&state, &msg);
+                        fail(&state, msg);
+                        return;
                     }
                 }
-                Err(e) => {
-                    let msg = format!("Failed to run nixos-install: {}", e);
+                mark_done(&state, step_counter);
+            }
+            step_counter += 1;
+
+            set_progress(&state, step_counter);
+            if should_run(step_counter) {
+                log(&state, &format!("Copying repository to /mnt{}/...", repo_dir));
+                if let Err(e) = disk::copy_repo_to_target(
+                    &base_path,
+                    &repo_dir,
+                    repo_owner.as_deref(),
+                    copy_git,
+                ) {
+                    let msg = format!("Failed to copy repo to target: {}", e);
                     log_error(&state, &msg);
                     fail(&state, msg);
                     return;
                 }
+                if copy_git && disk::is_shallow_repo(&base_path) {
+                    log(
+                        &state,
+                        &format!(
+                            "  note: {0} is a shallow clone. Run \
+                             `git -C {0} fetch --unshallow` after booting for full history.",
+                            target_repo
+                        ),
+                    );
+                }
+                mark_done(&state, step_counter);
             }
-
-            set_progress(&state, step_counter);
             step_counter += 1;
-            log(&state, "Copying repository to /mnt/etc/nixos/...");
-            if let Err(e) = disk::copy_repo_to_target(&base_path) {
-                let msg = format!("Failed to copy repo to target: {}", e);
-                log_error(&state, &msg);
-                fail(&state, msg);
-                return;
+
+            // Standalone Home Manager: build each user's activation package
+            // into the new system, activated on first boot. The system itself
+            // is installed by now, so a failure only costs the user a manual
+            // `home-manager switch`
+            if !hm_activation_users.is_empty() {
+                set_progress(&state, step_counter);
+                if should_run(step_counter) {
+                    let nix_config =
+                        nix_config_env(accept_flake_config, extra_nix_config.as_deref());
+                    for user in &hm_activation_users {
+                        log(&state, &format!("Building Home Manager for {}...", user));
+                        match nix::build_home_activation(
+                            &base_path,
+                            user,
+                            &host_name,
+                            offline,
+                            nix_config.as_deref(),
+                        ) {
+                            Ok(path) => {
+                                log(&state, &format!("  activated on first boot: {}", path))
+                            }
+                            Err(e) => {
+                                for line in e.lines() {
+                                    log(&state, &format!("  warning: {}", line));
+                                }
+                                log(
+                                    &state,
+                                    &format!(
+                                        "  warning: run `home-manager switch` as {} after \
+                                         booting to activate it",
+                                        user
+                                    ),
+                                );
+                            }
+                        }
+                    }
+                    mark_done(&state, step_counter);
+                }
+                step_counter += 1;
             }
 
             // Post-install hooks
             for hook in &post_hooks {
-                log(&state, &format!("Running post-install hook: {}...", hook));
                 set_progress(&state, step_counter);
-                match disk::run_hook(hook, &host_name, &base_path, &disk_path) {
-                    Ok(output) => {
-                        for line in output.lines() {
-                            let trimmed = line.trim();
-                            if !trimmed.is_empty() {
-                                log(&state, &format!("  [hook] {}", trimmed));
+                if should_run(step_counter) {
+                    log(&state, &format!("Running post-install hook: {}...", hook));
+                    match disk::run_hook(hook, &host_name, &base_path, &disk_path) {
+                        Ok(output) => {
+                            for line in output.lines() {
+                                let trimmed = line.trim();
+                                if !trimmed.is_empty() {
+                                    log(&state, &format!("  [hook] {}", trimmed));
+                                }
                             }
                         }
+                        Err(e) => {
+                            let msg = format!("Post-install hook failed: {}", e);
+                            log_error(&state, &msg);
+                            fail(&state, msg);
+                            return;
+                        }
                     }
-                    Err(e) => {
-                        let msg = format!("Post-install hook failed: {}", e);
-                        log_error(&state, &msg);
-                        fail(&state, msg);
-                        return;
-                    }
+                    mark_done(&state, step_counter);
                 }
                 step_counter += 1;
             }
@@ -1250,17 +4224,59 @@ impl App {
         });
     }
 
+    /// Copy the in-memory install log to the system clipboard and report
+    /// the outcome via the status popup.
+    pub fn copy_install_log(&mut self) {
+        let text = self.install_log.join("\n");
+        self.set_status(match disk::copy_to_clipboard(&text) {
+            Ok(tool) => format!(
+                "Copied {} log lines to the clipboard ({}).",
+                self.install_log.len(),
+                tool
+            ),
+            Err(e) => format!("Could not copy log: {}", e),
+        });
+    }
+
+    /// Save a timestamped copy of the log file to the current directory and
+    /// report the outcome via the status popup.
+    pub fn export_install_log(&mut self) {
+        let dest_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        self.set_status(
+            match disk::export_log(&self.log_file, &dest_dir) {
+                Ok(path) => format!("Log saved to {}", path.display()),
+                Err(e) => format!("Could not save log: {}", e),
+            },
+        );
+    }
+
+    /// Reopen the failure details popup. Failures before nixos-install have
+    /// no stderr tail; the popup then just points at the log.
+    pub fn show_install_diagnostics(&mut self) {
+        let stderr = self
+            .shared_install
+            .as_ref()
+            .and_then(|shared| shared.lock().ok())
+            .and_then(|s| s.diagnostics.clone());
+        self.install_diagnostics = Some(stderr.unwrap_or_default());
+        self.diagnostics_scroll = 0;
+    }
+
     /// Copy state from the background installation thread into App fields.
     /// Called each frame from the event loop during Step::Installing.
     pub fn sync_install_state(&mut self) {
+        let was_finished = self.install_done || self.install_error.is_some();
+        let mut diagnostics = None;
         if let Some(shared) = &self.shared_install {
             match shared.lock() {
                 Ok(s) => {
                     self.install_log = s.log.clone();
                     self.install_progress = s.progress;
+                    self.install_step_percent = s.step_percent;
                     self.install_total = s.total;
                     self.install_error = s.error.clone();
                     self.install_done = s.done;
+                    diagnostics = s.diagnostics.clone();
                 }
                 Err(_) => {
                     // Mutex poisoned — the install thread panicked
@@ -1269,48 +4285,170 @@ impl App {
                 }
             }
         }
+        if !was_finished {
+            if self.install_done {
+                self.finish_notice = Some("NixOS install complete");
+            } else if self.install_error.is_some() {
+                self.finish_notice = Some("NixOS install failed");
+                // Opened once on failure; `d` brings it back after closing
+                self.install_diagnostics = diagnostics;
+                self.diagnostics_scroll = 0;
+            }
+        }
+    }
+
+    /// The steps the wizard walks through given the choices made so far
+    /// (preset vs custom host, partition mode, swap type, config-only).
+    /// Repeated steps (more users, more partitions) appear once.
+    fn step_path(&self) -> Vec<Step> {
+        let mut path = Vec::new();
+        if self.repo_url.is_some() {
+            path.push(Step::CloningRepo);
+        }
+        if self.show_welcome() {
+            path.push(Step::Welcome);
+        }
+        if self.repo_is_empty() {
+            path.push(Step::EmptyRepo);
+        }
+        path.push(Step::SelectPreset);
+        if self.is_custom {
+            path.extend([
+                Step::HostName,
+                Step::SelectNixosModules,
+                Step::SelectSystemPackages,
+                Step::Bootloader,
+                Step::Kernel,
+                Step::Gpu,
+            ]);
+        } else if self.preset_config.is_some() {
+            path.extend([Step::SelectNixosModules, Step::SelectSystemPackages]);
+        }
+        path.extend([
+            Step::SshKey,
+            Step::CreateUser,
+            Step::UserShell,
+            Step::AddAnotherUser,
+            Step::ReviewUsers,
+        ]);
+        if self.home_manager {
+            path.extend([Step::SelectHmModules, Step::SelectUserPackages]);
+        }
+        let mut password_steps = vec![
+            Step::RootPassword,
+            Step::RootPasswordConfirm,
+            Step::UserPassword,
+            Step::UserPasswordConfirm,
+        ];
+        if self.root_password_policy() == RootPasswordPolicy::Disable {
+            password_steps.drain(..2);
+        }
+        if self.hashed_passwords() {
+            path.extend(password_steps.clone());
+        }
+        if self.config_only {
+            path.push(Step::Complete);
+            return path;
+        }
+        path.extend([Step::SelectDisk, Step::PartitionModeSelect]);
+        match self.partition_mode {
+            PartitionMode::FullDisk | PartitionMode::TmpfsRoot => {
+                path.extend([Step::EfiSize, Step::RootFs]);
+                if self.tmpfs_root() {
+                    path.push(Step::PersistSize);
+                }
+                path.push(Step::SwapMode);
+                match self.swap_mode_cursor {
+                    0 | 1 => path.push(Step::SwapSize),
+                    2 => path.push(Step::ZramPercent),
+                    _ => {}
+                }
+            }
+            PartitionMode::Custom => {
+                path.push(Step::CustomPartitionMount);
+                if !self.extra_disks.is_empty() {
+                    path.push(Step::CustomPartitionDisk);
+                }
+                path.extend([
+                    Step::CustomPartitionSize,
+                    Step::CustomPartitionFs,
+                    Step::CustomPartitionFormat,
+                    Step::CustomPartitionAnother,
+                ]);
+            }
+        }
+        path.extend([Step::Confirm, Step::Installing]);
+        if !self.hashed_passwords() {
+            path.extend(password_steps);
+        }
+        path.push(Step::Complete);
+        path
     }
 
     /// Get the current step number (1-indexed) for the progress bar.
+    /// Side screens share the number of the step they belong to.
     pub fn step_number(&self) -> usize {
-        match self.step {
-            Step::CloningRepo => 1,
-            Step::SelectPreset => 2,
-            Step::HostName | Step::SelectNixosModules | Step::SelectSystemPackages => 3,
-            Step::CreateUser
-            | Step::AddAnotherUser => 4,
-            Step::SelectHmModules | Step::SelectUserPackages => 5,
-            Step::SelectDisk => 6,
-            Step::PartitionModeSelect
-            | Step::SwapSize
-            | Step::CustomPartitionMount
-            | Step::CustomPartitionSize
-            | Step::CustomPartitionFs
-            | Step::CustomPartitionAnother => 7,
-            Step::Confirm => 8,
-            Step::Installing => 9,
-            Step::RootPassword | Step::RootPasswordConfirm => 10,
-            Step::UserPassword | Step::UserPasswordConfirm => 11,
-            Step::Complete => 12,
-        }
+        let step = match self.step {
+            Step::OverwriteHost => Step::HostName,
+            Step::PreviewConfig
+            | Step::EncryptionPassphrase
+            | Step::EncryptionPassphraseConfirm => Step::Confirm,
+            ref s => s.clone(),
+        };
+        self.step_path()
+            .iter()
+            .position(|s| *s == step)
+            .map_or(1, |i| i + 1)
     }
 
     pub fn total_steps(&self) -> usize {
-        12
+        self.step_path().len()
+    }
+
+    /// Overall wizard progress in 0.0..=1.0. While installing, the install
+    /// sub-steps fill in the gap between this step and the next.
+    pub fn progress_ratio(&self) -> f64 {
+        let total = self.total_steps() as f64;
+        let done = self.step_number() as f64;
+        if self.step == Step::Installing && self.install_total > 0 {
+            ((done - 1.0 + self.install_ratio()) / total).min(1.0)
+        } else {
+            done / total
+        }
+    }
+
+    /// Install progress in 0.0..=1.0. A step that reports how far it is
+    /// counts partially, so long ones (formatting a large disk) keep moving.
+    pub fn install_ratio(&self) -> f64 {
+        progress_ratio(self.install_progress, self.install_total, self.install_step_percent)
     }
 
     /// Step title for the header.
     pub fn step_title(&self) -> String {
-        match self.step {
+        self.title_of(&self.step)
+    }
+
+    /// Title of any step, as the header would show it.
+    pub fn title_of(&self, step: &Step) -> String {
+        match step {
             Step::CloningRepo => "Cloning Repository".to_string(),
+            Step::Welcome => "Welcome".to_string(),
+            Step::EmptyRepo => "Repository Looks Empty".to_string(),
             Step::SelectPreset => "Select Host Preset".to_string(),
             Step::HostName => "Enter Host Name".to_string(),
+            Step::OverwriteHost => "Host Already Exists".to_string(),
             Step::SelectNixosModules => "Select NixOS Modules".to_string(),
             Step::SelectSystemPackages => "Select System Packages".to_string(),
-            Step::CreateUser => {
-                let n = self.users.len() + 1;
-                format!("Create User #{}", n)
-            }
+            Step::Bootloader => "Bootloader".to_string(),
+            Step::Kernel => "Kernel".to_string(),
+            Step::Gpu => "Graphics Driver".to_string(),
+            Step::SshKey => "SSH Access".to_string(),
+            Step::CreateUser => match self.editing_user {
+                Some(index) if index < self.users.len() => {
+                    format!("Edit User '{}'", self.users[index].username)
+                }
+                _ => format!("Create User #{}", self.users.len() + 1),
+            },
             Step::UserPassword => {
                 if self.password_user_index < self.users.len() {
                     format!("Set Password for '{}'", self.users[self.password_user_index].username)
@@ -1325,20 +4463,37 @@ impl App {
                     "Confirm User Password".to_string()
                 }
             }
+            Step::UserShell => match self.shell_user() {
+                Some(index) => format!("Login Shell for '{}'", self.users[index].username),
+                None => "Login Shell".to_string(),
+            },
             Step::AddAnotherUser => "Add Another User?".to_string(),
+            Step::ReviewUsers => "Review Users".to_string(),
             Step::SelectHmModules => "Select Home Manager Modules".to_string(),
             Step::SelectUserPackages => "Select User Packages".to_string(),
             Step::SelectDisk => "Select Installation Disk".to_string(),
             Step::PartitionModeSelect => "Partition Mode".to_string(),
+            Step::EfiSize => "EFI Partition Size".to_string(),
+            Step::RootFs if self.tmpfs_root() => "Filesystem for /persist and /nix".to_string(),
+            Step::RootFs => "Root Filesystem".to_string(),
+            Step::PersistSize => "Persist Partition Size".to_string(),
+            Step::SwapMode => "Swap".to_string(),
+            Step::ZramPercent => "zram Size".to_string(),
             Step::SwapSize => "Swap Size".to_string(),
             Step::CustomPartitionMount => "Partition Mount Point".to_string(),
+            Step::CustomPartitionDisk => "Partition Disk".to_string(),
             Step::CustomPartitionSize => "Partition Size".to_string(),
             Step::CustomPartitionFs => "Partition Filesystem".to_string(),
+            Step::CustomPartitionFormat => "Format Partition?".to_string(),
             Step::CustomPartitionAnother => "Add Another Partition?".to_string(),
             Step::Confirm => "Confirm Installation".to_string(),
+            Step::PreviewConfig => "Preview Generated Files".to_string(),
+            Step::EncryptionPassphrase => "Disk Encryption Passphrase".to_string(),
+            Step::EncryptionPassphraseConfirm => "Confirm Disk Encryption Passphrase".to_string(),
             Step::Installing => "Installing NixOS".to_string(),
             Step::RootPassword => "Set Root Password".to_string(),
             Step::RootPasswordConfirm => "Confirm Root Password".to_string(),
+            Step::Complete if self.config_only => "Configuration Generated".to_string(),
             Step::Complete => "Installation Complete".to_string(),
         }
     }
@@ -1347,15 +4502,491 @@ impl App {
     pub fn current_input_ref(&self) -> Option<&str> {
         match self.step {
             Step::HostName => Some(&self.host_name_input),
+            Step::SshKey => Some(&self.ssh_key_input),
             Step::CreateUser => Some(&self.current_username),
             Step::UserPassword => Some(&self.current_password),
             Step::UserPasswordConfirm => Some(&self.current_password_confirm),
+            Step::EfiSize => Some(&self.efi_size_input),
+            Step::PersistSize => Some(&self.persist_size_input),
             Step::SwapSize => Some(&self.swap_size_input),
+            Step::ZramPercent => Some(&self.zram_percent_input),
             Step::CustomPartitionMount => Some(&self.part_mount_input),
             Step::CustomPartitionSize => Some(&self.part_size_input),
             Step::RootPassword => Some(&self.root_password),
             Step::RootPasswordConfirm => Some(&self.root_password_confirm),
+            Step::EncryptionPassphrase => Some(&self.encryption_passphrase),
+            Step::EncryptionPassphraseConfirm => Some(&self.encryption_passphrase_confirm),
             _ => None,
         }
     }
 }
+
+/// Initial bootloader cursor: the configured bootloader, else systemd-boot
+/// on UEFI and GRUB on BIOS.
+fn default_bootloader_cursor(cfg: &InstallerConfig, uefi: bool) -> usize {
+    match cfg.bootloader {
+        Some(nix::Bootloader::SystemdBoot) => 0,
+        Some(nix::Bootloader::Grub) => 1,
+        None if uefi => 0,
+        None => 1,
+    }
+}
+
+/// Whether an install run that resumes after `resume_after` completed steps
+/// runs step `n`. Steps 3 and 4 run as a unit and are both gated on 4.
+fn runs_step(resume_after: usize, n: usize) -> bool {
+    n > resume_after
+}
+
+/// Whether a run resuming after `resume_after` completed steps reruns step 2
+/// on top of a failed attempt, and has to undo its mounts first.
+fn undoes_partial_mount(resume_after: usize) -> bool {
+    resume_after == 1
+}
+
+/// Rough strength of a password or passphrase, shown while it's typed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PasswordStrength {
+    Weak,
+    Fair,
+    Strong,
+}
+
+/// Judge `password` by its length and how many character classes
+/// (lowercase, uppercase, digits, others) it mixes. Long passphrases are
+/// strong even in a single class.
+pub fn password_strength(password: &str) -> PasswordStrength {
+    let len = password.chars().count();
+    let classes = [
+        password.chars().any(|c| c.is_lowercase()),
+        password.chars().any(|c| c.is_uppercase()),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ]
+    .iter()
+    .filter(|&&has| has)
+    .count();
+    if len >= 20 || (len >= 12 && classes >= 3) {
+        PasswordStrength::Strong
+    } else if len >= 8 && classes >= 2 {
+        PasswordStrength::Fair
+    } else {
+        PasswordStrength::Weak
+    }
+}
+
+/// Why a user loaded from the preset can't be changed in the wizard.
+fn existing_user_notice(username: &str) -> String {
+    format!(
+        "'{}' is defined by user-{}.nix in the repo, which is kept as it is. Edit that file \
+         to change the user.",
+        username, username
+    )
+}
+
+/// Initial kernel cursor: the configured kernel, else the nixpkgs default.
+fn default_kernel_cursor(cfg: &InstallerConfig) -> usize {
+    cfg.kernel
+        .and_then(|k| nix::Kernel::ALL.iter().position(|&c| c == k))
+        .map_or(0, |i| i + 1)
+}
+
+/// Render the files generated for a host without writing anything:
+/// `configuration.nix` (custom hosts, and edited presets given their
+/// `settings.preset_config`) and one `user-<name>.nix` per user. Returns (file name,
+/// content) pairs.
+pub fn generate_files(
+    host_name: &str,
+    is_custom: bool,
+    nixos_modules: &[NixModule],
+    system_packages: &[NixModule],
+    users: &[UserEntry],
+    hm_base_modules: &[String],
+    settings: &nix::HostSettings,
+) -> Vec<(String, String)> {
+    let mut files = Vec::new();
+
+    if is_custom {
+        let usernames: Vec<String> = users.iter().map(|u| u.username.clone()).collect();
+        let config = nix::generate_configuration_nix(
+            host_name,
+            nixos_modules,
+            system_packages,
+            &usernames,
+            settings,
+        );
+        files.push(("configuration.nix".to_string(), config));
+    } else if let Some(preset_config) = &settings.preset_config {
+        let new_users: Vec<String> =
+            users.iter().filter(|u| !u.existing).map(|u| u.username.clone()).collect();
+        let config = nix::update_preset_imports(
+            preset_config,
+            host_name,
+            nixos_modules,
+            system_packages,
+            &new_users,
+            settings,
+        )
+        .unwrap_or_else(|| preset_config.to_string());
+        files.push(("configuration.nix".to_string(), config));
+    }
+
+    // Users whose files the preset already has are kept as they are
+    for (i, user) in users.iter().filter(|u| !u.existing).enumerate() {
+        // Preset hosts have no generated configuration.nix, so root's hash
+        // goes into the first generated user's module
+        let root_hashed_password = if !is_custom && i == 0 {
+            settings.root_hashed_password.clone()
+        } else {
+            None
+        };
+        let user_settings = nix::UserSettings {
+            groups: user.groups.clone(),
+            shell: user.shell,
+            // Host-wide, like root's password above
+            autologin_user: if !is_custom && i == 0 {
+                settings.autologin_user.clone()
+            } else {
+                None
+            },
+            hashed_password: user.hashed_password.clone(),
+            root_hashed_password,
+            refs: settings.refs.clone(),
+            home_manager: settings.home_manager,
+            hm_mode: settings.hm_mode,
+            flake_attr: settings.flake_attr.clone(),
+            authorized_keys: match &settings.ssh_authorized_key {
+                Some(key) if i == 0 => vec![key.clone()],
+                _ => Vec::new(),
+            },
+            openssh: !is_custom && i == 0 && settings.ssh_authorized_key.is_some(),
+        };
+        let user_nix = nix::generate_user_nix(
+            host_name,
+            &user.username,
+            &user_settings,
+            &user.hm_modules,
+            &user.package_modules,
+            hm_base_modules,
+        );
+        files.push((format!("user-{}.nix", user.username), user_nix));
+    }
+
+    files
+}
+
+/// Write files produced by `generate_files` into the host directory of the
+/// repo. Returns the paths of the written files.
+fn write_generated_files(
+    base_path: &std::path::Path,
+    layout: &nix::ModuleLayout,
+    host_name: &str,
+    files: &[(String, String)],
+) -> Result<Vec<PathBuf>, String> {
+    files
+        .iter()
+        .map(|(file_name, content)| {
+            nix::write_host_file(base_path, layout, host_name, file_name, content)
+        })
+        .collect()
+}
+
+/// `NIX_CONFIG` for nix commands run on the host's flake: accept-flake-config
+/// plus the `extra_nix_config` lines, or `None` if there is nothing to set.
+fn nix_config_env(accept_flake_config: bool, extra_nix_config: Option<&str>) -> Option<String> {
+    let mut nix_config: Vec<&str> = Vec::new();
+    if accept_flake_config {
+        nix_config.push("accept-flake-config = true");
+    }
+    if let Some(extra) = extra_nix_config.map(str::trim) {
+        if !extra.is_empty() {
+            nix_config.push(extra);
+        }
+    }
+    Some(nix_config.join("\n")).filter(|c| !c.is_empty())
+}
+
+/// Everything the `prebuild_check` thread needs to build the host's system
+/// before the disk is touched.
+struct PrebuildJob {
+    /// Resolve the flake inputs (`check_flake_inputs`).
+    check_inputs: bool,
+    /// Build `system.build.toplevel` (`prebuild_check`).
+    build: bool,
+    base_path: PathBuf,
+    layout: nix::ModuleLayout,
+    host_name: String,
+    flake_attr: String,
+    generated: Vec<(String, String)>,
+    /// (label, mount point, fsType) of every planned partition.
+    partitions: Vec<(String, String, String)>,
+    tmpfs_root: bool,
+    /// Path of the planned swap file in the installed system.
+    swap_file: Option<String>,
+    offline: bool,
+    nix_config: Option<String>,
+    cancel: Arc<nix::CancelHandle>,
+}
+
+impl PrebuildJob {
+    /// Check the flake inputs, then build `system.build.toplevel` in a
+    /// temporary copy of the repo holding the generated files and a
+    /// placeholder hardware config. The repo itself is left untouched.
+    fn run(&self) -> Result<(), String> {
+        if self.check_inputs {
+            nix::check_flake_inputs(&self.base_path, self.nix_config.as_deref(), &self.cancel)?;
+        }
+        if !self.build || self.cancel.is_cancelled() {
+            return Ok(());
+        }
+        let copy = disk::temp_repo_copy(&self.base_path)
+            .map_err(|e| format!("Failed to copy the repo for the build: {}", e))?;
+        let result = self.build_in(&copy);
+        let _ = std::fs::remove_dir_all(&copy);
+        result
+    }
+
+    /// Add the generated files to the repo copy at `repo` and build it.
+    fn build_in(&self, repo: &Path) -> Result<(), String> {
+        write_generated_files(repo, &self.layout, &self.host_name, &self.generated)?;
+        let live = disk::generate_live_hardware_config()
+            .map_err(|e| format!("Hardware config generation failed: {}", e))?;
+        let mut hw_config = nix::prebuild_hardware_config(&live, &self.partitions);
+        if self.tmpfs_root {
+            hw_config = nix::tmpfs_root_hardware_config(&hw_config);
+        }
+        if let Some(device) = &self.swap_file {
+            hw_config = nix::swap_file_hardware_config(&hw_config, device);
+        }
+        nix::write_hardware_config(repo, &self.layout, &self.host_name, &hw_config)?;
+        disk::git_add_all(repo).map_err(|e| format!("git add failed: {}", e))?;
+        nix::build_system_toplevel(
+            repo,
+            &self.flake_attr,
+            self.offline,
+            self.nix_config.as_deref(),
+            &self.cancel,
+        )
+    }
+}
+
+/// Number of stderr lines kept for the nixos-install diagnostics popup.
+const DIAGNOSTIC_LINES: usize = 30;
+
+/// The last `DIAGNOSTIC_LINES` stderr lines streamed into the install log.
+fn stderr_tail(log: &[String]) -> Vec<String> {
+    let mut tail: Vec<String> = log
+        .iter()
+        .rev()
+        .filter_map(|l| l.strip_prefix("[err] "))
+        .take(DIAGNOSTIC_LINES)
+        .map(str::to_string)
+        .collect();
+    tail.reverse();
+    tail
+}
+
+/// Stream lines from a child process pipe into the shared install log (and
+/// `log_file`) on a separate thread. Each line is tagged with `prefix` so
+/// interleaved stdout/stderr output can be told apart.
+fn spawn_log_reader<R: std::io::Read + Send + 'static>(
+    pipe: R,
+    prefix: &'static str,
+    state: Arc<Mutex<InstallState>>,
+    log_file: PathBuf,
+) -> std::thread::JoinHandle<()> {
+    std::thread::spawn(move || {
+        let reader = std::io::BufReader::new(pipe);
+        for line in reader.lines().map_while(Result::ok) {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let tagged = format!("{}{}", prefix, trimmed);
+            if let Ok(mut s) = state.lock() {
+                s.log.push(tagged.clone());
+            }
+            if let Ok(mut f) = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&log_file)
+            {
+                let _ = writeln!(f, "{}", tagged);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::FsType;
+    use crate::disk::PartitionPlan;
+    use crate::test_support::{module, temp_path, test_app};
+
+    fn encryptable_app() -> App {
+        let mut app = test_app(temp_path("encryption-repo"));
+        app.partitions = [("/boot", FsType::Fat32), ("/", FsType::Ext4)]
+            .into_iter()
+            .map(|(mount_point, fs_type)| PartitionPlan {
+                disk: "/dev/sda".to_string(),
+                label: mount_point.to_string(),
+                mount_point: mount_point.to_string(),
+                size_mb: None,
+                fs_type,
+                format: true,
+            })
+            .collect();
+        app.step = Step::Confirm;
+        app
+    }
+
+    #[test]
+    fn mismatched_passphrases_are_asked_again() {
+        let mut app = encryptable_app();
+        app.step = Step::EncryptionPassphraseConfirm;
+        app.encryption_passphrase = "correct horse".to_string();
+        app.encryption_passphrase_confirm = "correct hose".to_string();
+        app.confirm_encryption_passphrase_confirm();
+        assert!(app.encryption_mismatch);
+        assert!(app.encryption_passphrase.is_empty());
+        assert!(app.encryption_passphrase_confirm.is_empty());
+        assert_eq!(app.step, Step::EncryptionPassphrase);
+        assert!(!app.encrypt_root);
+
+        app.encryption_passphrase = "correct horse".to_string();
+        app.encryption_passphrase_confirm = "correct horse".to_string();
+        app.confirm_encryption_passphrase_confirm();
+        assert!(!app.encryption_mismatch);
+        assert!(app.encryption_passphrase_confirm.is_empty());
+        assert_eq!(app.encryption_passphrase, "correct horse");
+        assert_eq!(app.step, Step::Confirm);
+        assert!(app.encrypt_root);
+    }
+
+    #[test]
+    fn toggling_encryption_asks_for_the_passphrase() {
+        let mut app = encryptable_app();
+        app.toggle_encryption();
+        assert_eq!(app.step, Step::EncryptionPassphrase);
+        assert!(!app.encrypt_root);
+
+        // An empty passphrase is refused
+        app.confirm_encryption_passphrase();
+        assert_eq!(app.step, Step::EncryptionPassphrase);
+
+        app.encryption_passphrase = "secret".to_string();
+        app.confirm_encryption_passphrase();
+        assert_eq!(app.step, Step::EncryptionPassphraseConfirm);
+        app.encryption_passphrase_confirm = "secret".to_string();
+        app.confirm_encryption_passphrase_confirm();
+        assert_eq!(app.step, Step::Confirm);
+        assert!(app.encrypt_root);
+
+        // Toggling again turns it off and forgets the passphrase
+        app.toggle_encryption();
+        assert_eq!(app.step, Step::Confirm);
+        assert!(!app.encrypt_root);
+        assert!(app.encryption_passphrase.is_empty());
+
+        // A kept root can't be encrypted
+        app.partitions[1].format = false;
+        app.toggle_encryption();
+        assert_eq!(app.step, Step::Confirm);
+        assert!(!app.encrypt_root);
+    }
+
+    #[test]
+    fn password_strength_weighs_length_and_variety() {
+        assert_eq!(password_strength(""), PasswordStrength::Weak);
+        assert_eq!(password_strength("hunter2"), PasswordStrength::Weak);
+        assert_eq!(password_strength("password"), PasswordStrength::Weak);
+        assert_eq!(password_strength("hunter22"), PasswordStrength::Fair);
+        assert_eq!(password_strength("Tr0ub4dor&3x"), PasswordStrength::Strong);
+        assert_eq!(password_strength("correct horse battery staple"), PasswordStrength::Strong);
+    }
+
+    #[test]
+    fn retries_run_the_steps_after_the_completed_ones() {
+        let steps = |resume_after| -> Vec<usize> {
+            [1, 2, 4, 5, 6, 7].into_iter().filter(|&n| runs_step(resume_after, n)).collect()
+        };
+        assert_eq!(steps(0), [1, 2, 4, 5, 6, 7]);
+        assert_eq!(steps(1), [2, 4, 5, 6, 7]);
+        assert_eq!(steps(2), [4, 5, 6, 7]);
+        assert_eq!(steps(4), [5, 6, 7]);
+        assert_eq!(steps(6), [7]);
+        assert!(steps(7).is_empty());
+
+        // Only a rerun of step 2 has half-finished mounts to undo: a first
+        // run has none, and later steps need the mounts
+        assert!(!undoes_partial_mount(0));
+        assert!(undoes_partial_mount(1));
+        for resume_after in 2..=7 {
+            assert!(!undoes_partial_mount(resume_after));
+        }
+    }
+
+    #[test]
+    fn install_ratio_counts_partial_steps() {
+        assert_eq!(progress_ratio(0, 0, None), 0.0);
+        assert_eq!(progress_ratio(2, 4, None), 0.5);
+        // Step 3 of 4 at 50%: two steps done plus half of the third
+        assert_eq!(progress_ratio(3, 4, Some(50)), 0.625);
+        assert_eq!(progress_ratio(1, 4, Some(0)), 0.0);
+        assert_eq!(progress_ratio(5, 4, None), 1.0);
+    }
+
+    #[test]
+    fn exported_plans_load_back_unchanged() {
+        let plan = InstallPlan {
+            host_name: "laptop".to_string(),
+            is_custom: true,
+            disks: vec!["/dev/nvme0n1".to_string()],
+            partition_mode: PartitionMode::FullDisk,
+            partitions: vec![PartitionPlan {
+                disk: "/dev/nvme0n1".to_string(),
+                label: "root".to_string(),
+                mount_point: "/".to_string(),
+                size_mb: None,
+                fs_type: FsType::Btrfs,
+                format: true,
+            }],
+            zram_percent: Some(50),
+            swap_file_mb: None,
+            bootloader: Some(nix::Bootloader::SystemdBoot),
+            kernel: None,
+            gpu: None,
+            ssh_authorized_key: Some("ssh-ed25519 AAAA me@host".to_string()),
+            password_mode: PasswordMode::Hashed,
+            accept_flake_config: true,
+            secure_wipe: false,
+            fstrim: true,
+            hm_base_modules: vec!["base".to_string()],
+            nixos_modules: vec![module("desktop", true), module("gaming", false)],
+            system_packages: vec![module("dev", true)],
+            users: vec![UserEntry {
+                username: "alice".to_string(),
+                password: "secret".to_string(),
+                groups: vec!["wheel".to_string()],
+                shell: None,
+                hashed_password: Some("$6$hash".to_string()),
+                autologin: false,
+                hm_modules: vec![module("shell", true)],
+                package_modules: Vec::new(),
+                needs_hm_selection: false,
+                existing: false,
+            }],
+        };
+        let expected = serde_json::to_value(&plan).unwrap();
+        assert_eq!(expected["users"][0].get("password"), None);
+        assert_eq!(expected["users"][0].get("hashed_password"), None);
+
+        for file in ["plan.json", "plan.toml"] {
+            let path = Path::new(file);
+            let content = plan.to_file_content(path).unwrap();
+            let loaded = InstallPlan::from_file_content(path, &content).unwrap();
+            assert_eq!(serde_json::to_value(&loaded).unwrap(), expected, "{}", file);
+        }
+    }
+}
@@ -1,11 +1,27 @@
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
+use crate::nix::{AttrRefs, Bootloader, HiddenModules, HmMode, Kernel, ModuleLayout};
 use crate::theme::ThemeName;
 
 /// Default path for the system-wide installer configuration.
 pub const DEFAULT_CONFIG_PATH: &str = "/etc/nixos-installer/config.toml";
 
+/// Suppress non-essential stderr output (--quiet).
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Enable quiet mode: warnings that don't stop the installer are no longer
+/// printed to stderr. Errors still are.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Whether quiet mode is enabled.
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
 /// Custom theme color overrides defined inline in config.toml.
 /// Each field is an RGB hex string like "#89b4fa" or "89b4fa".
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -49,14 +65,67 @@ pub fn parse_hex_color(s: &str) -> Option<(u8, u8, u8)> {
     Some((r, g, b))
 }
 
+/// How user and root passwords are set.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PasswordMode {
+    /// Prompt after nixos-install and set them with `chpasswd` in the target.
+    #[default]
+    Chpasswd,
+    /// Prompt before installing and write `hashedPassword` into the
+    /// generated nix (works with `users.mutableUsers = false`).
+    Hashed,
+}
+
+/// Whether root gets a password.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RootPasswordPolicy {
+    /// Prompt for a root password; it can't be empty.
+    #[default]
+    Require,
+    /// Don't prompt; root's password is locked (`hashedPassword = "!"`).
+    Disable,
+    /// Prompt, but an empty Enter skips it.
+    Optional,
+}
+
+/// What happens when a custom host's directory already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostDirStrategy {
+    /// Ask whether to load it as a preset, overwrite it or pick another name.
+    #[default]
+    Overwrite,
+    /// Use the first free `<host>-2`, `<host>-3`, ... instead.
+    Suffix,
+}
+
 /// Installer-level configuration (lives at /etc/nixos-installer/config.toml or a custom path).
 /// This is the config the user edits via `--init` and loads via `--config`.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct InstallerConfig {
     /// The git repository URL to clone (overrides the built-in default).
     pub repo_url: Option<String>,
 
+    /// Branch or tag to clone instead of the repo's default branch.
+    pub repo_branch: Option<String>,
+
+    /// Access token for cloning a private repo over HTTPS. Never read from or
+    /// written to config.toml; set from --token or GIT_CLONE_TOKEN.
+    #[serde(skip)]
+    pub clone_token: Option<String>,
+
+    /// Write the resolved installation plan to this path (JSON, or TOML for
+    /// a `.toml` extension) before installing. Set from --export-plan.
+    #[serde(skip)]
+    pub export_plan: Option<PathBuf>,
+
+    /// Clone only the last N commits of the repo (`git clone --depth N`).
+    /// Full history if unset.
+    pub clone_depth: Option<u32>,
+
     /// Color theme name (e.g. "catppuccin-mocha", "nord", "dracula", "tokyo-night", "gruvbox").
     pub theme: Option<ThemeName>,
 
@@ -68,6 +137,67 @@ pub struct InstallerConfig {
     /// These are referenced as `self.homeManagerModules.<name>` in the generated nix.
     pub hm_base_modules: Vec<String>,
 
+    /// Don't use Home Manager: skip the HM module and user package steps and
+    /// generate users without a `home-manager.users.<name>` block. Implied
+    /// when the repo has no Home Manager modules. Defaults to false.
+    pub disable_home_manager: Option<bool>,
+
+    /// How Home Manager is used: "nixos-module" (the default; HM is imported
+    /// as a NixOS module) or "standalone" (`homeConfigurations` entries,
+    /// built at install time and activated on first boot).
+    pub hm_mode: Option<HmMode>,
+
+    // ---- Repository layout ----
+
+    /// NixOS module directory, relative to the repo root
+    /// (defaults to "modules/nixosModules").
+    pub nixos_modules_dir: Option<String>,
+
+    /// Home Manager module directory, relative to the repo root
+    /// (defaults to "modules/homeManagerModules").
+    pub hm_modules_dir: Option<String>,
+
+    /// Package set directory, relative to the repo root
+    /// (defaults to "modules/packages").
+    pub packages_dir: Option<String>,
+
+    /// Host directory, relative to the repo root (defaults to "modules/hosts").
+    /// Generated host configurations are written to `<hosts_dir>/<host>/`.
+    pub hosts_dir: Option<String>,
+
+    /// "overwrite" (the default) asks before reusing an existing host
+    /// directory; "suffix" writes a new custom host to `<host>-2`, `<host>-3`,
+    /// ... (and uses that as its hostName) instead.
+    pub host_dir_strategy: Option<HostDirStrategy>,
+
+    /// Name of the host's `nixosConfigurations` attribute, with `{host}`
+    /// replaced by the host name. Defaults to the host name itself.
+    pub flake_attr: Option<String>,
+
+    /// How generated files reference a NixOS module; `{name}` is replaced by
+    /// the module name. Defaults to "self.nixosModules.{name}".
+    pub nixos_module_ref: Option<String>,
+
+    /// How generated files reference a Home Manager module. Defaults to
+    /// "self.homeManagerModules.{name}".
+    pub hm_module_ref: Option<String>,
+
+    /// Module name the flake gives a package set file (`{name}` is the file
+    /// stem). Defaults to "packages-{name}".
+    pub package_module_name: Option<String>,
+
+    /// NixOS modules left out of the selection list: names or `*` globs,
+    /// matched ignoring case. Replaces the default ["home-*", "wsl"].
+    pub hidden_nixos_modules: Option<Vec<String>>,
+
+    /// Home Manager modules left out of the selection list. Replaces the
+    /// default ["home", "home-wsl", "packages-*"].
+    pub hidden_hm_modules: Option<Vec<String>>,
+
+    /// Package set files (by file stem) left out of the selection lists.
+    /// Replaces the default ["*wsl*"].
+    pub hidden_packages: Option<Vec<String>>,
+
     // ---- Defaults (pre-fill TUI fields) ----
 
     /// Default hostname to pre-fill in the hostname input.
@@ -76,14 +206,70 @@ pub struct InstallerConfig {
     /// Default username to pre-fill when creating the first user.
     pub default_username: Option<String>,
 
-    /// Default swap size in GiB (pre-fills the swap size input for full-disk mode).
+    /// SSH public key (or a path to a public key file) pre-filled for the
+    /// first user. When set, the SSH daemon is enabled on the new system.
+    pub ssh_authorized_key: Option<String>,
+
+    /// Supplementary groups (`extraGroups`) for every created user.
+    /// Defaults to ["wheel"] if unset.
+    pub default_user_groups: Option<Vec<String>>,
+
+    /// Default swap size (pre-fills the swap size input for full-disk mode):
+    /// GiB, or with an `M`/`G` suffix, or "auto" for the size of RAM.
     pub default_swap_size: Option<String>,
 
+    /// Default EFI partition size in MiB for full-disk mode (512 if unset).
+    pub default_efi_size_mb: Option<u64>,
+
+    /// Partition alignment in MiB: the offset of the first partition and the
+    /// boundary every later one starts on. A power of two up to 1024;
+    /// defaults to 1.
+    pub partition_alignment_mb: Option<u64>,
+
+    /// Bootloader preselected for custom hosts ("systemd-boot" or "grub").
+    /// If unset, systemd-boot on UEFI and GRUB on BIOS.
+    pub bootloader: Option<Bootloader>,
+
+    /// Kernel preselected for custom hosts ("lts", "latest" or "zen"). If
+    /// unset, no `boot.kernelPackages` is written (the nixpkgs default).
+    pub kernel: Option<Kernel>,
+
+    /// `system.stateVersion` for custom hosts (e.g. "24.05"). Defaults to
+    /// the release of the running installer.
+    pub state_version: Option<String>,
+
+    /// How passwords are set: "chpasswd" (after install, the default) or
+    /// "hashed" (written into the generated nix as `hashedPassword`).
+    pub password_mode: Option<PasswordMode>,
+
+    /// "require" (the default), "disable" to lock root's password, or
+    /// "optional" to let root's password prompt be skipped.
+    pub root_password_policy: Option<RootPasswordPolicy>,
+
+    /// Initial state of the "accept flake config" checkbox on the Confirm
+    /// screen. Defaults to true.
+    pub accept_flake_config: Option<bool>,
+
+    /// Initial state of the "secure wipe" checkbox on the Confirm screen:
+    /// erase the whole disk (blkdiscard on SSDs, zeros on HDDs) before
+    /// partitioning. Defaults to false.
+    pub secure_wipe: Option<bool>,
+
+    /// Check the selected disks with `smartctl -H` and warn before installing
+    /// onto one that reports failing. Skipped if smartctl isn't installed.
+    /// Defaults to true.
+    pub smart_check: Option<bool>,
+
     // ---- Branding ----
 
     /// Custom title shown in the TUI header. Defaults to "NixOS Installer".
     pub branding_title: Option<String>,
 
+    /// Start with a welcome screen showing the repo, boot mode and any
+    /// problems found in the repo layout. Defaults to true; when false,
+    /// layout warnings are shown as a popup over preset selection instead.
+    pub show_welcome: Option<bool>,
+
     // ---- Install hooks ----
 
     /// Scripts to run before nixos-install (after partitioning and config generation).
@@ -93,23 +279,152 @@ pub struct InstallerConfig {
     /// Scripts to run after nixos-install completes (before password setup).
     /// Each entry is a path to an executable script.
     pub post_install_hooks: Vec<String>,
+
+    // ---- nixos-install ----
+
+    /// Extra nix.conf lines added to the `NIX_CONFIG` passed to nixos-install
+    /// (e.g. `substituters = file:///mnt/cache` for a local binary cache).
+    pub extra_nix_config: Option<String>,
+
+    /// Air-gapped install: pass `--option substitute false` to nixos-install
+    /// so nothing is fetched from binary caches.
+    pub offline: bool,
+
+    /// Extra arguments appended to the nixos-install command line
+    /// (e.g. ["--max-jobs", "8", "--cores", "4"]).
+    pub nixos_install_extra_args: Vec<String>,
+
+    /// Build the host's system closure before touching the disk, so
+    /// evaluation and build errors surface while it can still be fixed.
+    /// Defaults to false.
+    pub prebuild_check: Option<bool>,
+
+    /// Run `nix flake metadata` on the repo when Install is pressed, so an
+    /// unreachable input or a broken lock file is reported before the disk
+    /// is touched. Defaults to false.
+    pub check_flake_inputs: Option<bool>,
+
+    /// Ring the terminal bell when the installation finishes or fails (the
+    /// terminal title is updated either way). Defaults to true.
+    pub notify_on_finish: Option<bool>,
+
+    /// Where the repo is copied to in the installed system. Defaults to
+    /// /etc/nixos; under /home it is owned by the first user.
+    pub target_repo_path: Option<String>,
+
+    /// Copy the repo's `.git` directory along with it. Defaults to true.
+    pub copy_git_history: Option<bool>,
+
+    // ---- Logging ----
+
+    /// Pre-fill the wizard with the choices made in the previous run
+    /// (~/.cache/nixos-installer/last.toml). Off unless set to true;
+    /// --no-remember turns it off for a single run.
+    pub remember_selections: Option<bool>,
+
+    /// Path of the persistent installation log. Defaults to /tmp/nixos-installer.log.
+    pub log_file: Option<String>,
 }
 
-impl Default for InstallerConfig {
-    fn default() -> Self {
-        Self {
-            repo_url: None,
-            theme: None,
-            theme_custom: None,
-            hm_base_modules: Vec::new(),
-            default_hostname: None,
-            default_username: None,
-            default_swap_size: None,
-            branding_title: None,
-            pre_install_hooks: Vec::new(),
-            post_install_hooks: Vec::new(),
+impl InstallerConfig {
+    /// The module directories of the repo: the configured `*_dir` fields,
+    /// falling back to the nixos-dots layout for any that are unset.
+    pub fn module_layout(&self) -> ModuleLayout {
+        let mut layout = ModuleLayout::default();
+        let dirs = [
+            (&self.nixos_modules_dir, &mut layout.nixos_modules),
+            (&self.hm_modules_dir, &mut layout.hm_modules),
+            (&self.packages_dir, &mut layout.packages),
+            (&self.hosts_dir, &mut layout.hosts),
+        ];
+        for (configured, dir) in dirs {
+            if let Some(path) = configured.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+                *dir = PathBuf::from(path.trim_end_matches('/'));
+            }
+        }
+        layout
+    }
+
+    /// The attribute templates for generated files, falling back to the
+    /// nixos-dots conventions for any that are unset.
+    pub fn attr_refs(&self) -> AttrRefs {
+        let mut refs = AttrRefs::default();
+        let templates = [
+            (&self.nixos_module_ref, &mut refs.nixos_module),
+            (&self.hm_module_ref, &mut refs.hm_module),
+            (&self.package_module_name, &mut refs.package),
+        ];
+        for (configured, template) in templates {
+            if let Some(t) = configured.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+                *template = t.to_string();
+            }
+        }
+        refs
+    }
+
+    /// The configured partition alignment in MiB (1 if unset), or an error
+    /// if it isn't a power of two between 1 and 1024.
+    pub fn partition_alignment_mb(&self) -> Result<u64, String> {
+        match self.partition_alignment_mb {
+            None => Ok(1),
+            Some(mb) if mb.is_power_of_two() && mb <= 1024 => Ok(mb),
+            Some(mb) => Err(format!(
+                "partition_alignment_mb = {} is invalid: use a power of two from 1 to 1024",
+                mb
+            )),
+        }
+    }
+
+    /// The `nixosConfigurations` attribute for `host_name`: the configured
+    /// `flake_attr` with `{host}` filled in, or the host name. An error if
+    /// the result isn't a plain Nix identifier.
+    pub fn flake_attr(&self, host_name: &str) -> Result<String, String> {
+        let attr = match &self.flake_attr {
+            Some(template) => template.trim().replace("{host}", host_name),
+            None => return Ok(host_name.to_string()),
+        };
+        let valid_start = attr.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+        let valid_rest =
+            attr.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''));
+        if valid_start && valid_rest {
+            Ok(attr)
+        } else {
+            Err(format!(
+                "flake_attr = \"{}\" gives '{}', which isn't a valid attribute name (letters, \
+                 digits, '_', '-' and '\'', starting with a letter or '_')",
+                self.flake_attr.as_deref().unwrap_or_default(),
+                attr
+            ))
         }
     }
+
+    /// Absolute path of the repo in the installed system: the configured
+    /// `target_repo_path`, or /etc/nixos.
+    pub fn target_repo_path(&self) -> String {
+        match self.target_repo_path.as_deref().map(str::trim) {
+            Some(path) if !path.trim_matches('/').is_empty() => {
+                format!("/{}", path.trim_matches('/'))
+            }
+            _ => "/etc/nixos".to_string(),
+        }
+    }
+
+    /// The hidden module patterns: the configured lists, or the built-in
+    /// defaults for any that are unset.
+    pub fn hidden_modules(&self) -> HiddenModules {
+        let mut hidden = HiddenModules::default();
+        let lists = [
+            (&self.hidden_nixos_modules, &mut hidden.nixos),
+            (&self.hidden_hm_modules, &mut hidden.hm),
+            (&self.hidden_packages, &mut hidden.packages),
+        ];
+        for (configured, patterns) in lists {
+            if let Some(list) = configured {
+                *patterns = list.clone();
+            }
+        }
+        hidden
+    }
 }
 
 /// Load the installer config from a given path.
@@ -119,7 +434,9 @@ pub fn load_config(path: &Path) -> InstallerConfig {
         Ok(content) => match toml::from_str::<InstallerConfig>(&content) {
             Ok(cfg) => cfg,
             Err(e) => {
-                eprintln!("Warning: Failed to parse {}: {}", path.display(), e);
+                if !is_quiet() {
+                    eprintln!("Warning: Failed to parse {}: {}", path.display(), e);
+                }
                 InstallerConfig::default()
             }
         },
@@ -152,6 +469,49 @@ pub fn load_repo_config(base_path: &Path, existing: &InstallerConfig) -> Install
                         merged.theme_custom = Some(tc);
                     }
                 }
+                if repo_cfg.disable_home_manager.is_some() {
+                    merged.disable_home_manager = repo_cfg.disable_home_manager;
+                }
+                if repo_cfg.hm_mode.is_some() {
+                    merged.hm_mode = repo_cfg.hm_mode;
+                }
+                // Repo-level module layout overrides if set
+                if repo_cfg.nixos_modules_dir.is_some() {
+                    merged.nixos_modules_dir = repo_cfg.nixos_modules_dir;
+                }
+                if repo_cfg.hm_modules_dir.is_some() {
+                    merged.hm_modules_dir = repo_cfg.hm_modules_dir;
+                }
+                if repo_cfg.packages_dir.is_some() {
+                    merged.packages_dir = repo_cfg.packages_dir;
+                }
+                if repo_cfg.hosts_dir.is_some() {
+                    merged.hosts_dir = repo_cfg.hosts_dir;
+                }
+                if repo_cfg.host_dir_strategy.is_some() {
+                    merged.host_dir_strategy = repo_cfg.host_dir_strategy;
+                }
+                if repo_cfg.flake_attr.is_some() {
+                    merged.flake_attr = repo_cfg.flake_attr;
+                }
+                if repo_cfg.nixos_module_ref.is_some() {
+                    merged.nixos_module_ref = repo_cfg.nixos_module_ref;
+                }
+                if repo_cfg.hm_module_ref.is_some() {
+                    merged.hm_module_ref = repo_cfg.hm_module_ref;
+                }
+                if repo_cfg.package_module_name.is_some() {
+                    merged.package_module_name = repo_cfg.package_module_name;
+                }
+                if repo_cfg.hidden_nixos_modules.is_some() {
+                    merged.hidden_nixos_modules = repo_cfg.hidden_nixos_modules;
+                }
+                if repo_cfg.hidden_hm_modules.is_some() {
+                    merged.hidden_hm_modules = repo_cfg.hidden_hm_modules;
+                }
+                if repo_cfg.hidden_packages.is_some() {
+                    merged.hidden_packages = repo_cfg.hidden_packages;
+                }
                 // Repo-level defaults override if set
                 if repo_cfg.default_hostname.is_some() {
                     merged.default_hostname = repo_cfg.default_hostname;
@@ -159,13 +519,67 @@ pub fn load_repo_config(base_path: &Path, existing: &InstallerConfig) -> Install
                 if repo_cfg.default_username.is_some() {
                     merged.default_username = repo_cfg.default_username;
                 }
+                if repo_cfg.ssh_authorized_key.is_some() {
+                    merged.ssh_authorized_key = repo_cfg.ssh_authorized_key;
+                }
+                if repo_cfg.default_user_groups.is_some() {
+                    merged.default_user_groups = repo_cfg.default_user_groups;
+                }
                 if repo_cfg.default_swap_size.is_some() {
                     merged.default_swap_size = repo_cfg.default_swap_size;
                 }
+                if repo_cfg.default_efi_size_mb.is_some() {
+                    merged.default_efi_size_mb = repo_cfg.default_efi_size_mb;
+                }
+                if repo_cfg.partition_alignment_mb.is_some() {
+                    merged.partition_alignment_mb = repo_cfg.partition_alignment_mb;
+                }
+                if repo_cfg.bootloader.is_some() {
+                    merged.bootloader = repo_cfg.bootloader;
+                }
+                if repo_cfg.kernel.is_some() {
+                    merged.kernel = repo_cfg.kernel;
+                }
+                if repo_cfg.state_version.is_some() {
+                    merged.state_version = repo_cfg.state_version;
+                }
+                if repo_cfg.password_mode.is_some() {
+                    merged.password_mode = repo_cfg.password_mode;
+                }
+                if repo_cfg.root_password_policy.is_some() {
+                    merged.root_password_policy = repo_cfg.root_password_policy;
+                }
+                if repo_cfg.accept_flake_config.is_some() {
+                    merged.accept_flake_config = repo_cfg.accept_flake_config;
+                }
+                if repo_cfg.secure_wipe.is_some() {
+                    merged.secure_wipe = repo_cfg.secure_wipe;
+                }
+                if repo_cfg.smart_check.is_some() {
+                    merged.smart_check = repo_cfg.smart_check;
+                }
+                if repo_cfg.prebuild_check.is_some() {
+                    merged.prebuild_check = repo_cfg.prebuild_check;
+                }
+                if repo_cfg.check_flake_inputs.is_some() {
+                    merged.check_flake_inputs = repo_cfg.check_flake_inputs;
+                }
+                if repo_cfg.target_repo_path.is_some() {
+                    merged.target_repo_path = repo_cfg.target_repo_path;
+                }
+                if repo_cfg.copy_git_history.is_some() {
+                    merged.copy_git_history = repo_cfg.copy_git_history;
+                }
                 if repo_cfg.branding_title.is_some() {
                     merged.branding_title = repo_cfg.branding_title;
                 }
+                if repo_cfg.show_welcome.is_some() {
+                    merged.show_welcome = repo_cfg.show_welcome;
+                }
                 // Repo-level hooks override if non-empty
+                if repo_cfg.extra_nix_config.is_some() {
+                    merged.extra_nix_config = repo_cfg.extra_nix_config;
+                }
                 if !repo_cfg.pre_install_hooks.is_empty() {
                     merged.pre_install_hooks = repo_cfg.pre_install_hooks;
                 }
@@ -175,7 +589,9 @@ pub fn load_repo_config(base_path: &Path, existing: &InstallerConfig) -> Install
                 merged
             }
             Err(e) => {
-                eprintln!("Warning: Failed to parse repo config.toml: {}", e);
+                if !is_quiet() {
+                    eprintln!("Warning: Failed to parse repo config.toml: {}", e);
+                }
                 existing.clone()
             }
         },
@@ -183,6 +599,50 @@ pub fn load_repo_config(base_path: &Path, existing: &InstallerConfig) -> Install
     }
 }
 
+/// Choices from the previous run, used to pre-fill the wizard. Unlike the
+/// install state this is best effort: a missing or unreadable file just
+/// means nothing is pre-filled.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LastSelections {
+    pub host_name: Option<String>,
+    pub username: Option<String>,
+    /// Names of the selected NixOS modules and system package sets.
+    pub nixos_modules: Vec<String>,
+    pub system_packages: Vec<String>,
+    pub swap_size: Option<String>,
+    pub efi_size_mb: Option<u64>,
+}
+
+/// Location of the remembered selections: `$XDG_CACHE_HOME` or
+/// `~/.cache`, under `nixos-installer/last.toml`.
+fn last_selections_path() -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+    Some(cache_dir.join("nixos-installer").join("last.toml"))
+}
+
+/// Load the selections remembered from the previous run, if any.
+pub fn load_last_selections() -> Option<LastSelections> {
+    let content = std::fs::read_to_string(last_selections_path()?).ok()?;
+    toml::from_str(&content).ok()
+}
+
+/// Remember this run's selections for the next one.
+pub fn save_last_selections(selections: &LastSelections) -> Result<(), String> {
+    let path = last_selections_path().ok_or("Neither XDG_CACHE_HOME nor HOME is set")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+    }
+    let content = toml::to_string(selections)
+        .map_err(|e| format!("Failed to serialize selections: {}", e))?;
+    std::fs::write(&path, content)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
 /// Generate the default config.toml content for `--init`.
 pub fn generate_default_config() -> String {
     let available = ThemeName::all_names().join(", ");
@@ -194,6 +654,13 @@ pub fn generate_default_config() -> String {
 # If not set, the built-in default is used.
 # repo_url = "https://github.com/ItzEmoji/nixos-dotfiles.git"
 
+# Branch or tag to clone (defaults to the repository's default branch).
+# repo_branch = "release"
+
+# Shallow clone: only fetch the last N commits (faster on slow connections).
+# Equivalent to --shallow when set to 1. Full history if not set.
+# clone_depth = 1
+
 # Color theme for the installer TUI.
 # Available themes: {available}
 # theme = "catppuccin-mocha"
@@ -202,12 +669,68 @@ pub fn generate_default_config() -> String {
 # (never shown in the selection screen).
 # hm_base_modules = ["home"]
 
+# Install without Home Manager: users are created without HM imports and the
+# Home Manager module / user package steps are skipped. This is automatic if
+# the repository has no Home Manager modules.
+# disable_home_manager = true
+
+# How Home Manager is wired into the flake:
+#   "nixos-module" - the flake exports nixosModules.home-manager; each user's
+#                    HM modules go into home-manager.users.<name>.imports and
+#                    are activated by nixos-install (default)
+#   "standalone"   - the flake has a home-manager input and no HM NixOS
+#                    module; each user gets homeConfigurations."<user>@<host>",
+#                    whose activation package is built into the new system
+#                    after nixos-install and activated on its first boot
+# hm_mode = "standalone"
+
+# ---- Repository layout ----
+# Where the module directories live, relative to the repository root.
+# Only needed if your flake doesn't use the modules/... layout below.
+
+# nixos_modules_dir = "modules/nixosModules"
+# hm_modules_dir = "modules/homeManagerModules"
+# packages_dir = "modules/packages"
+# hosts_dir = "modules/hosts"
+
+# When a custom host's directory already exists:
+#   "overwrite" - ask whether to load it, overwrite it or rename (default)
+#   "suffix"    - write to <host>-2, <host>-3, ... instead, with that as the
+#                 networking.hostName, so earlier generations are kept
+# host_dir_strategy = "suffix"
+
+# The nixosConfigurations attribute a host is installed from (and generated
+# as), if your flake doesn't name them after the hostname. {{host}} is
+# replaced by the host name.
+# flake_attr = "{{host}}-desktop"
+
+# How the generated configuration.nix / user-<name>.nix refer to flake
+# outputs. {{name}} is replaced by the module name. Generated user modules are
+# defined as the NixOS reference with "self." replaced by "flake."; a reference
+# that doesn't start with "self." uses the default for user modules.
+# nixos_module_ref = "self.nixosModules.{{name}}"
+# hm_module_ref = "self.homeManagerModules.{{name}}"
+# Module name of a file in packages_dir ({{name}} is the file stem).
+# package_module_name = "packages-{{name}}"
+
+# Modules hidden from the selection lists. Entries are names or globs with *
+# (matched ignoring case). Setting a list replaces its default, so repeat
+# the defaults you want to keep.
+# hidden_nixos_modules = ["home-*", "wsl"]
+# hidden_hm_modules = ["home", "home-wsl", "packages-*"]
+# Package set files, by file name without .nix.
+# hidden_packages = ["*wsl*"]
+
 # ---- Branding ----
 
 # Custom title displayed in the installer header.
 # Defaults to "NixOS Installer" if not set.
 # branding_title = "MyOrg NixOS Installer"
 
+# Start with a welcome screen showing the repo, boot mode and any problems
+# found in the repo layout (default: true).
+# show_welcome = true
+
 # ---- Defaults ----
 # Pre-fill TUI fields with these values. The user can still change them.
 
@@ -217,9 +740,68 @@ pub fn generate_default_config() -> String {
 # Default username for the first user.
 # default_username = "admin"
 
-# Default swap size in GiB (for full-disk partitioning mode).
+# SSH public key, or a path to a .pub file, authorized for the first user.
+# Setting one also enables the SSH daemon (services.openssh.enable).
+# ssh_authorized_key = "ssh-ed25519 AAAA... you@laptop"
+
+# Groups (extraGroups) given to every created user. Defaults to ["wheel"].
+# default_user_groups = ["wheel", "networkmanager", "video", "docker"]
+
+# Default swap size (for full-disk partitioning mode). A plain number is GiB;
+# "512M" / "8G" set the unit explicitly and "auto" matches the installed RAM.
 # default_swap_size = "4"
 
+# Default EFI partition size in MiB (for full-disk partitioning mode).
+# 512 is the default; 1024 is recommended if you keep many generations in /boot.
+# default_efi_size_mb = 1024
+
+# Partition alignment in MiB. The first partition starts at this offset and
+# each following one is rounded up to a multiple of it. Some 4Kn drives and
+# SSDs with large erase blocks prefer e.g. 4 or 8. Must be a power of two
+# up to 1024; defaults to 1.
+# partition_alignment_mb = 4
+
+# Bootloader preselected for custom hosts: "systemd-boot" or "grub".
+# Defaults to systemd-boot on UEFI systems and GRUB on BIOS systems.
+# bootloader = "grub"
+
+# Kernel preselected for custom hosts: "lts" (linuxPackages), "latest"
+# (linuxPackages_latest) or "zen" (linuxPackages_zen). Defaults to the
+# nixpkgs default kernel.
+# kernel = "latest"
+
+# system.stateVersion written into custom hosts. Defaults to the NixOS
+# release of the install media; only set it to pin an older release.
+# state_version = "24.05"
+
+# How user and root passwords are set:
+#   "chpasswd" - prompt after nixos-install and set them in the installed
+#                system (default)
+#   "hashed"   - prompt before installing and write hashedPassword = "..."
+#                into the generated nix (for users.mutableUsers = false)
+# password_mode = "hashed"
+
+# Root's password:
+#   "require"  - prompt for one, it can't be empty (default)
+#   "disable"  - don't prompt; root login by password is locked with
+#                users.users.root.hashedPassword = "!" (use sudo instead)
+#   "optional" - prompt, but an empty Enter skips it
+# root_password_policy = "disable"
+
+# Whether "accept flake config" starts checked on the Confirm screen
+# (it can still be toggled per run with Space). Defaults to true.
+# accept_flake_config = false
+
+# Whether "secure wipe" starts checked on the Confirm screen (toggle with w).
+# SSDs are erased with blkdiscard in seconds; spinning disks are overwritten
+# with zeros, which can take hours on large drives. Defaults to false.
+# secure_wipe = true
+
+# Read the SMART health of the selected disks (smartctl -H, when installed)
+# and show it on the Confirm screen. Installing onto a disk that reports
+# failing needs Enter twice. Defaults to true.
+# smart_check = false
+
 # ---- Install Hooks ----
 # Scripts to run at specific points during installation.
 # Each entry is a path to an executable script.
@@ -235,6 +817,60 @@ pub fn generate_default_config() -> String {
 # Scripts to run after nixos-install completes (before password setup).
 # post_install_hooks = ["/etc/nixos-installer/hooks/post-install.sh"]
 
+# ---- nixos-install ----
+
+# Extra nix.conf settings passed to nixos-install via NIX_CONFIG, e.g. to
+# use a local binary cache for offline/air-gapped installs.
+# extra_nix_config = """
+# substituters = file:///run/media/cache
+# require-sigs = false
+# """
+
+# Don't substitute from any binary cache (same as --offline). Everything not
+# already in the store or a configured local substituter is built locally.
+# offline = true
+
+# Extra arguments appended to nixos-install (after --flake ... --no-root-passwd).
+# nixos_install_extra_args = ["--max-jobs", "8", "--cores", "4"]
+
+# Build the host's system (system.build.toplevel) when Install is pressed,
+# before anything is written to disk. Evaluation or build errors are shown on
+# the Confirm screen instead of failing halfway through the install. The
+# build runs in the live system's store, so it needs enough RAM. Defaults to
+# false.
+# prebuild_check = true
+
+# Check that every flake input can be fetched and the lock file is valid
+# (`nix flake metadata`) when Install is pressed, before anything is written
+# to disk. Catches unreachable or broken inputs early. Defaults to false.
+# check_flake_inputs = true
+
+# Ring the terminal bell when the installation finishes or fails, so you
+# notice from another window. The terminal title is set to "NixOS install
+# complete" / "NixOS install failed" either way. Defaults to true.
+# notify_on_finish = false
+
+# Where the repo is copied to in the installed system (default /etc/nixos).
+# A path under /home is handed to the first user, so the flake can be edited
+# and pushed without root. With a tmpfs root it goes under /persist.
+# target_repo_path = "/home/alice/nixos-config"
+
+# Whether the copy includes .git (the full history after a non-shallow
+# clone). Without it the installed system gets a plain directory of files.
+# Defaults to true.
+# copy_git_history = false
+
+# ---- Logging ----
+
+# Remember the host name, modules, user name and partition sizes chosen in the
+# last run and pre-fill them next time (~/.cache/nixos-installer/last.toml).
+# Off by default; --no-remember disables it again for a single run.
+# remember_selections = true
+
+# Path of the persistent installation log (overridden by --log-file).
+# Falls back to /tmp/nixos-installer.log if the path is not writable.
+# log_file = "/var/log/nixos-installer.log"
+
 # ---- Custom Theme Colors ----
 # Override individual colors of the selected base theme.
 # Colors are RGB hex values (with or without '#' prefix).
@@ -255,6 +891,44 @@ pub fn generate_default_config() -> String {
     )
 }
 
+/// Set `theme = "<name>"` in the config file at `path`: an existing
+/// top-level `theme` line is replaced, otherwise one is added at the top, so
+/// the rest of the file (comments included) stays as it is. Creates the file
+/// if it doesn't exist.
+pub fn save_theme(path: &Path, theme: &ThemeName) -> Result<(), String> {
+    let content = std::fs::read_to_string(path).unwrap_or_default();
+    let theme_line = format!("theme = \"{}\"", theme);
+    let is_theme_key = |line: &str| {
+        line.strip_prefix("theme")
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    };
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut replaced = false;
+    let mut in_table = false;
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        // Keys after the first [table] header belong to that table
+        in_table |= trimmed.starts_with('[');
+        if !in_table && !replaced && is_theme_key(trimmed) {
+            lines.push(theme_line.clone());
+            replaced = true;
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    if !replaced {
+        lines.insert(0, theme_line);
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(path, lines.join("\n") + "\n")
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
 /// Write the default config to /etc/nixos-installer/config.toml (or a custom path).
 /// Creates the directory if it doesn't exist.
 pub fn init_config(path: &Path) -> Result<(), String> {
@@ -1,20 +1,80 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::process::Command;
 use std::sync::{Arc, Mutex};
 
+/// Log file that `run_cmd` echoes each command line to (verbose mode).
+static COMMAND_LOG: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Enable verbose mode: every command run via `run_cmd` is appended to
+/// `log_file` as `$ <cmd> <args>` before it executes.
+pub fn enable_command_logging(log_file: PathBuf) {
+    if let Ok(mut target) = COMMAND_LOG.lock() {
+        *target = Some(log_file);
+    }
+}
+
 /// Shared state for the git clone progress.
 #[derive(Debug, Clone)]
 pub struct CloneState {
     pub log: Vec<String>,
     pub phase: String,
     pub percent: u8,
+    /// Bytes received so far (e.g. "12.3 MiB"), while receiving objects.
+    pub transferred: Option<String>,
+    /// Current transfer rate (e.g. "5.0 MiB/s"), while receiving objects.
+    pub rate: Option<String>,
     pub error: Option<String>,
     pub done: bool,
 }
 
+/// Parse the "1.2 MiB | 5.0 MiB/s" part of a git progress line such as
+/// `Receiving objects:  42% (123/456), 1.2 MiB | 5.0 MiB/s` into
+/// (transferred, rate).
+fn parse_transfer(line: &str) -> Option<(String, String)> {
+    let (before, after) = line.split_once(" | ")?;
+    let transferred = before.rsplit(", ").next()?.trim();
+    let rate = after.trim_end_matches(", done.").trim();
+    if transferred.is_empty() || rate.is_empty() {
+        return None;
+    }
+    Some((transferred.to_string(), rate.to_string()))
+}
+
+/// Options for `clone_repo`.
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    /// Branch or tag to check out (`--branch <ref>`).
+    pub branch: Option<String>,
+    /// Shallow clone depth (`--depth N --single-branch`).
+    pub depth: Option<u32>,
+    /// Access token for private HTTPS repos. Handed to git through a
+    /// credential helper so it never appears in argv, logs or .git/config.
+    pub token: Option<String>,
+}
+
+/// Strip any `user:password@` credentials from a URL before logging it.
+pub fn redact_url(url: &str) -> String {
+    let Some(scheme_end) = url.find("://") else {
+        return url.to_string();
+    };
+    let rest = &url[scheme_end + 3..];
+    let host_end = rest.find('/').unwrap_or(rest.len());
+    match rest[..host_end].rfind('@') {
+        Some(at) => format!("{}***@{}", &url[..scheme_end + 3], &rest[at + 1..]),
+        None => url.to_string(),
+    }
+}
+
 /// Clone a git repository to `dest` with progress tracking.
 /// The progress is reported via the shared `CloneState`.
 /// Uses `git clone --progress` and parses stderr for progress info.
-pub fn clone_repo(url: &str, dest: &std::path::Path, state: Arc<Mutex<CloneState>>) {
+pub fn clone_repo(
+    url: &str,
+    dest: &std::path::Path,
+    options: &CloneOptions,
+    state: Arc<Mutex<CloneState>>,
+) {
     use std::io::Read;
 
     let log = |state: &Arc<Mutex<CloneState>>, msg: &str| {
@@ -23,13 +83,40 @@ pub fn clone_repo(url: &str, dest: &std::path::Path, state: Arc<Mutex<CloneState
         }
     };
 
-    log(&state, &format!("Cloning {}...", url));
+    let mut header = format!("Cloning {}", redact_url(url));
+    if let Some(ref branch) = options.branch {
+        header.push_str(&format!(" @ {}", branch));
+    }
+    if let Some(depth) = options.depth {
+        header.push_str(&format!(" (depth {})", depth));
+    }
+    log(&state, &format!("{}...", header));
     if let Ok(mut s) = state.lock() {
         s.phase = "Starting clone...".to_string();
     }
 
     let mut cmd = Command::new("git");
-    cmd.args(["clone", "--progress", url])
+    cmd.args(["clone", "--progress"]);
+    if let Some(ref branch) = options.branch {
+        cmd.args(["--branch", branch]);
+    }
+    // Never block on an interactive credential prompt inside the TUI
+    cmd.env("GIT_TERMINAL_PROMPT", "0");
+    // SSH URLs are left alone so an ssh-agent can handle authentication
+    if let (Some(token), true) = (&options.token, url.starts_with("https://")) {
+        log(&state, "Using access token for HTTPS authentication.");
+        cmd.env("GIT_CLONE_TOKEN", token)
+            .env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "credential.helper")
+            .env(
+                "GIT_CONFIG_VALUE_0",
+                "!f() { echo username=x-access-token; echo \"password=$GIT_CLONE_TOKEN\"; }; f",
+            );
+    }
+    if let Some(depth) = options.depth {
+        cmd.args(["--depth", &depth.to_string(), "--single-branch"]);
+    }
+    cmd.arg(url)
         .arg(dest.as_os_str())
         .stdout(std::process::Stdio::null())
         .stderr(std::process::Stdio::piped());
@@ -69,6 +156,10 @@ pub fn clone_repo(url: &str, dest: &std::path::Path, state: Arc<Mutex<CloneState
                                         s.percent = pct;
                                     }
                                 }
+                                // Only "Receiving objects" carries byte counts
+                                let (transferred, rate) = parse_transfer(&line).unzip();
+                                s.transferred = transferred;
+                                s.rate = rate;
                                 s.log.push(line);
                             }
                         }
@@ -115,7 +206,13 @@ pub fn clone_repo(url: &str, dest: &std::path::Path, state: Arc<Mutex<CloneState
             }
         }
         Err(e) => {
-            let msg = format!("Failed to run git clone: {}", e);
+            let msg = if e.kind() == std::io::ErrorKind::NotFound {
+                "git is not installed. Run `nix-shell -p git` and restart the installer, \
+                 or pass a local repo path: nixos-installer /path/to/repo"
+                    .to_string()
+            } else {
+                format!("Failed to run git clone: {}", e)
+            };
             log(&state, &msg);
             if let Ok(mut s) = state.lock() {
                 s.error = Some(msg);
@@ -124,32 +221,138 @@ pub fn clone_repo(url: &str, dest: &std::path::Path, state: Arc<Mutex<CloneState
     }
 }
 
+/// Whether `bin` is an executable file in one of the `PATH` directories.
+pub fn command_exists(bin: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| {
+            std::fs::metadata(dir.join(bin))
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+    })
+}
+
+/// Check for the binaries the wizard depends on before it starts, so every
+/// missing one is reported at once. Returns a message listing them, or
+/// `None` if everything is present.
+pub fn check_required_tools(needs_git: bool, needs_disk_tools: bool) -> Option<String> {
+    // (binary, nixpkgs package, what it's used for)
+    let mut tools = Vec::new();
+    // Module discovery uses fd and falls back to find; either one will do
+    if !command_exists("fd") {
+        tools.push(("find", "findutils", "module discovery, unless fd is installed"));
+    }
+    if needs_git {
+        tools.push(("git", "git", "cloning the configuration repo"));
+    }
+    if needs_disk_tools {
+        tools.push(("lsblk", "util-linux", "listing disks"));
+        tools.push(("wipefs", "util-linux", "wiping the target disk"));
+        tools.push(("parted", "parted", "partitioning"));
+    }
+
+    let missing: Vec<_> = tools.into_iter().filter(|(bin, _, _)| !command_exists(bin)).collect();
+    if missing.is_empty() {
+        return None;
+    }
+
+    let mut packages: Vec<&str> = missing.iter().map(|(_, pkg, _)| *pkg).collect();
+    packages.dedup();
+    let mut msg = String::from("Missing required tools:\n");
+    for (bin, _, purpose) in &missing {
+        msg.push_str(&format!("  - {} (needed for {})\n", bin, purpose));
+    }
+    msg.push_str(&format!(
+        "Install them with `nix-shell -p {}` and restart the installer.",
+        packages.join(" ")
+    ));
+    if needs_git && missing.iter().any(|(bin, _, _)| *bin == "git") {
+        msg.push_str("\nOr pass a local repo path to skip cloning: nixos-installer /path/to/repo");
+    }
+    Some(msg)
+}
+
+/// Verify every binary the install needs for this partition plan is present
+/// before anything touches the disk. mkfs tools are only required for the
+/// filesystems actually in the plan. Returns the missing binaries.
+pub fn preflight(partitions: &[PartitionPlan], encrypt_root: bool) -> Result<(), Vec<String>> {
+    let mut tools = vec![
+        "parted",
+        "wipefs",
+        "mount",
+        "nixos-generate-config",
+        "nixos-install",
+        "nixos-enter",
+    ];
+    for part in partitions.iter().filter(|p| p.format) {
+        tools.push(part.fs_type.mkfs_command());
+        if part.fs_type == FsType::Swap {
+            tools.push("swapon");
+        }
+    }
+    if encrypt_root {
+        tools.push("cryptsetup");
+    }
+
+    let mut missing: Vec<String> = Vec::new();
+    for tool in tools {
+        if !command_exists(tool) && !missing.iter().any(|m| m == tool) {
+            missing.push(tool.to_string());
+        }
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing)
+    }
+}
+
 /// Represents a physical block device detected on the system.
 #[derive(Debug, Clone)]
 pub struct BlockDevice {
-    #[allow(dead_code)]
     pub name: String,       // e.g. "sda", "nvme0n1"
     pub path: String,       // e.g. "/dev/sda"
-    #[allow(dead_code)]
     pub size_bytes: u64,
     pub size_human: String, // e.g. "500G"
     pub model: String,
+    /// Spinning disk (false for SSD/NVMe), see `is_rotational`.
+    pub rotational: bool,
 }
 
 /// Represents a single partition the user wants to create.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PartitionPlan {
+    pub disk: String,        // disk the partition is created on, e.g. "/dev/sda"
     pub label: String,       // user-facing label, e.g. "EFI", "root", "swap"
     pub mount_point: String, // e.g. "/boot", "/", "swap"
     pub size_mb: Option<u64>, // None = fill remaining space
     pub fs_type: FsType,
+    /// Create a new filesystem. When false the existing one is only mounted,
+    /// and the disk keeps its partition table (see `keeps_partition_table`).
+    pub format: bool,
+}
+
+impl PartitionPlan {
+    /// Whether this is the EFI system partition: FAT32 mounted at /boot or
+    /// below it (e.g. /boot/efi in GRUB-style layouts).
+    pub fn is_esp(&self) -> bool {
+        self.fs_type == FsType::Fat32 && self.is_boot_mount()
+    }
+
+    /// Whether this partition is mounted at /boot or below it.
+    pub fn is_boot_mount(&self) -> bool {
+        self.mount_point == "/boot" || self.mount_point.starts_with("/boot/")
+    }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FsType {
     Fat32,
     Ext4,
     Btrfs,
+    Xfs,
     Swap,
 }
 
@@ -159,11 +362,16 @@ impl FsType {
             FsType::Fat32 => "vfat",
             FsType::Ext4 => "ext4",
             FsType::Btrfs => "btrfs",
+            FsType::Xfs => "xfs",
             FsType::Swap => "swap",
         }
     }
 
-    pub const ALL: &[FsType] = &[FsType::Fat32, FsType::Ext4, FsType::Btrfs, FsType::Swap];
+    pub const ALL: &[FsType] =
+        &[FsType::Fat32, FsType::Ext4, FsType::Btrfs, FsType::Xfs, FsType::Swap];
+
+    /// Filesystems offered for the root partition in full-disk mode.
+    pub const ROOT: &[FsType] = &[FsType::Ext4, FsType::Btrfs, FsType::Xfs];
 
     pub fn all() -> &'static [FsType] {
         Self::ALL
@@ -174,9 +382,126 @@ impl FsType {
             FsType::Fat32 => "FAT32 (EFI)",
             FsType::Ext4 => "ext4",
             FsType::Btrfs => "Btrfs",
+            FsType::Xfs => "XFS",
             FsType::Swap => "swap",
         }
     }
+
+    /// The binary that creates this filesystem.
+    pub fn mkfs_command(&self) -> &'static str {
+        match self {
+            FsType::Fat32 => "mkfs.fat",
+            FsType::Ext4 => "mkfs.ext4",
+            FsType::Btrfs => "mkfs.btrfs",
+            FsType::Xfs => "mkfs.xfs",
+            FsType::Swap => "mkswap",
+        }
+    }
+}
+
+/// Whether the live system was booted in UEFI mode.
+pub fn is_uefi() -> bool {
+    std::path::Path::new("/sys/firmware/efi").is_dir()
+}
+
+/// Release of the running NixOS (e.g. "24.05"), from `VERSION_ID` in
+/// /etc/os-release or else `nixos-version`.
+pub fn nixos_release() -> Option<String> {
+    let from_os_release = std::fs::read_to_string("/etc/os-release").ok().and_then(|s| {
+        s.lines()
+            .find_map(|l| l.strip_prefix("VERSION_ID="))
+            .map(|v| v.trim().trim_matches('"').to_string())
+    });
+    let version = match from_os_release {
+        Some(v) if !v.is_empty() => v,
+        _ => {
+            let output = Command::new("nixos-version").output().ok()?;
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+    };
+    // "24.05.1234.abcdef (Uakari)" -> "24.05"
+    let mut parts = version.split(|c: char| c == '.' || c.is_whitespace());
+    let (major, minor) = (parts.next()?, parts.next()?);
+    let numeric = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    (numeric(major) && numeric(minor)).then(|| format!("{}.{}", major, minor))
+}
+
+/// Total installed RAM in bytes, read from `/proc/meminfo`.
+pub fn total_memory_bytes() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemTotal:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Output of `lspci` (PCI devices), used to guess the GPU vendor. None if
+/// pciutils isn't installed.
+pub fn lspci() -> Option<String> {
+    let output = Command::new("lspci").output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Filesystems and swap currently using `disk_path` (e.g. "/dev/sda") or
+/// one of its partitions, as (device, mount point) pairs read from
+/// /proc/mounts and /proc/swaps. Device-mapper devices (LUKS, LVM) count
+/// if they sit on the disk.
+pub fn disk_mounts(disk_path: &str) -> Vec<(String, String)> {
+    let disk_name = disk_path.trim_start_matches("/dev/");
+    let mut in_use = Vec::new();
+
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    for line in mounts.lines() {
+        let mut fields = line.split_whitespace();
+        if let (Some(dev), Some(mount_point)) = (fields.next(), fields.next()) {
+            if device_on_disk(dev, disk_name) {
+                // /proc/mounts escapes spaces in paths as \040
+                in_use.push((dev.to_string(), mount_point.replace("\\040", " ")));
+            }
+        }
+    }
+
+    let swaps = std::fs::read_to_string("/proc/swaps").unwrap_or_default();
+    for line in swaps.lines().skip(1) {
+        if let Some(dev) = line.split_whitespace().next() {
+            if device_on_disk(dev, disk_name) {
+                in_use.push((dev.to_string(), "swap".to_string()));
+            }
+        }
+    }
+
+    in_use
+}
+
+/// Whether the block device `dev` is the disk `disk_name` (e.g. "nvme0n1"),
+/// one of its partitions, or a device-mapper device backed by one.
+fn device_on_disk(dev: &str, disk_name: &str) -> bool {
+    if !dev.starts_with("/dev/") {
+        return false;
+    }
+    // Resolve /dev/disk/by-label/... and /dev/mapper/... symlinks
+    let resolved = std::fs::canonicalize(dev).unwrap_or_else(|_| PathBuf::from(dev));
+    let name = match resolved.file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => return false,
+    };
+    if name.starts_with("dm-") {
+        let slaves = std::fs::read_dir(format!("/sys/block/{}/slaves", name));
+        return slaves.into_iter().flatten().flatten().any(|slave| {
+            let slave = format!("/dev/{}", slave.file_name().to_string_lossy());
+            device_on_disk(&slave, disk_name)
+        });
+    }
+    match name.strip_prefix(disk_name) {
+        // The disk itself, "sda1", or "nvme0n1p1"
+        Some(rest) => {
+            let rest = rest.strip_prefix('p').unwrap_or(rest);
+            rest.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
 }
 
 /// List all block devices (disks, not partitions) using lsblk.
@@ -234,8 +559,10 @@ pub fn list_block_devices() -> Result<Vec<BlockDevice>, String> {
                 return None;
             }
 
+            let path = format!("/dev/{}", name);
             Some(BlockDevice {
-                path: format!("/dev/{}", name),
+                rotational: is_rotational(&path),
+                path,
                 size_human: format_bytes(size_bytes),
                 name,
                 size_bytes,
@@ -256,8 +583,238 @@ fn format_bytes(bytes: u64) -> String {
     }
 }
 
-/// Wipe the disk, create a GPT partition table, and create partitions.
-pub fn partition_disk(disk: &str, partitions: &[PartitionPlan]) -> Result<(), String> {
+/// How `secure_wipe` erases a disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WipeMethod {
+    /// Discard every block with `blkdiscard` (SSD/NVMe; takes seconds).
+    Discard,
+    /// Overwrite the whole disk with zeros using `dd` (spinning disks).
+    Zero,
+}
+
+impl WipeMethod {
+    /// The method suited to `disk_path`: discard on solid-state disks,
+    /// zeroing on spinning ones.
+    pub fn for_disk(disk_path: &str) -> Self {
+        if is_rotational(disk_path) {
+            WipeMethod::Zero
+        } else {
+            WipeMethod::Discard
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            WipeMethod::Discard => "blkdiscard",
+            WipeMethod::Zero => "overwriting with zeros",
+        }
+    }
+}
+
+/// Whether `disk_path` is a spinning disk, according to
+/// /sys/block/<dev>/queue/rotational. Unknown devices count as rotational.
+pub fn is_rotational(disk_path: &str) -> bool {
+    let name = disk_path.trim_start_matches("/dev/");
+    std::fs::read_to_string(format!("/sys/block/{}/queue/rotational", name))
+        .map(|s| s.trim() != "0")
+        .unwrap_or(true)
+}
+
+/// Summary of a disk's SMART data, from `smartctl -H -A`.
+#[derive(Debug, Clone)]
+pub struct SmartHealth {
+    /// The drive's overall health self-assessment passed.
+    pub passed: bool,
+    /// Raw Reallocated_Sector_Ct value (ATA disks only).
+    pub reallocated_sectors: Option<u64>,
+}
+
+/// Read the SMART health of `disk_path`. None if smartctl isn't installed or
+/// the device reports no overall assessment (USB bridges, virtual disks).
+pub fn smart_health(disk_path: &str) -> Option<SmartHealth> {
+    if !command_exists("smartctl") {
+        return None;
+    }
+    let args = ["-H", "-A", disk_path];
+    log_command("smartctl", &args);
+    // The exit status is a bitmask that is also non-zero for failing disks,
+    // so only the output is looked at
+    let output = Command::new("smartctl").args(args).output().ok()?;
+    parse_smart_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_smart_output(output: &str) -> Option<SmartHealth> {
+    let mut passed = None;
+    let mut reallocated_sectors = None;
+    for line in output.lines().map(str::trim) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let Some(result) = line.strip_prefix("SMART overall-health self-assessment test result:")
+        {
+            passed = Some(result.trim() == "PASSED");
+        } else if let Some(status) = line.strip_prefix("SMART Health Status:") {
+            // SCSI/SAS disks
+            passed = Some(status.trim() == "OK");
+        } else if fields.get(1) == Some(&"Reallocated_Sector_Ct") {
+            // ID# ATTRIBUTE_NAME FLAG VALUE WORST THRESH TYPE UPDATED WHEN_FAILED RAW_VALUE
+            reallocated_sectors = fields.get(9).and_then(|v| v.parse().ok());
+        }
+    }
+    Some(SmartHealth { passed: passed?, reallocated_sectors })
+}
+
+/// Erase every block of `disk` before it is partitioned. Progress lines
+/// (one per percent when zeroing) are passed to `on_progress`.
+pub fn secure_wipe(
+    disk: &str,
+    method: WipeMethod,
+    on_progress: &mut dyn FnMut(&str),
+) -> Result<(), String> {
+    match method {
+        WipeMethod::Discard => run_cmd("blkdiscard", &["-f", disk]),
+        WipeMethod::Zero => zero_disk(disk, on_progress),
+    }
+}
+
+/// Overwrite `disk` with zeros, reporting `dd status=progress` output.
+fn zero_disk(disk: &str, on_progress: &mut dyn FnMut(&str)) -> Result<(), String> {
+    use std::io::Read;
+
+    let name = disk.trim_start_matches("/dev/");
+    // /sys/block/<dev>/size is always in 512-byte sectors
+    let total_bytes = std::fs::read_to_string(format!("/sys/block/{}/size", name))
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(|sectors| sectors * 512)
+        .filter(|&b| b > 0);
+
+    let of = format!("of={}", disk);
+    let args = ["if=/dev/zero", of.as_str(), "bs=4M", "oflag=direct", "status=progress"];
+    log_command("dd", &args);
+    let mut child = Command::new("dd")
+        .args(args)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run 'dd': {}", e))?;
+
+    let mut last_pct = None;
+    let mut messages = Vec::new();
+    if let Some(stderr) = child.stderr.take() {
+        // Like git, dd redraws its progress line with \r
+        let mut line_buf = String::new();
+        for byte in std::io::BufReader::new(stderr).bytes() {
+            let Ok(byte) = byte else { break };
+            if byte != b'\r' && byte != b'\n' {
+                line_buf.push(byte as char);
+                continue;
+            }
+            let line = line_buf.trim().to_string();
+            line_buf.clear();
+            if line.is_empty() {
+                continue;
+            }
+            // "1234567890 bytes (1.2 GB, 1.1 GiB) copied, 10 s, 123 MB/s"
+            let written = line
+                .split_whitespace()
+                .next()
+                .and_then(|n| n.parse::<u64>().ok())
+                .filter(|_| line.contains("copied"));
+            match (written, total_bytes) {
+                (Some(written), Some(total)) => {
+                    let pct = (written * 100 / total).min(100);
+                    if last_pct != Some(pct) {
+                        last_pct = Some(pct);
+                        on_progress(&format!("  {:>3}%  {}", pct, line));
+                    }
+                }
+                (Some(_), None) => on_progress(&format!("  {}", line)),
+                _ => messages.push(line),
+            }
+        }
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("Failed to wait for 'dd': {}", e))?;
+    // Without a count, dd writes until the end of the device and then
+    // exits with ENOSPC; that is the expected way for it to finish.
+    if status.success() || messages.iter().any(|l| l.contains("No space left on device")) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Command 'dd' failed with exit code {:?}\n--- stderr ---\n{}",
+            status.code(),
+            messages.join("\n")
+        ))
+    }
+}
+
+/// The disks `partitions` are spread over, in the order they first appear.
+pub fn plan_disks(partitions: &[PartitionPlan]) -> Vec<String> {
+    let mut disks: Vec<String> = Vec::new();
+    for part in partitions {
+        if !disks.contains(&part.disk) {
+            disks.push(part.disk.clone());
+        }
+    }
+    disks
+}
+
+/// Whether `disk` keeps its partition table. Re-creating the table would
+/// lose the partitions marked to keep their data, so a disk with one of
+/// them is not repartitioned: its planned partitions are the existing ones,
+/// the Nth planned partition being partition N.
+pub fn keeps_partition_table(disk: &str, partitions: &[PartitionPlan]) -> bool {
+    partitions.iter().any(|p| p.disk == disk && !p.format)
+}
+
+/// Devices of the planned partitions on kept partition tables that don't
+/// exist, e.g. a third partition planned on a disk that only has two.
+pub fn missing_kept_devices(partitions: &[PartitionPlan]) -> Vec<String> {
+    partitions
+        .iter()
+        .zip(partition_devices(partitions))
+        .filter(|(p, dev)| {
+            keeps_partition_table(&p.disk, partitions) && !std::path::Path::new(dev).exists()
+        })
+        .map(|(_, dev)| dev)
+        .collect()
+}
+
+/// Device path of every planned partition (e.g. "/dev/sda2",
+/// "/dev/nvme0n1p1"), numbered in plan order within each disk.
+pub fn partition_devices(partitions: &[PartitionPlan]) -> Vec<String> {
+    partitions
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let number = partitions[..=i].iter().filter(|p| p.disk == part.disk).count();
+            let separator = if part.disk.contains("nvme") || part.disk.contains("mmcblk") {
+                "p"
+            } else {
+                ""
+            };
+            format!("{}{}{}", part.disk, separator, number)
+        })
+        .collect()
+}
+
+/// Wipe the disk, create a GPT partition table, and create the partitions
+/// planned on it (entries for other disks are ignored). Refuses disks that
+/// keep their partition table.
+pub fn partition_disk(
+    disk: &str,
+    partitions: &[PartitionPlan],
+    alignment_mb: u64,
+) -> Result<(), String> {
+    if keeps_partition_table(disk, partitions) {
+        return Err(format!(
+            "{} has partitions marked to keep their data, so it must not be repartitioned",
+            disk
+        ));
+    }
+    let partitions: Vec<&PartitionPlan> = partitions.iter().filter(|p| p.disk == disk).collect();
+
     // 1. Wipe existing partition table
     run_cmd("wipefs", &["-a", "-f", disk])?;
 
@@ -265,7 +822,7 @@ pub fn partition_disk(disk: &str, partitions: &[PartitionPlan]) -> Result<(), St
     run_cmd("parted", &["-s", disk, "mklabel", "gpt"])?;
 
     // 3. Create partitions sequentially
-    let mut start_mb: u64 = 1; // start at 1 MiB (alignment)
+    let mut start_mb: u64 = alignment_mb;
 
     for (i, part) in partitions.iter().enumerate() {
         let end = match part.size_mb {
@@ -280,6 +837,7 @@ pub fn partition_disk(disk: &str, partitions: &[PartitionPlan]) -> Result<(), St
             FsType::Fat32 => "fat32",
             FsType::Ext4 => "ext4",
             FsType::Btrfs => "btrfs",
+            FsType::Xfs => "xfs",
             FsType::Swap => "linux-swap",
         };
 
@@ -297,69 +855,222 @@ pub fn partition_disk(disk: &str, partitions: &[PartitionPlan]) -> Result<(), St
         )?;
 
         // Set ESP flag on EFI partition
-        if part.fs_type == FsType::Fat32 && part.mount_point == "/boot" {
+        if part.is_esp() {
             let part_num = format!("{}", i + 1);
             run_cmd("parted", &["-s", disk, "set", &part_num, "esp", "on"])?;
         }
 
+        // The next partition starts on the following alignment boundary
         if let Some(size) = part.size_mb {
-            start_mb += size;
+            start_mb = (start_mb + size).div_ceil(alignment_mb) * alignment_mb;
         }
     }
 
     Ok(())
 }
 
-/// Format the partitions and mount them.
-pub fn format_and_mount(disk: &str, partitions: &[PartitionPlan]) -> Result<(), String> {
-    // Resolve partition device paths
-    let part_prefix = if disk.contains("nvme") || disk.contains("mmcblk") {
-        format!("{}p", disk)
-    } else {
-        disk.to_string()
-    };
+/// Device-mapper name the encrypted root is opened as. nixos-generate-config
+/// sees the LUKS device behind it and adds it to `boot.initrd.luks.devices`.
+pub const LUKS_ROOT_NAME: &str = "cryptroot";
 
-    for (i, part) in partitions.iter().enumerate() {
-        let dev = format!("{}{}", part_prefix, i + 1);
-
-        // Format
-        match part.fs_type {
-            FsType::Fat32 => run_cmd("mkfs.fat", &["-F", "32", &dev])?,
-            FsType::Ext4 => run_cmd("mkfs.ext4", &["-F", &dev])?,
-            FsType::Btrfs => run_cmd("mkfs.btrfs", &["-f", &dev])?,
-            FsType::Swap => {
-                run_cmd("mkswap", &[&dev])?;
-                run_cmd("swapon", &[&dev])?;
-                continue; // no mount point
-            }
-        };
+/// Format the partitions (on every disk) and mount them. With
+/// `tmpfs_root`, / is a tmpfs mounted at /mnt and the partitions are
+/// mounted beneath it. With `root_passphrase`, a formatted / partition is
+/// encrypted with LUKS2 first. The mkfs output is passed to `on_output` as
+/// it comes, with the percentage done when it is a progress counter.
+pub fn format_and_mount(
+    partitions: &[PartitionPlan],
+    tmpfs_root: bool,
+    root_passphrase: Option<&str>,
+    on_output: &mut dyn FnMut(&str, Option<u8>),
+) -> Result<(), String> {
+    let devices = partition_devices(partitions);
+
+    if tmpfs_root {
+        run_cmd("mount", &["-t", "tmpfs", "-o", "mode=755", "none", "/mnt"])?;
+    }
+
+    for (part, dev) in partitions.iter().zip(&devices) {
+        let mut dev = dev.clone();
+        let encrypt = part.format && part.mount_point == "/";
+        if let Some(passphrase) = root_passphrase.filter(|_| encrypt) {
+            on_output(&format!("Encrypting {} with LUKS2...", dev), None);
+            dev = luks_format_and_open(&dev, passphrase)?;
+        }
+        let dev = dev.as_str();
+
+        // Format (kept partitions are mounted as they are)
+        if part.format {
+            on_output(&format!("Formatting {} as {}...", dev, part.fs_type.as_str()), None);
+            match part.fs_type {
+                FsType::Fat32 => run_cmd_streaming("mkfs.fat", &["-F", "32", dev], on_output)?,
+                FsType::Ext4 => run_cmd_streaming("mkfs.ext4", &["-F", dev], on_output)?,
+                FsType::Btrfs => run_cmd_streaming("mkfs.btrfs", &["-f", dev], on_output)?,
+                FsType::Xfs => run_cmd_streaming("mkfs.xfs", &["-f", dev], on_output)?,
+                FsType::Swap => run_cmd_streaming("mkswap", &[dev], on_output)?,
+            };
+        }
+        if part.fs_type == FsType::Swap {
+            run_cmd("swapon", &[dev])?;
+            continue; // no mount point
+        }
 
         // Mount
         if part.mount_point == "/" {
-            run_cmd("mount", &[&dev, "/mnt"])?;
+            run_cmd("mount", &[dev, "/mnt"])?;
         }
     }
 
     // Second pass: mount non-root partitions (they need /mnt to exist first)
-    for (i, part) in partitions.iter().enumerate() {
-        let dev = format!("{}{}", part_prefix, i + 1);
-
-        if part.fs_type == FsType::Swap || part.mount_point == "/" {
-            continue;
-        }
-
+    for i in nested_mount_order(partitions) {
+        let (part, dev) = (&partitions[i], devices[i].as_str());
         let target = format!("/mnt{}", part.mount_point);
         run_cmd("mkdir", &["-p", &target])?;
-        run_cmd("mount", &[&dev, &target])?;
+        run_cmd("mount", &[dev, &target])?;
     }
 
     Ok(())
 }
 
+/// Undo what a `format_and_mount` that failed partway left behind, so it
+/// can run again: the swap file and swap partitions are turned off, /mnt is
+/// unmounted recursively and the encrypted root is closed. Steps with
+/// nothing to undo are skipped.
+pub fn undo_format_and_mount(
+    partitions: &[PartitionPlan],
+    swap_file: Option<&str>,
+) -> Result<(), String> {
+    // The swap file is only reachable while /mnt is still mounted
+    if let Some(file) = swap_file {
+        if is_active_swap(file) {
+            run_cmd("swapoff", &[file])?;
+        }
+    }
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    let mnt_mounted = mounts.lines().any(|line| {
+        let mount_point = line.split_whitespace().nth(1);
+        mount_point.is_some_and(|m| m == "/mnt" || m.starts_with("/mnt/"))
+    });
+    if mnt_mounted {
+        run_cmd("umount", &["-R", "/mnt"])?;
+    }
+    for (part, dev) in partitions.iter().zip(partition_devices(partitions)) {
+        if part.fs_type == FsType::Swap && is_active_swap(&dev) {
+            run_cmd("swapoff", &[&dev])?;
+        }
+    }
+    if std::path::Path::new(&format!("/dev/mapper/{}", LUKS_ROOT_NAME)).exists() {
+        run_cmd("cryptsetup", &["close", LUKS_ROOT_NAME])?;
+    }
+    Ok(())
+}
+
+/// Whether `path` (a device or file) is listed in /proc/swaps.
+fn is_active_swap(path: &str) -> bool {
+    let resolved = std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let swaps = std::fs::read_to_string("/proc/swaps").unwrap_or_default();
+    swaps.lines().skip(1).filter_map(|line| line.split_whitespace().next()).any(|swap| {
+        swap == path || std::fs::canonicalize(swap).is_ok_and(|s| s == resolved)
+    })
+}
+
+/// Encrypt `dev` with LUKS2 and open it as `LUKS_ROOT_NAME`, returning the
+/// opened device. The passphrase is passed on stdin, never as an argument.
+fn luks_format_and_open(dev: &str, passphrase: &str) -> Result<String, String> {
+    // A mapping left open by an earlier attempt would block the name
+    let _ = run_cmd("cryptsetup", &["close", LUKS_ROOT_NAME]);
+    let key = passphrase.as_bytes();
+    run_cmd_with_input(
+        "cryptsetup",
+        &["luksFormat", "--type", "luks2", "--batch-mode", "--key-file", "-", dev],
+        key,
+    )?;
+    run_cmd_with_input("cryptsetup", &["open", "--key-file", "-", dev, LUKS_ROOT_NAME], key)?;
+    Ok(format!("/dev/mapper/{}", LUKS_ROOT_NAME))
+}
+
+/// Indices of the non-root, non-swap partitions in the order they must be
+/// mounted: parents before children (`/var` before `/var/lib`), otherwise
+/// in the order they were entered.
+fn nested_mount_order(partitions: &[PartitionPlan]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..partitions.len())
+        .filter(|&i| partitions[i].fs_type != FsType::Swap && partitions[i].mount_point != "/")
+        .collect();
+    order.sort_by_key(|&i| {
+        partitions[i].mount_point.split('/').filter(|c| !c.is_empty()).count()
+    });
+    order
+}
+
+/// Create and format a swap file of `size_mb` MiB at `<dir>/swapfile` in
+/// the mounted target. On Btrfs `dir` becomes its own subvolume (so root
+/// snapshots don't include the file) and the file is made NOCOW, which
+/// Btrfs requires for swap. Returns a warning when btrfs-progs is too old
+/// for `btrfs filesystem mkswapfile` and the file was set up by hand.
+pub fn create_swap_file(dir: &str, size_mb: u64, btrfs: bool) -> Result<Option<String>, String> {
+    let file = format!("{}/swapfile", dir);
+    let size = format!("{}M", size_mb);
+    if !btrfs {
+        run_cmd("mkdir", &["-p", dir])?;
+        run_cmd("fallocate", &["-l", &size, &file])?;
+        run_cmd("chmod", &["600", &file])?;
+        run_cmd("mkswap", &[&file])?;
+        return Ok(None);
+    }
+
+    if let Some(parent) = std::path::Path::new(dir).parent() {
+        run_cmd("mkdir", &["-p", &parent.to_string_lossy()])?;
+    }
+    run_cmd("btrfs", &["subvolume", "create", dir])?;
+    if btrfs_has_mkswapfile() {
+        run_cmd("btrfs", &["filesystem", "mkswapfile", "--size", &size, &file])?;
+        return Ok(None);
+    }
+    // Older tools: NOCOW only takes effect on an empty file, so set it
+    // before allocating
+    run_cmd("truncate", &["-s", "0", &file])?;
+    run_cmd("chattr", &["+C", &file])?;
+    run_cmd("fallocate", &["-l", &size, &file])?;
+    run_cmd("chmod", &["600", &file])?;
+    run_cmd("mkswap", &[&file])?;
+    Ok(Some(
+        "btrfs-progs is older than 6.1 (no `btrfs filesystem mkswapfile`); the swap file was \
+         created with chattr +C instead. Check it with `btrfs inspect-internal map-swapfile` \
+         if hibernation is needed."
+            .to_string(),
+    ))
+}
+
+/// Whether the installed btrfs-progs has `btrfs filesystem mkswapfile`
+/// (added in 6.1).
+fn btrfs_has_mkswapfile() -> bool {
+    let Ok(output) = Command::new("btrfs").arg("--version").output() else {
+        return false;
+    };
+    // "btrfs-progs v6.1.3"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let Some(version) = stdout.split_whitespace().find_map(|w| w.strip_prefix('v')) else {
+        return false;
+    };
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+    let (major, minor) = (parts.next().unwrap_or(0), parts.next().unwrap_or(0));
+    (major, minor) >= (6, 1)
+}
+
 /// Generate NixOS hardware configuration.
 pub fn generate_hardware_config() -> Result<String, String> {
+    nixos_generate_config(&["--root", "/mnt", "--show-hardware-config"])
+}
+
+/// Hardware configuration of the running (live) system without any
+/// `fileSystems`, for building the system before the disk is partitioned.
+pub fn generate_live_hardware_config() -> Result<String, String> {
+    nixos_generate_config(&["--show-hardware-config", "--no-filesystems"])
+}
+
+fn nixos_generate_config(args: &[&str]) -> Result<String, String> {
     let output = Command::new("nixos-generate-config")
-        .args(["--root", "/mnt", "--show-hardware-config"])
+        .args(args)
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .output()
@@ -428,13 +1139,58 @@ pub fn set_user_password_in_target(username: &str, password: &str) -> Result<(),
     Ok(())
 }
 
-/// Copy the repository into the target system's /etc/nixos/ so the user can
-/// modify the config and push to GitHub after reboot.
-pub fn copy_repo_to_target(base_path: &std::path::Path) -> Result<(), String> {
-    run_cmd("mkdir", &["-p", "/mnt/etc/nixos"])?;
-    // Copy contents (not the directory itself) preserving .git, permissions, etc.
+/// Copy the repository at `base_path`, with its .git and uncommitted
+/// changes, to a fresh temporary directory, so a trial build can add files
+/// to it without touching the repository itself.
+pub fn temp_repo_copy(base_path: &std::path::Path) -> Result<PathBuf, String> {
+    let dest = std::env::temp_dir().join(format!("nixos-installer-check-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dest);
+    std::fs::create_dir_all(&dest)
+        .map_err(|e| format!("Failed to create {}: {}", dest.display(), e))?;
     let src = format!("{}/.", base_path.to_string_lossy());
-    run_cmd("cp", &["-a", &src, "/mnt/etc/nixos/"])
+    run_cmd("cp", &["-a", &src, &dest.to_string_lossy()])?;
+    Ok(dest)
+}
+
+/// Copy the repository into the target system at `dest` (a path inside it,
+/// normally /etc/nixos) so the user can modify the config and push to
+/// GitHub after reboot. With `owner`, the copy is chown'ed to that user.
+/// Without `with_git`, the `.git` directory is left out.
+pub fn copy_repo_to_target(
+    base_path: &std::path::Path,
+    dest: &str,
+    owner: Option<&str>,
+    with_git: bool,
+) -> Result<(), String> {
+    let target = format!("/mnt{}", dest);
+    run_cmd("mkdir", &["-p", &target])?;
+    if with_git {
+        // Copy contents (not the directory itself) preserving .git, permissions, etc.
+        let src = format!("{}/.", base_path.to_string_lossy());
+        run_cmd("cp", &["-a", &src, &format!("{}/", target)])?;
+    } else {
+        let entries = std::fs::read_dir(base_path)
+            .map_err(|e| format!("Cannot read {}: {}", base_path.display(), e))?;
+        for entry in entries.flatten() {
+            if entry.file_name() == ".git" {
+                continue;
+            }
+            let src = entry.path().to_string_lossy().to_string();
+            run_cmd("cp", &["-a", &src, &format!("{}/", target)])?;
+        }
+    }
+    if let Some(user) = owner {
+        // The user only exists inside the target, so resolve it there;
+        // `user:` picks their login group
+        let owner = format!("{}:", user);
+        run_cmd("nixos-enter", &["--root", "/mnt", "--", "chown", "-R", &owner, dest])?;
+    }
+    Ok(())
+}
+
+/// Whether the git repo at `base_path` is a shallow clone.
+pub fn is_shallow_repo(base_path: &std::path::Path) -> bool {
+    base_path.join(".git").join("shallow").exists()
 }
 
 /// Stage all new/modified files in the repo so the flake can see them.
@@ -460,6 +1216,95 @@ pub fn reboot() -> Result<(), String> {
     run_cmd("reboot", &[])
 }
 
+/// Make the installed system the boot default without rebooting, like
+/// `nixos-rebuild boot` inside the target. The system is already built, so
+/// this runs the final step of `nixos-rebuild boot` directly
+/// (`switch-to-configuration boot` via nixos-enter) rather than
+/// re-evaluating the flake, and reinstalls the bootloader so the firmware
+/// entry points at the new disk.
+pub fn set_boot_default() -> Result<(), String> {
+    run_cmd(
+        "nixos-enter",
+        &[
+            "--root",
+            "/mnt",
+            "--",
+            "env",
+            "NIXOS_INSTALL_BOOTLOADER=1",
+            "/nix/var/nix/profiles/system/bin/switch-to-configuration",
+            "boot",
+        ],
+    )
+}
+
+/// Open an interactive shell inside the installed system via
+/// `nixos-enter --root /mnt`. Inherits the terminal, so the caller must
+/// restore it first. The shell's own exit status is not treated as an error.
+pub fn enter_chroot() -> Result<(), String> {
+    println!("Entering the installed system. Type `exit` to return to the installer.\n");
+    Command::new("nixos-enter")
+        .args(["--root", "/mnt"])
+        .status()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to run nixos-enter: {}", e))
+}
+
+/// Copy text to the system clipboard using whichever clipboard tool is
+/// available (wl-copy, xclip, xsel). Returns the name of the tool used.
+pub fn copy_to_clipboard(text: &str) -> Result<&'static str, String> {
+    use std::io::Write;
+
+    let candidates: [(&'static str, &[&str]); 3] = [
+        ("wl-copy", &[]),
+        ("xclip", &["-selection", "clipboard"]),
+        ("xsel", &["--clipboard", "--input"]),
+    ];
+
+    for (tool, args) in candidates {
+        let mut child = match Command::new(tool)
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+        {
+            Ok(c) => c,
+            Err(_) => continue, // not installed, try the next one
+        };
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .map_err(|e| format!("Failed to write to {}: {}", tool, e))?;
+        }
+
+        let status = child
+            .wait()
+            .map_err(|e| format!("Failed to wait for {}: {}", tool, e))?;
+        if status.success() {
+            return Ok(tool);
+        }
+    }
+
+    Err("No working clipboard tool found (tried wl-copy, xclip, xsel)".to_string())
+}
+
+/// Write a timestamped copy of the log file at `src` into `dest_dir`.
+/// Returns the path of the written copy.
+pub fn export_log(
+    src: &std::path::Path,
+    dest_dir: &std::path::Path,
+) -> Result<std::path::PathBuf, String> {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let dest = dest_dir.join(format!("nixos-installer-{}.log", secs));
+    std::fs::copy(src, &dest)
+        .map_err(|e| format!("Failed to copy {} to {}: {}", src.display(), dest.display(), e))?;
+    Ok(dest)
+}
+
 /// Run an install hook script with installer context as environment variables.
 /// Returns Ok(output) with the script's combined stdout+stderr, or Err on failure.
 pub fn run_hook(
@@ -494,14 +1339,63 @@ pub fn run_hook(
     Ok(combined)
 }
 
+/// Append `$ cmd args...` to the command log, if enabled.
+fn log_command(cmd: &str, args: &[&str]) {
+    if let Ok(target) = COMMAND_LOG.lock() {
+        if let Some(path) = target.as_ref() {
+            use std::io::Write;
+            if let Ok(mut f) = std::fs::OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(f, "$ {} {}", cmd, args.join(" "));
+            }
+        }
+    }
+}
+
 fn run_cmd(cmd: &str, args: &[&str]) -> Result<(), String> {
+    log_command(cmd, args);
+
     let output = Command::new(cmd)
         .args(args)
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .output()
-        .map_err(|e| format!("Failed to run '{}': {}", cmd, e))?;
+        .map_err(|e| spawn_error(cmd, e))?;
+    command_result(cmd, output)
+}
+
+/// Like `run_cmd`, with `input` written to the command's stdin.
+fn run_cmd_with_input(cmd: &str, args: &[&str], input: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+
+    log_command(cmd, args);
 
+    let mut child = Command::new(cmd)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| spawn_error(cmd, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input)
+            .map_err(|e| format!("Failed to write to '{}': {}", cmd, e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("Failed to wait for '{}': {}", cmd, e))?;
+    command_result(cmd, output)
+}
+
+fn spawn_error(cmd: &str, e: std::io::Error) -> String {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => format!("'{}' is not installed or not on PATH", cmd),
+        _ => format!("Failed to run '{}': {}", cmd, e),
+    }
+}
+
+/// Turn a finished command into an error with its output if it failed.
+fn command_result(cmd: &str, output: std::process::Output) -> Result<(), String> {
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -521,3 +1415,207 @@ fn run_cmd(cmd: &str, args: &[&str]) -> Result<(), String> {
 
     Ok(())
 }
+
+/// Like `run_cmd`, but passes stdout and stderr to `on_output` while the
+/// command runs, for long ones such as mkfs on a large partition. Output
+/// is split on \r and backspaces as well as newlines, since progress
+/// counters ("Writing inode tables: 12/960") redraw in place; those are
+/// reported as a percentage, once per percent.
+fn run_cmd_streaming(
+    cmd: &str,
+    args: &[&str],
+    on_output: &mut dyn FnMut(&str, Option<u8>),
+) -> Result<(), String> {
+    log_command(cmd, args);
+    let mut child = Command::new(cmd)
+        .args(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => format!("'{}' is not installed or not on PATH", cmd),
+            _ => format!("Failed to run '{}': {}", cmd, e),
+        })?;
+
+    // One reader thread per pipe, so neither can fill up and block the
+    // command; fragments arrive here in the order they were written
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut readers = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        let tx = tx.clone();
+        readers.push(std::thread::spawn(move || send_fragments(stdout, false, &tx)));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        let tx = tx.clone();
+        readers.push(std::thread::spawn(move || send_fragments(stderr, true, &tx)));
+    }
+    drop(tx);
+
+    let mut stderr_lines = Vec::new();
+    // Text before the last counter ("Writing inode tables:") and the last
+    // percentage reported for it
+    let mut counter_label = String::new();
+    let mut last_pct = None;
+    for (is_stderr, fragment) in rx {
+        if is_stderr {
+            stderr_lines.push(fragment.clone());
+        }
+        match parse_counter(&fragment) {
+            Some((label, pct)) => {
+                if !label.is_empty() {
+                    counter_label = label.to_string();
+                }
+                if last_pct != Some(pct) {
+                    last_pct = Some(pct);
+                    on_output(&format!("  {} {}%", counter_label, pct), Some(pct));
+                }
+            }
+            None => {
+                last_pct = None;
+                on_output(&format!("  {}", fragment), None);
+            }
+        }
+    }
+    for reader in readers {
+        let _ = reader.join();
+    }
+
+    let status = child
+        .wait()
+        .map_err(|e| format!("Failed to wait for '{}': {}", cmd, e))?;
+    if !status.success() {
+        let mut msg = format!("Command '{}' failed with exit code {:?}", cmd, status.code());
+        if !stderr_lines.is_empty() {
+            msg.push_str(&format!("\n--- stderr ---\n{}", stderr_lines.join("\n")));
+        }
+        return Err(msg);
+    }
+    Ok(())
+}
+
+/// Send the non-empty fragments of `pipe`, split on newlines, \r and
+/// backspaces, tagged with whether they came from stderr.
+fn send_fragments(
+    pipe: impl std::io::Read,
+    is_stderr: bool,
+    tx: &std::sync::mpsc::Sender<(bool, String)>,
+) {
+    use std::io::Read;
+
+    let mut fragment = Vec::new();
+    let send = |fragment: &mut Vec<u8>| {
+        let text = String::from_utf8_lossy(fragment);
+        if !text.trim().is_empty() {
+            let _ = tx.send((is_stderr, text.trim().to_string()));
+        }
+        fragment.clear();
+    };
+    for byte in std::io::BufReader::new(pipe).bytes() {
+        let Ok(byte) = byte else { break };
+        if matches!(byte, b'\r' | b'\n' | 0x08) {
+            send(&mut fragment);
+        } else {
+            fragment.push(byte);
+        }
+    }
+    send(&mut fragment);
+}
+
+/// Split a progress fragment ending in a "done/total" counter into the text
+/// before it and the percentage done.
+fn parse_counter(fragment: &str) -> Option<(&str, u8)> {
+    let (label, counter) = match fragment.rsplit_once(char::is_whitespace) {
+        Some((label, counter)) => (label.trim(), counter),
+        None => ("", fragment),
+    };
+    let (done, total) = counter.split_once('/')?;
+    let (done, total): (u64, u64) = (done.parse().ok()?, total.parse().ok()?);
+    if total == 0 || done > total {
+        return None;
+    }
+    Some((label, (done * 100 / total) as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(mount_point: &str, fs_type: FsType) -> PartitionPlan {
+        PartitionPlan {
+            disk: "/dev/sda".to_string(),
+            label: mount_point.to_string(),
+            mount_point: mount_point.to_string(),
+            size_mb: None,
+            fs_type,
+            format: true,
+        }
+    }
+
+    #[test]
+    fn boot_mounts_include_partitions_below_boot() {
+        assert!(part("/boot", FsType::Ext4).is_boot_mount());
+        assert!(part("/boot/", FsType::Ext4).is_boot_mount());
+        assert!(part("/boot/efi", FsType::Fat32).is_boot_mount());
+        assert!(part("/boot/efi", FsType::Fat32).is_esp());
+        assert!(!part("/bootstrap", FsType::Fat32).is_boot_mount());
+        assert!(!part("/boot", FsType::Ext4).is_esp());
+    }
+
+    #[test]
+    fn progress_counters_are_parsed_as_percentages() {
+        assert_eq!(
+            parse_counter("Writing inode tables: 12/960"),
+            Some(("Writing inode tables:", 1))
+        );
+        assert_eq!(parse_counter("480/960"), Some(("", 50)));
+        assert_eq!(parse_counter("Allocating group tables: 0/0"), None);
+        assert_eq!(parse_counter("Allocating group tables: 961/960"), None);
+        assert_eq!(parse_counter("Writing superblocks: done"), None);
+    }
+
+    #[test]
+    fn fragments_keep_utf8_output_intact() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let output = "Étiquette « données »\r12/960\x08\x08\n\n".as_bytes();
+        send_fragments(output, true, &tx);
+        drop(tx);
+        let fragments: Vec<(bool, String)> = rx.iter().collect();
+        assert_eq!(
+            fragments,
+            vec![
+                (true, "Étiquette « données »".to_string()),
+                (true, "12/960".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_mounts_are_ordered_parent_first() {
+        let partitions = vec![
+            part("/var/lib", FsType::Ext4),
+            part("/boot", FsType::Fat32),
+            part("swap", FsType::Swap),
+            part("/", FsType::Btrfs),
+            part("/var", FsType::Ext4),
+        ];
+        let order: Vec<&str> = nested_mount_order(&partitions)
+            .into_iter()
+            .map(|i| partitions[i].mount_point.as_str())
+            .collect();
+        assert_eq!(order, ["/boot", "/var", "/var/lib"]);
+    }
+
+    #[test]
+    fn kept_partitions_are_never_recreated() {
+        let mut home = part("/home", FsType::Ext4);
+        home.format = false;
+        let mut other = part("/", FsType::Ext4);
+        other.disk = "/dev/sdb".to_string();
+        let partitions = vec![part("/boot", FsType::Fat32), home, other];
+
+        assert!(keeps_partition_table("/dev/sda", &partitions));
+        assert!(!keeps_partition_table("/dev/sdb", &partitions));
+        // Refused before wipefs or parted run
+        assert!(partition_disk("/dev/sda", &partitions, 1).is_err());
+    }
+}
@@ -0,0 +1,23 @@
+//! Installer logic behind the `nixos-installer` TUI.
+//!
+//! The binary is a thin frontend: it parses arguments, draws the wizard and
+//! forwards key presses to [`app::App`]. Everything else lives here so it
+//! can be driven from tests or another frontend:
+//!
+//! - [`app`]: the wizard state machine (`App`, `Step`, the `confirm_*`
+//!   transitions) and the background clone/install threads
+//! - [`nix`]: module scanning and generation of the host and user files
+//! - [`disk`]: block devices, partitioning, formatting and mounting
+//! - [`config`]: installer config and remembered selections
+//! - [`status`]: JSON status updates over a Unix socket for monitoring
+//! - [`theme`]: color themes
+
+pub mod app;
+pub mod config;
+pub mod disk;
+pub mod nix;
+pub mod status;
+pub mod theme;
+
+#[cfg(test)]
+mod test_support;
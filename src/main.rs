@@ -1,20 +1,19 @@
-mod app;
-mod config;
-mod disk;
-mod nix;
-mod theme;
 mod ui;
 
+use nixos_installer::{app, config, disk, nix, status, theme};
+
 use std::env;
 use std::io;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+    MouseButton, MouseEvent, MouseEventKind,
+};
 use ratatui::DefaultTerminal;
 
-use app::{App, Step};
-use config::InstallerConfig;
+use app::{App, Jump, Step};
 use disk::FsType;
 use theme::ThemeName;
 
@@ -22,11 +21,12 @@ use theme::ThemeName;
 const DEFAULT_REPO_URL: &str = "https://github.com/itzemoji/nixos-dotfiles.git";
 
 /// Walk upwards from `start` looking for a directory that contains both
-/// `flake.nix` and a `modules/` subdirectory (the nixos-dots repo root).
-fn find_repo_root(start: &Path) -> Option<PathBuf> {
+/// `flake.nix` and one of the module directories of `layout` (the
+/// nixos-dots repo root).
+fn find_repo_root(start: &Path, layout: &nix::ModuleLayout) -> Option<PathBuf> {
     let mut current = start.to_path_buf();
     loop {
-        if current.join("flake.nix").exists() && current.join("modules").is_dir() {
+        if current.join("flake.nix").exists() && layout.exists_in(&current) {
             return Some(current);
         }
         if !current.pop() {
@@ -40,7 +40,21 @@ fn find_repo_root(start: &Path) -> Option<PathBuf> {
 ///   --repo <URL>        Override the dotfiles repository URL
 ///   --config <PATH>     Load installer config from a custom path
 ///   --theme <NAME>      Override the color theme
+///   --log-file <PATH>   Write the installation log to a custom path
+///   --branch <REF>      Clone a specific branch or tag
+///   --shallow           Shallow-clone the repo (--depth 1)
+///   --token <TOKEN>     Access token for cloning a private HTTPS repo
+///   --offline           Install without binary caches (--option substitute false)
+///   --install-arg <ARG> Extra argument for nixos-install (repeatable)
+///   --verbose           Also log every command line the installer runs
+///   --config-only       Only generate host/user config files (no disk changes)
+///   --no-remember       Don't pre-fill or save the previous run's selections
+///   --export-plan <PATH> Write the resolved installation plan (JSON or TOML) before installing
+///   --answers <PATH>    Load an exported plan and go straight to the Confirm screen
+///   --check <PATH>      Check a repo against the installer's expectations and exit
 ///   --init              Generate a default config.toml at /etc/nixos-installer/
+///   --quiet             Suppress non-essential stderr output
+///   --status-socket <PATH> Publish JSON status updates on a Unix socket
 ///   --help              Show usage information
 ///   <PATH>              Use an existing local repo instead of cloning
 struct CliArgs {
@@ -52,8 +66,36 @@ struct CliArgs {
     config_path: Option<PathBuf>,
     /// Theme override from CLI.
     theme_override: Option<ThemeName>,
+    /// Custom log file path.
+    log_file: Option<PathBuf>,
+    /// Branch or tag to clone.
+    branch: Option<String>,
+    /// Shallow-clone the repo (depth 1).
+    shallow: bool,
+    /// Access token for private HTTPS repos.
+    token: Option<String>,
+    /// Air-gapped install: don't substitute from binary caches.
+    offline: bool,
+    /// Extra arguments appended to nixos-install.
+    install_args: Vec<String>,
+    /// Log the exact command lines run during installation.
+    verbose: bool,
+    /// Generate configuration files only; skip partitioning and nixos-install.
+    config_only: bool,
+    /// Don't remember selections across runs.
+    no_remember: bool,
+    /// Write the resolved installation plan to this path.
+    export_plan: Option<PathBuf>,
+    /// Load this exported plan and continue at the Confirm screen.
+    answers: Option<PathBuf>,
+    /// Run --check mode on this repo: report what the installer finds and exit.
+    check: Option<PathBuf>,
     /// Run --init mode: generate config and exit.
     init: bool,
+    /// Suppress non-essential stderr output.
+    quiet: bool,
+    /// Unix socket to publish JSON status updates on.
+    status_socket: Option<PathBuf>,
     /// Show help.
     help: bool,
 }
@@ -64,7 +106,21 @@ fn parse_args() -> CliArgs {
     let mut base_path: Option<PathBuf> = None;
     let mut config_path: Option<PathBuf> = None;
     let mut theme_override: Option<ThemeName> = None;
+    let mut log_file: Option<PathBuf> = None;
+    let mut branch: Option<String> = None;
+    let mut shallow = false;
+    let mut token: Option<String> = None;
+    let mut offline = false;
+    let mut install_args: Vec<String> = Vec::new();
+    let mut verbose = false;
+    let mut config_only = false;
+    let mut no_remember = false;
+    let mut export_plan: Option<PathBuf> = None;
+    let mut answers: Option<PathBuf> = None;
+    let mut check: Option<PathBuf> = None;
     let mut init = false;
+    let mut quiet = false;
+    let mut status_socket: Option<PathBuf> = None;
     let mut help = false;
 
     let mut i = 0;
@@ -98,7 +154,61 @@ fn parse_args() -> CliArgs {
                     }
                 }
             }
+            "--log-file" => {
+                i += 1;
+                if i < args.len() {
+                    log_file = Some(PathBuf::from(&args[i]));
+                }
+            }
+            "--branch" => {
+                i += 1;
+                if i < args.len() {
+                    branch = Some(args[i].clone());
+                }
+            }
+            "--shallow" => shallow = true,
+            "--token" => {
+                i += 1;
+                if i < args.len() {
+                    token = Some(args[i].clone());
+                }
+            }
+            "--offline" => offline = true,
+            "--install-arg" => {
+                i += 1;
+                if i < args.len() {
+                    install_args.push(args[i].clone());
+                }
+            }
+            "--verbose" | "-v" => verbose = true,
+            "--config-only" => config_only = true,
+            "--no-remember" => no_remember = true,
+            "--export-plan" => {
+                i += 1;
+                if i < args.len() {
+                    export_plan = Some(PathBuf::from(&args[i]));
+                }
+            }
+            "--answers" => {
+                i += 1;
+                if i < args.len() {
+                    answers = Some(PathBuf::from(&args[i]));
+                }
+            }
+            "--check" => {
+                i += 1;
+                if i < args.len() {
+                    check = Some(PathBuf::from(&args[i]));
+                }
+            }
             "--init" => init = true,
+            "--quiet" | "-q" => quiet = true,
+            "--status-socket" => {
+                i += 1;
+                if i < args.len() {
+                    status_socket = Some(PathBuf::from(&args[i]));
+                }
+            }
             "--help" | "-h" => help = true,
             other => {
                 // Positional argument: local base path
@@ -122,7 +232,21 @@ fn parse_args() -> CliArgs {
         base_path,
         config_path,
         theme_override,
+        log_file,
+        branch,
+        shallow,
+        token,
+        offline,
+        install_args,
+        verbose,
+        config_only,
+        no_remember,
+        export_plan,
+        answers,
+        check,
         init,
+        quiet,
+        status_socket,
         help,
     }
 }
@@ -140,7 +264,21 @@ fn print_help() {
     println!("    --repo <URL>        Override the dotfiles repository URL");
     println!("    --config <PATH>     Load config from a custom path (default: /etc/nixos-installer/config.toml)");
     println!("    --theme <NAME>      Override the color theme");
+    println!("    --log-file <PATH>   Write the installation log to PATH (default: {})", app::LOG_FILE);
+    println!("    --branch <REF>      Clone this branch or tag instead of the default branch");
+    println!("    --shallow           Shallow-clone the repo (only the latest commit)");
+    println!("    --token <TOKEN>     Access token for a private HTTPS repo (prefer GIT_CLONE_TOKEN)");
+    println!("    --offline           Air-gapped install: pass `--option substitute false` to nixos-install");
+    println!("    --install-arg <ARG> Append ARG to the nixos-install command (repeatable)");
+    println!("    --verbose, -v       Also log every command line the installer runs");
+    println!("    --config-only       Only generate the host's .nix files; skip disk setup and nixos-install");
+    println!("    --no-remember       Don't pre-fill or save the previous run's selections");
+    println!("    --export-plan <PATH> Write the resolved plan to PATH (JSON, or TOML for .toml) before installing");
+    println!("    --answers <PATH>    Load a plan from --export-plan and go to the Confirm screen");
+    println!("    --check <PATH>      Check that the repo at PATH has the layout the installer expects");
     println!("    --init              Generate a default config.toml at /etc/nixos-installer/");
+    println!("    --quiet, -q         Don't print warnings or the log file location to stderr");
+    println!("    --status-socket <PATH> Publish newline-delimited JSON status updates on a Unix socket");
     println!("    --help, -h          Show this help message");
     println!();
     println!("AVAILABLE THEMES:");
@@ -150,6 +288,68 @@ fn print_help() {
     println!();
     println!("ENVIRONMENT:");
     println!("    NIXOS_DOTFILES_REPO    Fallback repository URL if --repo is not given");
+    println!("    GIT_CLONE_TOKEN        Access token for cloning a private HTTPS repo");
+}
+
+/// Print what the installer finds in the repo at `path`: missing module
+/// directories, the number of presets, modules and package sets, and the
+/// names that are filtered out of the selection lists. Returns false if
+/// none of the module directories exist.
+fn check_repo(path: &Path, installer_config: &config::InstallerConfig) -> bool {
+    let cfg = config::load_repo_config(path, installer_config);
+    let layout = cfg.module_layout();
+    println!("Checking {}", path.display());
+
+    if !path.join("flake.nix").exists() {
+        println!("  warning: no flake.nix at the repo root");
+    }
+    for warning in nix::validate_base_path(path, &layout) {
+        println!("  warning: {}", warning);
+    }
+    if let Err(e) = cfg.partition_alignment_mb() {
+        println!("  warning: {}", e);
+    }
+    if let Err(e) = cfg.flake_attr("example") {
+        println!("  warning: {}", e);
+    }
+    if !layout.exists_in(path) {
+        println!();
+        println!("FAILED: none of the module directories exist.");
+        return false;
+    }
+
+    let presets = nix::scan_host_presets(path, &layout);
+    let hidden = cfg.hidden_modules();
+    let nixos_modules = nix::scan_nixos_modules(path, &layout, &hidden);
+    let hm_modules = nix::scan_hm_modules(path, &layout, &hidden);
+    let packages = nix::scan_package_modules(path, &layout, &hidden, &cfg.attr_refs());
+    println!();
+    let counts = [
+        (presets.len(), "host presets", &layout.hosts),
+        (nixos_modules.len(), "NixOS modules", &layout.nixos_modules),
+        (hm_modules.len(), "Home Manager modules", &layout.hm_modules),
+        (packages.len(), "package sets", &layout.packages),
+    ];
+    for (count, what, dir) in counts {
+        println!("  {:>4} {:<21} ({})", count, what, dir.display());
+    }
+    if !presets.is_empty() {
+        let names: Vec<&str> = presets.iter().map(|p| p.name.as_str()).collect();
+        println!("       presets: {}", names.join(", "));
+    }
+
+    let filtered = nix::filtered_module_names(path, &layout, &hidden);
+    if !filtered.is_empty() {
+        println!();
+        println!("  Not offered for selection:");
+        for (dir, name) in &filtered {
+            println!("    {}/{}", dir.display(), name);
+        }
+    }
+
+    println!();
+    println!("OK");
+    true
 }
 
 fn main() -> io::Result<()> {
@@ -159,6 +359,7 @@ fn main() -> io::Result<()> {
         print_help();
         return Ok(());
     }
+    config::set_quiet(cli.quiet);
 
     // --init: generate config and exit
     if cli.init {
@@ -190,10 +391,46 @@ fn main() -> io::Result<()> {
         .unwrap_or_else(|| Path::new(config::DEFAULT_CONFIG_PATH));
     let mut installer_config = config::load_config(config_file);
 
+    // --check: report what the installer would find in the repo and exit
+    if let Some(path) = &cli.check {
+        if !check_repo(path, &installer_config) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // CLI overrides
     if let Some(theme) = cli.theme_override {
         installer_config.theme = Some(theme);
     }
+    if cli.branch.is_some() {
+        installer_config.repo_branch = cli.branch;
+    }
+    if cli.shallow {
+        installer_config.clone_depth = Some(1);
+    }
+    if cli.offline {
+        installer_config.offline = true;
+    }
+    if cli.no_remember {
+        installer_config.remember_selections = Some(false);
+    }
+    if cli.export_plan.is_some() {
+        installer_config.export_plan = cli.export_plan;
+    }
+    installer_config.nixos_install_extra_args.extend(cli.install_args);
+    if installer_config
+        .nixos_install_extra_args
+        .iter()
+        .any(|a| a.trim().is_empty())
+    {
+        eprintln!("Error: nixos-install extra arguments must not be empty strings.");
+        std::process::exit(1);
+    }
+    installer_config.clone_token = cli
+        .token
+        .or_else(|| env::var("GIT_CLONE_TOKEN").ok())
+        .filter(|t| !t.is_empty());
 
     // Resolve the theme (base theme + optional custom overrides)
     let mut theme = installer_config
@@ -221,12 +458,13 @@ fn main() -> io::Result<()> {
     } else {
         // Try auto-detect
         let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        if let Some(root) = find_repo_root(&cwd) {
+        let layout = installer_config.module_layout();
+        if let Some(root) = find_repo_root(&cwd, &layout) {
             (Some(root), None)
         } else if let Some(root) = env::current_exe()
             .ok()
             .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-            .and_then(|p| find_repo_root(&p))
+            .and_then(|p| find_repo_root(&p, &layout))
         {
             (Some(root), None)
         } else {
@@ -236,36 +474,188 @@ fn main() -> io::Result<()> {
         }
     };
 
+    // Log file: CLI --log-file > config log_file > default (if writable)
+    let requested_log = cli
+        .log_file
+        .or_else(|| installer_config.log_file.as_ref().map(PathBuf::from));
+    let log_file = app::resolve_log_file(requested_log);
+
+    // An unreadable answers file is reported before the TUI starts
+    let answers = match cli.answers.as_deref().map(app::InstallPlan::load) {
+        Some(Ok(plan)) => Some(plan),
+        Some(Err(e)) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    // Status socket for monitoring scripts; bound before the TUI starts so a
+    // bad path is reported on a normal terminal
+    let status_socket = match cli.status_socket.as_deref().map(status::StatusSocket::bind) {
+        Some(Ok(socket)) => Some(socket),
+        Some(Err(e)) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    // Logging starts here, after every argument has been checked
+    let (log_file, log_warning) = app::open_log_file(log_file);
+    if cli.verbose {
+        disk::enable_command_logging(log_file.clone());
+    }
+
+    // Preflight: report every missing tool up front instead of failing mid-wizard
+    let missing_tools = disk::check_required_tools(repo_url.is_some(), !cli.config_only);
+
+    let mut app = App::new(base_path, repo_url, installer_config, theme, log_file.clone());
+    app.config_only = cli.config_only;
+    app.config_path = config_file.to_path_buf();
+    app.answers = answers;
+    app.apply_answers();
+    for warning in [log_warning, missing_tools].into_iter().flatten() {
+        app.status_message = Some(match app.status_message.take() {
+            Some(existing) => format!("{}\n{}", existing, warning),
+            None => warning,
+        });
+    }
+
     let mut terminal = ratatui::init();
-    let result = run(&mut terminal, base_path, repo_url, installer_config, theme);
-    ratatui::restore();
+    enable_mouse_capture();
+    let result = run(&mut terminal, app, status_socket.as_ref());
+    restore_terminal();
 
     // Print log file location after TUI exits so the user can review
-    if std::path::Path::new(app::LOG_FILE).exists() {
-        eprintln!("Installation log saved to: {}", app::LOG_FILE);
+    if log_file.exists() && !cli.quiet {
+        eprintln!("Installation log saved to: {}", log_file.display());
     }
 
     result
 }
 
+/// Turn on mouse reporting and make sure a panic turns it off again,
+/// alongside the terminal restore hook installed by `ratatui::init`.
+fn enable_mouse_capture() {
+    let _ = crossterm::execute!(io::stdout(), EnableMouseCapture);
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = crossterm::execute!(io::stdout(), DisableMouseCapture);
+        hook(info);
+    }));
+}
+
+/// Disable mouse capture and restore the terminal to its original state.
+fn restore_terminal() {
+    let _ = crossterm::execute!(io::stdout(), DisableMouseCapture);
+    ratatui::restore();
+}
+
+/// Handle a mouse event: clicks select (and toggle) list rows, the scroll
+/// wheel moves the list cursor or scrolls the clone/install log.
+fn handle_mouse(app: &mut App, mouse: MouseEvent) {
+    match mouse.kind {
+        MouseEventKind::Down(MouseButton::Left) => {
+            // A click dismisses the status popup or help overlay, like a key press
+            if app.status_message.is_some() {
+                app.dismiss_status(false);
+                return;
+            }
+            if app.show_help {
+                app.show_help = false;
+                return;
+            }
+            let index = app
+                .list_hitbox
+                .as_ref()
+                .and_then(|h| h.index_at(mouse.column, mouse.row));
+            if let Some(index) = index {
+                if let Some((cursor, len)) = app.active_list() {
+                    if index < len {
+                        *cursor = index;
+                        app.toggle_current_module();
+                    }
+                }
+            }
+        }
+        MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+            let down = mouse.kind == MouseEventKind::ScrollDown;
+            if matches!(
+                app.step,
+                Step::CloningRepo | Step::Installing | Step::PreviewConfig
+            ) {
+                app.scroll_log(down);
+            } else if let Some((cursor, len)) = app.active_list() {
+                if down {
+                    App::list_next(len, cursor);
+                } else {
+                    App::list_prev(len, cursor);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Set the terminal title and, unless disabled, ring the bell, so a finished
+/// install gets noticed from another window or workspace.
+fn notify_finished(title: &str, bell: bool) {
+    use std::io::Write;
+    let mut out = io::stdout();
+    let _ = crossterm::execute!(out, crossterm::terminal::SetTitle(title));
+    if bell {
+        let _ = out.write_all(b"\x07");
+    }
+    let _ = out.flush();
+}
+
+/// Keys of a scrollable error popup: move `scroll` (the renderer clamps it
+/// to the content), or return true when the popup should close.
+fn scroll_popup_key(code: KeyCode, scroll: &mut usize) -> bool {
+    const PAGE: usize = 10;
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => *scroll = scroll.saturating_add(1),
+        KeyCode::PageUp => *scroll = scroll.saturating_sub(PAGE),
+        KeyCode::PageDown => *scroll = scroll.saturating_add(PAGE),
+        KeyCode::Home => *scroll = 0,
+        KeyCode::End => *scroll = usize::MAX,
+        KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => return true,
+        _ => {}
+    }
+    false
+}
+
 fn run(
     terminal: &mut DefaultTerminal,
-    base_path: Option<PathBuf>,
-    repo_url: Option<String>,
-    installer_config: InstallerConfig,
-    theme: theme::Theme,
+    mut app: App,
+    status_socket: Option<&status::StatusSocket>,
 ) -> io::Result<()> {
-    let mut app = App::new(base_path, repo_url, installer_config, theme);
-
     loop {
+        app.frame_count = app.frame_count.wrapping_add(1);
+
         // Sync shared clone state each frame when cloning
         if app.step == Step::CloningRepo {
             app.sync_clone_state();
         }
 
+        // Pick up the result of the pre-install build check
+        if app.prebuild_running() {
+            app.sync_prebuild();
+        }
+
+        // And of the module name check started after the package lists
+        if app.module_check_running() {
+            app.sync_module_check();
+        }
+
         // Sync shared install state each frame when installing
         if app.step == Step::Installing {
             app.sync_install_state();
+            if let Some(title) = app.finish_notice.take() {
+                notify_finished(title, app.config.notify_on_finish.unwrap_or(true));
+            }
 
             // Auto-scroll: keep log scrolled to bottom
             if app.auto_scroll && !app.install_log.is_empty() {
@@ -276,6 +666,9 @@ fn run(
         }
 
         terminal.draw(|frame| ui::render(frame, &mut app))?;
+        if let Some(socket) = status_socket {
+            socket.publish(&status::StatusUpdate::from_app(&app));
+        }
 
         if app.should_quit {
             break;
@@ -289,16 +682,30 @@ fn run(
 
         // Auto-advance when installation finishes
         if app.step == Step::Installing && app.install_done {
-            app.step = Step::RootPassword;
+            app.finish_installation();
             continue;
         }
 
-        // Poll with timeout so the UI redraws during installation
-        if !event::poll(Duration::from_millis(50))? {
+        // Redraw every 50 ms while a clone, install or build check updates
+        // the screen; otherwise sleep until the next input event
+        if app.background_active() && !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+
+        let ev = event::read()?;
+        if let Event::Resize(_, _) = ev {
+            // Redraw from scratch so nothing of the old layout is left behind
+            terminal.autoresize()?;
+            terminal.clear()?;
+            app.handle_resize();
+            continue;
+        }
+        if let Event::Mouse(mouse) = ev {
+            handle_mouse(&mut app, mouse);
             continue;
         }
 
-        if let Event::Key(key) = event::read()? {
+        if let Event::Key(key) = ev {
             if key.kind != KeyEventKind::Press {
                 continue;
             }
@@ -309,9 +716,77 @@ fn run(
                 continue;
             }
 
-            // Clear status message on any key press
+            // Ctrl+L repaints the whole screen (e.g. after stray output over
+            // a serial console) without touching the current step
+            if key.code == KeyCode::Char('l') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                terminal.clear()?;
+                continue;
+            }
+
+            // Clear status message on any key press; `f` goes to the step
+            // that fixes the reported problem, if there is one
             if app.status_message.is_some() {
-                app.status_message = None;
+                app.dismiss_status(key.code == KeyCode::Char('f'));
+                continue;
+            }
+
+            // Only Esc (cancel) while the build check runs; Ctrl+C still quits
+            if app.prebuild_running() {
+                if key.code == KeyCode::Esc {
+                    app.cancel_prebuild();
+                }
+                continue;
+            }
+
+            // Build check error popup: scroll it, or close it to stay on Confirm
+            if app.prebuild_error.is_some() {
+                if scroll_popup_key(key.code, &mut app.prebuild_scroll) {
+                    app.prebuild_error = None;
+                }
+                continue;
+            }
+
+            // nixos-install failure popup: scroll it, or close it to the log
+            if app.install_diagnostics.is_some() {
+                if scroll_popup_key(key.code, &mut app.diagnostics_scroll)
+                    || key.code == KeyCode::Char('d')
+                {
+                    app.install_diagnostics = None;
+                }
+                continue;
+            }
+
+            // Help overlay: `?` (F1 on text inputs, where `?` is typed) toggles
+            // it; while open, Esc closes it and every other key is swallowed
+            let help_key = key.code == KeyCode::F(1)
+                || (key.code == KeyCode::Char('?') && app.current_input_ref().is_none());
+            if app.show_help {
+                if help_key || key.code == KeyCode::Esc {
+                    app.show_help = false;
+                }
+                continue;
+            }
+            if help_key {
+                app.show_help = true;
+                continue;
+            }
+
+            // F2: skip ahead to Confirm when everything is already filled in
+            if key.code == KeyCode::F(2) && app.can_skip_to_confirm() {
+                app.skip_to_confirm();
+                continue;
+            }
+
+            // Home/End/PageUp/PageDown move through any list or log
+            let jump = match key.code {
+                KeyCode::Home => Some(Jump::First),
+                KeyCode::End => Some(Jump::Last),
+                KeyCode::PageUp => Some(Jump::PageUp),
+                KeyCode::PageDown => Some(Jump::PageDown),
+                _ => None,
+            };
+            if let Some(jump) = jump {
+                app.jump(jump);
                 continue;
             }
 
@@ -331,16 +806,29 @@ fn run(
 
             // q to quit on list/selection steps
             match app.step {
-                Step::SelectPreset
+                Step::Welcome
+                | Step::EmptyRepo
+                | Step::SelectPreset
                 | Step::SelectDisk
                 | Step::SelectNixosModules
+                | Step::ReviewUsers
                 | Step::SelectHmModules
                 | Step::SelectSystemPackages
-                | Step::SelectUserPackages => {
-                    if key.code == KeyCode::Char('q') {
-                        app.should_quit = true;
-                        continue;
-                    }
+                | Step::SelectUserPackages
+                    if key.code == KeyCode::Char('q') =>
+                {
+                    app.should_quit = true;
+                    continue;
+                }
+                // A: reveal (or hide again) the modules hidden_modules filters out
+                Step::SelectNixosModules
+                | Step::SelectSystemPackages
+                | Step::SelectHmModules
+                | Step::SelectUserPackages
+                    if key.code == KeyCode::Char('A') =>
+                {
+                    app.toggle_hidden_modules();
+                    continue;
                 }
                 _ => {}
             }
@@ -349,30 +837,40 @@ fn run(
                 // ---- Cloning repository ----
                 Step::CloningRepo => {
                     match key.code {
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            app.auto_scroll = false;
-                            if app.clone_log_scroll > 0 {
-                                app.clone_log_scroll -= 1;
-                            }
-                        }
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            let max = app.clone_log.len().saturating_sub(1);
-                            if app.clone_log_scroll < max {
-                                app.clone_log_scroll += 1;
-                            }
-                            if app.clone_log_scroll >= max {
-                                app.auto_scroll = true;
-                            }
-                        }
-                        KeyCode::Enter => {
-                            if app.clone_error.is_some() {
-                                app.should_quit = true;
-                            }
+                        KeyCode::Up | KeyCode::Char('k') => app.scroll_log(false),
+                        KeyCode::Down | KeyCode::Char('j') => app.scroll_log(true),
+                        KeyCode::Enter if app.clone_error.is_some() => app.retry_clone(),
+                        KeyCode::Char('q') if app.clone_error.is_some() => {
+                            app.should_quit = true;
                         }
                         _ => {}
                     }
                 }
 
+                // ---- Welcome ----
+                Step::Welcome => match key.code {
+                    KeyCode::Enter => app.enter_preset_selection(),
+                    KeyCode::Char('t') => app.cycle_theme(),
+                    KeyCode::Char('T') => app.save_theme(),
+                    _ => {}
+                },
+
+                // ---- Nothing found in the repo: quit or continue ----
+                Step::EmptyRepo => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        let mut c = app.empty_repo_cursor;
+                        App::list_prev(2, &mut c);
+                        app.empty_repo_cursor = c;
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        let mut c = app.empty_repo_cursor;
+                        App::list_next(2, &mut c);
+                        app.empty_repo_cursor = c;
+                    }
+                    KeyCode::Enter => app.confirm_empty_repo(),
+                    _ => {}
+                },
+
                 // ---- Preset selection ----
                 Step::SelectPreset => {
                     let len = app.preset_display_items().len();
@@ -388,6 +886,9 @@ fn run(
                             app.preset_cursor = c;
                         }
                         KeyCode::Enter => app.confirm_preset_selection(),
+                        KeyCode::Char('e') => app.edit_preset(),
+                        KeyCode::Char('t') => app.cycle_theme(),
+                        KeyCode::Char('T') => app.save_theme(),
                         _ => {}
                     }
                 }
@@ -407,6 +908,22 @@ fn run(
                     _ => {}
                 },
 
+                // ---- Existing host: load, overwrite or rename ----
+                Step::OverwriteHost => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        let mut c = app.overwrite_host_cursor;
+                        App::list_prev(3, &mut c);
+                        app.overwrite_host_cursor = c;
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        let mut c = app.overwrite_host_cursor;
+                        App::list_next(3, &mut c);
+                        app.overwrite_host_cursor = c;
+                    }
+                    KeyCode::Enter => app.confirm_overwrite_host(),
+                    _ => {}
+                },
+
                 // ---- NixOS module multi-select ----
                 Step::SelectNixosModules => {
                     let len = app.nixos_modules.len();
@@ -421,11 +938,7 @@ fn run(
                             App::list_next(len, &mut c);
                             app.nixos_cursor = c;
                         }
-                        KeyCode::Char(' ') => {
-                            if let Some(m) = app.nixos_modules.get_mut(app.nixos_cursor) {
-                                m.selected = !m.selected;
-                            }
-                        }
+                        KeyCode::Char(' ') => app.toggle_current_module(),
                         KeyCode::Enter => app.confirm_nixos_modules(),
                         _ => {}
                     }
@@ -445,16 +958,30 @@ fn run(
                             App::list_next(len, &mut c);
                             app.system_package_cursor = c;
                         }
-                        KeyCode::Char(' ') => {
-                            if let Some(m) = app.system_packages.get_mut(app.system_package_cursor) {
-                                m.selected = !m.selected;
-                            }
-                        }
+                        KeyCode::Char(' ') => app.toggle_current_module(),
                         KeyCode::Enter => app.confirm_system_packages(),
                         _ => {}
                     }
                 }
 
+                // ---- SSH key for the first user ----
+                Step::SshKey => match key.code {
+                    KeyCode::Enter => app.confirm_ssh_key(),
+                    KeyCode::Backspace => {
+                        app.ssh_key_input.pop();
+                    }
+                    KeyCode::Char('h') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.ssh_key_input.pop();
+                    }
+                    KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.ssh_key_input.clear();
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.ssh_key_input.push(c)
+                    }
+                    _ => {}
+                },
+
                 // ---- Create user ----
                 Step::CreateUser => match key.code {
                     KeyCode::Enter => app.confirm_username(),
@@ -470,14 +997,59 @@ fn run(
                     _ => {}
                 },
 
+                // ---- Login shell for the user ----
+                Step::UserShell => {
+                    let len = nix::Shell::ALL.len() + 1;
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            let mut c = app.shell_cursor;
+                            App::list_prev(len, &mut c);
+                            app.shell_cursor = c;
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let mut c = app.shell_cursor;
+                            App::list_next(len, &mut c);
+                            app.shell_cursor = c;
+                        }
+                        KeyCode::Enter => app.confirm_user_shell(),
+                        _ => {}
+                    }
+                }
+
                 // ---- Add another user? ----
                 Step::AddAnotherUser => match key.code {
                     KeyCode::Left | KeyCode::Char('h') => app.another_user_cursor = 0,
                     KeyCode::Right | KeyCode::Char('l') => app.another_user_cursor = 1,
+                    KeyCode::Tab | KeyCode::BackTab => app.another_user_cursor ^= 1,
+                    KeyCode::Char(c @ ('y' | 'n')) => {
+                        app.another_user_cursor = usize::from(c == 'n');
+                        app.confirm_another_user();
+                    }
                     KeyCode::Enter => app.confirm_another_user(),
                     _ => {}
                 },
 
+                // ---- Review users ----
+                Step::ReviewUsers => {
+                    let len = app.review_user_items();
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            let mut c = app.review_user_cursor;
+                            App::list_prev(len, &mut c);
+                            app.review_user_cursor = c;
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let mut c = app.review_user_cursor;
+                            App::list_next(len, &mut c);
+                            app.review_user_cursor = c;
+                        }
+                        KeyCode::Char('d') | KeyCode::Delete => app.delete_reviewed_user(),
+                        KeyCode::Char('a') => app.toggle_autologin_user(),
+                        KeyCode::Enter => app.confirm_review_users(),
+                        _ => {}
+                    }
+                }
+
                 // ---- HM module selection ----
                 Step::SelectHmModules => {
                     let len = app.hm_modules.len();
@@ -492,11 +1064,7 @@ fn run(
                             App::list_next(len, &mut c);
                             app.hm_cursor = c;
                         }
-                        KeyCode::Char(' ') => {
-                            if let Some(m) = app.hm_modules.get_mut(app.hm_cursor) {
-                                m.selected = !m.selected;
-                            }
-                        }
+                        KeyCode::Char(' ') => app.toggle_current_module(),
                         KeyCode::Enter => app.confirm_hm_modules(),
                         _ => {}
                     }
@@ -516,11 +1084,7 @@ fn run(
                             App::list_next(len, &mut c);
                             app.user_pkg_cursor = c;
                         }
-                        KeyCode::Char(' ') => {
-                            if let Some(m) = app.user_pkg_modules.get_mut(app.user_pkg_cursor) {
-                                m.selected = !m.selected;
-                            }
-                        }
+                        KeyCode::Char(' ') => app.toggle_current_module(),
                         KeyCode::Enter => app.confirm_user_packages(),
                         _ => {}
                     }
@@ -540,6 +1104,7 @@ fn run(
                             App::list_next(len, &mut c);
                             app.disk_cursor = c;
                         }
+                        KeyCode::Char(' ') => app.toggle_disk_mark(),
                         KeyCode::Enter => app.confirm_disk(),
                         _ => {}
                     }
@@ -549,18 +1114,161 @@ fn run(
                 Step::PartitionModeSelect => match key.code {
                     KeyCode::Up | KeyCode::Char('k') => {
                         let mut c = app.partition_mode_cursor;
-                        App::list_prev(2, &mut c);
+                        App::list_prev(3, &mut c);
                         app.partition_mode_cursor = c;
                     }
                     KeyCode::Down | KeyCode::Char('j') => {
                         let mut c = app.partition_mode_cursor;
-                        App::list_next(2, &mut c);
+                        App::list_next(3, &mut c);
                         app.partition_mode_cursor = c;
                     }
                     KeyCode::Enter => app.confirm_partition_mode(),
                     _ => {}
                 },
 
+                // ---- EFI partition size ----
+                Step::EfiSize => match key.code {
+                    KeyCode::Enter => app.confirm_efi_size(),
+                    KeyCode::Backspace => {
+                        app.efi_size_input.pop();
+                    }
+                    KeyCode::Char('h') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.efi_size_input.pop();
+                    }
+                    KeyCode::Char(c)
+                        if c.is_ascii_digit()
+                            && !key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        app.efi_size_input.push(c)
+                    }
+                    _ => {}
+                },
+
+                // ---- Full disk: root filesystem ----
+                Step::RootFs => {
+                    let len = FsType::ROOT.len();
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            let mut c = app.root_fs_cursor;
+                            App::list_prev(len, &mut c);
+                            app.root_fs_cursor = c;
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let mut c = app.root_fs_cursor;
+                            App::list_next(len, &mut c);
+                            app.root_fs_cursor = c;
+                        }
+                        KeyCode::Enter => app.confirm_root_fs(),
+                        _ => {}
+                    }
+                }
+
+                // ---- Bootloader (custom hosts) ----
+                Step::Bootloader => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        let mut c = app.bootloader_cursor;
+                        App::list_prev(3, &mut c);
+                        app.bootloader_cursor = c;
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        let mut c = app.bootloader_cursor;
+                        App::list_next(3, &mut c);
+                        app.bootloader_cursor = c;
+                    }
+                    KeyCode::Enter => app.confirm_bootloader(),
+                    _ => {}
+                },
+
+                // ---- Kernel (custom hosts) ----
+                Step::Kernel => {
+                    let len = nix::Kernel::ALL.len() + 1;
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            let mut c = app.kernel_cursor;
+                            App::list_prev(len, &mut c);
+                            app.kernel_cursor = c;
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let mut c = app.kernel_cursor;
+                            App::list_next(len, &mut c);
+                            app.kernel_cursor = c;
+                        }
+                        KeyCode::Enter => app.confirm_kernel(),
+                        _ => {}
+                    }
+                }
+
+                // ---- Graphics driver (custom hosts) ----
+                Step::Gpu => {
+                    let len = nix::GpuDriver::ALL.len() + 1;
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            let mut c = app.gpu_cursor;
+                            App::list_prev(len, &mut c);
+                            app.gpu_cursor = c;
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let mut c = app.gpu_cursor;
+                            App::list_next(len, &mut c);
+                            app.gpu_cursor = c;
+                        }
+                        KeyCode::Enter => app.confirm_gpu(),
+                        _ => {}
+                    }
+                }
+
+                // ---- Full disk: swap type ----
+                Step::SwapMode => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        let mut c = app.swap_mode_cursor;
+                        App::list_prev(4, &mut c);
+                        app.swap_mode_cursor = c;
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        let mut c = app.swap_mode_cursor;
+                        App::list_next(4, &mut c);
+                        app.swap_mode_cursor = c;
+                    }
+                    KeyCode::Enter => app.confirm_swap_mode(),
+                    _ => {}
+                },
+
+                // ---- zram percentage ----
+                Step::ZramPercent => match key.code {
+                    KeyCode::Enter => app.confirm_zram_percent(),
+                    KeyCode::Backspace => {
+                        app.zram_percent_input.pop();
+                    }
+                    KeyCode::Char('h') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.zram_percent_input.pop();
+                    }
+                    KeyCode::Char(c)
+                        if c.is_ascii_digit()
+                            && !key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        app.zram_percent_input.push(c)
+                    }
+                    _ => {}
+                },
+
+                // ---- Tmpfs root: /persist size ----
+                Step::PersistSize => match key.code {
+                    KeyCode::Enter => app.confirm_persist_size(),
+                    KeyCode::Backspace => {
+                        app.persist_size_input.pop();
+                    }
+                    KeyCode::Char('h') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.persist_size_input.pop();
+                    }
+                    KeyCode::Char(c)
+                        if c.is_ascii_digit()
+                            && !key.modifiers.contains(KeyModifiers::CONTROL) =>
+                    {
+                        app.persist_size_input.push(c)
+                    }
+                    _ => {}
+                },
+
                 // ---- Swap size ----
                 Step::SwapSize => match key.code {
                     KeyCode::Enter => app.confirm_swap_size(),
@@ -571,7 +1279,7 @@ fn run(
                         app.swap_size_input.pop();
                     }
                     KeyCode::Char(c)
-                        if c.is_ascii_digit()
+                        if app.accepts_swap_char(c)
                             && !key.modifiers.contains(KeyModifiers::CONTROL) =>
                     {
                         app.swap_size_input.push(c)
@@ -612,6 +1320,25 @@ fn run(
                     _ => {}
                 },
 
+                // ---- Custom partition: disk ----
+                Step::CustomPartitionDisk => {
+                    let len = app.install_disks().len();
+                    match key.code {
+                        KeyCode::Up | KeyCode::Char('k') => {
+                            let mut c = app.part_disk_cursor;
+                            App::list_prev(len, &mut c);
+                            app.part_disk_cursor = c;
+                        }
+                        KeyCode::Down | KeyCode::Char('j') => {
+                            let mut c = app.part_disk_cursor;
+                            App::list_next(len, &mut c);
+                            app.part_disk_cursor = c;
+                        }
+                        KeyCode::Enter => app.confirm_custom_disk(),
+                        _ => {}
+                    }
+                }
+
                 // ---- Custom partition: filesystem type ----
                 Step::CustomPartitionFs => {
                     let len = FsType::all().len();
@@ -631,10 +1358,28 @@ fn run(
                     }
                 }
 
+                // ---- Format the custom partition? ----
+                Step::CustomPartitionFormat => match key.code {
+                    KeyCode::Left | KeyCode::Char('h') => app.part_format_cursor = 0,
+                    KeyCode::Right | KeyCode::Char('l') => app.part_format_cursor = 1,
+                    KeyCode::Tab | KeyCode::BackTab => app.part_format_cursor ^= 1,
+                    KeyCode::Char(c @ ('y' | 'n')) => {
+                        app.part_format_cursor = usize::from(c == 'n');
+                        app.confirm_custom_format();
+                    }
+                    KeyCode::Enter => app.confirm_custom_format(),
+                    _ => {}
+                },
+
                 // ---- Add another partition? ----
                 Step::CustomPartitionAnother => match key.code {
                     KeyCode::Left | KeyCode::Char('h') => app.another_partition_cursor = 0,
                     KeyCode::Right | KeyCode::Char('l') => app.another_partition_cursor = 1,
+                    KeyCode::Tab | KeyCode::BackTab => app.another_partition_cursor ^= 1,
+                    KeyCode::Char(c @ ('y' | 'n')) => {
+                        app.another_partition_cursor = usize::from(c == 'n');
+                        app.confirm_custom_another();
+                    }
                     KeyCode::Enter => app.confirm_custom_another(),
                     _ => {}
                 },
@@ -643,39 +1388,95 @@ fn run(
                 Step::Confirm => match key.code {
                     KeyCode::Left | KeyCode::Char('h') => app.confirm_cursor = 0,
                     KeyCode::Right | KeyCode::Char('l') => app.confirm_cursor = 1,
+                    KeyCode::Tab | KeyCode::BackTab => app.confirm_cursor ^= 1,
+                    KeyCode::Char(c @ ('y' | 'n')) => {
+                        app.confirm_cursor = usize::from(c == 'n');
+                        app.confirm_install();
+                    }
                     KeyCode::Char(' ') => {
                         app.accept_flake_config = !app.accept_flake_config;
                     }
+                    KeyCode::Char('t') if app.offers_fstrim() => {
+                        app.enable_fstrim = !app.enable_fstrim;
+                    }
+                    KeyCode::Char('w') => app.secure_wipe = !app.secure_wipe,
+                    KeyCode::Char('e') => app.toggle_encryption(),
+                    KeyCode::Char('p') => app.open_preview(),
+                    KeyCode::Up | KeyCode::Char('k') if app.can_reorder_partitions() => {
+                        app.move_confirm_partition_cursor(false)
+                    }
+                    KeyCode::Down | KeyCode::Char('j') if app.can_reorder_partitions() => {
+                        app.move_confirm_partition_cursor(true)
+                    }
+                    KeyCode::Char('K') if app.can_reorder_partitions() => app.move_partition(false),
+                    KeyCode::Char('J') if app.can_reorder_partitions() => app.move_partition(true),
                     KeyCode::Enter => app.confirm_install(),
                     _ => {}
                 },
 
+                // ---- Preview generated files ----
+                Step::PreviewConfig => match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => app.scroll_log(false),
+                    KeyCode::Down | KeyCode::Char('j') => app.scroll_log(true),
+                    KeyCode::Enter | KeyCode::Char('p') => app.step = Step::Confirm,
+                    _ => {}
+                },
+
+                // ---- Disk encryption passphrase ----
+                Step::EncryptionPassphrase => match key.code {
+                    KeyCode::Enter => app.confirm_encryption_passphrase(),
+                    KeyCode::Backspace => {
+                        app.encryption_passphrase.pop();
+                    }
+                    KeyCode::Char('h') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.encryption_passphrase.pop();
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.encryption_passphrase.push(c)
+                    }
+                    _ => {}
+                },
+
+                // ---- Disk encryption passphrase confirm ----
+                Step::EncryptionPassphraseConfirm => match key.code {
+                    KeyCode::Enter => app.confirm_encryption_passphrase_confirm(),
+                    KeyCode::Backspace => {
+                        app.encryption_passphrase_confirm.pop();
+                    }
+                    KeyCode::Char('h') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.encryption_passphrase_confirm.pop();
+                    }
+                    KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.encryption_passphrase_confirm.push(c)
+                    }
+                    _ => {}
+                },
+
                 // ---- Installing (wait) ----
                 Step::Installing => {
                     match key.code {
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            app.auto_scroll = false;
-                            if app.log_scroll > 0 {
-                                app.log_scroll -= 1;
-                            }
+                        KeyCode::Up | KeyCode::Char('k') => app.scroll_log(false),
+                        // Re-enables auto-scroll if the user scrolled to bottom
+                        KeyCode::Down | KeyCode::Char('j') => app.scroll_log(true),
+                        KeyCode::Char('d') if app.install_error.is_some() => {
+                            app.show_install_diagnostics()
                         }
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            let max = app.install_log.len().saturating_sub(1);
-                            if app.log_scroll < max {
-                                app.log_scroll += 1;
-                            }
-                            // Re-enable auto-scroll if user scrolled to bottom
-                            if app.log_scroll >= max {
-                                app.auto_scroll = true;
-                            }
+                        KeyCode::Char('y') if app.install_error.is_some() => {
+                            app.copy_install_log()
+                        }
+                        KeyCode::Char('s') if app.install_error.is_some() => {
+                            app.export_install_log()
                         }
                         KeyCode::Enter => {
                             if app.install_done {
-                                app.step = Step::RootPassword;
+                                app.finish_installation();
                             } else if app.install_error.is_some() {
-                                app.should_quit = true;
+                                app.retry_installation();
                             }
                         }
+                        KeyCode::Char('q') if app.install_error.is_some() => {
+                            app.should_quit = true;
+                        }
                         _ => {}
                     }
                 }
@@ -742,8 +1543,33 @@ fn run(
 
                 // ---- Complete ----
                 Step::Complete => match key.code {
-                    KeyCode::Left | KeyCode::Char('h') => app.reboot_cursor = 0,
-                    KeyCode::Right | KeyCode::Char('l') => app.reboot_cursor = 1,
+                    KeyCode::Left | KeyCode::Char('h') if !app.config_only => {
+                        app.reboot_cursor = app.reboot_cursor.saturating_sub(1);
+                    }
+                    KeyCode::Right | KeyCode::Char('l') if !app.config_only => {
+                        app.reboot_cursor = (app.reboot_cursor + 1).min(3);
+                    }
+                    KeyCode::Tab if !app.config_only => {
+                        app.reboot_cursor = (app.reboot_cursor + 1) % 4;
+                    }
+                    KeyCode::BackTab if !app.config_only => {
+                        app.reboot_cursor = (app.reboot_cursor + 3) % 4;
+                    }
+                    // y reboots, n exits without rebooting
+                    KeyCode::Char(c @ ('y' | 'n')) if !app.config_only => {
+                        app.reboot_cursor = if c == 'y' { 0 } else { 3 };
+                        app.confirm_reboot();
+                    }
+                    KeyCode::Enter if app.reboot_cursor == 2 && !app.config_only => {
+                        // Hand the terminal to nixos-enter, then come back
+                        restore_terminal();
+                        let result = disk::enter_chroot();
+                        *terminal = ratatui::init();
+                        let _ = crossterm::execute!(io::stdout(), EnableMouseCapture);
+                        if let Err(e) = result {
+                            app.set_status(e);
+                        }
+                    }
                     KeyCode::Enter => app.confirm_reboot(),
                     _ => {}
                 },
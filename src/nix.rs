@@ -1,31 +1,395 @@
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 
-/// Represents an existing host preset found in ./modules/hosts/.
+/// Where the module directories live, relative to the repo root. The
+/// defaults match the nixos-dots layout (`modules/nixosModules`, ...).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleLayout {
+    pub nixos_modules: PathBuf,
+    pub hm_modules: PathBuf,
+    pub packages: PathBuf,
+    pub hosts: PathBuf,
+}
+
+impl Default for ModuleLayout {
+    fn default() -> Self {
+        let modules = Path::new("modules");
+        Self {
+            nixos_modules: modules.join("nixosModules"),
+            hm_modules: modules.join("homeManagerModules"),
+            packages: modules.join("packages"),
+            hosts: modules.join("hosts"),
+        }
+    }
+}
+
+impl ModuleLayout {
+    /// The directory of `host_name` under the hosts directory.
+    pub fn host_dir(&self, base_path: &Path, host_name: &str) -> PathBuf {
+        base_path.join(&self.hosts).join(host_name)
+    }
+
+    /// Whether any of the module directories exists under `base_path`.
+    pub fn exists_in(&self, base_path: &Path) -> bool {
+        self.entries().iter().any(|(_, dir)| base_path.join(dir).is_dir())
+    }
+
+    /// (config key, relative path) of every module directory.
+    fn entries(&self) -> [(&'static str, &Path); 4] {
+        [
+            ("nixos_modules_dir", &self.nixos_modules),
+            ("hm_modules_dir", &self.hm_modules),
+            ("packages_dir", &self.packages),
+            ("hosts_dir", &self.hosts),
+        ]
+    }
+}
+
+/// Represents an existing host preset found in the hosts directory.
 #[derive(Debug, Clone)]
 pub struct HostPreset {
     pub name: String,
-    #[allow(dead_code)]
     pub path: PathBuf,
-    #[allow(dead_code)]
     pub has_hardware_config: bool,
 }
 
 /// Represents a discovered NixOS or Home Manager module.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NixModule {
     pub name: String,
     pub selected: bool,
+    /// Parent directory relative to the scanned module root (e.g. "desktop"
+    /// or "services/web"). Empty for modules at the top level.
+    pub category: String,
+    /// Modules that can't be selected together with this one, from
+    /// `# installer:conflicts-with` comments or an `installer.toml`.
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    /// Marked `# installer:required`: always selected and emitted.
+    #[serde(default)]
+    pub required: bool,
+    /// Matches a `hidden_modules` pattern and is only listed because hidden
+    /// modules were revealed (`A` on the module lists).
+    #[serde(default)]
+    pub revealed: bool,
+}
+
+/// Bootloader written into a generated host configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Bootloader {
+    SystemdBoot,
+    Grub,
+}
+
+impl Bootloader {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Bootloader::SystemdBoot => "systemd-boot",
+            Bootloader::Grub => "GRUB",
+        }
+    }
+}
+
+/// Kernel package set written into a generated host configuration. Hosts
+/// without one use the nixpkgs default.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kernel {
+    Lts,
+    Latest,
+    Zen,
+}
+
+impl Kernel {
+    pub const ALL: [Kernel; 3] = [Kernel::Lts, Kernel::Latest, Kernel::Zen];
+
+    /// Attribute of the package set in nixpkgs.
+    pub fn attr(&self) -> &'static str {
+        match self {
+            Kernel::Lts => "linuxPackages",
+            Kernel::Latest => "linuxPackages_latest",
+            Kernel::Zen => "linuxPackages_zen",
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Kernel::Lts => "LTS",
+            Kernel::Latest => "Latest",
+            Kernel::Zen => "Zen",
+        }
+    }
+}
+
+/// Graphics driver boilerplate written into a generated host
+/// configuration. A best-effort starting point to review after install.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GpuDriver {
+    Nvidia,
+    Amd,
+    Intel,
+}
+
+impl GpuDriver {
+    pub const ALL: [GpuDriver; 3] = [GpuDriver::Nvidia, GpuDriver::Amd, GpuDriver::Intel];
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            GpuDriver::Nvidia => "NVIDIA",
+            GpuDriver::Amd => "AMD",
+            GpuDriver::Intel => "Intel",
+        }
+    }
+
+    /// Settings-block lines for this driver.
+    fn lines(&self) -> &'static [&'static str] {
+        match self {
+            GpuDriver::Nvidia => &[
+                "        services.xserver.videoDrivers = [ \"nvidia\" ];",
+                "        hardware.nvidia.modesetting.enable = true;",
+                "        # The open kernel module needs Turing (GTX 16xx / RTX 20xx) or newer",
+                "        hardware.nvidia.open = true;",
+            ],
+            GpuDriver::Amd => &[
+                "        services.xserver.videoDrivers = [ \"amdgpu\" ];",
+                "        hardware.amdgpu.initrd.enable = true;",
+            ],
+            GpuDriver::Intel => &[
+                "        services.xserver.videoDrivers = [ \"modesetting\" ];",
+                "        hardware.graphics.extraPackages = [ pkgs.intel-media-driver ];",
+            ],
+        }
+    }
+}
+
+/// The GPU vendor in `lspci` output. With several GPUs (hybrid laptops) the
+/// discrete one wins: NVIDIA, then AMD, then Intel.
+pub fn gpu_from_lspci(output: &str) -> Option<GpuDriver> {
+    let gpus: Vec<String> = output
+        .lines()
+        .filter(|l| {
+            l.contains("VGA compatible controller")
+                || l.contains("3D controller")
+                || l.contains("Display controller")
+        })
+        .map(str::to_ascii_lowercase)
+        .collect();
+    let has = |names: &[&str]| gpus.iter().any(|l| names.iter().any(|n| l.contains(n)));
+    if has(&["nvidia"]) {
+        Some(GpuDriver::Nvidia)
+    } else if has(&["advanced micro devices", "amd/ati", " ati "]) {
+        Some(GpuDriver::Amd)
+    } else if has(&["intel"]) {
+        Some(GpuDriver::Intel)
+    } else {
+        None
+    }
+}
+
+/// How Home Manager is wired into the flake.
+///
+/// - `NixosModule`: the flake exports `nixosModules.home-manager` (the HM
+///   NixOS module); users' HM modules are imported through
+///   `home-manager.users.<name>.imports` and activated by nixos-install.
+/// - `Standalone`: the flake has a `home-manager` input and HM runs on its
+///   own; each user gets `homeConfigurations."<user>@<host>"` (built with
+///   `inputs.home-manager.lib.homeManagerConfiguration`), whose activation
+///   package is built into the new system and activated on first boot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HmMode {
+    #[default]
+    NixosModule,
+    Standalone,
+}
+
+/// Login shell for a created user. Users without one keep the NixOS
+/// default (bash).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+}
+
+impl Shell {
+    pub const ALL: [Shell; 4] = [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Nushell];
+
+    /// Attribute name of the shell package in nixpkgs.
+    pub fn package(&self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Nushell => "nushell",
+        }
+    }
+
+    /// `programs.<name>` module that must be enabled for the shell to be a
+    /// valid login shell, if NixOS has one.
+    fn program(&self) -> Option<&'static str> {
+        match self {
+            Shell::Zsh => Some("zsh"),
+            Shell::Fish => Some("fish"),
+            Shell::Bash | Shell::Nushell => None,
+        }
+    }
+}
+
+/// How generated files refer to flake outputs. Each template has `{name}`
+/// replaced by the module name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttrRefs {
+    /// Reference to a NixOS module (default `self.nixosModules.{name}`).
+    pub nixos_module: String,
+    /// Reference to a Home Manager module (default
+    /// `self.homeManagerModules.{name}`).
+    pub hm_module: String,
+    /// Module name of a package set file (default `packages-{name}`, where
+    /// `{name}` is the file stem).
+    pub package: String,
+}
+
+impl Default for AttrRefs {
+    fn default() -> Self {
+        Self {
+            nixos_module: "self.nixosModules.{name}".to_string(),
+            hm_module: "self.homeManagerModules.{name}".to_string(),
+            package: "packages-{name}".to_string(),
+        }
+    }
+}
+
+impl AttrRefs {
+    pub fn nixos(&self, name: &str) -> String {
+        self.nixos_module.replace("{name}", name)
+    }
+
+    pub fn hm(&self, name: &str) -> String {
+        self.hm_module.replace("{name}", name)
+    }
+
+    pub fn package_name(&self, stem: &str) -> String {
+        self.package.replace("{name}", stem)
+    }
+
+    /// Reference to a generated user module. Its definition is derived from
+    /// it (see `nixos_definition`), so a template that isn't a `self.` path
+    /// falls back to the default one for both.
+    pub fn user_module(&self, name: &str) -> String {
+        let reference = self.nixos(name);
+        if reference.starts_with("self.") {
+            reference
+        } else {
+            AttrRefs::default().nixos(name)
+        }
+    }
+
+    /// The flake-parts attribute a generated user module is defined as: its
+    /// `user_module` reference with `self.` swapped for `flake.`.
+    fn nixos_definition(&self, name: &str) -> String {
+        let reference = self.user_module(name);
+        format!("flake.{}", reference.strip_prefix("self.").unwrap_or(&reference))
+    }
+
+    /// Whether NixOS modules are referenced as `self.nixosModules.<name>`,
+    /// so names can be checked against the flake's `nixosModules`.
+    pub fn uses_default_nixos_modules(&self) -> bool {
+        self.nixos_module == AttrRefs::default().nixos_module
+    }
+}
+
+/// Per-user settings emitted into a generated `user-<name>.nix`.
+#[derive(Debug, Clone, Default)]
+pub struct UserSettings {
+    /// Supplementary groups (`extraGroups`).
+    pub groups: Vec<String>,
+    /// Login shell; `None` keeps the NixOS default.
+    pub shell: Option<Shell>,
+    /// The host's autologin user, for preset hosts whose
+    /// `configuration.nix` is not generated (set for one user module only).
+    pub autologin_user: Option<String>,
+    /// Crypt hash emitted as `hashedPassword` (password_mode = "hashed").
+    pub hashed_password: Option<String>,
+    /// Root's `hashedPassword`, for preset hosts whose `configuration.nix`
+    /// is not generated.
+    pub root_hashed_password: Option<String>,
+    /// How module references are written.
+    pub refs: AttrRefs,
+    /// Emit the user's Home Manager imports.
+    pub home_manager: bool,
+    /// Where the imports go: `home-manager.users.<name>` or a standalone
+    /// `homeConfigurations` entry.
+    pub hm_mode: HmMode,
+    /// The host's `nixosConfigurations` attribute, if it isn't the host name.
+    pub flake_attr: Option<String>,
+    /// SSH public keys emitted as `openssh.authorizedKeys.keys`.
+    pub authorized_keys: Vec<String>,
+    /// Enable the SSH daemon, for preset hosts whose `configuration.nix` is
+    /// not generated.
+    pub openssh: bool,
+}
+
+/// Host-level settings emitted into the inline settings block of a
+/// generated `configuration.nix`, next to `networking.hostName`.
+#[derive(Debug, Clone, Default)]
+pub struct HostSettings {
+    /// Enable zram swap using this percentage of RAM.
+    pub zram_percent: Option<u8>,
+    /// Bootloader to configure. `None` leaves it to the selected modules,
+    /// falling back to the boot-mode default if none of them provides one.
+    pub bootloader: Option<Bootloader>,
+    /// `boot.kernelPackages`; `None` keeps the nixpkgs default.
+    pub kernel: Option<Kernel>,
+    /// Graphics driver boilerplate; `None` writes nothing.
+    pub gpu: Option<GpuDriver>,
+    /// Whether the target boots via UEFI (GRUB gets `efiSupport`).
+    pub uefi: bool,
+    /// Where the ESP is mounted, if not at /boot (`efiSysMountPoint`).
+    pub efi_mount_point: Option<String>,
+    /// Root's `hashedPassword` (password_mode = "hashed").
+    pub root_hashed_password: Option<String>,
+    /// Disk GRUB is installed to in BIOS mode (e.g. "/dev/sda").
+    pub grub_device: Option<String>,
+    /// Enable the weekly `fstrim` timer (SSD/NVMe disks).
+    pub fstrim: bool,
+    /// How module references are written.
+    pub refs: AttrRefs,
+    /// Import the flake's `home-manager` NixOS module for the users (only
+    /// in `HmMode::NixosModule`).
+    pub home_manager: bool,
+    pub hm_mode: HmMode,
+    /// `system.stateVersion`, normally the release of the install media.
+    pub state_version: Option<String>,
+    /// The `nixosConfigurations` attribute to generate, if it isn't the host
+    /// name.
+    pub flake_attr: Option<String>,
+    /// Public key authorized for the first user; also enables the SSH
+    /// daemon.
+    pub ssh_authorized_key: Option<String>,
+    /// User logged in automatically on the console and in the display
+    /// manager. These options are host-wide, so there is at most one.
+    pub autologin_user: Option<String>,
+    /// `configuration.nix` of a preset being edited: its imports are
+    /// updated (`update_preset_imports`) instead of generating a new one.
+    pub preset_config: Option<String>,
 }
 
 // ---------------------------------------------------------------------------
 // fd-based module discovery
 // ---------------------------------------------------------------------------
 
-/// Use `fd` to find all `.nix` files under a directory.
-/// Tries bare `fd` first; if unavailable, falls back to `nix-shell -p fd --run`.
+/// Use `fd` to find all `.nix` files under a directory, or `find` when fd
+/// is missing or fails.
 /// Returns a list of (module_name, file_path) pairs.
 /// The module name is the filename stem (without `.nix`).
 /// Duplicates are eliminated (first occurrence wins).
@@ -90,18 +454,98 @@ fn discover_nix_files_with_fd(dir: &Path) -> Vec<(String, PathBuf)> {
     results
 }
 
+/// Derive a module's category from its path relative to the scanned `root`.
+/// `desktop/hyprland.nix` and `desktop/hyprland/default.nix` both yield
+/// `desktop`; top-level modules yield an empty string.
+fn module_category(root: &Path, path: &Path) -> String {
+    let mut parent = path.parent();
+    // Directory-modules are named after their directory, so the category
+    // is one level further up.
+    if path.file_stem().and_then(|s| s.to_str()) == Some("default") {
+        parent = parent.and_then(|p| p.parent());
+    }
+    let Some(parent) = parent else {
+        return String::new();
+    };
+
+    // fd reports absolute paths while `root` may be relative, so also try
+    // the canonicalized root before giving up.
+    let relative = parent.strip_prefix(root).ok().map(Path::to_path_buf).or_else(|| {
+        root.canonicalize()
+            .ok()
+            .and_then(|r| parent.strip_prefix(r).ok().map(Path::to_path_buf))
+    });
+
+    relative
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
 // ---------------------------------------------------------------------------
 // Filtering patterns
 // ---------------------------------------------------------------------------
 
-/// Check whether a NixOS module name should be filtered out from user selection.
-fn should_skip_nixos_module(name: &str) -> bool {
-    name.starts_with("home-") || name == "wsl"
+/// Name patterns of modules left out of the selection lists. Each entry is
+/// a name or a glob where `*` matches any run of characters; matching
+/// ignores case.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HiddenModules {
+    /// NixOS modules (default: `home-*`, `wsl`).
+    pub nixos: Vec<String>,
+    /// Home Manager modules (default: `home`, `home-wsl`, `packages-*`).
+    pub hm: Vec<String>,
+    /// Package set files, by file stem (default: `*wsl*`).
+    pub packages: Vec<String>,
+    /// List matching modules anyway, marked `revealed`, instead of leaving
+    /// them out.
+    pub reveal: bool,
+}
+
+impl Default for HiddenModules {
+    fn default() -> Self {
+        let patterns = |p: &[&str]| p.iter().map(|s| s.to_string()).collect();
+        Self {
+            nixos: patterns(&["home-*", "wsl"]),
+            hm: patterns(&["home", "home-wsl", "packages-*"]),
+            packages: patterns(&["*wsl*"]),
+            reveal: false,
+        }
+    }
+}
+
+/// Whether `name` matches any of `patterns`.
+fn is_hidden(patterns: &[String], name: &str) -> bool {
+    patterns.iter().any(|p| glob_match(p, name))
+}
+
+/// Case-insensitive match of `name` against `pattern`, where `*` matches any
+/// (possibly empty) run of characters.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let name = name.to_lowercase();
+    let mut parts = pattern.split('*');
+    // split always yields at least one part
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*`: the whole name must match
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
 }
 
-/// Check whether a Home Manager module name should be filtered out from user selection.
-fn should_skip_hm_module(name: &str) -> bool {
-    name == "home" || name == "home-wsl" || name.starts_with("packages-")
+/// WSL hosts are not offered for bare-metal installs.
+fn is_wsl(name: &str) -> bool {
+    name.to_lowercase().contains("wsl")
 }
 
 // ---------------------------------------------------------------------------
@@ -110,25 +554,24 @@ fn should_skip_hm_module(name: &str) -> bool {
 
 /// Validate that the base path contains the expected module directories.
 /// Returns a list of warning messages for any missing directories.
-pub fn validate_base_path(base_path: &Path) -> Vec<String> {
-    let mut warnings = Vec::new();
-    let modules_dir = base_path.join("modules");
-
-    if !modules_dir.is_dir() {
-        warnings.push(format!(
-            "modules/ directory not found at '{}'. Module scanning will not work.",
+pub fn validate_base_path(base_path: &Path, layout: &ModuleLayout) -> Vec<String> {
+    if !layout.exists_in(base_path) {
+        return vec![format!(
+            "No module directories ({}, ...) found at '{}'. Module scanning will not work.",
+            layout.nixos_modules.display(),
             base_path.display()
-        ));
-        return warnings;
+        )];
     }
 
-    for subdir in &["nixosModules", "homeManagerModules", "packages", "hosts"] {
-        let dir = modules_dir.join(subdir);
+    let mut warnings = Vec::new();
+    for (key, subdir) in layout.entries() {
+        let dir = base_path.join(subdir);
         if !dir.is_dir() {
             warnings.push(format!(
-                "modules/{} directory not found at '{}'",
-                subdir,
-                dir.display()
+                "{}/ directory not found at '{}' (set {} in config.toml)",
+                subdir.display(),
+                dir.display(),
+                key
             ));
         }
     }
@@ -140,10 +583,10 @@ pub fn validate_base_path(base_path: &Path) -> Vec<String> {
 // Scanning
 // ---------------------------------------------------------------------------
 
-/// Scan ./modules/hosts/<name>/* for existing host presets.
-/// Each subdirectory under hosts/ is a host preset.
-pub fn scan_host_presets(base_path: &Path) -> Vec<HostPreset> {
-    let hosts_dir = base_path.join("modules").join("hosts");
+/// Scan <hosts dir>/<name>/* for existing host presets.
+/// Each subdirectory under the hosts directory is a host preset.
+pub fn scan_host_presets(base_path: &Path, layout: &ModuleLayout) -> Vec<HostPreset> {
+    let hosts_dir = base_path.join(&layout.hosts);
     let mut presets = Vec::new();
 
     match fs::read_dir(&hosts_dir) {
@@ -152,7 +595,7 @@ pub fn scan_host_presets(base_path: &Path) -> Vec<HostPreset> {
                 if entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false) {
                     let name = entry.file_name().to_string_lossy().to_string();
                     // Skip WSL hosts (they are not selectable presets)
-                    if name.to_lowercase().contains("wsl") {
+                    if is_wsl(&name) {
                         continue;
                     }
                     let path = entry.path();
@@ -174,102 +617,566 @@ pub fn scan_host_presets(base_path: &Path) -> Vec<HostPreset> {
     presets
 }
 
-/// Scan ./modules/nixosModules/ for available NixOS modules using `fd`.
+/// Scan the NixOS module directory (`modules/nixosModules/` by default) for
+/// available NixOS modules using `fd`.
 /// Each `.nix` file becomes a module (name = file stem).
 /// Directories with `default.nix` become modules (name = directory name).
-pub fn scan_nixos_modules(base_path: &Path) -> Vec<NixModule> {
-    let dir = base_path.join("modules").join("nixosModules");
-    scan_modules_in_dir(&dir, should_skip_nixos_module)
+pub fn scan_nixos_modules(
+    base_path: &Path,
+    layout: &ModuleLayout,
+    hidden: &HiddenModules,
+) -> Vec<NixModule> {
+    let dir = base_path.join(&layout.nixos_modules);
+    scan_modules_in_dir(&dir, &hidden.nixos, hidden.reveal)
 }
 
-/// Scan ./modules/homeManagerModules/ for available Home Manager modules using `fd`.
+/// Scan the Home Manager module directory (`modules/homeManagerModules/` by
+/// default) for available Home Manager modules using `fd`.
 /// Each `.nix` file becomes a module (name = file stem).
 /// Directories with `default.nix` become modules (name = directory name).
-pub fn scan_hm_modules(base_path: &Path) -> Vec<NixModule> {
-    let dir = base_path.join("modules").join("homeManagerModules");
-    scan_modules_in_dir(&dir, should_skip_hm_module)
+pub fn scan_hm_modules(
+    base_path: &Path,
+    layout: &ModuleLayout,
+    hidden: &HiddenModules,
+) -> Vec<NixModule> {
+    let dir = base_path.join(&layout.hm_modules);
+    scan_modules_in_dir(&dir, &hidden.hm, hidden.reveal)
 }
 
-/// Scan ./modules/packages/ for available package sets using `fd`.
+/// Scan the package directory (`modules/packages/` by default) for available
+/// package sets using `fd`.
 /// Only `.nix` files are considered (name = file stem).
-/// The flake registers these as `packages-<name>`, so we prepend the prefix.
-pub fn scan_package_modules(base_path: &Path) -> Vec<NixModule> {
-    let dir = base_path.join("modules").join("packages");
+/// The flake registers these as `packages-<name>` (or whatever `refs.package`
+/// says), so the module name is built from that template.
+pub fn scan_package_modules(
+    base_path: &Path,
+    layout: &ModuleLayout,
+    hidden: &HiddenModules,
+    refs: &AttrRefs,
+) -> Vec<NixModule> {
+    let dir = base_path.join(&layout.packages);
     let collected = discover_nix_files_with_fd(&dir);
+    let mut tables = HashMap::new();
 
     let mut modules: Vec<NixModule> = collected
         .into_iter()
-        .filter(|(name, _)| !name.to_lowercase().contains("wsl"))
-        .map(|(name, _)| NixModule {
-            name: format!("packages-{}", name),
-            selected: false,
+        .filter(|(name, _)| hidden.reveal || !is_hidden(&hidden.packages, name))
+        .map(|(name, path)| {
+            let revealed = is_hidden(&hidden.packages, &name);
+            let mut m = scanned_module(&dir, name, &path, &mut tables);
+            m.revealed = revealed;
+            m.name = refs.package_name(&m.name);
+            m.conflicts = m.conflicts.iter().map(|c| refs.package_name(c)).collect();
+            m
         })
         .collect();
 
-    modules.sort_by(|a, b| a.name.cmp(&b.name));
+    sort_modules(&mut modules);
     modules
 }
 
-/// Scan a module directory using `fd` and apply a skip filter.
+/// Names in the module directories that the scans leave out of the
+/// selection lists (`hidden` patterns and WSL hosts), as (directory relative
+/// to `base_path`, name) pairs.
+pub fn filtered_module_names(
+    base_path: &Path,
+    layout: &ModuleLayout,
+    hidden: &HiddenModules,
+) -> Vec<(PathBuf, String)> {
+    let mut filtered = Vec::new();
+    let scans = [
+        (&layout.nixos_modules, &hidden.nixos),
+        (&layout.hm_modules, &hidden.hm),
+        (&layout.packages, &hidden.packages),
+    ];
+    for (dir, patterns) in scans {
+        for (name, _) in discover_nix_files_with_fd(&base_path.join(dir)) {
+            if is_hidden(patterns, &name) {
+                filtered.push((dir.to_path_buf(), name));
+            }
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(base_path.join(&layout.hosts)) {
+        let mut hosts: Vec<String> = entries
+            .flatten()
+            .filter(|e| e.file_type().map(|ft| ft.is_dir()).unwrap_or(false))
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|name| is_wsl(name))
+            .collect();
+        hosts.sort();
+        filtered.extend(hosts.into_iter().map(|name| (layout.hosts.clone(), name)));
+    }
+    filtered
+}
+
+/// Whether the repo has any Home Manager modules at all. Without them users
+/// get no `home-manager.users.<name>` block and the HM steps are skipped.
+pub fn has_hm_modules(base_path: &Path, layout: &ModuleLayout) -> bool {
+    !discover_nix_files_with_fd(&base_path.join(&layout.hm_modules)).is_empty()
+}
+
+/// Scan a module directory using `fd`, leaving out names matching `hidden`
+/// (or, with `reveal`, marking them `revealed`).
 /// The module name is the filename stem or directory name (for `default.nix`).
 /// Duplicate names are skipped (first found wins).
-fn scan_modules_in_dir(
-    dir: &Path,
-    skip_fn: fn(&str) -> bool,
-) -> Vec<NixModule> {
+fn scan_modules_in_dir(dir: &Path, hidden: &[String], reveal: bool) -> Vec<NixModule> {
     let collected = discover_nix_files_with_fd(dir);
+    let mut tables = HashMap::new();
 
     let mut modules: Vec<NixModule> = collected
         .into_iter()
-        .filter(|(name, _)| !skip_fn(name))
-        .map(|(name, _)| NixModule {
-            name,
-            selected: false,
+        .filter(|(name, _)| reveal || !is_hidden(hidden, name))
+        .map(|(name, path)| {
+            let revealed = is_hidden(hidden, &name);
+            NixModule { revealed, ..scanned_module(dir, name, &path, &mut tables) }
         })
         .collect();
 
-    modules.sort_by(|a, b| a.name.cmp(&b.name));
+    sort_modules(&mut modules);
     modules
 }
 
+/// A module found at `path` under the scanned `dir`, with the installer
+/// markers of its file applied. Required modules start out selected.
+fn scanned_module(
+    dir: &Path,
+    name: String,
+    path: &Path,
+    tables: &mut HashMap<PathBuf, HashMap<String, Vec<String>>>,
+) -> NixModule {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    let required = has_required_marker(&content);
+    NixModule {
+        category: module_category(dir, path),
+        conflicts: module_conflicts(&name, path, &content, tables),
+        name,
+        selected: required,
+        required,
+        revealed: false,
+    }
+}
+
+/// Conflicts declared for the module `name` at `path`: the
+/// `# installer:conflicts-with` comments in its `content` plus its entry in
+/// the `[conflicts]` table of an `installer.toml` next to it. `tables`
+/// caches the parsed files by directory.
+fn module_conflicts(
+    name: &str,
+    path: &Path,
+    content: &str,
+    tables: &mut HashMap<PathBuf, HashMap<String, Vec<String>>>,
+) -> Vec<String> {
+    let mut conflicts = conflict_comments(content);
+    // A directory module's default.nix sits inside the module's own directory
+    let file_dir = path.parent().unwrap_or(Path::new("."));
+    let dir = if path.file_stem().is_some_and(|s| s == "default") {
+        file_dir.parent().unwrap_or(file_dir)
+    } else {
+        file_dir
+    };
+    let table = tables.entry(dir.to_path_buf()).or_insert_with(|| {
+        #[derive(Deserialize)]
+        struct InstallerToml {
+            #[serde(default)]
+            conflicts: HashMap<String, Vec<String>>,
+        }
+        fs::read_to_string(dir.join("installer.toml"))
+            .ok()
+            .and_then(|s| toml::from_str::<InstallerToml>(&s).ok())
+            .map(|t| t.conflicts)
+            .unwrap_or_default()
+    });
+    for other in table.get(name).into_iter().flatten() {
+        if !conflicts.contains(other) {
+            conflicts.push(other.clone());
+        }
+    }
+    conflicts
+}
+
+/// Whether a module file has an `# installer:required` comment line.
+fn has_required_marker(content: &str) -> bool {
+    content.lines().any(|line| {
+        line.trim()
+            .strip_prefix('#')
+            .is_some_and(|c| c.trim() == "installer:required")
+    })
+}
+
+/// Names listed by `# installer:conflicts-with a b` (or `a, b`) comments.
+fn conflict_comments(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for line in content.lines() {
+        let Some(comment) = line.trim().strip_prefix('#') else {
+            continue;
+        };
+        let Some(rest) = comment.trim().strip_prefix("installer:conflicts-with") else {
+            continue;
+        };
+        for name in rest.split(|c: char| c == ',' || c.is_whitespace()) {
+            if !name.is_empty() && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Sort modules by category, then by name, so each category forms a
+/// contiguous group (top-level modules first).
+fn sort_modules(modules: &mut [NixModule]) {
+    modules.sort_by(|a, b| (&a.category, &a.name).cmp(&(&b.category, &b.name)));
+}
+
 // ---------------------------------------------------------------------------
 // Existence check
 // ---------------------------------------------------------------------------
 
+/// Check if a host directory already exists under the hosts directory.
+pub fn host_exists(base_path: &Path, layout: &ModuleLayout, host_name: &str) -> bool {
+    layout.host_dir(base_path, host_name).is_dir()
+}
+
+/// `host_name` if it has no host directory yet, else the first of
+/// `<host_name>-2`, `<host_name>-3`, ... that doesn't.
+pub fn next_free_host_name(base_path: &Path, layout: &ModuleLayout, host_name: &str) -> String {
+    if !host_exists(base_path, layout, host_name) {
+        return host_name.to_string();
+    }
+    (2..)
+        .map(|n| format!("{}-{}", host_name, n))
+        .find(|name| !host_exists(base_path, layout, name))
+        .unwrap_or_else(|| host_name.to_string())
+}
+
 /// Check if a user-<username>.nix already exists for this host.
-pub fn user_config_exists(base_path: &Path, host_name: &str, username: &str) -> bool {
-    let file = base_path
-        .join("modules")
-        .join("hosts")
-        .join(host_name)
+pub fn user_config_exists(
+    base_path: &Path,
+    layout: &ModuleLayout,
+    host_name: &str,
+    username: &str,
+) -> bool {
+    let file = layout
+        .host_dir(base_path, host_name)
         .join(format!("user-{}.nix", username));
     file.exists()
 }
 
+/// Usernames that already have a `user-<name>.nix` in the host directory,
+/// sorted by name.
+pub fn scan_existing_users(
+    base_path: &Path,
+    layout: &ModuleLayout,
+    host_name: &str,
+) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(layout.host_dir(base_path, host_name)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let file_name = e.file_name().to_string_lossy().into_owned();
+            let name = file_name.strip_prefix("user-")?.strip_suffix(".nix")?;
+            Some(name.to_string()).filter(|n| !n.is_empty())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+// ---------------------------------------------------------------------------
+// Flake verification
+// ---------------------------------------------------------------------------
+
+/// Attribute names the flake actually exposes under `nixosModules`, via
+/// `nix eval .#nixosModules --apply builtins.attrNames`. Scanned module
+/// names are derived from file names, so this catches modules the flake
+/// exports under a different name (or not at all).
+pub fn flake_nixos_module_names(base_path: &Path) -> Result<HashSet<String>, String> {
+    let flake_ref = flake_ref(base_path, "nixosModules");
+    let output = Command::new("nix")
+        .args(["--extra-experimental-features", "nix-command flakes"])
+        .args(["eval", "--json", &flake_ref, "--apply", "builtins.attrNames"])
+        .current_dir(base_path)
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => "'nix' is not installed or not on PATH".to_string(),
+            _ => format!("Failed to run 'nix eval': {}", e),
+        })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .rev()
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or("unknown error");
+        return Err(format!("nix eval failed: {}", reason.trim()));
+    }
+
+    serde_json::from_slice::<Vec<String>>(&output.stdout)
+        .map(|names| names.into_iter().collect())
+        .map_err(|e| format!("Unexpected nix eval output: {}", e))
+}
+
+/// Flake reference to `attr` of the flake at `base_path`, for commands run
+/// with `base_path` as the working directory.
+pub(crate) fn flake_ref(base_path: &Path, attr: &str) -> String {
+    format!("{}#{}", flake_url(base_path), attr)
+}
+
+/// The repo as a flake URL, for nix commands run in `base_path`.
+fn flake_url(base_path: &Path) -> String {
+    // Nix refuses shallow git repos unless the flake ref opts in
+    if crate::disk::is_shallow_repo(base_path) {
+        format!("git+file://{}?shallow=1", base_path.display())
+    } else {
+        ".".to_string()
+    }
+}
+
+/// Lets another thread stop the nix commands of a running check: the pid of
+/// the current one is killed and no further one starts.
+#[derive(Debug, Default)]
+pub struct CancelHandle {
+    pid: Mutex<Option<u32>>,
+    cancelled: AtomicBool,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        if let Ok(pid) = self.pid.lock() {
+            if let Some(pid) = *pid {
+                let _ = Command::new("kill").arg(pid.to_string()).status();
+            }
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Run `cmd` to completion like `Command::output`, killable by `cancel`.
+    fn output(&self, cmd: &mut Command) -> std::io::Result<std::process::Output> {
+        if self.is_cancelled() {
+            return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "cancelled"));
+        }
+        let child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        if let Ok(mut pid) = self.pid.lock() {
+            *pid = Some(child.id());
+        }
+        // Cancelled before the pid was known
+        if self.is_cancelled() {
+            let _ = Command::new("kill").arg(child.id().to_string()).status();
+        }
+        let output = child.wait_with_output();
+        if let Ok(mut pid) = self.pid.lock() {
+            *pid = None;
+        }
+        output
+    }
+}
+
+/// Check that the flake's inputs resolve and its lock file is valid with
+/// `nix flake metadata`, so an unreachable or broken input is reported
+/// before the disk is touched instead of deep into nixos-install.
+pub fn check_flake_inputs(
+    base_path: &Path,
+    nix_config: Option<&str>,
+    cancel: &CancelHandle,
+) -> Result<(), String> {
+    let mut cmd = Command::new("nix");
+    cmd.args(["--extra-experimental-features", "nix-command flakes"])
+        .args(["flake", "metadata", &flake_url(base_path)])
+        .current_dir(base_path);
+    if let Some(nix_config) = nix_config {
+        cmd.env("NIX_CONFIG", nix_config);
+    }
+    let output = cancel.output(&mut cmd).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => "'nix' is not installed or not on PATH".to_string(),
+        _ => format!("Failed to run 'nix flake metadata': {}", e),
+    })?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "The flake inputs could not be resolved (nix flake metadata exit {:?}):\n{}",
+            output.status.code(),
+            stderr.trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Build the system closure (`system.build.toplevel`) of the host's
+/// `nixosConfigurations` attribute without installing it. On failure the
+/// error holds the last lines of nix's output.
+pub fn build_system_toplevel(
+    base_path: &Path,
+    flake_attr: &str,
+    offline: bool,
+    nix_config: Option<&str>,
+    cancel: &CancelHandle,
+) -> Result<(), String> {
+    let attr = format!("nixosConfigurations.{}.config.system.build.toplevel", flake_attr);
+    let mut cmd = Command::new("nix");
+    cmd.args(["--extra-experimental-features", "nix-command flakes"])
+        .args(["build", "--no-link", &flake_ref(base_path, &attr)])
+        .current_dir(base_path);
+    if offline {
+        cmd.args(["--option", "substitute", "false"]);
+    }
+    if let Some(nix_config) = nix_config {
+        cmd.env("NIX_CONFIG", nix_config);
+    }
+    let output = cancel.output(&mut cmd).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => "'nix' is not installed or not on PATH".to_string(),
+        _ => format!("Failed to run 'nix build': {}", e),
+    })?;
+
+    if !output.status.success() {
+        const TAIL_LINES: usize = 60;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(TAIL_LINES)..].join("\n");
+        return Err(format!(
+            "nix build failed (exit {:?}):\n{}",
+            output.status.code(),
+            tail.trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Directory in the target's /nix/var/nix/gcroots holding, per user, the
+/// standalone Home Manager activation package the first boot activates.
+pub const HM_INIT_GCROOTS: &str = "/nix/var/nix/gcroots/home-manager-init";
+
+/// Build `homeConfigurations."<user>@<host>".activationPackage` as root into
+/// the store at /mnt and register it under `HM_INIT_GCROOTS` for the
+/// `home-manager-init-<user>` unit to activate on first boot. Returns the
+/// store path.
+pub fn build_home_activation(
+    base_path: &Path,
+    username: &str,
+    host_name: &str,
+    offline: bool,
+    nix_config: Option<&str>,
+) -> Result<String, String> {
+    let attr = format!("homeConfigurations.\"{}@{}\".activationPackage", username, host_name);
+    let mut cmd = Command::new("nix");
+    cmd.args(["--extra-experimental-features", "nix-command flakes"])
+        .args(["build", "--store", "/mnt", "--no-link", "--print-out-paths"])
+        .arg(flake_ref(base_path, &attr))
+        .current_dir(base_path);
+    if offline {
+        cmd.args(["--option", "substitute", "false"]);
+    }
+    if let Some(nix_config) = nix_config {
+        cmd.env("NIX_CONFIG", nix_config);
+    }
+    let output = cmd.output().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => "'nix' is not installed or not on PATH".to_string(),
+        _ => format!("Failed to run 'nix build': {}", e),
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "Building the Home Manager configuration of {} failed (exit {:?}):\n{}",
+            username,
+            output.status.code(),
+            stderr.trim()
+        ));
+    }
+    let store_path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    // The link is both the GC root and what the unit activates
+    let roots = format!("/mnt{}", HM_INIT_GCROOTS);
+    std::fs::create_dir_all(&roots)
+        .map_err(|e| format!("Failed to create {}: {}", roots, e))?;
+    let link = format!("{}/{}", roots, username);
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(&store_path, &link)
+        .map_err(|e| format!("Failed to link {}: {}", link, e))?;
+    Ok(store_path)
+}
+
+// ---------------------------------------------------------------------------
+// Failure diagnostics
+// ---------------------------------------------------------------------------
+
+/// Well-known nix failure signatures (lowercase substrings, all of which must
+/// appear in the line) with a hint about the likely cause.
+const FAILURE_SIGNATURES: &[(&[&str], &str)] = &[
+    (
+        &["hash mismatch"],
+        "A fixed-output hash is out of date: update it in the repo, or the flake input",
+    ),
+    (
+        &["no space left"],
+        "Ran out of disk space: use a larger root partition (or free up the live system's /tmp)",
+    ),
+    (
+        &["attribute", "missing"],
+        "The config refers to an option or package this nixpkgs doesn't have: \
+         check the module and the flake inputs",
+    ),
+    (
+        &["undefined variable"],
+        "A module uses a name that isn't in scope: check its function arguments",
+    ),
+    (
+        &["infinite recursion"],
+        "A module's options depend on themselves: often `imports` using `config` or `pkgs`",
+    ),
+    (
+        &["unable to download"],
+        "A download failed: check the network connection and the substituters",
+    ),
+    (
+        &["could not resolve host"],
+        "No DNS or network: connect to the internet and retry",
+    ),
+];
+
+/// Hint for a line of nix output matching a well-known failure signature.
+pub fn failure_hint(line: &str) -> Option<&'static str> {
+    let lower = line.to_lowercase();
+    FAILURE_SIGNATURES
+        .iter()
+        .find(|(needles, _)| needles.iter().all(|n| lower.contains(n)))
+        .map(|(_, hint)| *hint)
+}
+
 // ---------------------------------------------------------------------------
 // Configuration generation (mirrors install.sh generate_host_config)
 // ---------------------------------------------------------------------------
 
 /// Helper: produce a module line, commented out if not selected.
-fn mod_line(kind: &str, name: &str, selected: bool) -> String {
+fn mod_line(reference: &str, selected: bool) -> String {
     if selected {
-        format!("      self.{}.{}", kind, name)
+        format!("      {}", reference)
     } else {
-        format!("      # self.{}.{}", kind, name)
+        format!("      # {}", reference)
     }
 }
 
 /// Generate the configuration.nix for a new custom host.
 /// ALL discovered modules are included; unselected ones are commented out.
 /// Uses hyphens for user module names: `<host>-user-<user>`.
-/// Loads `self.nixosModules.home-manager` once when there are users.
+/// Loads `self.nixosModules.home-manager` once when there are users (unless
+/// Home Manager is disabled).
 /// System packages are included as `self.nixosModules.packages-*`.
+/// (References follow `settings.refs`; these are the defaults.)
 /// Adds `{ networking.hostName = "<host>"; }` as the last modules entry.
 pub fn generate_configuration_nix(
     host_name: &str,
     nixos_modules: &[NixModule],
     system_packages: &[NixModule],
     users: &[String],
+    settings: &HostSettings,
 ) -> String {
     let mut lines: Vec<String> = Vec::new();
     lines.push("      ./_hardware-configuration.nix".to_string());
@@ -279,7 +1186,7 @@ pub fn generate_configuration_nix(
         lines.push(String::new());
     }
     for m in nixos_modules {
-        lines.push(mod_line("nixosModules", &m.name, m.selected));
+        lines.push(mod_line(&settings.refs.nixos(&m.name), m.selected || m.required));
     }
 
     // System packages (all discovered, comment out unselected)
@@ -287,115 +1194,433 @@ pub fn generate_configuration_nix(
         lines.push(String::new());
     }
     for m in system_packages {
-        lines.push(mod_line("nixosModules", &m.name, m.selected));
+        lines.push(mod_line(&settings.refs.nixos(&m.name), m.selected || m.required));
     }
 
     // User management: home-manager integration + per-user modules
     if !users.is_empty() {
         lines.push(String::new());
-        lines.push("      self.nixosModules.home-manager".to_string());
-        for user in users {
-            lines.push(format!(
-                "      self.nixosModules.{}-user-{}",
-                host_name, user
-            ));
+        for (_, reference) in user_imports(host_name, users, settings) {
+            lines.push(format!("      {}", reference));
         }
     }
 
-    // networking.hostName inline block
-    lines.push("      {".to_string());
+    // Inline settings block: networking.hostName plus any toggles chosen in
+    // the installer. It only needs to be a function when it refers to pkgs
+    let needs_pkgs = settings.kernel.is_some() || settings.gpu == Some(GpuDriver::Intel);
+    if needs_pkgs {
+        lines.push("      ({ pkgs, ... }: {".to_string());
+    } else {
+        lines.push("      {".to_string());
+    }
     lines.push(format!("        networking.hostName = \"{}\";", host_name));
-    lines.push("      }".to_string());
+    if let Some(version) = &settings.state_version {
+        lines.push(format!("        system.stateVersion = \"{}\";", version));
+    }
+    let module_bootloader = nixos_modules
+        .iter()
+        .chain(system_packages)
+        .any(|m| (m.selected || m.required) && provides_bootloader(&m.name));
+    let bootloader = match settings.bootloader {
+        Some(b) => Some(b),
+        None if module_bootloader => None,
+        None if settings.uefi => Some(Bootloader::SystemdBoot),
+        None => Some(Bootloader::Grub),
+    };
+    lines.extend(bootloader_lines(bootloader, settings));
+    if let Some(kernel) = settings.kernel {
+        lines.push(format!("        boot.kernelPackages = pkgs.{};", kernel.attr()));
+    }
+    if let Some(gpu) = settings.gpu {
+        lines.push(format!(
+            "        # {} graphics: best-effort starting point from the installer, review it",
+            gpu.display_name()
+        ));
+        lines.push("        hardware.graphics.enable = true;".to_string());
+        lines.extend(gpu.lines().iter().map(|l| l.to_string()));
+    }
+    if let Some(percent) = settings.zram_percent {
+        lines.push("        zramSwap.enable = true;".to_string());
+        lines.push(format!("        zramSwap.memoryPercent = {};", percent));
+    }
+    if settings.fstrim {
+        lines.push("        services.fstrim.enable = true;".to_string());
+    }
+    if settings.ssh_authorized_key.is_some() {
+        lines.push("        services.openssh.enable = true;".to_string());
+    }
+    if let Some(hash) = &settings.root_hashed_password {
+        lines.push(format!("        users.users.root.hashedPassword = \"{}\";", hash));
+    }
+    if let Some(user) = &settings.autologin_user {
+        lines.extend(autologin_lines(user).iter().map(|l| format!("        {}", l)));
+    }
+    if needs_pkgs {
+        lines.push("      })".to_string());
+    } else {
+        lines.push("      }".to_string());
+    }
 
     let module_lines = lines.join("\n");
 
     format!(
         "{{ inputs, self, ... }}:\n\
          {{\n\
-         \x20 flake.nixosConfigurations.{host_name} = inputs.nixpkgs.lib.nixosSystem {{\n\
+         \x20 flake.nixosConfigurations.{flake_attr} = inputs.nixpkgs.lib.nixosSystem {{\n\
          \x20   specialArgs = {{ inherit inputs self; }};\n\
          \x20   modules = [\n\
          {module_lines}\n\
          \x20   ];\n\
          \x20 }};\n\
          }}\n",
-        host_name = host_name,
+        flake_attr = settings.flake_attr.as_deref().unwrap_or(host_name),
         module_lines = module_lines,
     )
 }
 
-/// Helper: format a homeManagerModules attribute reference.
-fn hm_attr(name: &str) -> String {
-    format!("self.homeManagerModules.{}", name)
+/// Autologin on the console and, if a display manager is enabled, in it.
+fn autologin_lines(username: &str) -> [String; 5] {
+    [
+        format!("services.getty.autologinUser = \"{}\";", username),
+        "services.displayManager.autoLogin = {".to_string(),
+        "  enable = true;".to_string(),
+        format!("  user = \"{}\";", username),
+        "};".to_string(),
+    ]
+}
+
+/// The modules a host imports for its `users`, as (name, reference) pairs:
+/// the flake's home-manager module (in `HmMode::NixosModule`) and one
+/// generated `<host>-user-<user>` each.
+fn user_imports(
+    host_name: &str,
+    users: &[String],
+    settings: &HostSettings,
+) -> Vec<(String, String)> {
+    let mut imports = Vec::new();
+    if users.is_empty() {
+        return imports;
+    }
+    if settings.home_manager && settings.hm_mode == HmMode::NixosModule {
+        imports.push(("home-manager".to_string(), settings.refs.nixos("home-manager")));
+    }
+    imports.extend(users.iter().map(|user| {
+        let name = format!("{}-user-{}", host_name, user);
+        let reference = settings.refs.user_module(&name);
+        (name, reference)
+    }));
+    imports
+}
+
+/// Update the imports of a preset's `configuration.nix` ("Edit preset") to
+/// follow the module selection. Only reference lines change: those of the
+/// listed modules are commented out or back in, and newly selected modules
+/// and the modules of the new `users` are added after the last reference.
+/// Everything else (the settings block, `inputs.*` modules, hand-written
+/// options) stays as it is. `None` if there is no reference to add after.
+pub fn update_preset_imports(
+    config_nix: &str,
+    host_name: &str,
+    nixos_modules: &[NixModule],
+    system_packages: &[NixModule],
+    users: &[String],
+    settings: &HostSettings,
+) -> Option<String> {
+    let refs = &settings.refs;
+    let listed: Vec<&NixModule> = nixos_modules.iter().chain(system_packages).collect();
+    let mut lines: Vec<String> = Vec::new();
+    // Index and indentation of the last reference line
+    let mut last_ref: Option<(usize, &str)> = None;
+    let mut imported: Vec<String> = Vec::new();
+
+    for line in config_nix.lines() {
+        let (indent, code) = line.split_at(line.len() - line.trim_start().len());
+        let commented = code.starts_with('#');
+        let body = code.trim_start_matches('#').trim_start();
+        let name = referenced_nixos_modules(body, refs)
+            .into_iter()
+            .next()
+            .filter(|name| body.starts_with(&refs.nixos(name)));
+        let Some(name) = name else {
+            lines.push(line.to_string());
+            continue;
+        };
+        last_ref = Some((lines.len(), indent));
+        let enabled = match listed.iter().find(|m| m.name == name) {
+            Some(m) => m.selected || m.required,
+            None => !commented,
+        };
+        if enabled {
+            imported.push(name);
+        }
+        lines.push(match (enabled, commented) {
+            (true, true) => format!("{}{}", indent, body),
+            (false, false) => format!("{}# {}", indent, body),
+            _ => line.to_string(),
+        });
+    }
+
+    let mut additions: Vec<String> = listed
+        .iter()
+        .filter(|m| (m.selected || m.required) && !imported.contains(&m.name))
+        .map(|m| refs.nixos(&m.name))
+        .chain(
+            user_imports(host_name, users, settings)
+                .into_iter()
+                .filter(|(name, _)| !imported.contains(name))
+                .map(|(_, reference)| reference),
+        )
+        .collect();
+    additions.dedup();
+    if !additions.is_empty() {
+        let (index, indent) = last_ref?;
+        for (i, reference) in additions.iter().enumerate() {
+            lines.insert(index + 1 + i, format!("{}{}", indent, reference));
+        }
+    }
+
+    let mut updated = lines.join("\n");
+    if config_nix.ends_with('\n') {
+        updated.push('\n');
+    }
+    Some(updated)
+}
+
+/// Names of the NixOS modules an existing host's `configuration.nix`
+/// references (`self.nixosModules.<name>` by default), in order of first
+/// appearance. Commented-out references are ignored.
+pub fn referenced_nixos_modules(config_nix: &str, refs: &AttrRefs) -> Vec<String> {
+    let (prefix, suffix) = refs
+        .nixos_module
+        .split_once("{name}")
+        .unwrap_or((refs.nixos_module.as_str(), ""));
+    let mut names: Vec<String> = Vec::new();
+    for line in config_nix.lines() {
+        let code = line.split('#').next().unwrap_or("");
+        let mut rest = code;
+        while let Some(start) = rest.find(prefix) {
+            rest = &rest[start + prefix.len()..];
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                .unwrap_or(rest.len());
+            let (name, after) = rest.split_at(end);
+            if !name.is_empty() && after.starts_with(suffix) && !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+            rest = after;
+        }
+    }
+    names
+}
+
+/// Module names taken to configure `boot.loader.*` themselves. Matched
+/// exactly: a near miss like "plymouth-boot" or "secureboot-keys" must not
+/// suppress the generated bootloader, which would leave the host unbootable.
+const BOOTLOADER_MODULES: &[&str] =
+    &["bootloader", "grub", "systemd-boot", "lanzaboote", "limine", "refind"];
+
+/// Whether the module named `name` configures the bootloader itself.
+fn provides_bootloader(name: &str) -> bool {
+    BOOTLOADER_MODULES.contains(&name.to_lowercase().as_str())
+}
+
+/// Settings-block lines for the chosen bootloader, if any.
+fn bootloader_lines(bootloader: Option<Bootloader>, settings: &HostSettings) -> Vec<String> {
+    let lines: Vec<&str> = match bootloader {
+        None => return Vec::new(),
+        Some(Bootloader::SystemdBoot) => vec![
+            "boot.loader.systemd-boot.enable = true;",
+            "boot.loader.efi.canTouchEfiVariables = true;",
+        ],
+        Some(Bootloader::Grub) if settings.uefi => vec![
+            "boot.loader.grub.enable = true;",
+            "boot.loader.grub.efiSupport = true;",
+            "boot.loader.grub.device = \"nodev\";",
+            "boot.loader.efi.canTouchEfiVariables = true;",
+        ],
+        Some(Bootloader::Grub) => {
+            let device = settings.grub_device.as_deref().unwrap_or("nodev");
+            return vec![
+                "        boot.loader.grub.enable = true;".to_string(),
+                format!("        boot.loader.grub.device = \"{}\";", device),
+            ];
+        }
+    };
+    let mut lines: Vec<String> = lines.iter().map(|l| format!("        {}", l)).collect();
+    if let Some(mount) = &settings.efi_mount_point {
+        lines.push(format!("        boot.loader.efi.efiSysMountPoint = \"{}\";", mount));
+    }
+    lines
 }
 
 /// Generate a user-<username>.nix that defines both the system user AND
-/// the Home Manager imports. Everything lives in a single nixosModule
-/// named `<host>-user-<user>`.
+/// the Home Manager imports (if `settings.home_manager`). Everything lives
+/// in a single nixosModule named `<host>-user-<user>`.
 ///
 /// `hm_base_modules` comes from config.toml and lists modules that are
 /// always included (e.g. `["home"]`).
 ///
-/// Passwords are NOT embedded in the Nix configuration. They are set
-/// post-install via `nixos-enter --root /mnt -- chpasswd`.
+/// Passwords are only embedded (as `hashedPassword`) in password_mode
+/// "hashed"; otherwise they are set post-install via
+/// `nixos-enter --root /mnt -- chpasswd`.
 pub fn generate_user_nix(
     host_name: &str,
     username: &str,
+    settings: &UserSettings,
     hm_modules: &[NixModule],
     package_modules: &[NixModule],
     hm_base_modules: &[String],
 ) -> String {
+    let hm_attr = |name: &str| settings.refs.hm(name);
+    // Import lines without indentation; unselected ones are commented out
     let mut import_lines: Vec<String> = Vec::new();
 
     // The `home` module is always required (sets home.stateVersion etc.)
-    import_lines.push(format!("        {}", hm_attr("home")));
+    import_lines.push(hm_attr("home"));
 
     // Additional base modules from config.toml (always active)
     for base in hm_base_modules {
         if base != "home" {
-            import_lines.push(format!("        {}", hm_attr(base)));
-        }
-    }
-
-    // HM modules (all discovered; comment out unselected)
-    for m in hm_modules {
-        if m.selected {
-            import_lines.push(format!("        {}", hm_attr(&m.name)));
-        } else {
-            import_lines.push(format!("        # {}", hm_attr(&m.name)));
+            import_lines.push(hm_attr(base));
         }
     }
 
-    // Package sets (all discovered; comment out unselected)
-    for m in package_modules {
-        if m.selected {
-            import_lines.push(format!("        {}", hm_attr(&m.name)));
+    // HM modules and package sets (all discovered; comment out unselected)
+    for m in hm_modules.iter().chain(package_modules) {
+        if m.selected || m.required {
+            import_lines.push(hm_attr(&m.name));
         } else {
-            import_lines.push(format!("        # {}", hm_attr(&m.name)));
+            import_lines.push(format!("# {}", hm_attr(&m.name)));
         }
     }
 
-    let imports = import_lines.join("\n");
+    let indent = |width: usize| {
+        import_lines
+            .iter()
+            .map(|l| format!("{:width$}{}", "", l, width = width))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
 
     // Build the HM imports block only if there are any modules to import
-    let hm_block = if !imports.is_empty() {
+    let nixos_module_hm = settings.home_manager && settings.hm_mode == HmMode::NixosModule;
+    let hm_block = if nixos_module_hm && !import_lines.is_empty() {
         format!(
             "\n      home-manager.users.{username}.imports = [\n\
              {imports}\n\
              \x20     ];",
             username = username,
-            imports = imports,
+            imports = indent(8),
+        )
+    } else {
+        String::new()
+    };
+
+    // Standalone Home Manager: a `homeConfigurations."<user>@<host>"` entry
+    // next to the NixOS module. The installer builds its activation package
+    // into the new system; a one-shot unit activates it on first boot
+    let standalone = settings.home_manager && settings.hm_mode == HmMode::Standalone;
+    let hm_init_block = if standalone {
+        format!(
+            "\n      systemd.services.\"home-manager-init-{username}\" = {{\n\
+             \x20       description = \"First activation of Home Manager for {username}\";\n\
+             \x20       wantedBy = [ \"multi-user.target\" ];\n\
+             \x20       wants = [ \"nix-daemon.socket\" ];\n\
+             \x20       after = [ \"nix-daemon.socket\" ];\n\
+             \x20       path = [ pkgs.nix ];\n\
+             \x20       unitConfig.ConditionPathExists = \"{link}\";\n\
+             \x20       serviceConfig = {{\n\
+             \x20         Type = \"oneshot\";\n\
+             \x20         User = \"{username}\";\n\
+             \x20         ExecStart = \"{link}/activate\";\n\
+             \x20         # Later generations come from `home-manager switch`\n\
+             \x20         ExecStartPost = \"+${{pkgs.coreutils}}/bin/rm {link}\";\n\
+             \x20       }};\n\
+             \x20     }};",
+            username = username,
+            link = format!("{}/{}", HM_INIT_GCROOTS, username),
+        )
+    } else {
+        String::new()
+    };
+    let standalone_block = if standalone {
+        format!(
+            "\n\
+             \x20 flake.homeConfigurations.\"{username}@{host_name}\" =\n\
+             \x20   inputs.home-manager.lib.homeManagerConfiguration {{\n\
+             \x20     pkgs = self.nixosConfigurations.{flake_attr}.pkgs;\n\
+             \x20     extraSpecialArgs = {{ inherit inputs self; }};\n\
+             \x20     modules = [\n\
+             {imports}\n\
+             \x20       {{\n\
+             \x20         home.username = \"{username}\";\n\
+             \x20         home.homeDirectory = \"/home/{username}\";\n\
+             \x20       }}\n\
+             \x20     ];\n\
+             \x20   }};\n",
+            username = username,
+            host_name = host_name,
+            flake_attr = settings.flake_attr.as_deref().unwrap_or(host_name),
+            imports = indent(8),
         )
     } else {
         String::new()
     };
 
     let module_name = format!("{}-user-{}", host_name, username);
+    let autologin_block: String = settings
+        .autologin_user
+        .iter()
+        .flat_map(|user| autologin_lines(user))
+        .map(|l| format!("\n      {}", l))
+        .collect();
+    // Login shell; zsh and fish also need their program module enabled, which
+    // is done here so it works for preset hosts too
+    let shell_line = match settings.shell {
+        Some(shell) => format!("\n\x20       shell = pkgs.{};", shell.package()),
+        None => String::new(),
+    };
+    let program_block = match settings.shell.and_then(|s| s.program()) {
+        Some(program) => format!("\n      programs.{}.enable = true;", program),
+        None => String::new(),
+    };
+    let password_line = match &settings.hashed_password {
+        Some(hash) => format!("\n\x20       hashedPassword = \"{}\";", hash),
+        None => String::new(),
+    };
+    let keys_line = if settings.authorized_keys.is_empty() {
+        String::new()
+    } else {
+        let keys = settings
+            .authorized_keys
+            .iter()
+            .map(|k| format!("\"{}\"", k))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("\n\x20       openssh.authorizedKeys.keys = [ {} ];", keys)
+    };
+    let openssh_line = if settings.openssh {
+        "\n      services.openssh.enable = true;"
+    } else {
+        ""
+    };
+    let root_password_block = match &settings.root_hashed_password {
+        Some(hash) => format!("\n      users.users.root.hashedPassword = \"{}\";", hash),
+        None => String::new(),
+    };
+    let extra_groups = settings
+        .groups
+        .iter()
+        .map(|g| format!("\"{}\"", g))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let args = if standalone_block.is_empty() { "..." } else { "inputs, self, ..." };
 
     format!(
-        "{{ ... }}:\n\
+        "{{ {args} }}:\n\
          {{\n\
-         \x20 flake.nixosModules.{module_name} =\n\
+         \x20 {definition} =\n\
          \x20   {{\n\
          \x20     pkgs,\n\
          \x20     self,\n\
@@ -405,73 +1630,189 @@ pub fn generate_user_nix(
          \x20   {{\n\
          \x20     users.users.{username} = {{\n\
          \x20       isNormalUser = true;\n\
-         \x20       extraGroups = [ \"wheel\" ];\n\
-         \x20     }};{hm_block}\n\
+         \x20       extraGroups = [ {extra_groups} ];{shell_line}{password_line}{keys_line}\n\
+         \x20     }};{root_password_block}{openssh_line}\
+         {program_block}{autologin_block}{hm_block}{hm_init_block}\n\
          \x20   }};\n\
+         {standalone_block}\
          }}\n",
-        module_name = module_name,
+        args = args,
+        definition = settings.refs.nixos_definition(&module_name),
         username = username,
+        extra_groups = extra_groups,
+        shell_line = shell_line,
+        password_line = password_line,
+        keys_line = keys_line,
+        root_password_block = root_password_block,
+        openssh_line = openssh_line,
+        program_block = program_block,
+        autologin_block = autologin_block,
         hm_block = hm_block,
+        hm_init_block = hm_init_block,
+        standalone_block = standalone_block,
     )
 }
 
+/// Whether `key` looks like an OpenSSH public key line
+/// (`<type> <base64> [comment]`) that can be written into a Nix string.
+pub fn is_ssh_public_key(key: &str) -> bool {
+    const KEY_TYPES: &[&str] = &[
+        "ssh-ed25519",
+        "ssh-rsa",
+        "ecdsa-sha2-nistp256",
+        "ecdsa-sha2-nistp384",
+        "ecdsa-sha2-nistp521",
+        "sk-ssh-ed25519@openssh.com",
+        "sk-ecdsa-sha2-nistp256@openssh.com",
+    ];
+    let mut fields = key.split_whitespace();
+    let (Some(key_type), Some(data)) = (fields.next(), fields.next()) else {
+        return false;
+    };
+    KEY_TYPES.contains(&key_type)
+        && data.len() >= 16
+        && data.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '='))
+        && !key.contains(['"', '\\', '$', '\n'])
+}
+
 // ---------------------------------------------------------------------------
 // File writing
 // ---------------------------------------------------------------------------
 
 /// Ensure the host directory exists and return its path.
-fn ensure_host_dir(base_path: &Path, host_name: &str) -> Result<PathBuf, String> {
-    let host_dir = base_path.join("modules").join("hosts").join(host_name);
+fn ensure_host_dir(
+    base_path: &Path,
+    layout: &ModuleLayout,
+    host_name: &str,
+) -> Result<PathBuf, String> {
+    let host_dir = layout.host_dir(base_path, host_name);
     fs::create_dir_all(&host_dir)
         .map_err(|e| format!("Failed to create host directory: {}", e))?;
     Ok(host_dir)
 }
 
-/// Write configuration files to the host directory.
-pub fn write_host_config(
-    base_path: &Path,
-    host_name: &str,
-    config_content: &str,
-) -> Result<(), String> {
-    let host_dir = ensure_host_dir(base_path, host_name)?;
-    let config_path = host_dir.join("configuration.nix");
-    fs::write(&config_path, config_content)
-        .map_err(|e| format!("Failed to write configuration.nix: {}", e))?;
-    Ok(())
-}
-
-/// Write the user-<username>.nix system user definition to the host directory.
-pub fn write_user_config(
+/// Write a generated file (`configuration.nix`, `user-<name>.nix`, ...) to
+/// the host directory. Returns the path written.
+pub fn write_host_file(
     base_path: &Path,
+    layout: &ModuleLayout,
     host_name: &str,
-    username: &str,
+    file_name: &str,
     content: &str,
-) -> Result<(), String> {
-    let host_dir = ensure_host_dir(base_path, host_name)?;
-    let user_path = host_dir.join(format!("user-{}.nix", username));
-    fs::write(&user_path, content)
-        .map_err(|e| format!("Failed to write user config: {}", e))?;
-    Ok(())
+) -> Result<PathBuf, String> {
+    let host_dir = ensure_host_dir(base_path, layout, host_name)?;
+    let path = host_dir.join(file_name);
+    fs::write(&path, content)
+        .map_err(|e| format!("Failed to write {}: {}", file_name, e))?;
+    Ok(path)
 }
 
 /// Write the hardware configuration to the host directory.
 pub fn write_hardware_config(
     base_path: &Path,
+    layout: &ModuleLayout,
     host_name: &str,
     content: &str,
 ) -> Result<(), String> {
-    let host_dir = ensure_host_dir(base_path, host_name)?;
+    let host_dir = ensure_host_dir(base_path, layout, host_name)?;
     let hw_path = host_dir.join("_hardware-configuration.nix");
     fs::write(&hw_path, content)
         .map_err(|e| format!("Failed to write hardware config: {}", e))?;
     Ok(())
 }
 
+/// Rewrite a generated hardware configuration for an ephemeral root: the
+/// `fileSystems."/"` entry nixos-generate-config wrote is replaced by a
+/// tmpfs, and /persist is marked `neededForBoot` so impermanence can bind
+/// mount from it early.
+pub fn tmpfs_root_hardware_config(hw_config: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut skipping = false;
+    for line in hw_config.lines() {
+        if line.trim_start().starts_with("fileSystems.\"/\" =") {
+            skipping = true;
+        }
+        if !skipping {
+            lines.push(line.to_string());
+        } else if line.trim_end().ends_with("};") {
+            skipping = false;
+        }
+    }
+
+    let tmpfs_entry = [
+        "  fileSystems.\"/\" = {",
+        "    device = \"none\";",
+        "    fsType = \"tmpfs\";",
+        "    options = [ \"defaults\" \"size=25%\" \"mode=755\" ];",
+        "  };",
+        "",
+        "  fileSystems.\"/persist\".neededForBoot = true;",
+        "",
+    ];
+    insert_before_close(&mut lines, tmpfs_entry.iter().map(|e| e.to_string()).collect());
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+/// Hardware configuration for building the system before the disk is
+/// partitioned: the live system's hardware config (generated with
+/// `--no-filesystems`) plus placeholder `fileSystems` and `swapDevices` for
+/// the planned (label, mount point, fsType) partitions.
+pub fn prebuild_hardware_config(
+    hw_config: &str,
+    partitions: &[(String, String, String)],
+) -> String {
+    let mut entries = Vec::new();
+    let mut swap_devices = Vec::new();
+    for (label, mount_point, fs_type) in partitions {
+        let device = format!("/dev/disk/by-partlabel/{}", label);
+        if fs_type == "swap" {
+            swap_devices.push(format!("{{ device = \"{}\"; }}", device));
+        } else {
+            entries.push(format!(
+                "  fileSystems.\"{}\" = {{ device = \"{}\"; fsType = \"{}\"; }};",
+                mount_point, device, fs_type
+            ));
+        }
+    }
+    if !swap_devices.is_empty() {
+        entries.push(format!("  swapDevices = [ {} ];", swap_devices.join(" ")));
+    }
+    entries.push(String::new());
+    let mut lines: Vec<String> = hw_config.lines().map(str::to_string).collect();
+    insert_before_close(&mut lines, entries);
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+/// Add a swap file at `device` (a path in the installed system) to a
+/// generated hardware configuration. nixos-generate-config only lists swap
+/// partitions, so the file would otherwise never be enabled.
+pub fn swap_file_hardware_config(hw_config: &str, device: &str) -> String {
+    let entry = format!("  swapDevices = [ {{ device = \"{}\"; }} ];", device);
+    let mut lines: Vec<String> = hw_config.lines().map(str::to_string).collect();
+    match lines.iter().position(|l| l.trim() == "swapDevices = [ ];") {
+        Some(i) => lines[i] = entry,
+        None => insert_before_close(&mut lines, vec![entry, String::new()]),
+    }
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+/// Insert `entries` before the closing brace of a NixOS module.
+fn insert_before_close(lines: &mut Vec<String>, entries: Vec<String>) {
+    let close = lines.iter().rposition(|l| l.trim() == "}").unwrap_or(lines.len());
+    for (i, entry) in entries.into_iter().enumerate() {
+        lines.insert(close + i, entry);
+    }
+}
+
 /// Hash a password using mkpasswd or openssl (mirrors install.sh step_set_password).
 /// Passes the password via stdin to avoid exposing it in /proc/<pid>/cmdline.
-/// NOTE: This is kept for potential future use but is no longer called during
-/// the wizard flow. Passwords are set post-install via nixos-enter + chpasswd.
-#[allow(dead_code)]
+/// Used in password_mode "hashed"; the result must never be logged.
 pub fn hash_password(password: &str) -> Result<String, String> {
     use std::io::Write;
 
@@ -513,3 +1854,567 @@ pub fn hash_password(password: &str) -> Result<String, String> {
 
     Err("Neither mkpasswd nor openssl available for password hashing".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::module;
+
+    /// configuration.nix for host "box" without modules or users.
+    fn host_config(settings: &HostSettings) -> String {
+        generate_configuration_nix("box", &[], &[], &[], settings)
+    }
+
+    /// user.nix for "alice" on "box" without modules or packages.
+    fn alice_user_nix(settings: &UserSettings) -> String {
+        generate_user_nix("box", "alice", settings, &[], &[], &[])
+    }
+
+    #[test]
+    fn conflict_comments_are_parsed() {
+        let content = "# installer:conflicts-with sddm lightdm\n\
+                       {\n\
+                       \x20 #installer:conflicts-with gdm, sddm\n\
+                       \x20 services.xserver.enable = true; # installer:conflicts-with nope\n\
+                       }\n";
+        assert_eq!(conflict_comments(content), ["sddm", "lightdm", "gdm"]);
+        assert!(conflict_comments("{ }").is_empty());
+        assert!(has_required_marker("# installer:required\n{ }\n"));
+        assert!(!has_required_marker("{ }  # installer:required\n"));
+    }
+
+    #[test]
+    fn required_modules_are_always_imported() {
+        let mut core = module("core", false);
+        core.required = true;
+        let config =
+            generate_configuration_nix("box", &[core], &[], &[], &HostSettings::default());
+        assert!(config.contains("      self.nixosModules.core\n"));
+        assert!(!config.contains("# self.nixosModules.core"));
+    }
+
+    #[test]
+    fn user_nix_uses_configured_groups() {
+        let groups = ["wheel".to_string(), "networkmanager".to_string(), "docker".to_string()];
+        let settings = UserSettings { groups: groups.to_vec(), ..Default::default() };
+        let user_nix = alice_user_nix(&settings);
+        assert!(user_nix.contains("extraGroups = [ \"wheel\" \"networkmanager\" \"docker\" ];"));
+    }
+
+    #[test]
+    fn ssh_key_is_validated_and_authorized() {
+        let key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0 me@laptop";
+        assert!(is_ssh_public_key(key));
+        assert!(!is_ssh_public_key("ssh-ed25519"));
+        assert!(!is_ssh_public_key("hunter2 AAAAC3NzaC1lZDI1NTE5AAAA"));
+        assert!(!is_ssh_public_key("ssh-rsa AAAAB3NzaC1yc2EAAAADAQAB \"${evil}\""));
+
+        let settings = UserSettings {
+            authorized_keys: vec![key.to_string()],
+            openssh: true,
+            ..Default::default()
+        };
+        let user_nix = alice_user_nix(&settings);
+        assert!(user_nix.contains(&format!("openssh.authorizedKeys.keys = [ \"{}\" ];", key)));
+        assert!(user_nix.contains("services.openssh.enable = true;"));
+    }
+
+    #[test]
+    fn custom_host_gets_default_bootloader() {
+        let settings = HostSettings {
+            uefi: true,
+            ..Default::default()
+        };
+        let modules = [module("desktop", true)];
+        let config = generate_configuration_nix("box", &modules, &[], &[], &settings);
+        assert!(config.contains("boot.loader.systemd-boot.enable = true;"));
+        assert!(config.contains("boot.loader.efi.canTouchEfiVariables = true;"));
+        assert!(!config.contains("efiSysMountPoint"));
+    }
+
+    #[test]
+    fn esp_below_boot_sets_efi_mount_point() {
+        let settings = HostSettings {
+            bootloader: Some(Bootloader::Grub),
+            uefi: true,
+            efi_mount_point: Some("/boot/efi".to_string()),
+            ..Default::default()
+        };
+        let config = host_config(&settings);
+        assert!(config.contains("boot.loader.grub.efiSupport = true;"));
+        assert!(config.contains("boot.loader.efi.efiSysMountPoint = \"/boot/efi\";"));
+    }
+
+    #[test]
+    fn autologin_is_set_once_for_the_host() {
+        let users = ["alice".to_string(), "bob".to_string()];
+        let settings = HostSettings {
+            autologin_user: Some("bob".to_string()),
+            ..Default::default()
+        };
+        let config = generate_configuration_nix("box", &[], &[], &users, &settings);
+        assert_eq!(config.matches("autologinUser").count(), 1);
+        assert!(config.contains("        services.getty.autologinUser = \"bob\";\n"));
+        assert!(config.contains("          user = \"bob\";\n"));
+
+        let user_nix = generate_user_nix("box", "bob", &UserSettings::default(), &[], &[], &[]);
+        assert!(!user_nix.contains("autoLogin"));
+        let settings = UserSettings {
+            autologin_user: Some("bob".to_string()),
+            ..Default::default()
+        };
+        let user_nix = alice_user_nix(&settings);
+        assert!(user_nix.contains("      services.getty.autologinUser = \"bob\";\n"));
+    }
+
+    #[test]
+    fn state_version_is_emitted() {
+        let settings = HostSettings {
+            state_version: Some("24.05".to_string()),
+            ..Default::default()
+        };
+        let config = host_config(&settings);
+        assert!(config.contains("        system.stateVersion = \"24.05\";\n"));
+
+        let config = host_config(&HostSettings::default());
+        assert!(!config.contains("stateVersion"));
+    }
+
+    #[test]
+    fn kernel_choice_makes_settings_block_a_function() {
+        let settings = HostSettings { kernel: Some(Kernel::Zen), ..Default::default() };
+        let config = host_config(&settings);
+        assert!(config.contains("      ({ pkgs, ... }: {\n"));
+        assert!(config.contains("        boot.kernelPackages = pkgs.linuxPackages_zen;\n"));
+        assert!(config.contains("      })\n"));
+    }
+
+    #[test]
+    fn flake_attr_names_the_configuration_not_the_host() {
+        let settings =
+            HostSettings { flake_attr: Some("box-dev".to_string()), ..Default::default() };
+        let config = host_config(&settings);
+        assert!(config.contains("  flake.nixosConfigurations.box-dev = "));
+        assert!(config.contains("        networking.hostName = \"box\";\n"));
+    }
+
+    #[test]
+    fn gpu_vendor_is_read_from_lspci() {
+        let intel = "00:02.0 VGA compatible controller: Intel Corporation UHD Graphics 620";
+        let nvidia = "01:00.0 3D controller: NVIDIA Corporation GP108M [GeForce MX150] (rev a1)";
+        let amd = "03:00.0 VGA compatible controller: Advanced Micro Devices, Inc. [AMD/ATI] \
+                   Navi 23";
+        assert_eq!(gpu_from_lspci(intel), Some(GpuDriver::Intel));
+        assert_eq!(gpu_from_lspci(&format!("{}\n{}", intel, nvidia)), Some(GpuDriver::Nvidia));
+        assert_eq!(gpu_from_lspci(amd), Some(GpuDriver::Amd));
+        assert_eq!(gpu_from_lspci("00:1f.3 Audio device: Intel Corporation"), None);
+    }
+
+    #[test]
+    fn bootloader_module_suppresses_default() {
+        let settings = HostSettings {
+            uefi: true,
+            ..Default::default()
+        };
+        let modules = [module("bootloader", true)];
+        let config = generate_configuration_nix("box", &modules, &[], &[], &settings);
+        assert!(!config.contains("boot.loader."));
+
+        // An unselected bootloader module doesn't count
+        let modules = [module("bootloader", false)];
+        let config = generate_configuration_nix("box", &modules, &[], &[], &settings);
+        assert!(config.contains("boot.loader.systemd-boot.enable = true;"));
+
+        // Names that merely contain "boot" don't either
+        for name in ["plymouth-boot", "secureboot-keys", "reboot-notify", "grub-theme"] {
+            let modules = [module(name, true)];
+            let config = generate_configuration_nix("box", &modules, &[], &[], &settings);
+            assert!(config.contains("boot.loader.systemd-boot.enable = true;"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn configuration_nix_exact_output() {
+        let settings = HostSettings {
+            uefi: true,
+            home_manager: true,
+            ..Default::default()
+        };
+        let config = generate_configuration_nix(
+            "box",
+            &[module("desktop", true), module("gaming", false)],
+            &[module("packages-dev", true)],
+            &["alice".to_string(), "bob".to_string()],
+            &settings,
+        );
+        let expected = r#"{ inputs, self, ... }:
+{
+  flake.nixosConfigurations.box = inputs.nixpkgs.lib.nixosSystem {
+    specialArgs = { inherit inputs self; };
+    modules = [
+      ./_hardware-configuration.nix
+
+      self.nixosModules.desktop
+      # self.nixosModules.gaming
+
+      self.nixosModules.packages-dev
+
+      self.nixosModules.home-manager
+      self.nixosModules.box-user-alice
+      self.nixosModules.box-user-bob
+      {
+        networking.hostName = "box";
+        boot.loader.systemd-boot.enable = true;
+        boot.loader.efi.canTouchEfiVariables = true;
+      }
+    ];
+  };
+}
+"#;
+        assert_eq!(config, expected);
+        assert_eq!(config.matches("home-manager").count(), 1);
+    }
+
+    #[test]
+    fn configuration_nix_without_users_has_no_home_manager() {
+        let settings = HostSettings {
+            bootloader: Some(Bootloader::Grub),
+            grub_device: Some("/dev/sda".to_string()),
+            zram_percent: Some(50),
+            home_manager: true,
+            ..Default::default()
+        };
+        let config = host_config(&settings);
+        let expected = r#"{ inputs, self, ... }:
+{
+  flake.nixosConfigurations.box = inputs.nixpkgs.lib.nixosSystem {
+    specialArgs = { inherit inputs self; };
+    modules = [
+      ./_hardware-configuration.nix
+      {
+        networking.hostName = "box";
+        boot.loader.grub.enable = true;
+        boot.loader.grub.device = "/dev/sda";
+        zramSwap.enable = true;
+        zramSwap.memoryPercent = 50;
+      }
+    ];
+  };
+}
+"#;
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn user_nix_exact_output() {
+        let settings = UserSettings {
+            groups: vec!["wheel".to_string()],
+            home_manager: true,
+            ..Default::default()
+        };
+        let user_nix = generate_user_nix(
+            "box",
+            "alice",
+            &settings,
+            &[module("git", true), module("nvim", false)],
+            &[module("packages-cli", true)],
+            &["home".to_string(), "shell".to_string()],
+        );
+        let expected = r#"{ ... }:
+{
+  flake.nixosModules.box-user-alice =
+    {
+      pkgs,
+      self,
+      inputs,
+      ...
+    }:
+    {
+      users.users.alice = {
+        isNormalUser = true;
+        extraGroups = [ "wheel" ];
+      };
+      home-manager.users.alice.imports = [
+        self.homeManagerModules.home
+        self.homeManagerModules.shell
+        self.homeManagerModules.git
+        # self.homeManagerModules.nvim
+        self.homeManagerModules.packages-cli
+      ];
+    };
+}
+"#;
+        assert_eq!(user_nix, expected);
+    }
+
+    #[test]
+    fn custom_attr_refs_are_used_in_generated_files() {
+        let refs = AttrRefs {
+            nixos_module: "self.modules.nixos.{name}".to_string(),
+            hm_module: "self.modules.homeManager.{name}".to_string(),
+            package: "pkgs-{name}".to_string(),
+        };
+        let settings = HostSettings {
+            refs: refs.clone(),
+            home_manager: true,
+            ..Default::default()
+        };
+        let packages = [module(&refs.package_name("dev"), true)];
+        let config = generate_configuration_nix(
+            "box",
+            &[module("desktop", true), module("gaming", false)],
+            &packages,
+            &["alice".to_string()],
+            &settings,
+        );
+        assert!(config.contains("      self.modules.nixos.desktop\n"));
+        assert!(config.contains("      # self.modules.nixos.gaming\n"));
+        assert!(config.contains("      self.modules.nixos.pkgs-dev\n"));
+        assert!(config.contains("      self.modules.nixos.home-manager\n"));
+        assert!(config.contains("      self.modules.nixos.box-user-alice\n"));
+        assert!(!config.contains("nixosModules"));
+
+        let settings = UserSettings {
+            refs,
+            home_manager: true,
+            ..Default::default()
+        };
+        let hm_modules = [module("git", true)];
+        let user_nix = generate_user_nix("box", "alice", &settings, &hm_modules, &[], &[]);
+        assert!(user_nix.contains("flake.modules.nixos.box-user-alice ="));
+        assert!(user_nix.contains("        self.modules.homeManager.home\n"));
+        assert!(user_nix.contains("        self.modules.homeManager.git\n"));
+        assert!(!user_nix.contains("homeManagerModules"));
+    }
+
+    #[test]
+    fn user_module_references_match_their_definitions() {
+        let users = ["alice".to_string()];
+        for template in ["self.nixosModules.{name}", "self.modules.nixos.{name}", "nixos.{name}"] {
+            let refs = AttrRefs { nixos_module: template.to_string(), ..Default::default() };
+            let reference = refs.user_module("box-user-alice");
+            let definition = refs.nixos_definition("box-user-alice");
+            assert_eq!(definition.strip_prefix("flake."), reference.strip_prefix("self."));
+
+            let settings = HostSettings { refs: refs.clone(), ..Default::default() };
+            let config = generate_configuration_nix("box", &[], &[], &users, &settings);
+            assert!(config.contains(&format!("      {}\n", reference)), "{}", template);
+            let settings = UserSettings { refs, ..Default::default() };
+            let user_nix = alice_user_nix(&settings);
+            assert!(user_nix.contains(&format!("{} =", definition)), "{}", template);
+        }
+    }
+
+    #[test]
+    fn default_attr_refs_match_nixos_dots() {
+        let settings = UserSettings { home_manager: true, ..Default::default() };
+        let user_nix = alice_user_nix(&settings);
+        assert!(user_nix.contains("flake.nixosModules.box-user-alice ="));
+        assert!(user_nix.contains("self.homeManagerModules.home"));
+        assert_eq!(AttrRefs::default().package_name("dev"), "packages-dev");
+    }
+
+    #[test]
+    fn without_home_manager_no_hm_imports_are_generated() {
+        let settings = HostSettings::default();
+        let users = ["alice".to_string()];
+        let config = generate_configuration_nix("box", &[], &[], &users, &settings);
+        assert!(!config.contains("home-manager"));
+        assert!(config.contains("self.nixosModules.box-user-alice"));
+
+        let settings = UserSettings::default();
+        let hm_modules = [module("git", true)];
+        let user_nix = generate_user_nix("box", "alice", &settings, &hm_modules, &[], &[]);
+        assert!(!user_nix.contains("home-manager"));
+        assert!(user_nix.contains("users.users.alice = {"));
+    }
+
+    #[test]
+    fn standalone_hm_generates_home_configuration() {
+        let host = HostSettings {
+            home_manager: true,
+            hm_mode: HmMode::Standalone,
+            ..Default::default()
+        };
+        let config = generate_configuration_nix("box", &[], &[], &["alice".to_string()], &host);
+        assert!(!config.contains("home-manager"));
+        assert!(config.contains("      self.nixosModules.box-user-alice\n"));
+
+        let settings = UserSettings {
+            home_manager: true,
+            hm_mode: HmMode::Standalone,
+            ..Default::default()
+        };
+        let hm_modules = [module("git", true)];
+        let user_nix = generate_user_nix("box", "alice", &settings, &hm_modules, &[], &[]);
+        assert!(user_nix.starts_with("{ inputs, self, ... }:\n"));
+        assert!(!user_nix.contains("home-manager.users"));
+        assert!(user_nix.contains(
+            "      systemd.services.\"home-manager-init-alice\" = {\n\
+             \x20       description = \"First activation of Home Manager for alice\";\n"
+        ));
+        assert!(user_nix.contains(
+            "        unitConfig.ConditionPathExists = \
+             \"/nix/var/nix/gcroots/home-manager-init/alice\";\n"
+        ));
+        let expected = r#"
+  flake.homeConfigurations."alice@box" =
+    inputs.home-manager.lib.homeManagerConfiguration {
+      pkgs = self.nixosConfigurations.box.pkgs;
+      extraSpecialArgs = { inherit inputs self; };
+      modules = [
+        self.homeManagerModules.home
+        self.homeManagerModules.git
+        {
+          home.username = "alice";
+          home.homeDirectory = "/home/alice";
+        }
+      ];
+    };
+}
+"#;
+        assert!(user_nix.ends_with(expected), "{}", user_nix);
+    }
+
+    #[test]
+    fn preset_references_are_parsed_and_kept() {
+        let preset = "      ./_hardware-configuration.nix\n\
+                      \x20     self.nixosModules.desktop\n\
+                      \x20     # self.nixosModules.gaming\n\
+                      \x20     self.nixosModules.packages-dev # dev tools\n\
+                      \x20     self.nixosModules.home-manager\n\
+                      \x20     self.nixosModules.box-user-alice\n\
+                      \x20     self.nixosModules.desktop\n";
+        let names = referenced_nixos_modules(preset, &AttrRefs::default());
+        assert_eq!(names, ["desktop", "packages-dev", "home-manager", "box-user-alice"]);
+    }
+
+    #[test]
+    fn editing_a_preset_only_changes_its_imports() {
+        let preset = "{ inputs, self, ... }:\n\
+                      {\n\
+                      \x20 flake.nixosConfigurations.box = inputs.nixpkgs.lib.nixosSystem {\n\
+                      \x20   modules = [\n\
+                      \x20     ./_hardware-configuration.nix\n\
+                      \x20     inputs.disko.nixosModules.disko\n\
+                      \x20     self.nixosModules.desktop\n\
+                      \x20     # self.nixosModules.gaming\n\
+                      \x20     self.nixosModules.packages-dev # dev tools\n\
+                      \x20     self.nixosModules.home-manager\n\
+                      \x20     self.nixosModules.box-user-alice\n\
+                      \x20     { services.printing.enable = true; }\n\
+                      \x20   ];\n\
+                      \x20 };\n\
+                      }\n";
+        let settings = HostSettings {
+            home_manager: true,
+            ..Default::default()
+        };
+        let config = update_preset_imports(
+            preset,
+            "box",
+            &[module("desktop", false), module("gaming", true), module("audio", true)],
+            &[module("packages-dev", true)],
+            &["bob".to_string()],
+            &settings,
+        )
+        .unwrap();
+
+        let expected = preset
+            .replace("      self.nixosModules.desktop\n", "      # self.nixosModules.desktop\n")
+            .replace("      # self.nixosModules.gaming\n", "      self.nixosModules.gaming\n")
+            .replace(
+                "      self.nixosModules.box-user-alice\n",
+                "      self.nixosModules.box-user-alice\n\
+                 \x20     self.nixosModules.audio\n\
+                 \x20     self.nixosModules.box-user-bob\n",
+            );
+        assert_eq!(config, expected);
+
+        // Nothing to add the new references after
+        let bare = "{ modules = [ ./a.nix ]; }\n";
+        let modules = [module("audio", true)];
+        assert!(update_preset_imports(bare, "box", &modules, &[], &[], &settings).is_none());
+        assert_eq!(update_preset_imports(bare, "box", &[], &[], &[], &settings).unwrap(), bare);
+    }
+
+    #[test]
+    fn hidden_patterns_glob_match() {
+        let hidden = HiddenModules::default();
+        assert!(is_hidden(&hidden.nixos, "home-desktop"));
+        assert!(is_hidden(&hidden.nixos, "wsl"));
+        assert!(!is_hidden(&hidden.nixos, "wslg"));
+        assert!(!is_hidden(&hidden.nixos, "desktop"));
+        assert!(is_hidden(&hidden.hm, "packages-cli"));
+        assert!(!is_hidden(&hidden.hm, "homebrew"));
+        assert!(is_hidden(&hidden.packages, "dev-WSL"));
+
+        assert!(glob_match("*", ""));
+        assert!(glob_match("gaming-*-extra", "gaming-steam-extra"));
+        assert!(!glob_match("gaming-*-extra", "gaming-steam"));
+        assert!(glob_match("a*a", "aa"));
+        assert!(!glob_match("a*a", "a"));
+    }
+
+    #[test]
+    fn failure_hints_match_known_signatures() {
+        assert!(failure_hint("error: hash mismatch in fixed-output derivation").is_some());
+        assert!(failure_hint("error: writing to file: No space left on device").is_some());
+        assert_eq!(
+            failure_hint("error: attribute 'foo' missing"),
+            failure_hint("error: Attribute 'bar' MISSING at /nix/store/x.nix:3:5"),
+        );
+        assert!(failure_hint("error: attribute 'foo' already defined").is_none());
+        assert!(failure_hint("building '/nix/store/abc-foo.drv'...").is_none());
+    }
+
+    #[test]
+    fn tmpfs_root_replaces_generated_root_filesystem() {
+        let hw = "{ config, lib, ... }:\n\
+                  {\n\
+                  \x20 fileSystems.\"/\" =\n\
+                  \x20   { device = \"none\";\n\
+                  \x20     fsType = \"tmpfs\";\n\
+                  \x20   };\n\
+                  \n\
+                  \x20 fileSystems.\"/persist\" =\n\
+                  \x20   { device = \"/dev/disk/by-uuid/1234\";\n\
+                  \x20     fsType = \"ext4\";\n\
+                  \x20   };\n\
+                  }\n";
+        let out = tmpfs_root_hardware_config(hw);
+        assert_eq!(out.matches("fileSystems.\"/\" =").count(), 1);
+        assert!(out.contains("options = [ \"defaults\" \"size=25%\" \"mode=755\" ];"));
+        assert!(out.contains("device = \"/dev/disk/by-uuid/1234\";"));
+        assert!(out.contains("fileSystems.\"/persist\".neededForBoot = true;"));
+        assert!(out.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn swap_file_replaces_empty_swap_devices() {
+        let hw = "{\n  swapDevices = [ ];\n}\n";
+        let out = swap_file_hardware_config(hw, "/swap/swapfile");
+        assert_eq!(out, "{\n  swapDevices = [ { device = \"/swap/swapfile\"; } ];\n}\n");
+        let out = swap_file_hardware_config("{\n}\n", "/swap/swapfile");
+        assert!(out.contains("swapDevices = [ { device = \"/swap/swapfile\"; } ];"));
+        assert!(out.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn existing_users_are_scanned_from_the_host_dir() {
+        let base = crate::test_support::temp_path("users");
+        let layout = ModuleLayout::default();
+        let host_dir = layout.host_dir(&base, "box");
+        fs::create_dir_all(&host_dir).unwrap();
+        for file in ["user-bob.nix", "user-alice.nix", "user-.nix", "configuration.nix"] {
+            fs::write(host_dir.join(file), "{ }\n").unwrap();
+        }
+
+        let users = scan_existing_users(&base, &layout, "box");
+        let missing_host = scan_existing_users(&base, &layout, "other");
+        fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(users, ["alice", "bob"]);
+        assert!(missing_host.is_empty());
+    }
+}
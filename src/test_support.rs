@@ -0,0 +1,32 @@
+//! Fixtures shared by the unit tests of several modules.
+
+use std::path::PathBuf;
+
+use crate::app::App;
+use crate::config::InstallerConfig;
+use crate::nix::NixModule;
+use crate::theme::ThemeName;
+
+/// A plain module: no category, conflicts or requirement.
+pub(crate) fn module(name: &str, selected: bool) -> NixModule {
+    NixModule {
+        name: name.to_string(),
+        selected,
+        category: String::new(),
+        conflicts: Vec::new(),
+        required: false,
+        revealed: false,
+    }
+}
+
+/// An app on the repo at `repo`, with the default config and theme.
+pub(crate) fn test_app(repo: PathBuf) -> App {
+    let log_file = temp_path("test.log");
+    let theme = ThemeName::CatppuccinMocha.to_theme();
+    App::new(Some(repo), None, InstallerConfig::default(), theme, log_file)
+}
+
+/// A path under the temp directory that is unique to this test run.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("nixos-installer-{}-{}", name, std::process::id()))
+}
@@ -60,9 +60,10 @@ impl Theme {
 }
 
 /// Theme names that can be specified in config or CLI.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum ThemeName {
+    #[default]
     CatppuccinMocha,
     Nord,
     Dracula,
@@ -70,12 +71,6 @@ pub enum ThemeName {
     Gruvbox,
 }
 
-impl Default for ThemeName {
-    fn default() -> Self {
-        Self::CatppuccinMocha
-    }
-}
-
 impl std::fmt::Display for ThemeName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -1,14 +1,19 @@
 use ratatui::{
-    layout::{Constraint, Layout, Rect},
+    layout::{Constraint, Layout, Margin, Rect},
     style::{Color, Modifier, Style, Stylize},
     text::{Line, Span, Text},
-    widgets::{Block, BorderType, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{
+        Block, BorderType, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Scrollbar,
+        ScrollbarOrientation, ScrollbarState, Wrap,
+    },
     Frame,
 };
 
-use crate::app::{App, Step};
-use crate::disk::FsType;
-use crate::theme::Theme;
+use nixos_installer::app::{password_strength, App, ListHitbox, PasswordStrength, Step};
+use nixos_installer::config::RootPasswordPolicy;
+use nixos_installer::disk::{self, redact_url, BlockDevice, FsType};
+use nixos_installer::nix;
+use nixos_installer::theme::Theme;
 
 /// Helper to create a rounded block with the theme's border style.
 fn themed_block<'a>(theme: &Theme, title: &str) -> Block<'a> {
@@ -32,13 +37,153 @@ fn themed_block_colored<'a>(theme: &Theme, title: &str, border_color: Color) ->
         .style(Style::default().bg(theme.bg))
 }
 
+/// Record where a bordered list was drawn so mouse clicks can be mapped
+/// back to item indices. `rows` holds the item index for each rendered row.
+fn list_hitbox(area: Rect, state: &ListState, rows: Vec<Option<usize>>) -> ListHitbox {
+    ListHitbox {
+        area: area.inner(Margin::new(1, 1)),
+        offset: state.offset(),
+        rows,
+    }
+}
+
+/// Draw a vertical scrollbar on the right border of a bordered `area` when
+/// `content_len` rows don't fit. `position` is the first visible row.
+fn render_scrollbar(
+    frame: &mut Frame,
+    theme: &Theme,
+    area: Rect,
+    content_len: usize,
+    position: usize,
+) {
+    let viewport = area.height.saturating_sub(2) as usize;
+    if content_len <= viewport {
+        return;
+    }
+    let mut state = ScrollbarState::new(content_len.saturating_sub(viewport))
+        .position(position)
+        .viewport_content_length(viewport);
+    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+        .begin_symbol(None)
+        .end_symbol(None)
+        .thumb_style(Style::default().fg(theme.accent))
+        .track_style(Style::default().fg(theme.surface));
+    frame.render_stateful_widget(scrollbar, area.inner(Margin::new(0, 1)), &mut state);
+}
+
+/// Show "▼ N more" on the bottom border of a list when items are scrolled
+/// off below the visible rows. Header rows (`None`) are not counted.
+fn render_overflow_hint(frame: &mut Frame, theme: &Theme, hitbox: &ListHitbox) {
+    let visible_end = hitbox.offset + hitbox.area.height as usize;
+    let mut hidden: Vec<usize> = hitbox
+        .rows
+        .iter()
+        .skip(visible_end)
+        .flatten()
+        .copied()
+        .collect();
+    // Multi-row items repeat their index; count each item once
+    hidden.dedup();
+    if hidden.is_empty() {
+        return;
+    }
+    let hint = format!(" ▼ {} more ", hidden.len());
+    let width = hint.chars().count() as u16;
+    if hitbox.area.width < width + 1 {
+        return;
+    }
+    let hint_area = Rect {
+        x: hitbox.area.right() - width - 1,
+        y: hitbox.area.bottom(),
+        width,
+        height: 1,
+    };
+    frame.render_widget(
+        Paragraph::new(hint).style(Style::default().fg(theme.yellow).bg(theme.bg)),
+        hint_area,
+    );
+}
+
+/// Single-choice list of (name, description) options, each spanning two
+/// rows. `danger` is an option drawn in red unless highlighted (a
+/// destructive choice).
+fn render_option_list<N: AsRef<str>, D: AsRef<str>>(
+    frame: &mut Frame,
+    app: &mut App,
+    area: Rect,
+    block: Block,
+    options: &[(N, D)],
+    cursor: usize,
+    danger: Option<usize>,
+) {
+    let t = &app.theme;
+    let items: Vec<ListItem> = options
+        .iter()
+        .enumerate()
+        .map(|(i, (name, desc))| {
+            let style = if i == cursor {
+                Style::default()
+                    .fg(t.bg)
+                    .bg(t.accent)
+                    .add_modifier(Modifier::BOLD)
+            } else if danger == Some(i) {
+                Style::default().fg(t.red)
+            } else {
+                Style::default().fg(t.text)
+            };
+            ListItem::new(Text::from(vec![
+                Line::from(format!("  {}", name.as_ref())),
+                Line::from(format!("    {}", desc.as_ref())).style(Style::default().fg(t.text_dim)),
+            ]))
+            .style(style)
+        })
+        .collect();
+
+    let count = items.len();
+    let list = List::new(items).block(block);
+
+    let mut state = ListState::default();
+    state.select(Some(cursor));
+    frame.render_stateful_widget(list, area, &mut state);
+    // Each option spans two rows (name + description)
+    let rows = (0..count).flat_map(|i| [Some(i), Some(i)]).collect();
+    let mut hitbox = list_hitbox(area, &state, rows);
+    hitbox.offset *= 2;
+    render_overflow_hint(frame, t, &hitbox);
+    app.list_hitbox = Some(hitbox);
+}
+
+/// Set the first visible row of a log with `len` lines shown in
+/// `inner_height` rows. While auto-scrolling the last line stays at the
+/// bottom; otherwise the offset is clamped so the view never scrolls past
+/// the end (e.g. after the terminal grew), and reaching the end resumes
+/// auto-scroll.
+fn pin_log_scroll(scroll: &mut usize, auto_scroll: &mut bool, len: usize, inner_height: usize) {
+    let bottom = len.saturating_sub(inner_height);
+    if *auto_scroll || *scroll >= bottom {
+        *scroll = bottom;
+        *auto_scroll = true;
+    }
+}
+
+/// Smallest terminal the wizard is laid out for; below this only a notice
+/// is drawn instead of overlapping widgets.
+const MIN_WIDTH: u16 = 60;
+const MIN_HEIGHT: u16 = 18;
+
 /// Main render function dispatching to step-specific renderers.
 pub fn render(frame: &mut Frame, app: &mut App) {
     let area = frame.area();
+    app.list_hitbox = None;
     let t = &app.theme;
 
     frame.render_widget(Block::default().style(Style::default().bg(t.bg)), area);
 
+    if area.width < MIN_WIDTH || area.height < MIN_HEIGHT {
+        render_too_small(frame, t, area);
+        return;
+    }
+
     let [header_area, progress_area, body_area, footer_area] = Layout::vertical([
         Constraint::Length(3),
         Constraint::Length(3),
@@ -51,32 +196,82 @@ pub fn render(frame: &mut Frame, app: &mut App) {
     render_progress(frame, app, progress_area);
     render_footer(frame, footer_area, app);
 
+    // Custom partitioning: keep the layout of the disk being edited in view
+    let custom_partition_step = matches!(
+        app.step,
+        Step::CustomPartitionMount
+            | Step::CustomPartitionDisk
+            | Step::CustomPartitionSize
+            | Step::CustomPartitionFs
+            | Step::CustomPartitionFormat
+            | Step::CustomPartitionAnother
+    );
+    let body_area = match app.install_disks().get(app.part_disk_cursor) {
+        Some(disk) if custom_partition_step => {
+            let [body, bar_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(4)]).areas(body_area);
+            let t = &app.theme;
+            let title = format!(" {} ({}) ", disk.path, disk.size_human);
+            let lines = partition_bar(app, disk, bar_area.width.saturating_sub(4));
+            let p = Paragraph::new(Text::from(lines))
+                .block(themed_block_colored(t, &title, t.accent_dim))
+                .style(Style::default().bg(t.bg));
+            frame.render_widget(p, bar_area);
+            body
+        }
+        _ => body_area,
+    };
+
     match app.step {
         Step::CloningRepo => render_cloning(frame, app, body_area),
+        Step::Welcome => render_welcome(frame, app, body_area),
+        Step::EmptyRepo => render_empty_repo(frame, app, body_area),
         Step::SelectPreset => render_select_preset(frame, app, body_area),
         Step::HostName => render_text_input(frame, app, body_area, "Host Name", false),
-        Step::SelectNixosModules => render_module_checklist(
-            frame,
-            &app.theme,
-            &app.nixos_modules,
-            app.nixos_cursor,
-            " Select NixOS Modules (Space to toggle) ",
-            body_area,
-        ),
-        Step::SelectSystemPackages => render_module_checklist(
+        Step::OverwriteHost => render_overwrite_host(frame, app, body_area),
+        Step::SelectNixosModules => {
+            app.list_hitbox = render_module_checklist(
+                frame,
+                &app.theme,
+                &app.nixos_modules,
+                app.nixos_cursor,
+                " Select NixOS Modules (Space to toggle) ",
+                body_area,
+            )
+        }
+        Step::SelectSystemPackages => {
+            app.list_hitbox = render_module_checklist(
+                frame,
+                &app.theme,
+                &app.system_packages,
+                app.system_package_cursor,
+                " Select System Packages (Space to toggle) ",
+                body_area,
+            )
+        }
+        Step::SshKey => render_text_input(
             frame,
-            &app.theme,
-            &app.system_packages,
-            app.system_package_cursor,
-            " Select System Packages (Space to toggle) ",
+            app,
             body_area,
+            "SSH Public Key or Path for the First User (empty to skip)",
+            false,
         ),
         Step::CreateUser => render_text_input(frame, app, body_area, "Username", false),
         Step::UserPassword => render_text_input(frame, app, body_area, "Password", true),
         Step::UserPasswordConfirm => {
             render_text_input(frame, app, body_area, "Confirm Password", true)
         }
-        Step::AddAnotherUser => render_yes_no(frame, &app.theme, app.another_user_cursor, body_area, "Add another user?"),
+        Step::UserShell => render_user_shell(frame, app, body_area),
+        Step::AddAnotherUser => {
+            let question = if app.users.iter().all(|u| u.existing) {
+                let names: Vec<&str> = app.users.iter().map(|u| u.username.as_str()).collect();
+                format!("'{}' already has users: {}. Add one?", app.host_name, names.join(", "))
+            } else {
+                "Add another user?".to_string()
+            };
+            render_yes_no(frame, &app.theme, app.another_user_cursor, body_area, &question)
+        }
+        Step::ReviewUsers => render_review_users(frame, app, body_area),
         Step::SelectHmModules => {
             let title = if app.hm_user_index < app.users.len() {
                 format!(
@@ -86,7 +281,7 @@ pub fn render(frame: &mut Frame, app: &mut App) {
             } else {
                 " Select Home Manager Modules (Space to toggle) ".to_string()
             };
-            render_module_checklist(frame, &app.theme, &app.hm_modules, app.hm_cursor, &title, body_area);
+            app.list_hitbox = render_module_checklist(frame, &app.theme, &app.hm_modules, app.hm_cursor, &title, body_area);
         }
         Step::SelectUserPackages => {
             let title = if app.hm_user_index < app.users.len() {
@@ -97,14 +292,45 @@ pub fn render(frame: &mut Frame, app: &mut App) {
             } else {
                 " Select User Packages (Space to toggle) ".to_string()
             };
-            render_module_checklist(frame, &app.theme, &app.user_pkg_modules, app.user_pkg_cursor, &title, body_area);
+            app.list_hitbox = render_module_checklist(frame, &app.theme, &app.user_pkg_modules, app.user_pkg_cursor, &title, body_area);
         }
         Step::SelectDisk => render_select_disk(frame, app, body_area),
         Step::PartitionModeSelect => render_partition_mode(frame, app, body_area),
-        Step::SwapSize => render_text_input(frame, app, body_area, "Swap Size (GiB)", false),
+        Step::EfiSize => render_text_input(
+            frame,
+            app,
+            body_area,
+            "EFI Partition Size in MiB (512 default, 1024 recommended)",
+            false,
+        ),
+        Step::PersistSize => render_text_input(
+            frame,
+            app,
+            body_area,
+            "/persist Size in GiB (the rest of the disk goes to /nix)",
+            false,
+        ),
+        Step::SwapMode => render_swap_mode(frame, app, body_area),
+        Step::Bootloader => render_bootloader(frame, app, body_area),
+        Step::Kernel => render_kernel(frame, app, body_area),
+        Step::Gpu => render_gpu(frame, app, body_area),
+        Step::SwapSize => {
+            let label = match app.hibernation_swap_gb() {
+                Some(gb) => format!(
+                    "Swap Size: GiB, 512M, 8G or auto ({}G or more to allow hibernation)",
+                    gb
+                ),
+                None => "Swap Size: GiB, 512M, 8G or auto".to_string(),
+            };
+            render_text_input(frame, app, body_area, &label, false)
+        }
+        Step::ZramPercent => {
+            render_text_input(frame, app, body_area, "zram Size (% of RAM, default 50)", false)
+        }
         Step::CustomPartitionMount => {
             render_text_input(frame, app, body_area, "Mount Point (e.g. /, /boot, swap)", false)
         }
+        Step::CustomPartitionDisk => render_partition_disk(frame, app, body_area),
         Step::CustomPartitionSize => render_text_input(
             frame,
             app,
@@ -112,22 +338,247 @@ pub fn render(frame: &mut Frame, app: &mut App) {
             "Size in GiB (leave empty for remaining space)",
             false,
         ),
-        Step::CustomPartitionFs => render_fs_select(frame, app, body_area),
+        Step::CustomPartitionFs => {
+            let title = format!(" Filesystem for '{}' ", app.part_mount_input);
+            let cursor = app.part_fs_cursor;
+            render_fs_select(frame, app, body_area, FsType::all(), cursor, &title)
+        }
+        Step::RootFs => {
+            let cursor = app.root_fs_cursor;
+            let choices = FsType::ROOT;
+            let title = if app.tmpfs_root() {
+                " Filesystem for /persist and /nix "
+            } else {
+                " Root Filesystem "
+            };
+            render_fs_select(frame, app, body_area, choices, cursor, title)
+        }
+        Step::CustomPartitionFormat => {
+            let question = format!(
+                "Format '{}'? (No = keep data; the disk then keeps its existing partitions)",
+                app.part_mount_input.trim()
+            );
+            render_yes_no(frame, &app.theme, app.part_format_cursor, body_area, &question)
+        }
         Step::CustomPartitionAnother => {
             render_yes_no(frame, &app.theme, app.another_partition_cursor, body_area, "Add another partition?")
         }
         Step::Confirm => render_confirm(frame, app, body_area),
+        Step::PreviewConfig => render_preview(frame, app, body_area),
         Step::Installing => render_installing(frame, app, body_area),
-        Step::RootPassword => render_text_input(frame, app, body_area, "Root Password", true),
+        Step::RootPassword => {
+            let label = if app.root_password_policy() == RootPasswordPolicy::Optional {
+                "Root Password (leave empty to skip)"
+            } else {
+                "Root Password"
+            };
+            render_text_input(frame, app, body_area, label, true)
+        }
         Step::RootPasswordConfirm => {
             render_text_input(frame, app, body_area, "Confirm Root Password", true)
         }
+        Step::EncryptionPassphrase => {
+            render_text_input(frame, app, body_area, "Disk Encryption Passphrase", true)
+        }
+        Step::EncryptionPassphraseConfirm => {
+            render_text_input(frame, app, body_area, "Confirm Disk Encryption Passphrase", true)
+        }
         Step::Complete => render_complete(frame, app, body_area),
     }
 
+    if app.show_help {
+        render_help_popup(frame, app, area);
+    }
+
+    if app.prebuild_error.is_some() {
+        render_prebuild_error(frame, app, area);
+    }
+
+    if app.install_diagnostics.is_some() {
+        render_install_diagnostics(frame, app, area);
+    }
+
     if let Some(msg) = &app.status_message {
-        render_status_popup(frame, &app.theme, area, msg);
+        let msg = match &app.fix_step {
+            Some(step) => format!("{}\n\nPress f to go to {}.", msg, app.title_of(step)),
+            None => msg.clone(),
+        };
+        render_status_popup(frame, &app.theme, area, &msg);
+    }
+}
+
+/// Keybindings for the current step, as (keys, action) pairs.
+fn help_entries(app: &App) -> Vec<(&'static str, &'static str)> {
+    let mut entries = match app.step {
+        Step::CloningRepo => vec![
+            ("Up/Down, j/k", "Scroll the clone log"),
+            ("PgUp/PgDn, Home/End", "Scroll by a page, or to the top/bottom"),
+            ("Enter", "Retry the clone (after a failure)"),
+            ("q", "Quit (after a failure)"),
+        ],
+        Step::SelectNixosModules
+        | Step::SelectSystemPackages
+        | Step::SelectHmModules
+        | Step::SelectUserPackages => vec![
+            ("Up/Down, j/k", "Move the cursor"),
+            ("PgUp/PgDn, Home/End", "Move by a page, or to the first/last module"),
+            ("Space", "Toggle the module"),
+            ("Click", "Toggle the clicked module"),
+            ("A", "Show/hide the modules hidden_modules filters out"),
+            ("Enter", "Continue"),
+            ("q", "Quit"),
+        ],
+        Step::Welcome => vec![
+            ("Enter", "Continue to preset selection"),
+            ("t", "Switch to the next color theme"),
+            ("T", "Save the current theme to the config file"),
+            ("q, Esc", "Quit"),
+        ],
+        Step::SelectPreset => vec![
+            ("Up/Down, j/k", "Move the cursor"),
+            ("PgUp/PgDn, Home/End", "Move by a page, or to the first/last entry"),
+            ("Enter, Click", "Select"),
+            ("e", "Edit the preset: change the modules its configuration.nix imports"),
+            ("t", "Switch to the next color theme"),
+            ("T", "Save the current theme to the config file"),
+            ("q", "Quit"),
+        ],
+        Step::SelectDisk => vec![
+            ("Up/Down, j/k", "Move the cursor"),
+            ("PgUp/PgDn, Home/End", "Move by a page, or to the first/last entry"),
+            ("Space", "Mark the disk to install across several disks"),
+            ("Enter", "Use the marked disks, or the one under the cursor"),
+            ("q", "Quit"),
+        ],
+        Step::EmptyRepo
+        | Step::OverwriteHost
+        | Step::PartitionModeSelect
+        | Step::RootFs
+        | Step::SwapMode
+        | Step::Bootloader
+        | Step::Kernel
+        | Step::Gpu
+        | Step::UserShell
+        | Step::CustomPartitionDisk
+        | Step::CustomPartitionFs => vec![
+            ("Up/Down, j/k", "Move the cursor"),
+            ("Enter, Click", "Select"),
+        ],
+        Step::ReviewUsers => vec![
+            ("Up/Down, j/k", "Move the cursor"),
+            ("Enter", "Edit the user, or continue"),
+            ("d, Delete", "Delete the user"),
+            ("a", "Make the user the autologin user (toggle)"),
+            ("q", "Quit"),
+        ],
+        Step::AddAnotherUser | Step::CustomPartitionFormat | Step::CustomPartitionAnother => vec![
+            ("Left/Right, h/l, Tab", "Choose Yes or No"),
+            ("y/n", "Answer Yes or No right away"),
+            ("Enter", "Confirm"),
+        ],
+        Step::Confirm => vec![
+            ("Left/Right, h/l, Tab", "Choose Install or Back"),
+            ("y/n", "Install, or go Back"),
+            ("Space", "Toggle accept-flake-config"),
+            ("t", "Toggle periodic TRIM (SSD/NVMe disks)"),
+            ("w", "Toggle secure wipe before partitioning"),
+            ("e", "Toggle LUKS2 encryption of / (asks for a passphrase)"),
+            ("p", "Preview the generated files"),
+            ("Up/Down, j/k", "Select a partition (custom partitioning)"),
+            ("K/J", "Move the selected partition up/down"),
+            ("Enter", "Confirm"),
+        ],
+        Step::PreviewConfig => vec![
+            ("Up/Down, j/k", "Scroll"),
+            ("PgUp/PgDn, Home/End", "Scroll by a page, or to the top/bottom"),
+            ("Enter, p", "Back to the summary"),
+        ],
+        Step::Installing => vec![
+            ("Up/Down, j/k", "Scroll the install log"),
+            ("PgUp/PgDn, Home/End", "Scroll by a page; End resumes following the log"),
+            ("d", "Show the failure details (after a failure)"),
+            ("y", "Copy the log (after a failure)"),
+            ("s", "Save the log to a file (after a failure)"),
+            ("Enter", "Retry (after a failure)"),
+            ("q", "Quit (after a failure)"),
+        ],
+        Step::Complete => vec![
+            ("Left/Right, h/l, Tab", "Choose: reboot, set as boot default, shell or exit"),
+            ("y/n", "Reboot now, or exit without rebooting"),
+            ("Enter", "Confirm"),
+        ],
+        Step::SshKey => vec![
+            ("Type", "Paste a public key, or the path to a .pub file"),
+            ("Backspace, Ctrl+H", "Delete a character"),
+            ("Ctrl+U", "Clear the input"),
+            ("Enter", "Confirm; leave empty to skip SSH"),
+        ],
+        Step::HostName
+        | Step::CreateUser
+        | Step::EfiSize
+        | Step::PersistSize
+        | Step::SwapSize
+        | Step::ZramPercent
+        | Step::CustomPartitionMount
+        | Step::CustomPartitionSize
+        | Step::RootPassword
+        | Step::RootPasswordConfirm
+        | Step::UserPassword
+        | Step::UserPasswordConfirm
+        | Step::EncryptionPassphrase
+        | Step::EncryptionPassphraseConfirm => vec![
+            ("Type", "Edit the input"),
+            ("Backspace, Ctrl+H", "Delete a character"),
+            ("Enter", "Confirm"),
+        ],
+    };
+    match app.step {
+        Step::Installing | Step::Complete | Step::CloningRepo => {}
+        _ => entries.push(("Esc", "Go back")),
+    }
+    if app.can_skip_to_confirm() {
+        entries.push(("F2", "Skip to Confirm (once disk, host and users are set)"));
+    }
+    entries.push(("Ctrl+L", "Redraw the screen"));
+    entries.push(("Ctrl+C", "Quit immediately"));
+    if app.current_input_ref().is_some() {
+        entries.push(("F1", "Close this help"));
+    } else {
+        entries.push(("?, F1, Esc", "Close this help"));
+    }
+    entries
+}
+
+fn render_help_popup(frame: &mut Frame, app: &App, area: Rect) {
+    let t = &app.theme;
+    let entries = help_entries(app);
+    let key_width = entries.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+
+    let mut lines = vec![Line::from("")];
+    for (keys, action) in &entries {
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("  {:>width$}  ", keys, width = key_width),
+                Style::default().fg(t.accent).bold(),
+            ),
+            Span::styled(action.to_string(), Style::default().fg(t.text)),
+        ]));
     }
+
+    let popup = popup_area(area, 60, 70);
+    frame.render_widget(Clear, popup);
+    let p = Paragraph::new(Text::from(lines))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(t.accent))
+                .title(format!(" Keys: {} ", app.step_title()))
+                .title_style(Style::default().fg(t.accent).bold())
+                .style(Style::default().bg(t.surface)),
+        )
+        .wrap(Wrap { trim: false });
+    frame.render_widget(p, popup);
 }
 
 fn render_header(frame: &mut Frame, app: &App, area: Rect) {
@@ -146,12 +597,32 @@ fn render_header(frame: &mut Frame, app: &App, area: Rect) {
         .title(title)
         .title_style(Style::default().fg(t.accent).add_modifier(Modifier::BOLD))
         .style(Style::default().bg(t.bg));
+    let block = if app.module_check_running() {
+        block.title(
+            Line::from(format!(" {} Checking module names... ", app.spinner()))
+                .style(Style::default().fg(t.text_dim))
+                .right_aligned(),
+        )
+    } else {
+        block
+    };
     frame.render_widget(block, area);
 }
 
 fn render_progress(frame: &mut Frame, app: &App, area: Rect) {
     let t = &app.theme;
-    let ratio = app.step_number() as f64 / app.total_steps() as f64;
+    let ratio = app.progress_ratio();
+    let label = if app.step == Step::Installing && app.install_total > 0 {
+        format!(
+            "Step {}/{} (install {}/{})",
+            app.step_number(),
+            app.total_steps(),
+            app.install_progress,
+            app.install_total
+        )
+    } else {
+        format!("Step {}/{}", app.step_number(), app.total_steps())
+    };
     let gauge = Gauge::default()
         .block(
             Block::default()
@@ -162,7 +633,7 @@ fn render_progress(frame: &mut Frame, app: &App, area: Rect) {
         )
         .gauge_style(Style::default().fg(t.accent).bg(t.surface))
         .ratio(ratio)
-        .label(format!("Step {}/{}", app.step_number(), app.total_steps()));
+        .label(label);
     frame.render_widget(gauge, area);
 }
 
@@ -174,7 +645,9 @@ fn render_footer(frame: &mut Frame, area: Rect, app: &App) {
                 vec![
                     Span::styled(" Up/Down ", Style::default().fg(t.accent).bold()),
                     Span::styled("Scroll log ", Style::default().fg(t.text_dim)),
-                    Span::styled(" Enter ", Style::default().fg(t.red).bold()),
+                    Span::styled(" Enter ", Style::default().fg(t.accent).bold()),
+                    Span::styled("Retry ", Style::default().fg(t.text_dim)),
+                    Span::styled(" q ", Style::default().fg(t.red).bold()),
                     Span::styled("Quit ", Style::default().fg(t.text_dim)),
                 ]
             } else {
@@ -184,17 +657,52 @@ fn render_footer(frame: &mut Frame, area: Rect, app: &App) {
                 )]
             }
         }
-        Step::SelectPreset | Step::SelectDisk => {
+        Step::Welcome => {
+            vec![
+                Span::styled(" Enter ", Style::default().fg(t.accent).bold()),
+                Span::styled("Continue ", Style::default().fg(t.text_dim)),
+                Span::styled(" t ", Style::default().fg(t.accent).bold()),
+                Span::styled("Theme ", Style::default().fg(t.text_dim)),
+                Span::styled(" q ", Style::default().fg(t.red).bold()),
+                Span::styled("Quit", Style::default().fg(t.text_dim)),
+            ]
+        }
+        Step::SelectPreset => {
+            vec![
+                Span::styled(" Up/Down ", Style::default().fg(t.accent).bold()),
+                Span::styled("Navigate ", Style::default().fg(t.text_dim)),
+                Span::styled(" Enter ", Style::default().fg(t.accent).bold()),
+                Span::styled("Select ", Style::default().fg(t.text_dim)),
+                Span::styled(" e ", Style::default().fg(t.accent).bold()),
+                Span::styled("Edit preset ", Style::default().fg(t.text_dim)),
+                Span::styled(" t ", Style::default().fg(t.accent).bold()),
+                Span::styled("Theme ", Style::default().fg(t.text_dim)),
+                Span::styled(" q ", Style::default().fg(t.red).bold()),
+                Span::styled("Quit", Style::default().fg(t.text_dim)),
+            ]
+        }
+        Step::SelectDisk => {
             vec![
                 Span::styled(" Up/Down ", Style::default().fg(t.accent).bold()),
                 Span::styled("Navigate ", Style::default().fg(t.text_dim)),
+                Span::styled(" Space ", Style::default().fg(t.accent).bold()),
+                Span::styled("Mark ", Style::default().fg(t.text_dim)),
                 Span::styled(" Enter ", Style::default().fg(t.accent).bold()),
                 Span::styled("Select ", Style::default().fg(t.text_dim)),
+                Span::styled(" Esc ", Style::default().fg(t.yellow).bold()),
+                Span::styled("Back ", Style::default().fg(t.text_dim)),
                 Span::styled(" q ", Style::default().fg(t.red).bold()),
                 Span::styled("Quit", Style::default().fg(t.text_dim)),
             ]
         }
-        Step::PartitionModeSelect => {
+        Step::PartitionModeSelect
+        | Step::EmptyRepo
+        | Step::OverwriteHost
+        | Step::SwapMode
+        | Step::Bootloader
+        | Step::Kernel
+        | Step::Gpu
+        | Step::UserShell => {
             vec![
                 Span::styled(" Up/Down ", Style::default().fg(t.accent).bold()),
                 Span::styled("Navigate ", Style::default().fg(t.text_dim)),
@@ -218,30 +726,85 @@ fn render_footer(frame: &mut Frame, area: Rect, app: &App) {
                 Span::styled("Quit", Style::default().fg(t.text_dim)),
             ]
         }
-        Step::AddAnotherUser | Step::CustomPartitionAnother | Step::Complete => {
+        Step::AddAnotherUser => {
             vec![
                 Span::styled(" Left/Right ", Style::default().fg(t.accent).bold()),
                 Span::styled("Choose ", Style::default().fg(t.text_dim)),
                 Span::styled(" Enter ", Style::default().fg(t.accent).bold()),
                 Span::styled("Confirm ", Style::default().fg(t.text_dim)),
+                Span::styled(" Esc ", Style::default().fg(t.yellow).bold()),
+                Span::styled("Undo user", Style::default().fg(t.text_dim)),
             ]
         }
-        Step::Confirm => {
+        Step::ReviewUsers => {
+            vec![
+                Span::styled(" Up/Down ", Style::default().fg(t.accent).bold()),
+                Span::styled("Navigate ", Style::default().fg(t.text_dim)),
+                Span::styled(" Enter ", Style::default().fg(t.accent).bold()),
+                Span::styled("Edit/Continue ", Style::default().fg(t.text_dim)),
+                Span::styled(" d ", Style::default().fg(t.red).bold()),
+                Span::styled("Delete ", Style::default().fg(t.text_dim)),
+                Span::styled(" a ", Style::default().fg(t.accent).bold()),
+                Span::styled("Autologin ", Style::default().fg(t.text_dim)),
+                Span::styled(" Esc ", Style::default().fg(t.yellow).bold()),
+                Span::styled("Back ", Style::default().fg(t.text_dim)),
+                Span::styled(" q ", Style::default().fg(t.red).bold()),
+                Span::styled("Quit", Style::default().fg(t.text_dim)),
+            ]
+        }
+        Step::Complete if app.config_only => {
+            vec![
+                Span::styled(" Enter ", Style::default().fg(t.accent).bold()),
+                Span::styled("Exit ", Style::default().fg(t.text_dim)),
+            ]
+        }
+        Step::CustomPartitionFormat | Step::CustomPartitionAnother | Step::Complete => {
             vec![
                 Span::styled(" Left/Right ", Style::default().fg(t.accent).bold()),
                 Span::styled("Choose ", Style::default().fg(t.text_dim)),
-                Span::styled(" Space ", Style::default().fg(t.accent).bold()),
-                Span::styled("Toggle ", Style::default().fg(t.text_dim)),
                 Span::styled(" Enter ", Style::default().fg(t.accent).bold()),
                 Span::styled("Confirm ", Style::default().fg(t.text_dim)),
             ]
         }
+        Step::Confirm => {
+            let mut spans = vec![
+                Span::styled(" Left/Right ", Style::default().fg(t.accent).bold()),
+                Span::styled("Choose ", Style::default().fg(t.text_dim)),
+                Span::styled(" Space ", Style::default().fg(t.accent).bold()),
+                Span::styled("Toggle ", Style::default().fg(t.text_dim)),
+                Span::styled(" p ", Style::default().fg(t.accent).bold()),
+                Span::styled("Preview files ", Style::default().fg(t.text_dim)),
+            ];
+            if app.can_reorder_partitions() {
+                spans.push(Span::styled(" K/J ", Style::default().fg(t.accent).bold()));
+                spans.push(Span::styled("Reorder ", Style::default().fg(t.text_dim)));
+            }
+            spans.push(Span::styled(" Enter ", Style::default().fg(t.accent).bold()));
+            spans.push(Span::styled("Confirm ", Style::default().fg(t.text_dim)));
+            spans
+        }
+        Step::PreviewConfig => {
+            vec![
+                Span::styled(" Up/Down ", Style::default().fg(t.accent).bold()),
+                Span::styled("Scroll ", Style::default().fg(t.text_dim)),
+                Span::styled(" Esc/Enter ", Style::default().fg(t.yellow).bold()),
+                Span::styled("Back to summary", Style::default().fg(t.text_dim)),
+            ]
+        }
         Step::Installing => {
             if app.install_error.is_some() {
                 vec![
                     Span::styled(" Up/Down ", Style::default().fg(t.accent).bold()),
                     Span::styled("Scroll log ", Style::default().fg(t.text_dim)),
-                    Span::styled(" Enter ", Style::default().fg(t.red).bold()),
+                    Span::styled(" d ", Style::default().fg(t.accent).bold()),
+                    Span::styled("Details ", Style::default().fg(t.text_dim)),
+                    Span::styled(" y ", Style::default().fg(t.accent).bold()),
+                    Span::styled("Copy log ", Style::default().fg(t.text_dim)),
+                    Span::styled(" s ", Style::default().fg(t.accent).bold()),
+                    Span::styled("Save log ", Style::default().fg(t.text_dim)),
+                    Span::styled(" Enter ", Style::default().fg(t.yellow).bold()),
+                    Span::styled("Retry ", Style::default().fg(t.text_dim)),
+                    Span::styled(" q ", Style::default().fg(t.red).bold()),
                     Span::styled("Quit ", Style::default().fg(t.text_dim)),
                 ]
             } else if app.install_done {
@@ -274,6 +837,90 @@ fn render_footer(frame: &mut Frame, area: Rect, app: &App) {
 
 // ---- Step-specific renderers ----
 
+/// Gauge label for a running clone, e.g. "Receiving 42% — 12.3 MiB @ 5.0 MiB/s"
+/// or "Resolving deltas 80%". Lines without a percentage are shown as is.
+fn clone_progress_label(app: &App) -> String {
+    let phase = app.clone_phase.trim_start_matches("remote:").trim();
+    let Some((name, _)) = phase.split_once(':').filter(|_| phase.contains('%')) else {
+        return phase.to_string();
+    };
+    if name.starts_with("Resolving deltas") {
+        return format!("Resolving deltas {}%", app.clone_percent);
+    }
+    let verb = name.split_whitespace().next().unwrap_or(name);
+    match (&app.clone_transferred, &app.clone_rate) {
+        (Some(transferred), Some(rate)) => {
+            format!("{} {}% — {} @ {}", verb, app.clone_percent, transferred, rate)
+        }
+        _ => format!("{} {}%", verb, app.clone_percent),
+    }
+}
+
+fn render_welcome(frame: &mut Frame, app: &App, area: Rect) {
+    let t = &app.theme;
+    let mut lines: Vec<Line> = vec![
+        Line::from(""),
+        Line::from(format!("  {}", app.branding_title)).style(Style::default().fg(t.accent).bold()),
+        Line::from(""),
+    ];
+
+    let mut facts = Vec::new();
+    if let Some(url) = &app.repo_url {
+        facts.push(("Repository", redact_url(url)));
+    }
+    facts.push(("Path", app.base_path.display().to_string()));
+    let boot_mode = if app.uefi { "UEFI" } else { "BIOS (legacy)" };
+    facts.push(("Boot mode", boot_mode.to_string()));
+    facts.push(("Theme", format!("{}  (t to change, T to save)", app.theme.name)));
+    if let Some(release) = &app.nixos_release {
+        facts.push(("Live system", format!("NixOS {}", release)));
+    }
+    facts.push((
+        "Found",
+        format!(
+            "{} host presets, {} NixOS modules, {} package sets",
+            app.presets.len(),
+            app.nixos_modules.len(),
+            app.system_packages.len()
+        ),
+    ));
+    for (label, value) in facts {
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {:<12}", label), Style::default().fg(t.text_dim)),
+            Span::styled(value, Style::default().fg(t.text)),
+        ]));
+    }
+
+    lines.push(Line::from(""));
+    if app.base_path_warnings.is_empty() {
+        lines.push(
+            Line::from("  No problems found in the repo layout.")
+                .style(Style::default().fg(t.green)),
+        );
+    } else {
+        lines.push(
+            Line::from("  Problems found in the repo layout:")
+                .style(Style::default().fg(t.yellow).bold()),
+        );
+        for warning in &app.base_path_warnings {
+            for (i, part) in warning.lines().enumerate() {
+                let prefix = if i == 0 { "  ! " } else { "    " };
+                lines.push(
+                    Line::from(format!("{}{}", prefix, part)).style(Style::default().fg(t.yellow)),
+                );
+            }
+        }
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from("  Press Enter to continue.").style(Style::default().fg(t.text_dim)));
+
+    let p = Paragraph::new(Text::from(lines))
+        .block(themed_block(t, " Welcome "))
+        .style(Style::default().bg(t.bg))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(p, area);
+}
+
 fn render_cloning(frame: &mut Frame, app: &mut App, area: Rect) {
     let t = app.theme.clone();
     let [progress_area, log_area] =
@@ -294,9 +941,9 @@ fn render_cloning(frame: &mut Frame, app: &mut App, area: Rect) {
     } else if app.clone_done {
         "Clone complete!".to_string()
     } else if app.clone_phase.is_empty() {
-        "Starting...".to_string()
+        format!("{} Starting...", app.spinner())
     } else {
-        app.clone_phase.clone()
+        format!("{} {}", app.spinner(), clone_progress_label(app))
     };
 
     let gauge = Gauge::default()
@@ -306,15 +953,15 @@ fn render_cloning(frame: &mut Frame, app: &mut App, area: Rect) {
         .label(label);
     frame.render_widget(gauge, progress_area);
 
+    app.log_view_height = log_area.height.saturating_sub(2) as usize;
+
     // Auto-scroll clone log
-    if app.auto_scroll && !app.clone_log.is_empty() {
-        let inner_height = log_area.height.saturating_sub(2) as usize;
-        if app.clone_log.len() > inner_height {
-            app.clone_log_scroll = app.clone_log.len() - inner_height;
-        } else {
-            app.clone_log_scroll = 0;
-        }
-    }
+    pin_log_scroll(
+        &mut app.clone_log_scroll,
+        &mut app.auto_scroll,
+        app.clone_log.len(),
+        app.log_view_height,
+    );
 
     let log_lines: Vec<Line> = app
         .clone_log
@@ -332,7 +979,7 @@ fn render_cloning(frame: &mut Frame, app: &mut App, area: Rect) {
         .collect();
 
     let log_title = if app.clone_error.is_some() {
-        " Log (Up/Down to scroll | Enter to quit) ".to_string()
+        " Log (Up/Down to scroll | Enter to retry | q to quit) ".to_string()
     } else {
         " Log ".to_string()
     };
@@ -348,6 +995,7 @@ fn render_cloning(frame: &mut Frame, app: &mut App, area: Rect) {
         .scroll((app.clone_log_scroll as u16, 0))
         .wrap(Wrap { trim: false });
     frame.render_widget(log, log_area);
+    render_scrollbar(frame, &t, log_area, app.clone_log.len(), app.clone_log_scroll);
 }
 
 fn render_select_preset(frame: &mut Frame, app: &mut App, area: Rect) {
@@ -372,11 +1020,65 @@ fn render_select_preset(frame: &mut Frame, app: &mut App, area: Rect) {
         })
         .collect();
 
+    let count = items.len();
     let list = List::new(items).block(themed_block(t, " Select a host preset "));
 
     let mut state = ListState::default();
     state.select(Some(app.preset_cursor));
     frame.render_stateful_widget(list, area, &mut state);
+    let hitbox = list_hitbox(area, &state, (0..count).map(Some).collect());
+    render_overflow_hint(frame, t, &hitbox);
+    app.list_hitbox = Some(hitbox);
+}
+
+fn render_review_users(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let mut items: Vec<ListItem> = app
+        .users
+        .iter()
+        .enumerate()
+        .map(|(i, u)| {
+            let style = if i == app.review_user_cursor {
+                Style::default()
+                    .fg(t.bg)
+                    .bg(t.accent)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(t.text)
+            };
+            let note = if u.existing {
+                "defined in repo, kept as is"
+            } else if u.needs_hm_selection {
+                "new config"
+            } else {
+                "existing config"
+            };
+            let shell = u.shell.map(|s| format!(", {}", s.package())).unwrap_or_default();
+            let autologin = if u.autologin { ", autologin" } else { "" };
+            ListItem::new(format!("  {}  ({}{}{})", u.username, note, shell, autologin))
+                .style(style)
+        })
+        .collect();
+
+    let continue_style = if app.review_user_cursor == app.users.len() {
+        Style::default()
+            .fg(t.bg)
+            .bg(t.accent)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(t.yellow)
+    };
+    items.push(ListItem::new("> Continue").style(continue_style));
+
+    let count = items.len();
+    let list = List::new(items).block(themed_block(t, " Review users (d to delete, Enter to edit) "));
+
+    let mut state = ListState::default();
+    state.select(Some(app.review_user_cursor));
+    frame.render_stateful_widget(list, area, &mut state);
+    let hitbox = list_hitbox(area, &state, (0..count).map(Some).collect());
+    render_overflow_hint(frame, t, &hitbox);
+    app.list_hitbox = Some(hitbox);
 }
 
 fn render_text_input(frame: &mut Frame, app: &App, area: Rect, label: &str, masked: bool) {
@@ -384,7 +1086,7 @@ fn render_text_input(frame: &mut Frame, app: &App, area: Rect, label: &str, mask
     let [_spacer_top, input_area, msg_area, _spacer_bottom] = Layout::vertical([
         Constraint::Fill(1),
         Constraint::Length(5),
-        Constraint::Length(3),
+        Constraint::Length(4),
         Constraint::Fill(1),
     ])
     .areas(area);
@@ -414,6 +1116,7 @@ fn render_text_input(frame: &mut Frame, app: &App, area: Rect, label: &str, mask
     };
 
     let input = Paragraph::new(cursor)
+        .wrap(Wrap { trim: false })
         .block(
             Block::default()
                 .borders(Borders::ALL)
@@ -440,23 +1143,62 @@ fn render_text_input(frame: &mut Frame, app: &App, area: Rect, label: &str, mask
     let show_root_warn = app.root_password_mismatch
         && (app.step == Step::RootPassword || app.step == Step::RootPasswordConfirm);
 
+    let passphrase_step =
+        app.step == Step::EncryptionPassphrase || app.step == Step::EncryptionPassphraseConfirm;
+
+    let mut lines = Vec::new();
     if show_pw_warn || show_root_warn {
-        let warn = Paragraph::new("Passwords did not match. Please try again.")
-            .style(Style::default().fg(t.red))
-            .wrap(Wrap { trim: true });
-        frame.render_widget(warn, msg_center);
+        lines.push(
+            Line::from("Passwords did not match. Please try again.")
+                .style(Style::default().fg(t.red)),
+        );
     }
-}
-
-/// Render a checklist of NixModule items.
-fn render_module_checklist(
-    frame: &mut Frame,
-    theme: &Theme,
-    modules: &[crate::nix::NixModule],
-    cursor: usize,
-    title: &str,
+    if app.encryption_mismatch && passphrase_step {
+        lines.push(
+            Line::from("Passphrases did not match. Please try again.")
+                .style(Style::default().fg(t.red)),
+        );
+    }
+    let strength_shown = matches!(
+        app.step,
+        Step::RootPassword | Step::UserPassword | Step::EncryptionPassphrase
+    );
+    if strength_shown && !input_text.is_empty() {
+        let (word, color) = match password_strength(app.current_input_ref().unwrap_or_default()) {
+            PasswordStrength::Weak => ("weak", t.red),
+            PasswordStrength::Fair => ("fair", t.yellow),
+            PasswordStrength::Strong => ("strong", t.green),
+        };
+        lines.push(Line::from(vec![
+            Span::styled("Strength: ", Style::default().fg(t.text_dim)),
+            Span::styled(word, Style::default().fg(color).bold()),
+        ]));
+    }
+    if passphrase_step {
+        lines.push(
+            Line::from(
+                "There is no recovery if you lose this passphrase: \
+                 the data on / can't be decrypted without it.",
+            )
+            .style(Style::default().fg(t.yellow).bold()),
+        );
+    }
+    if !lines.is_empty() {
+        let msg = Paragraph::new(Text::from(lines)).wrap(Wrap { trim: true });
+        frame.render_widget(msg, msg_center);
+    }
+}
+
+/// Render a checklist of NixModule items.
+/// Returns the list geometry for mouse hit-testing (None if nothing is listed).
+fn render_module_checklist(
+    frame: &mut Frame,
+    theme: &Theme,
+    modules: &[nix::NixModule],
+    cursor: usize,
+    title: &str,
     area: Rect,
-) {
+) -> Option<ListHitbox> {
     if modules.is_empty() {
         let msg = Paragraph::new(Text::from(vec![
             Line::from(""),
@@ -478,36 +1220,89 @@ fn render_module_checklist(
         ]))
         .block(themed_block_colored(theme, title, theme.red));
         frame.render_widget(msg, area);
-        return;
+        return None;
     }
 
-    let items: Vec<ListItem> = modules
-        .iter()
-        .enumerate()
-        .map(|(i, m)| {
-            let checkbox = if m.selected { "[x]" } else { "[ ]" };
-            let style = if i == cursor {
-                Style::default()
-                    .fg(theme.bg)
-                    .bg(theme.accent)
-                    .add_modifier(Modifier::BOLD)
-            } else if m.selected {
-                Style::default().fg(theme.green)
+    // Group rows under dim category headers when the module tree has
+    // subdirectories. The cursor indexes `modules`, so headers are never
+    // selectable; we only translate it to the rendered row below.
+    let grouped = modules.iter().any(|m| !m.category.is_empty());
+    let mut items: Vec<ListItem> = Vec::new();
+    let mut rows: Vec<Option<usize>> = Vec::new();
+    let mut cursor_row = 0;
+    let mut current_category: Option<&str> = None;
+
+    for (i, m) in modules.iter().enumerate() {
+        if grouped && current_category != Some(m.category.as_str()) {
+            current_category = Some(m.category.as_str());
+            let header = if m.category.is_empty() {
+                "(top level)".to_string()
             } else {
-                Style::default().fg(theme.text)
+                format!("{}/", m.category)
             };
+            items.push(
+                ListItem::new(format!(" {}", header))
+                    .style(Style::default().fg(theme.text_dim).add_modifier(Modifier::BOLD)),
+            );
+            rows.push(None);
+        }
 
-            let display = format!(" {} {}", checkbox, m.name);
+        if i == cursor {
+            cursor_row = items.len();
+        }
 
-            ListItem::new(display).style(style)
-        })
-        .collect();
+        let checkbox = if m.required {
+            "[#]"
+        } else if m.selected {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        let style = if i == cursor {
+            Style::default()
+                .fg(theme.bg)
+                .bg(theme.accent)
+                .add_modifier(Modifier::BOLD)
+        } else if m.required {
+            Style::default().fg(theme.text_dim)
+        } else if m.revealed {
+            Style::default().fg(theme.yellow)
+        } else if m.selected {
+            Style::default().fg(theme.green)
+        } else {
+            Style::default().fg(theme.text)
+        };
 
-    let list = List::new(items).block(themed_block(theme, title));
+        let indent = if grouped { "   " } else { " " };
+        let display = format!(
+            "{}{} {}{}{}",
+            indent,
+            checkbox,
+            m.name,
+            if m.required { " (required)" } else { "" },
+            if m.revealed { " (normally hidden)" } else { "" }
+        );
+
+        items.push(ListItem::new(display).style(style));
+        rows.push(Some(i));
+    }
+
+    let mut block = themed_block(theme, title);
+    if modules.iter().any(|m| m.revealed) {
+        block = block.title_bottom(
+            Line::from(" Normally hidden modules may need special handling (WSL, HM-only, ...) ")
+                .style(Style::default().fg(theme.yellow)),
+        );
+    }
+    let list = List::new(items).block(block);
 
     let mut state = ListState::default();
-    state.select(Some(cursor));
+    state.select(Some(cursor_row));
     frame.render_stateful_widget(list, area, &mut state);
+    render_scrollbar(frame, theme, area, rows.len(), state.offset());
+    let hitbox = list_hitbox(area, &state, rows);
+    render_overflow_hint(frame, theme, &hitbox);
+    Some(hitbox)
 }
 
 fn render_select_disk(frame: &mut Frame, app: &mut App, area: Rect) {
@@ -523,7 +1318,7 @@ fn render_select_disk(frame: &mut Frame, app: &mut App, area: Rect) {
             Line::from("  physical disks attached to the system.")
                 .style(Style::default().fg(t.text_dim)),
             Line::from(""),
-            Line::from("  Press Esc to quit.")
+            Line::from("  Press Esc to go back or q to quit.")
                 .style(Style::default().fg(t.text_dim)),
         ]))
         .block(themed_block_colored(t, " Error ", t.red));
@@ -544,32 +1339,180 @@ fn render_select_disk(frame: &mut Frame, app: &mut App, area: Rect) {
             } else {
                 Style::default().fg(t.text)
             };
-            ListItem::new(format!("  {} - {} [{}]", d.path, d.size_human, d.model)).style(style)
+            let kind = if d.rotational { "HDD" } else { "SSD" };
+            // Checkboxes only matter when there is more than one disk
+            let mark = match (app.disks.len() > 1, app.marked_disks.contains(&d.path)) {
+                (false, _) => "",
+                (true, true) => "[x] ",
+                (true, false) => "[ ] ",
+            };
+            ListItem::new(format!(
+                "  {}{} - {} {} [{}]",
+                mark, d.path, d.size_human, kind, d.model
+            ))
+            .style(style)
         })
         .collect();
 
+    let count = items.len();
     let list = List::new(items).block(themed_block(t, " Select Installation Disk "));
 
     let mut state = ListState::default();
     state.select(Some(app.disk_cursor));
     frame.render_stateful_widget(list, area, &mut state);
+    let hitbox = list_hitbox(area, &state, (0..count).map(Some).collect());
+    render_overflow_hint(frame, t, &hitbox);
+    app.list_hitbox = Some(hitbox);
+}
+
+/// Choose which of the selected disks a custom partition is created on.
+fn render_partition_disk(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let items: Vec<ListItem> = app
+        .install_disks()
+        .iter()
+        .enumerate()
+        .map(|(i, d)| {
+            let style = if i == app.part_disk_cursor {
+                Style::default()
+                    .fg(t.bg)
+                    .bg(t.accent)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(t.text)
+            };
+            let planned = app.partitions.iter().filter(|p| p.disk == d.path).count();
+            ListItem::new(format!(
+                "  {} - {} [{}]  {} partition(s) planned",
+                d.path, d.size_human, d.model, planned
+            ))
+            .style(style)
+        })
+        .collect();
+
+    let count = items.len();
+    let title = format!(" Disk for '{}' ", app.part_mount_input.trim());
+    let list = List::new(items).block(themed_block(t, &title));
+
+    let mut state = ListState::default();
+    state.select(Some(app.part_disk_cursor));
+    frame.render_stateful_widget(list, area, &mut state);
+    let hitbox = list_hitbox(area, &state, (0..count).map(Some).collect());
+    render_overflow_hint(frame, t, &hitbox);
+    app.list_hitbox = Some(hitbox);
 }
 
 fn render_partition_mode(frame: &mut Frame, app: &mut App, area: Rect) {
     let t = &app.theme;
-    let options = vec![
+    let options = [
         ("Use Full Disk", "Automatic EFI + swap + root partitioning"),
         (
             "Custom Partitions",
             "Manually define mount points, sizes, and filesystems",
         ),
+        (
+            "Ephemeral Root on tmpfs (advanced)",
+            "EFI + swap + /persist + /nix; / is wiped on every boot (needs impermanence)",
+        ),
+    ];
+
+    let block = themed_block(t, " Partition Mode ");
+    let cursor = app.partition_mode_cursor;
+    render_option_list(frame, app, area, block, &options, cursor, None);
+}
+
+fn render_swap_mode(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let options = [
+        ("Swap partition", "Dedicated swap partition on the target disk"),
+        ("Swap file", "File on the root filesystem; on Btrfs in its own NOCOW subvolume"),
+        ("zram (compressed RAM swap)", "No swap partition; enables zramSwap in the host config"),
+        ("No swap", "Root takes the rest of the disk after EFI"),
+    ];
+
+    let block = themed_block(t, " Swap ");
+    let cursor = app.swap_mode_cursor;
+    render_option_list(frame, app, area, block, &options, cursor, None);
+}
+
+fn render_bootloader(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let grub_desc = if app.uefi {
+        "GRUB with efiSupport (themes, encrypted /boot)"
+    } else {
+        "GRUB installed to the target disk (BIOS)"
+    };
+    let options = [
+        ("systemd-boot", "Simple UEFI boot manager (UEFI only)"),
+        ("GRUB", grub_desc),
+        (
+            "Provided by a selected module",
+            "Skip boot.loader settings if a selected module sets them, else use the default",
+        ),
     ];
 
+    let mode = if app.uefi { "UEFI" } else { "BIOS" };
+    let block = themed_block(t, &format!(" Bootloader ({}) ", mode));
+    let cursor = app.bootloader_cursor;
+    render_option_list(frame, app, area, block, &options, cursor, None);
+}
+
+fn render_kernel(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let mut options = vec![(
+        "Default".to_string(),
+        "Leave boot.kernelPackages unset and use the nixpkgs default".to_string(),
+    )];
+    options.extend(nix::Kernel::ALL.iter().map(|k| {
+        let desc = match k {
+            nix::Kernel::Lts => "Current long-term support kernel, pinned explicitly",
+            nix::Kernel::Latest => "Newest stable kernel, for recent hardware",
+            nix::Kernel::Zen => "Tuned for desktop responsiveness",
+        };
+        (k.display_name().to_string(), format!("pkgs.{} - {}", k.attr(), desc))
+    }));
+
+    let block = themed_block(t, " Kernel ");
+    let cursor = app.kernel_cursor;
+    render_option_list(frame, app, area, block, &options, cursor, None);
+}
+
+fn render_gpu(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let mut options = vec![(
+        "None".to_string(),
+        "Write no graphics settings; the kernel's default drivers are used".to_string(),
+    )];
+    options.extend(nix::GpuDriver::ALL.iter().map(|&g| {
+        let desc = match g {
+            nix::GpuDriver::Nvidia => "Proprietary driver, open kernel module, modesetting",
+            nix::GpuDriver::Amd => "amdgpu, loaded early in the initrd",
+            nix::GpuDriver::Intel => "modesetting with the VA-API media driver",
+        };
+        let name = if app.detected_gpu == Some(g) {
+            format!("{} (detected)", g.display_name())
+        } else {
+            g.display_name().to_string()
+        };
+        (name, desc.to_string())
+    }));
+
+    let title = " Graphics Driver (best-effort starting point, review after install) ";
+    let block = themed_block(t, title);
+    let cursor = app.gpu_cursor;
+    render_option_list(frame, app, area, block, &options, cursor, None);
+}
+
+fn render_user_shell(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let mut options = vec!["Keep the default (bash)".to_string()];
+    options.extend(nix::Shell::ALL.iter().map(|s| s.package().to_string()));
+
     let items: Vec<ListItem> = options
         .iter()
         .enumerate()
-        .map(|(i, (name, desc))| {
-            let style = if i == app.partition_mode_cursor {
+        .map(|(i, name)| {
+            let style = if i == app.shell_cursor {
                 Style::default()
                     .fg(t.bg)
                     .bg(t.accent)
@@ -577,29 +1520,122 @@ fn render_partition_mode(frame: &mut Frame, app: &mut App, area: Rect) {
             } else {
                 Style::default().fg(t.text)
             };
-            ListItem::new(Text::from(vec![
-                Line::from(format!("  {}", name)),
-                Line::from(format!("    {}", desc)).style(Style::default().fg(t.text_dim)),
-            ]))
-            .style(style)
+            ListItem::new(format!("  {}", name)).style(style)
         })
         .collect();
 
-    let list = List::new(items).block(themed_block(t, " Partition Mode "));
+    let count = items.len();
+    let list = List::new(items).block(themed_block(t, " Login Shell "));
 
     let mut state = ListState::default();
-    state.select(Some(app.partition_mode_cursor));
+    state.select(Some(app.shell_cursor));
     frame.render_stateful_widget(list, area, &mut state);
+    let hitbox = list_hitbox(area, &state, (0..count).map(Some).collect());
+    render_overflow_hint(frame, t, &hitbox);
+    app.list_hitbox = Some(hitbox);
+}
+
+fn render_overwrite_host(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let options = [
+        (
+            "Load existing host as preset".to_string(),
+            "Keep its configuration.nix and continue with user setup".to_string(),
+        ),
+        (
+            "Overwrite existing configuration".to_string(),
+            format!(
+                "Replace {}/{}/configuration.nix with a new custom config",
+                app.config.module_layout().hosts.display(),
+                app.host_name
+            ),
+        ),
+        (
+            "Choose a different name".to_string(),
+            "Go back to the host name input".to_string(),
+        ),
+    ];
+
+    let title = format!(" Host '{}' already exists ", app.host_name);
+    let block = themed_block_colored(t, &title, t.yellow);
+    let cursor = app.overwrite_host_cursor;
+    render_option_list(frame, app, area, block, &options, cursor, Some(1));
 }
 
-fn render_fs_select(frame: &mut Frame, app: &mut App, area: Rect) {
+fn render_empty_repo(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let [text_area, list_area] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(6)]).areas(area);
+
+    let layout = app.config.module_layout();
+    let mut lines: Vec<Line> = vec![
+        Line::from(""),
+        Line::from(format!(
+            "  No host presets and no modules were found in '{}'.",
+            app.base_path.display()
+        ))
+        .style(Style::default().fg(t.yellow).bold()),
+        Line::from(""),
+    ];
+    if app.base_path_warnings.is_empty() {
+        // The directories are there, they just hold nothing the scan picks up
+        lines.push(
+            Line::from(format!(
+                "  The module directories exist but are empty: {}/, {}/ and {}/ contain no \
+                 .nix files or host directories.",
+                layout.hosts.display(),
+                layout.nixos_modules.display(),
+                layout.packages.display()
+            ))
+            .style(Style::default().fg(t.text)),
+        );
+    } else {
+        lines.push(
+            Line::from("  The repo layout doesn't match what the installer expects:")
+                .style(Style::default().fg(t.text)),
+        );
+        for warning in &app.base_path_warnings {
+            lines.push(Line::from(format!("  ! {}", warning)).style(Style::default().fg(t.yellow)));
+        }
+    }
+    lines.push(Line::from(""));
+    lines.push(
+        Line::from(
+            "  Continuing only offers a custom host with nothing to import, so the generated \
+             configuration will be close to empty. Check the repository URL and branch, or the \
+             *_dir settings in config.toml.",
+        )
+        .style(Style::default().fg(t.text_dim)),
+    );
+    let p = Paragraph::new(Text::from(lines))
+        .block(themed_block_colored(t, " Repository looks empty ", t.yellow))
+        .style(Style::default().bg(t.bg))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(p, text_area);
+
+    let options = [
+        ("Quit", "Fix the repository and run the installer again"),
+        ("Continue anyway", "Set up a custom host at your own risk"),
+    ];
+    let block = themed_block(t, " What now? ");
+    let cursor = app.empty_repo_cursor;
+    render_option_list(frame, app, list_area, block, &options, cursor, None);
+}
+
+fn render_fs_select(
+    frame: &mut Frame,
+    app: &mut App,
+    area: Rect,
+    fs_types: &[FsType],
+    cursor: usize,
+    title: &str,
+) {
     let t = &app.theme;
-    let fs_types = FsType::all();
     let items: Vec<ListItem> = fs_types
         .iter()
         .enumerate()
         .map(|(i, fs)| {
-            let style = if i == app.part_fs_cursor {
+            let style = if i == cursor {
                 Style::default()
                     .fg(t.bg)
                     .bg(t.accent)
@@ -611,13 +1647,15 @@ fn render_fs_select(frame: &mut Frame, app: &mut App, area: Rect) {
         })
         .collect();
 
-    let list = List::new(items).block(
-        themed_block(t, &format!(" Filesystem for '{}' ", app.part_mount_input)),
-    );
+    let count = items.len();
+    let list = List::new(items).block(themed_block(t, title));
 
     let mut state = ListState::default();
-    state.select(Some(app.part_fs_cursor));
+    state.select(Some(cursor));
     frame.render_stateful_widget(list, area, &mut state);
+    let hitbox = list_hitbox(area, &state, (0..count).map(Some).collect());
+    render_overflow_hint(frame, t, &hitbox);
+    app.list_hitbox = Some(hitbox);
 }
 
 fn render_yes_no(frame: &mut Frame, theme: &Theme, cursor: usize, area: Rect, question: &str) {
@@ -692,29 +1730,97 @@ fn render_confirm(frame: &mut Frame, app: &App, area: Rect) {
         ))
         .style(Style::default().fg(t.text)),
     );
+    let flake_attr = app.flake_attr();
+    if flake_attr != app.host_name {
+        lines.push(
+            Line::from(format!("  Flake attribute: nixosConfigurations.{}", flake_attr))
+                .style(Style::default().fg(t.text)),
+        );
+    }
 
-    if let Some(disk) = &app.selected_disk {
+    let disks = app.install_disks();
+    if !disks.is_empty() {
         lines.push(Line::from(""));
+    }
+    for disk in &disks {
         lines.push(
             Line::from(format!("  Disk: {} ({})", disk.path, disk.size_human))
                 .style(Style::default().fg(t.accent)),
         );
     }
+    let multi_disk = disks.len() > 1;
 
     lines.push(Line::from(""));
     lines.push(Line::from("  Partitions:").style(Style::default().fg(t.yellow).bold()));
-    for p in &app.partitions {
+    if app.tmpfs_root() {
+        lines.push(
+            Line::from("    / -> tmpfs (ephemeral, wiped on every boot)")
+                .style(Style::default().fg(t.text)),
+        );
+    }
+    let reorder = app.can_reorder_partitions();
+    let devices = disk::partition_devices(&app.partitions);
+    for (i, p) in app.partitions.iter().enumerate() {
+        // Kept partition tables are used as they are: show the partition
+        // each entry maps to instead of a size that won't be applied
         let size = match p.size_mb {
-            Some(mb) => format!("{:.1} GiB", mb as f64 / 1024.0),
+            _ if disk::keeps_partition_table(&p.disk, &app.partitions) => devices[i].clone(),
+            Some(mb) => format_mb(mb),
             None => "remaining".to_string(),
         };
+        let selected = reorder && i == app.confirm_partition_cursor;
+        let style = if selected {
+            Style::default().fg(t.accent).bold()
+        } else {
+            Style::default().fg(t.text)
+        };
         lines.push(
             Line::from(format!(
-                "    {} -> {} ({}) [{}]",
+                "  {} {} -> {} ({}) [{}]{}{}",
+                if selected { ">" } else { " " },
                 p.label,
                 p.mount_point,
                 size,
-                p.fs_type.as_str()
+                p.fs_type.as_str(),
+                if multi_disk { format!(" on {}", p.disk) } else { String::new() },
+                if p.format { "" } else { " keep existing data" }
+            ))
+            .style(style),
+        );
+    }
+
+    let bar_width = summary_area.width.saturating_sub(8);
+    for disk in &disks {
+        if multi_disk {
+            lines.push(
+                Line::from(format!("    {}:", disk.path)).style(Style::default().fg(t.text_dim)),
+            );
+        }
+        lines.extend(partition_bar(app, disk, bar_width).into_iter().map(|l| {
+            let mut spans = vec![Span::raw("    ")];
+            spans.extend(l.spans);
+            Line::from(spans)
+        }));
+    }
+
+    if let Some(percent) = app.zram_percent {
+        lines.push(
+            Line::from(format!("    zram swap ({}% of RAM)", percent))
+                .style(Style::default().fg(t.text)),
+        );
+    }
+    if let Some(mb) = app.swap_file_mb {
+        let subvolume = if app.swap_file_fs() == Some(FsType::Btrfs) {
+            " (Btrfs subvolume, NOCOW)"
+        } else {
+            ""
+        };
+        lines.push(
+            Line::from(format!(
+                "    swap file {} ({}){}",
+                app.swap_file_path(),
+                format_mb(mb),
+                subvolume
             ))
             .style(Style::default().fg(t.text)),
         );
@@ -725,8 +1831,29 @@ fn render_confirm(frame: &mut Frame, app: &App, area: Rect) {
     for u in &app.users {
         let mod_count = u.hm_modules.iter().filter(|m| m.selected).count();
         let pkg_count = u.package_modules.iter().filter(|m| m.selected).count();
+        let hm = if u.existing {
+            "existing, kept as is".to_string()
+        } else if app.home_manager {
+            format!("{} HM modules, {} packages", mod_count, pkg_count)
+        } else {
+            "no Home Manager".to_string()
+        };
         lines.push(
-            Line::from(format!("    {} ({} HM modules, {} packages)", u.username, mod_count, pkg_count))
+            Line::from(format!(
+                "    {} ({}{}{})",
+                u.username,
+                hm,
+                u.shell.map(|s| format!(", {}", s.package())).unwrap_or_default(),
+                if u.autologin { ", autologin" } else { "" }
+            ))
+            .style(Style::default().fg(t.text)),
+        );
+    }
+    let first_generated = app.users.iter().find(|u| !u.existing);
+    if let (Some(key), Some(first)) = (&app.ssh_authorized_key, first_generated) {
+        let key_type = key.split_whitespace().next().unwrap_or_default();
+        lines.push(
+            Line::from(format!("  SSH: enabled, {} key for {}", key_type, first.username))
                 .style(Style::default().fg(t.text)),
         );
     }
@@ -742,6 +1869,24 @@ fn render_confirm(frame: &mut Frame, app: &App, area: Rect) {
             ))
             .style(Style::default().fg(t.text)),
         );
+        let bootloader = app
+            .bootloader
+            .map(|b| b.display_name())
+            .unwrap_or("from selected modules");
+        lines.push(
+            Line::from(format!("  Bootloader: {}", bootloader)).style(Style::default().fg(t.text)),
+        );
+        let kernel = match app.kernel {
+            Some(k) => format!("{} ({})", k.display_name(), k.attr()),
+            None => "nixpkgs default".to_string(),
+        };
+        lines.push(Line::from(format!("  Kernel: {}", kernel)).style(Style::default().fg(t.text)));
+        if let Some(gpu) = app.gpu {
+            lines.push(
+                Line::from(format!("  Graphics: {} (review after install)", gpu.display_name()))
+                    .style(Style::default().fg(t.text)),
+            );
+        }
     }
 
     lines.push(Line::from(""));
@@ -762,10 +1907,94 @@ fn render_confirm(frame: &mut Frame, app: &App, area: Rect) {
         ))
         .style(flake_style),
     );
+    if app.offers_fstrim() {
+        let trim_style = if app.enable_fstrim {
+            Style::default().fg(t.green)
+        } else {
+            Style::default().fg(t.text_dim)
+        };
+        lines.push(
+            Line::from(format!(
+                "  {} services.fstrim.enable, SSD detected  (t to toggle)",
+                if app.enable_fstrim { "[x]" } else { "[ ]" }
+            ))
+            .style(trim_style),
+        );
+    }
+    let wipe_style = if app.secure_wipe {
+        Style::default().fg(t.green)
+    } else {
+        Style::default().fg(t.text_dim)
+    };
+    lines.push(
+        Line::from(format!(
+            "  {} secure wipe before partitioning  (w to toggle)",
+            if app.secure_wipe { "[x]" } else { "[ ]" }
+        ))
+        .style(wipe_style),
+    );
+    if app.offers_encryption() || app.encrypt_root {
+        let encrypt_style = if app.encrypt_root {
+            Style::default().fg(t.green)
+        } else {
+            Style::default().fg(t.text_dim)
+        };
+        lines.push(
+            Line::from(format!(
+                "  {} encrypt / with LUKS2  (e to toggle)",
+                if app.encrypt_root { "[x]" } else { "[ ]" }
+            ))
+            .style(encrypt_style),
+        );
+    }
+    if app.secure_wipe {
+        if let Some(warning) = app.secure_wipe_warning() {
+            lines.push(
+                Line::from(format!("      {}", warning)).style(Style::default().fg(t.yellow)),
+            );
+        }
+    }
+    for (path, health) in &app.disk_health {
+        let reallocated = health.reallocated_sectors.unwrap_or(0);
+        let (text, style) = if !health.passed {
+            (
+                format!("  SMART {}: FAILING - this disk may be dying", path),
+                Style::default().fg(t.red).add_modifier(Modifier::BOLD),
+            )
+        } else if reallocated > 0 {
+            (
+                format!("  SMART {}: PASSED, but {} reallocated sectors", path, reallocated),
+                Style::default().fg(t.yellow),
+            )
+        } else {
+            (format!("  SMART {}: PASSED", path), Style::default().fg(t.green))
+        };
+        lines.push(Line::from(text).style(style));
+    }
+
+    if app.prebuild_running() {
+        let what = if app.config.prebuild_check.unwrap_or(false) {
+            format!("Building {} to check it", app.host_name)
+        } else {
+            "Checking the flake inputs".to_string()
+        };
+        lines.push(Line::from(""));
+        lines.push(
+            Line::from(format!(
+                "  {} {} before installing (the disk is untouched, Esc cancels)...",
+                app.spinner(),
+                what
+            ))
+            .style(Style::default().fg(t.yellow).add_modifier(Modifier::BOLD)),
+        );
+    }
 
     lines.push(Line::from(""));
     lines.push(
-        Line::from("  WARNING: This will ERASE all data on the selected disk!")
+        Line::from(format!(
+            "  WARNING: This will ERASE all data on the selected {}!",
+            if multi_disk { "disks" } else { "disk" }
+        ))
             .style(Style::default().fg(t.red).add_modifier(Modifier::BOLD)),
     );
 
@@ -804,16 +2033,76 @@ fn render_confirm(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(buttons, button_area);
 }
 
+/// Color a line of generated Nix: comments (unselected modules) dim,
+/// `self.*` module references green, the host name in the accent color,
+/// other settings yellow and everything else as plain text.
+fn nix_line_style(theme: &Theme, line: &str) -> Style {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("networking.hostName") {
+        Style::default().fg(theme.accent).add_modifier(Modifier::BOLD)
+    } else if trimmed.starts_with('#') {
+        Style::default().fg(theme.text_dim)
+    } else if trimmed.starts_with("self.") || trimmed.starts_with("./") {
+        Style::default().fg(theme.green)
+    } else if trimmed.contains(" = ") {
+        Style::default().fg(theme.yellow)
+    } else {
+        Style::default().fg(theme.text)
+    }
+}
+
+/// Turn a generated Nix file into indented, highlighted lines.
+fn highlight_nix(theme: &Theme, content: &str) -> Vec<Line<'static>> {
+    content
+        .lines()
+        .map(|line| Line::from(format!("  {}", line)).style(nix_line_style(theme, line)))
+        .collect()
+}
+
+fn render_preview(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = app.theme.clone();
+    let files = app.preview_files();
+    let hosts_dir = app.config.module_layout().hosts;
+
+    let mut lines: Vec<Line> = Vec::new();
+    if files.is_empty() {
+        lines.push(
+            Line::from("  No files will be generated (preset host with existing users).")
+                .style(Style::default().fg(t.text_dim)),
+        );
+    }
+    for (file_name, content) in &files {
+        lines.push(
+            Line::from(format!(
+                "── {}/{}/{} ──",
+                hosts_dir.display(),
+                app.host_name,
+                file_name
+            ))
+            .style(Style::default().fg(t.accent).add_modifier(Modifier::BOLD)),
+        );
+        lines.extend(highlight_nix(&t, content));
+        lines.push(Line::from(""));
+    }
+
+    // Clamp scrolling so the last line can't scroll out of view
+    let inner_height = area.height.saturating_sub(2) as usize;
+    let max_scroll = lines.len().saturating_sub(inner_height);
+    app.log_view_height = inner_height;
+    app.preview_scroll = app.preview_scroll.min(max_scroll);
+
+    let preview = Paragraph::new(Text::from(lines))
+        .block(themed_block(&t, " Generated files (not yet written) "))
+        .scroll((app.preview_scroll as u16, 0));
+    frame.render_widget(preview, area);
+}
+
 fn render_installing(frame: &mut Frame, app: &mut App, area: Rect) {
     let t = app.theme.clone();
     let [progress_area, log_area] =
         Layout::vertical([Constraint::Length(5), Constraint::Fill(1)]).areas(area);
 
-    let ratio = if app.install_total > 0 {
-        app.install_progress as f64 / app.install_total as f64
-    } else {
-        0.0
-    };
+    let ratio = app.install_ratio();
 
     let gauge_style = if app.install_error.is_some() {
         Style::default().fg(t.red).bg(t.surface)
@@ -830,8 +2119,16 @@ fn render_installing(frame: &mut Frame, app: &mut App, area: Rect) {
         )
     } else if app.install_done {
         "Complete!".to_string()
+    } else if let Some(percent) = app.install_step_percent {
+        format!(
+            "{} {}/{} ({}%)",
+            app.spinner(),
+            app.install_progress,
+            app.install_total,
+            percent
+        )
     } else {
-        format!("{}/{}", app.install_progress, app.install_total)
+        format!("{} {}/{}", app.spinner(), app.install_progress, app.install_total)
     };
 
     let gauge = Gauge::default()
@@ -841,16 +2138,16 @@ fn render_installing(frame: &mut Frame, app: &mut App, area: Rect) {
         .label(label);
     frame.render_widget(gauge, progress_area);
 
-    // Auto-scroll: if enabled, set scroll so the last log line is visible.
     // The log block has 2 lines of border (top + bottom), leaving inner height.
-    if app.auto_scroll && !app.install_log.is_empty() {
-        let inner_height = log_area.height.saturating_sub(2) as usize;
-        if app.install_log.len() > inner_height {
-            app.log_scroll = app.install_log.len() - inner_height;
-        } else {
-            app.log_scroll = 0;
-        }
-    }
+    app.log_view_height = log_area.height.saturating_sub(2) as usize;
+
+    // Auto-scroll: if enabled, set scroll so the last log line is visible.
+    pin_log_scroll(
+        &mut app.log_scroll,
+        &mut app.auto_scroll,
+        app.install_log.len(),
+        app.log_view_height,
+    );
 
     let log_lines: Vec<Line> = app
         .install_log
@@ -871,7 +2168,7 @@ fn render_installing(frame: &mut Frame, app: &mut App, area: Rect) {
     let log_title = if app.install_error.is_some() {
         format!(
             " Log (Up/Down to scroll) | Full log: {} ",
-            crate::app::LOG_FILE
+            app.log_file.display()
         )
     } else {
         " Log ".to_string()
@@ -888,20 +2185,54 @@ fn render_installing(frame: &mut Frame, app: &mut App, area: Rect) {
         .scroll((app.log_scroll as u16, 0))
         .wrap(Wrap { trim: false });
     frame.render_widget(log, log_area);
+    render_scrollbar(frame, &t, log_area, app.install_log.len(), app.log_scroll);
+}
+
+/// Summary shown at the end of `--config-only` runs: the files written.
+fn render_config_only_complete(frame: &mut Frame, app: &App, area: Rect) {
+    let t = &app.theme;
+    let mut lines = vec![
+        Line::from(""),
+        Line::from("  Configuration files generated.")
+            .style(Style::default().fg(t.green).add_modifier(Modifier::BOLD)),
+        Line::from(""),
+        Line::from(format!("  Host: {}", app.host_name)).style(Style::default().fg(t.accent)),
+        Line::from(""),
+        Line::from("  Files written:").style(Style::default().fg(t.yellow).bold()),
+    ];
+    for path in &app.generated_files {
+        lines.push(
+            Line::from(format!("    {}", path.display())).style(Style::default().fg(t.text)),
+        );
+    }
+    lines.push(Line::from(""));
+    lines.push(
+        Line::from("  No disks were touched. Review the files, then `git add` them.")
+            .style(Style::default().fg(t.text_dim)),
+    );
+
+    let p = Paragraph::new(Text::from(lines))
+        .block(themed_block_colored(t, " Complete ", t.green))
+        .wrap(Wrap { trim: false });
+    frame.render_widget(p, area);
 }
 
 fn render_complete(frame: &mut Frame, app: &App, area: Rect) {
     let t = &app.theme;
+    if app.config_only {
+        render_config_only_complete(frame, app, area);
+        return;
+    }
     let [_top, center, _bottom] = Layout::vertical([
         Constraint::Fill(1),
-        Constraint::Length(11),
+        Constraint::Length(12),
         Constraint::Fill(1),
     ])
     .areas(area);
 
     let [_left, mid, _right] = Layout::horizontal([
         Constraint::Fill(1),
-        Constraint::Percentage(60),
+        Constraint::Percentage(80),
         Constraint::Fill(1),
     ])
     .areas(center);
@@ -915,7 +2246,23 @@ fn render_complete(frame: &mut Frame, app: &App, area: Rect) {
     } else {
         Style::default().fg(t.green)
     };
-    let no_style = if cursor == 1 {
+    let boot_style = if cursor == 1 {
+        Style::default()
+            .fg(t.bg)
+            .bg(t.accent)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(t.accent)
+    };
+    let shell_style = if cursor == 2 {
+        Style::default()
+            .fg(t.bg)
+            .bg(t.accent)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(t.accent)
+    };
+    let no_style = if cursor == 3 {
         Style::default()
             .fg(t.bg)
             .bg(t.red)
@@ -940,13 +2287,19 @@ fn render_complete(frame: &mut Frame, app: &App, area: Rect) {
         ))
         .style(Style::default().fg(t.text)),
         Line::from(""),
-        Line::from("  Would you like to reboot now?")
+        Line::from("  Reboot now, or inspect the new system first?")
             .style(Style::default().fg(t.text).bold()),
         Line::from(""),
         Line::from(vec![
             Span::raw("    "),
             Span::styled("  Reboot  ", yes_style),
+            Span::raw("  "),
+            Span::styled("  Set as boot default without rebooting  ", boot_style),
+        ]),
+        Line::from(vec![
             Span::raw("    "),
+            Span::styled("  Open shell in installed system  ", shell_style),
+            Span::raw("  "),
             Span::styled("  Exit  ", no_style),
         ]),
     ]);
@@ -963,6 +2316,79 @@ fn render_complete(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(p, mid);
 }
 
+fn format_mb(mb: u64) -> String {
+    if mb < 1024 {
+        format!("{} MiB", mb)
+    } else {
+        format!("{:.1} GiB", mb as f64 / 1024.0)
+    }
+}
+
+/// A bar `width` cells wide showing the partitions planned on `disk` in
+/// proportion to its size, colored by filesystem, plus a legend line. The
+/// partition without a size gets whatever the others leave.
+fn partition_bar(app: &App, disk: &BlockDevice, width: u16) -> Vec<Line<'static>> {
+    let t = &app.theme;
+    let disk_mb = (disk.size_bytes / (1024 * 1024)).max(1);
+    let planned: Vec<_> = app.partitions.iter().filter(|p| p.disk == disk.path).collect();
+    let fixed_mb: u64 = planned.iter().filter_map(|p| p.size_mb).sum();
+    let free_mb = disk_mb.saturating_sub(fixed_mb);
+
+    // (label, MiB, color, fill char)
+    let mut segments: Vec<(String, u64, Color, char)> = Vec::new();
+    let mut filler_used = false;
+    for p in &planned {
+        let color = match p.fs_type {
+            FsType::Fat32 => t.yellow,
+            FsType::Ext4 => t.accent,
+            FsType::Btrfs => t.green,
+            FsType::Xfs => t.accent_dim,
+            FsType::Swap => t.red,
+        };
+        let (mb, label) = match p.size_mb {
+            Some(mb) => (mb, format!("{} {}", p.mount_point, format_mb(mb))),
+            None if !filler_used => {
+                filler_used = true;
+                (free_mb, format!("{} {} (remaining)", p.mount_point, format_mb(free_mb)))
+            }
+            None => (0, format!("{} (no space left)", p.mount_point)),
+        };
+        segments.push((label, mb, color, '█'));
+    }
+    if !filler_used && free_mb > 0 {
+        segments.push((format!("free {}", format_mb(free_mb)), free_mb, t.text_dim, '░'));
+    }
+
+    // Cell boundaries from the running total, so rounding doesn't drift
+    let total_mb = disk_mb.max(fixed_mb);
+    let mut bar = Vec::new();
+    let mut legend = Vec::new();
+    let mut used_mb = 0;
+    let mut drawn = 0usize;
+    for (i, (label, mb, color, fill)) in segments.into_iter().enumerate() {
+        used_mb += mb;
+        let end = (used_mb as f64 / total_mb as f64 * width as f64).round() as usize;
+        // Every partition stays visible, however small
+        let cells = end.saturating_sub(drawn).max(usize::from(mb > 0));
+        drawn += cells;
+        bar.push(Span::styled(fill.to_string().repeat(cells), Style::default().fg(color)));
+        if i > 0 {
+            legend.push(Span::styled("  ", Style::default()));
+        }
+        legend.push(Span::styled(label, Style::default().fg(color)));
+    }
+    if fixed_mb > disk_mb {
+        legend.push(Span::styled(
+            format!("  {} over the disk size", format_mb(fixed_mb - disk_mb)),
+            Style::default().fg(t.red).bold(),
+        ));
+    }
+    if bar.is_empty() {
+        bar.push(Span::styled("░".repeat(width as usize), Style::default().fg(t.text_dim)));
+    }
+    vec![Line::from(bar), Line::from(legend)]
+}
+
 fn render_status_popup(frame: &mut Frame, theme: &Theme, area: Rect, msg: &str) {
     let popup = popup_area(area, 50, 20);
     frame.render_widget(Clear, popup);
@@ -983,6 +2409,139 @@ fn render_status_popup(frame: &mut Frame, theme: &Theme, area: Rect, msg: &str)
     frame.render_widget(p, popup);
 }
 
+/// Scrollable popup with the stderr tail of a failed nixos-install, with
+/// hints for well-known failure signatures.
+fn render_install_diagnostics(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let Some(stderr) = &app.install_diagnostics else {
+        return;
+    };
+
+    let mut lines = vec![
+        Line::from(app.install_error.clone().unwrap_or_default())
+            .style(Style::default().fg(t.yellow).bold()),
+        Line::from(format!("Full log: {}", app.log_file.display()))
+            .style(Style::default().fg(t.text_dim)),
+        Line::from(""),
+    ];
+    let mut hints: Vec<&str> = Vec::new();
+    for hint in stderr.iter().filter_map(|l| nix::failure_hint(l)) {
+        if !hints.contains(&hint) {
+            hints.push(hint);
+        }
+    }
+    for hint in &hints {
+        lines.push(Line::from(format!("Hint: {}", hint)).style(Style::default().fg(t.green)));
+    }
+    if !hints.is_empty() {
+        lines.push(Line::from(""));
+    }
+    if stderr.is_empty() {
+        lines.push(
+            Line::from("No nixos-install stderr for this failure; see the log.")
+                .style(Style::default().fg(t.text_dim)),
+        );
+    } else {
+        lines.push(
+            Line::from(format!("Last {} lines of stderr:", stderr.len()))
+                .style(Style::default().fg(t.text_dim)),
+        );
+    }
+    lines.extend(stderr.iter().map(|l| {
+        let color = if nix::failure_hint(l).is_some() {
+            t.red
+        } else {
+            t.text
+        };
+        Line::from(l.to_string()).style(Style::default().fg(color))
+    }));
+
+    let title = " Installation failed ";
+    render_scroll_popup(frame, t, area, title, lines, &mut app.diagnostics_scroll);
+}
+
+/// Scrollable popup with the output of a failed `check_flake_inputs` check
+/// or `prebuild_check` build.
+fn render_prebuild_error(frame: &mut Frame, app: &mut App, area: Rect) {
+    let t = &app.theme;
+    let Some(error) = &app.prebuild_error else {
+        return;
+    };
+
+    let mut lines = vec![
+        Line::from("The check before installing failed. Nothing has been written to disk.")
+            .style(Style::default().fg(t.yellow).bold()),
+        Line::from(""),
+    ];
+    lines.extend(
+        error
+            .lines()
+            .map(|l| Line::from(l.to_string()).style(Style::default().fg(t.text))),
+    );
+
+    let title = " Pre-install check failed ";
+    render_scroll_popup(frame, t, area, title, lines, &mut app.prebuild_scroll);
+}
+
+/// Red error popup over most of `area` showing `lines` from `scroll`, which
+/// is clamped so it can't scroll past the last page.
+fn render_scroll_popup(
+    frame: &mut Frame,
+    theme: &Theme,
+    area: Rect,
+    title: &str,
+    lines: Vec<Line>,
+    scroll: &mut usize,
+) {
+    let popup = popup_area(area, 80, 70);
+    frame.render_widget(Clear, popup);
+
+    let inner_height = popup.height.saturating_sub(2) as usize;
+    let max_scroll = lines.len().saturating_sub(inner_height);
+    *scroll = (*scroll).min(max_scroll);
+
+    let p = Paragraph::new(Text::from(lines))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(theme.red))
+                .title(title)
+                .title_style(Style::default().fg(theme.red).bold())
+                .title_bottom(" Up/Down/PgUp/PgDn scroll · Esc close ")
+                .style(Style::default().bg(theme.surface)),
+        )
+        .wrap(Wrap { trim: false })
+        .scroll((*scroll as u16, 0));
+    frame.render_widget(p, popup);
+}
+
+/// Shown instead of the wizard when the terminal is below the minimum size.
+fn render_too_small(frame: &mut Frame, theme: &Theme, area: Rect) {
+    let text = vec![
+        Line::from("Terminal too small").style(Style::default().fg(theme.yellow).bold()),
+        Line::from(""),
+        Line::from(format!("Current size: {}x{}", area.width, area.height))
+            .style(Style::default().fg(theme.text)),
+        Line::from(format!("Needed: at least {}x{}", MIN_WIDTH, MIN_HEIGHT))
+            .style(Style::default().fg(theme.text)),
+        Line::from(""),
+        Line::from("Enlarge the window or use a smaller font (e.g. setfont).")
+            .style(Style::default().fg(theme.text_dim)),
+    ];
+    let height = (text.len() as u16).min(area.height);
+    let [_, center, _] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(height),
+        Constraint::Fill(1),
+    ])
+    .areas(area);
+    frame.render_widget(
+        Paragraph::new(text).centered().wrap(Wrap { trim: true }),
+        center,
+    );
+}
+
 fn popup_area(area: Rect, percent_x: u16, percent_y: u16) -> Rect {
     let [_, vert_center, _] = Layout::vertical([
         Constraint::Fill(1),